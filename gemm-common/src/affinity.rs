@@ -0,0 +1,196 @@
+//! Pinning of the threads that run the parallel regions to specific cores.
+//!
+//! Pinned threads don't migrate between cores, which keeps their caches warm and makes the run
+//! time more stable on busy machines. [`pinned_thread_pool`] builds a rayon pool with each worker
+//! pinned to its own core, to be used with [`Parallelism::Custom`](crate::Parallelism::Custom).
+//! If the [`PIN_THREADS_ENV`] environment variable is set to a list of cores such as `0-7` or
+//! `0,2,4,6`, [`Parallelism::Rayon`](crate::Parallelism::Rayon) runs on such a pool, created on
+//! first use, instead of the global one, unless the caller is already running in a rayon pool.
+//!
+//! Pinning is only supported on Linux, and does nothing elsewhere.
+//!
+//! [`available_cores`] is the number of threads used when none is given, which is limited by the
+//! affinity mask of the process and, in containers, by the CPU quota of its cgroup.
+
+use alloc::vec::Vec;
+
+/// Environment variable listing the cores that the parallel regions are pinned to.
+pub const PIN_THREADS_ENV: &str = "GEMM_PIN_THREADS";
+
+/// Pins the calling thread to `core`, and returns whether it succeeded.
+pub fn pin_current_thread(core: usize) -> bool {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        if core >= libc::CPU_SETSIZE as usize {
+            return false;
+        }
+        let mut set: libc::cpu_set_t = core::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, core::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = core;
+        false
+    }
+}
+
+/// Cores the calling thread is allowed to run on, or `None` if they can't be queried.
+pub fn current_cores() -> Option<Vec<usize>> {
+    #[cfg(target_os = "linux")]
+    {
+        affinity(0)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Cores the thread `tid` is allowed to run on, where `0` is the calling thread, and the id of
+/// the process is its main thread.
+#[cfg(target_os = "linux")]
+fn affinity(tid: libc::pid_t) -> Option<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = core::mem::zeroed();
+        if libc::sched_getaffinity(tid, core::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return None;
+        }
+        Some(
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|&core| libc::CPU_ISSET(core, &set))
+                .collect(),
+        )
+    }
+}
+
+/// Number of cores the process may run on at the same time, which is the smallest of the number
+/// of cores in the affinity mask of its main thread and its cgroup CPU quota, rounded up. It's
+/// detected on first use, and falls back to [`std::thread::available_parallelism`] if neither
+/// can be read.
+pub fn available_cores() -> usize {
+    static AVAILABLE: once_cell::race::OnceNonZeroUsize = once_cell::race::OnceNonZeroUsize::new();
+    AVAILABLE
+        .get_or_init(|| {
+            #[cfg(target_os = "linux")]
+            let affinity = affinity(unsafe { libc::getpid() }).map(|cores| cores.len());
+            #[cfg(not(target_os = "linux"))]
+            let affinity: Option<usize> = None;
+            let quota = cgroup_cpu_quota();
+            let cores = match (affinity, quota) {
+                (Some(affinity), Some(quota)) => Ord::min(affinity, quota),
+                (Some(cores), None) | (None, Some(cores)) => cores,
+                (None, None) => std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1),
+            };
+            core::num::NonZeroUsize::new(cores)
+                .unwrap_or_else(|| core::num::NonZeroUsize::new(1).unwrap())
+        })
+        .get()
+}
+
+/// CPU quota of the cgroup of the process, in cores rounded up, or `None` if it's unlimited or
+/// can't be read. Both cgroup v1 (`cpu.cfs_quota_us`) and v2 (`cpu.max`) are supported.
+pub fn cgroup_cpu_quota() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        let read = |path: &str| std::fs::read_to_string(path).ok();
+        let cgroups = read("/proc/self/cgroup")?;
+        for line in cgroups.lines() {
+            let mut fields = line.splitn(3, ':');
+            let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+            // the cgroup may be the root of the mount in a container, so its path is tried
+            // first, then the root
+            let path = path.trim_end_matches('/');
+            if controllers.is_empty() {
+                for dir in [path, ""] {
+                    if let Some(max) = read(&std::format!("/sys/fs/cgroup{}/cpu.max", dir)) {
+                        return parse_cpu_max(&max);
+                    }
+                }
+            } else if controllers.split(',').any(|c| c == "cpu") {
+                for dir in [path, ""] {
+                    let dir = std::format!("/sys/fs/cgroup/{}{}", controllers, dir);
+                    let quota = read(&std::format!("{}/cpu.cfs_quota_us", dir));
+                    let period = read(&std::format!("{}/cpu.cfs_period_us", dir));
+                    if let (Some(quota), Some(period)) = (quota, period) {
+                        return parse_cpu_max(&std::format!("{} {}", quota.trim(), period.trim()));
+                    }
+                }
+            }
+        }
+        None
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Parses a CPU quota in the format of the cgroup v2 `cpu.max` file, such as `150000 100000`, in
+/// cores rounded up, or returns `None` if it's unlimited (`max` or negative) or invalid.
+pub fn parse_cpu_max(cpu_max: &str) -> Option<usize> {
+    let mut fields = cpu_max.split_whitespace();
+    let quota: i64 = fields.next()?.parse().ok()?;
+    let period: i64 = fields.next().unwrap_or("100000").parse().ok()?;
+    if quota <= 0 || period <= 0 {
+        return None;
+    }
+    Some(((quota + period - 1) / period) as usize)
+}
+
+/// Parses a list of cores such as `0-3,8,10-11`, in the format used by `taskset` and sysfs, or
+/// returns `None` if it's invalid.
+pub fn parse_core_list(list: &str) -> Option<Vec<usize>> {
+    let mut cores = Vec::new();
+    for range in list.trim().split(',') {
+        match range.split_once('-') {
+            Some((lo, hi)) => {
+                let lo: usize = lo.trim().parse().ok()?;
+                let hi: usize = hi.trim().parse().ok()?;
+                if lo > hi {
+                    return None;
+                }
+                cores.extend(lo..=hi);
+            }
+            None => cores.push(range.trim().parse().ok()?),
+        }
+    }
+    Some(cores)
+}
+
+/// Builds a rayon pool with one worker per element of `cores`, each pinned to that core. Workers
+/// that can't be pinned run unpinned, and an empty list gives a pool with the default number of
+/// unpinned workers.
+#[cfg(feature = "rayon")]
+pub fn pinned_thread_pool(
+    cores: &[usize],
+) -> Result<rayon::ThreadPool, rayon::ThreadPoolBuildError> {
+    let cores = cores.to_vec();
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(cores.len())
+        .thread_name(|i| std::format!("gemm-pinned-{}", i))
+        .start_handler(move |i| {
+            if let Some(&core) = cores.get(i) {
+                pin_current_thread(core);
+            }
+        })
+        .build()
+}
+
+/// Pool pinned to the cores listed by [`PIN_THREADS_ENV`], or `None` if it's unset or invalid.
+#[cfg(feature = "rayon")]
+pub(crate) fn env_thread_pool() -> Option<&'static rayon::ThreadPool> {
+    static POOL: once_cell::sync::OnceCell<Option<rayon::ThreadPool>> =
+        once_cell::sync::OnceCell::new();
+    POOL.get_or_init(|| {
+        let cores = parse_core_list(&std::env::var(PIN_THREADS_ENV).ok()?)?;
+        if cores.is_empty() {
+            return None;
+        }
+        pinned_thread_pool(&cores).ok()
+    })
+    .as_ref()
+}
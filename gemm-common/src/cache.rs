@@ -1,8 +1,21 @@
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
 #[derive(Default, Debug, Copy, Clone)]
 pub struct CacheInfo {
     pub associativity: usize,
+    /// Size of one instance of the cache.
     pub cache_bytes: usize,
     pub cache_line_bytes: usize,
+    /// Number of hardware threads sharing one instance of the cache, or 1 when unknown.
+    pub shared_threads: usize,
+}
+
+impl CacheInfo {
+    /// Share of the cache that each of the threads sharing it can count on.
+    #[inline]
+    pub fn bytes_per_thread(&self) -> usize {
+        self.cache_bytes / self.shared_threads.max(1)
+    }
 }
 
 #[derive(Default, Debug, Copy, Clone)]
@@ -12,10 +25,123 @@ pub struct KernelParams {
     pub nc: usize,
 }
 
+/// Environment variables overriding the blocking parameters of every product, read on first use.
+pub const KC_ENV: &str = "GEMM_KC";
+pub const MC_ENV: &str = "GEMM_MC";
+pub const NC_ENV: &str = "GEMM_NC";
+
+// `usize::MAX` until read from the environment
+static KC_OVERRIDE: AtomicUsize = AtomicUsize::new(usize::MAX);
+static MC_OVERRIDE: AtomicUsize = AtomicUsize::new(usize::MAX);
+static NC_OVERRIDE: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+#[inline]
+fn load_override(value: &AtomicUsize, env: &str) -> usize {
+    let current = value.load(Relaxed);
+    if current != usize::MAX {
+        return current;
+    }
+    #[cfg(feature = "std")]
+    let from_env = std::env::var(env)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0);
+    #[cfg(not(feature = "std"))]
+    let from_env = {
+        let _ = env;
+        0
+    };
+    // unless it was set in the meantime
+    match value.compare_exchange(usize::MAX, from_env, Relaxed, Relaxed) {
+        Ok(_) => from_env,
+        Err(current) => current,
+    }
+}
+
+/// Blocking parameters that override the ones chosen by the drivers for every product, where the
+/// parameters that are `0` are left to the drivers. They're initially read from [`KC_ENV`],
+/// [`MC_ENV`] and [`NC_ENV`].
+#[inline]
+pub fn get_kernel_params_override() -> KernelParams {
+    KernelParams {
+        kc: load_override(&KC_OVERRIDE, KC_ENV),
+        mc: load_override(&MC_OVERRIDE, MC_ENV),
+        nc: load_override(&NC_OVERRIDE, NC_ENV),
+    }
+}
+/// Overrides the blocking parameters of every product, where the parameters that are `0` are left
+/// to the drivers.
+#[inline]
+pub fn set_kernel_params_override(params: KernelParams) {
+    KC_OVERRIDE.store(params.kc, Relaxed);
+    MC_OVERRIDE.store(params.mc, Relaxed);
+    NC_OVERRIDE.store(params.nc, Relaxed);
+}
+
+// splits `dim` into blocks of at most `block` elements, rounded up to `align`, of nearly equal
+// sizes
+#[inline]
+fn fit(dim: usize, block: usize, align: usize) -> usize {
+    let n_blocks = dim.msrv_div_ceil(Ord::max(block, align));
+    dim.msrv_div_ceil(n_blocks * align) * align
+}
+
+/// Adjusts `params` to an `m×n×k` problem with an `mr×nr` microkernel, such that each dimension
+/// is split into blocks of nearly equal sizes, no larger than the ones of `params`.
+#[inline]
+pub fn fit_kernel_params(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    params: KernelParams,
+) -> KernelParams {
+    KernelParams {
+        kc: fit(k, params.kc, 1),
+        mc: fit(m, params.mc, mr),
+        nc: fit(n, params.nc, nr),
+    }
+}
+
+/// Replaces the parameters of `params` that are set in `over`, for an `m×n×k` problem with an
+/// `mr×nr` microkernel. `kc` is capped by `k`, while `mc` and `nc` are rounded up to multiples of
+/// `mr` and `nr`, and capped by `m` and `n` rounded up the same way.
+#[inline]
+pub fn override_kernel_params(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    params: KernelParams,
+    over: KernelParams,
+) -> KernelParams {
+    if m == 0 || n == 0 || k == 0 {
+        return params;
+    }
+    let pick = |chosen: usize, over: usize, dim: usize, align: usize| {
+        if over == 0 {
+            chosen
+        } else {
+            Ord::min(
+                over.msrv_next_multiple_of(align),
+                dim.msrv_next_multiple_of(align),
+            )
+        }
+    };
+    KernelParams {
+        kc: pick(params.kc, over.kc, k, 1),
+        mc: pick(params.mc, over.mc, m, mr),
+        nc: pick(params.nc, over.nc, n, nr),
+    }
+}
+
 pub trait DivCeil: Sized {
     fn msrv_div_ceil(self, rhs: Self) -> Self;
     fn msrv_next_multiple_of(self, rhs: Self) -> Self;
     fn msrv_checked_next_multiple_of(self, rhs: Self) -> Option<Self>;
+    fn msrv_is_multiple_of(self, rhs: Self) -> bool;
 }
 
 impl DivCeil for usize {
@@ -47,6 +173,14 @@ impl DivCeil for usize {
             }
         }
     }
+
+    #[inline]
+    fn msrv_is_multiple_of(self, rhs: Self) -> bool {
+        match self.checked_rem(rhs) {
+            Some(r) => r == 0,
+            None => self == 0,
+        }
+    }
 }
 
 #[cfg(target_vendor = "apple")]
@@ -72,6 +206,13 @@ fn has_amx_impl() -> bool {
 
 fn cache_info() -> Option<[CacheInfo; 3]> {
     if !cfg!(miri) {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if let Some(info) = cache_info_cpuid() {
+                return Some(info);
+            }
+        }
+
         #[cfg(feature = "std")]
         {
             #[cfg(target_os = "linux")]
@@ -82,6 +223,7 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
                         associativity: 8,
                         cache_bytes: 0,
                         cache_line_bytes: 64,
+                        shared_threads: 1,
                     }; 3];
 
                     for cpu_x in fs::read_dir("/sys/devices/system/cpu")? {
@@ -113,6 +255,7 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
                                 associativity: 8,
                                 cache_bytes: 0,
                                 cache_line_bytes: 64,
+                                shared_threads: 1,
                             };
                             let mut level: usize = 0;
                             let mut shared_count: usize = 0;
@@ -200,9 +343,6 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
                                     }
                                 }
                             }
-                            if level == 3 {
-                                shared_count = 1;
-                            }
                             if level > 0 {
                                 if cache_info.cache_line_bytes
                                     >= all_info[level - 1].cache_line_bytes
@@ -210,8 +350,8 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
                                     all_info[level - 1].associativity = cache_info.associativity;
                                     all_info[level - 1].cache_line_bytes =
                                         cache_info.cache_line_bytes;
-                                    all_info[level - 1].cache_bytes =
-                                        cache_info.cache_bytes / shared_count;
+                                    all_info[level - 1].cache_bytes = cache_info.cache_bytes;
+                                    all_info[level - 1].shared_threads = shared_count.max(1);
                                 }
                             }
                         }
@@ -231,15 +371,20 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
 
                 if let Ok(lscpu) = std::process::Command::new("lscpu")
                     .arg("-B")
-                    .arg("-C=type,level,ways,coherency-size,one-size")
+                    .arg("-C=type,level,ways,coherency-size,one-size,all-size")
                     .output()
                 {
                     if lscpu.status.success() {
                         if let Ok(lscpu) = String::from_utf8(lscpu.stdout).as_deref() {
+                            // the threads are assumed to be spread evenly between the instances
+                            let n_cpus =
+                                Ord::max(unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }, 1)
+                                    as usize;
                             let mut info = CACHE_INFO_DEFAULT;
                             for line in lscpu.lines().skip(1) {
                                 let mut iter = line.split_whitespace();
-                                if let [Some(cache_type), Some(level), Some(ways), Some(coherency_size), Some(one_size)] = [
+                                if let [Some(cache_type), Some(level), Some(ways), Some(coherency_size), Some(one_size), Some(all_size)] = [
+                                    iter.next(),
                                     iter.next(),
                                     iter.next(),
                                     iter.next(),
@@ -252,10 +397,14 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
                                         let coherency_size =
                                             coherency_size.parse::<usize>().unwrap();
                                         let one_size = one_size.parse::<usize>().unwrap();
+                                        let all_size = all_size.parse::<usize>().unwrap();
+                                        let instances = Ord::max(all_size / one_size.max(1), 1);
 
                                         info[level - 1].associativity = ways;
                                         info[level - 1].cache_line_bytes = coherency_size;
                                         info[level - 1].cache_bytes = one_size;
+                                        info[level - 1].shared_threads =
+                                            Ord::max(n_cpus / instances, 1);
                                     }
                                 }
                             }
@@ -287,105 +436,120 @@ fn cache_info() -> Option<[CacheInfo; 3]> {
                         cpusperl2.parse::<usize>(),
                         l2.parse::<usize>(),
                     ) {
-                        all_info[1].cache_bytes = l2 / cpusperl2;
+                        all_info[1].cache_bytes = l2;
+                        all_info[1].shared_threads = cpusperl2.max(1);
                     }
                 }
                 all_info[2].cache_bytes = 0;
                 return Some(all_info);
             }
         }
+    }
+    None
+}
 
-        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        {
-            use raw_cpuid::CpuId;
-            let cpuid = CpuId::new();
-
-            if let Some(vf) = cpuid.get_vendor_info() {
-                let vf = vf.as_str();
-                if vf == "GenuineIntel" {
-                    if let Some(cparams) = cpuid.get_cache_parameters() {
-                        // not sure why, intel cpus seem to prefer smaller mc
-                        let mut info = [CacheInfo {
-                            cache_bytes: 0,
-                            associativity: 0,
-                            cache_line_bytes: 64,
-                        }; 3];
-
-                        for cache in cparams {
-                            use raw_cpuid::CacheType::*;
-                            match cache.cache_type() {
-                                Null | Instruction | Reserved => continue,
-                                Data | Unified => {
-                                    let level = cache.level() as usize;
-                                    let associativity = cache.associativity();
-                                    let nsets = cache.sets();
-                                    let cache_line_bytes = cache.coherency_line_size();
-                                    if level > 0 && level < 4 {
-                                        let info = &mut info[level - 1];
-                                        info.cache_line_bytes = cache_line_bytes;
-                                        info.associativity = associativity;
-                                        info.cache_bytes = associativity * nsets * cache_line_bytes;
-                                    }
-                                }
-                            }
-                        }
-                        return Some(info);
+/// Reads the cache hierarchy from the deterministic cache parameters leaf (0x4 on Intel,
+/// 0x8000_001D on AMD), which also reports how many threads share each cache, falling back to the
+/// legacy AMD leaves on older processors.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn cache_info_cpuid() -> Option<[CacheInfo; 3]> {
+    use raw_cpuid::CpuId;
+    let cpuid = CpuId::new();
+
+    // the sharing fields count thread ids rather than threads, so they can overshoot
+    let max_threads = match cpuid.get_feature_info() {
+        Some(features) if features.has_htt() => {
+            Ord::max(features.max_logical_processor_ids() as usize, 1)
+        }
+        _ => 1,
+    };
+
+    if let Some(cparams) = cpuid.get_cache_parameters() {
+        let mut info = [CacheInfo::default(); 3];
+
+        for cache in cparams {
+            use raw_cpuid::CacheType::*;
+            match cache.cache_type() {
+                Null | Instruction | Reserved => continue,
+                Data | Unified => {
+                    let level = cache.level() as usize;
+                    if level > 0 && level < 4 {
+                        let cache_line_bytes = cache.coherency_line_size();
+                        let cache_bytes = cache.associativity()
+                            * cache.physical_line_partitions()
+                            * cache_line_bytes
+                            * cache.sets();
+                        let info = &mut info[level - 1];
+                        info.cache_line_bytes = cache_line_bytes;
+                        info.cache_bytes = cache_bytes;
+                        info.associativity = if cache.is_fully_associative() {
+                            cache_bytes / cache_line_bytes
+                        } else {
+                            cache.associativity()
+                        };
+                        info.shared_threads = cache.max_cores_for_cache().clamp(1, max_threads);
                     }
                 }
+            }
+        }
 
-                if vf == "AuthenticAMD" {
-                    if let Some(l1) = cpuid.get_l1_cache_and_tlb_info() {
-                        if let Some(l23) = cpuid.get_l2_l3_cache_and_tlb_info() {
-                            let compute_info = |associativity: raw_cpuid::Associativity,
-                                                cache_kb: usize,
-                                                cache_line_bytes: u8|
-                             -> CacheInfo {
-                                let cache_bytes = cache_kb as usize * 1024;
-                                let cache_line_bytes = cache_line_bytes as usize;
-
-                                use raw_cpuid::Associativity::*;
-                                let associativity = match associativity {
-                                    Unknown | Disabled => {
-                                        return CacheInfo {
-                                            associativity: 0,
-                                            cache_bytes: 0,
-                                            cache_line_bytes: 64,
-                                        }
-                                    }
-                                    FullyAssociative => cache_bytes / cache_line_bytes,
-                                    DirectMapped => 1,
-                                    NWay(n) => n as usize,
-                                };
-
-                                CacheInfo {
-                                    associativity,
-                                    cache_bytes,
-                                    cache_line_bytes,
-                                }
-                            };
-                            return Some([
-                                compute_info(
-                                    l1.dcache_associativity(),
-                                    l1.dcache_size() as usize,
-                                    l1.dcache_line_size(),
-                                ),
-                                compute_info(
-                                    l23.l2cache_associativity(),
-                                    l23.l2cache_size() as usize,
-                                    l23.l2cache_line_size(),
-                                ),
-                                compute_info(
-                                    l23.l3cache_associativity(),
-                                    l23.l3cache_size() as usize * 512,
-                                    l23.l3cache_line_size(),
-                                ),
-                            ]);
-                        }
+        // a missing l3 is meaningful, but some hypervisors report no caches at all
+        if info[0].cache_bytes != 0 && info[1].cache_bytes != 0 {
+            return Some(info);
+        }
+    }
+
+    if cpuid.get_vendor_info()?.as_str() == "AuthenticAMD" {
+        let l1 = cpuid.get_l1_cache_and_tlb_info()?;
+        let l23 = cpuid.get_l2_l3_cache_and_tlb_info()?;
+        let compute_info = |associativity: raw_cpuid::Associativity,
+                            cache_kb: usize,
+                            cache_line_bytes: u8|
+         -> CacheInfo {
+            let cache_bytes = cache_kb * 1024;
+            let cache_line_bytes = cache_line_bytes as usize;
+
+            use raw_cpuid::Associativity::*;
+            let associativity = match associativity {
+                Unknown | Disabled => {
+                    return CacheInfo {
+                        associativity: 0,
+                        cache_bytes: 0,
+                        cache_line_bytes: 64,
+                        shared_threads: 1,
                     }
                 }
+                FullyAssociative => cache_bytes / cache_line_bytes,
+                DirectMapped => 1,
+                NWay(n) => n as usize,
+            };
+
+            CacheInfo {
+                associativity,
+                cache_bytes,
+                cache_line_bytes,
+                shared_threads: 1,
             }
-        }
+        };
+        return Some([
+            compute_info(
+                l1.dcache_associativity(),
+                l1.dcache_size() as usize,
+                l1.dcache_line_size(),
+            ),
+            compute_info(
+                l23.l2cache_associativity(),
+                l23.l2cache_size() as usize,
+                l23.l2cache_line_size(),
+            ),
+            compute_info(
+                l23.l3cache_associativity(),
+                l23.l3cache_size() as usize * 512,
+                l23.l3cache_line_size(),
+            ),
+        ]);
     }
+
     None
 }
 
@@ -395,16 +559,19 @@ static CACHE_INFO_DEFAULT: [CacheInfo; 3] = [
         associativity: 8,
         cache_bytes: 32 * 1024, // 32KiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
     CacheInfo {
         associativity: 8,
         cache_bytes: 256 * 1024, // 256KiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
     CacheInfo {
         associativity: 8,
         cache_bytes: 2 * 1024 * 1024, // 2MiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
 ];
 
@@ -414,16 +581,19 @@ static CACHE_INFO_DEFAULT: [CacheInfo; 3] = [
         associativity: 8,
         cache_bytes: 64 * 1024, // 64KiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
     CacheInfo {
         associativity: 8,
         cache_bytes: 512 * 1024, // 512KiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
     CacheInfo {
         associativity: 8,
         cache_bytes: 4 * 1024 * 1024, // 4MiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
 ];
 
@@ -438,16 +608,19 @@ static CACHE_INFO_DEFAULT: [CacheInfo; 3] = [
         associativity: 8,
         cache_bytes: 16 * 1024, // 16KiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
     CacheInfo {
         associativity: 8,
         cache_bytes: 512 * 1024, // 512KiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
     CacheInfo {
         associativity: 8,
         cache_bytes: 1024 * 1024, // 1MiB
         cache_line_bytes: 64,
+        shared_threads: 1,
     },
 ];
 
@@ -515,6 +688,24 @@ pub fn kernel_params(
     mr: usize,
     nr: usize,
     sizeof: usize,
+) -> KernelParams {
+    kernel_params_for_caches(&CACHE_INFO, m, n, k, mr, nr, sizeof)
+}
+
+/// Same as [`kernel_params`], for the cache hierarchy described by `info` instead of the one of
+/// the machine.
+///
+/// The rhs macropanel is sized for a single instance of the l3, along with the lhs macropanels of
+/// the threads sharing it. On machines with several l3 domains, such as multi-socket and chiplet
+/// processors, each domain caches its own copy of the rhs macropanel rather than splitting it.
+pub fn kernel_params_for_caches(
+    info: &[CacheInfo; 3],
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    sizeof: usize,
 ) -> KernelParams {
     if m == 0 || n == 0 || k == 0 {
         return KernelParams {
@@ -524,11 +715,12 @@ pub fn kernel_params(
         };
     }
 
-    let info = *CACHE_INFO;
-
-    let l1_cache_bytes = info[0].cache_bytes.max(32 * 1024);
-    let l2_cache_bytes = info[1].cache_bytes;
+    // the l1 blocking only depends on its geometry. the threads sharing an l2 each keep their own
+    // lhs macropanel in it, while the rhs macropanel in l3 is shared by all of them
+    let l1_cache_bytes = info[0].cache_bytes;
+    let l2_cache_bytes = info[1].bytes_per_thread();
     let l3_cache_bytes = info[2].cache_bytes;
+    let l3_shared_threads = info[2].shared_threads.max(1);
 
     let l1_line_bytes = info[0].cache_line_bytes.max(64);
 
@@ -536,7 +728,7 @@ pub fn kernel_params(
     let l2_assoc = info[1].associativity.max(2);
     let l3_assoc = info[2].associativity.max(2);
 
-    let l1_n_sets = l1_cache_bytes / (l1_line_bytes * l1_assoc);
+    let l1_n_sets = Ord::max(l1_cache_bytes / (l1_line_bytes * l1_assoc), 1);
 
     // requires
     // A micropanels must occupy different cache sets
@@ -586,17 +778,22 @@ pub fn kernel_params(
     let auto_mc = Ord::min(auto_mc, 8 * mr);
 
     // l3 cache must hold
-    //  - A macropanel: mc×kc: assume 1 assoc degree
+    //  - A macropanels: mc×kc for each thread sharing the l3
     //  - B macropanel: nc×kc
     let auto_nc = if l3_cache_bytes == 0 {
         0
     } else {
-        // let lhs_macropanel_bytes = auto_mc * auto_kc * sizeof;
-        // let lhs_l3_assoc = msrv_div_ceil(lhs_macropanel_bytes, l3_cache_bytes / l3_assoc);
-        let rhs_l3_assoc = l3_assoc - 1;
+        let lhs_macropanel_bytes = l3_shared_threads * auto_mc * auto_kc * sizeof;
+        let lhs_l3_assoc = lhs_macropanel_bytes
+            .msrv_div_ceil(Ord::max(l3_cache_bytes / l3_assoc, 1))
+            .max(1);
+        let rhs_l3_assoc = l3_assoc.saturating_sub(lhs_l3_assoc).max(1);
         let rhs_macropanel_max_bytes = (rhs_l3_assoc * l3_cache_bytes) / l3_assoc;
 
-        let auto_nc = round_down(rhs_macropanel_max_bytes / (sizeof * auto_kc), nr);
+        let auto_nc = Ord::max(
+            round_down(rhs_macropanel_max_bytes / (sizeof * auto_kc), nr),
+            nr,
+        );
         let n_iter = n.msrv_div_ceil(auto_nc);
         n.msrv_div_ceil(n_iter * nr) * nr
     };
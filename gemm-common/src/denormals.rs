@@ -0,0 +1,144 @@
+//! Flushing of denormal numbers.
+//!
+//! Arithmetic on denormal numbers is handled by microcode on most x86 processors, which makes
+//! products whose operands or partial sums are denormal one to two orders of magnitude slower.
+//! When flushing is enabled, the threads computing a product flush denormal results to zero and
+//! treat denormal inputs as zero (FTZ and DAZ in the MXCSR register on x86_64, FZ in the FPCR
+//! register on aarch64), and restore their previous mode afterwards.
+//!
+//! The parallel regions run with the mode of the thread that starts them, so that the worker
+//! threads also flush denormals while they compute a product for a thread that does.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the products flush denormal numbers by default.
+pub const DEFAULT_FLUSH_DENORMALS: bool = false;
+
+static FLUSH_DENORMALS: AtomicBool = AtomicBool::new(DEFAULT_FLUSH_DENORMALS);
+
+/// Whether the products flush denormal numbers to zero while they're computed, unless their
+/// config says otherwise. When disabled, the threads keep their own mode.
+#[inline]
+pub fn get_flush_denormals() -> bool {
+    FLUSH_DENORMALS.load(Ordering::Relaxed)
+}
+#[inline]
+pub fn set_flush_denormals(enable: bool) {
+    FLUSH_DENORMALS.store(enable, Ordering::Relaxed);
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    /// MXCSR register.
+    pub type Mode = u32;
+    // FTZ | DAZ
+    pub const FLUSH: Mode = (1 << 15) | (1 << 6);
+
+    #[inline]
+    pub fn mode() -> Mode {
+        let mut csr: Mode = 0;
+        unsafe {
+            core::arch::asm!(
+                "stmxcsr [{}]",
+                in(reg) &mut csr,
+                options(nostack, preserves_flags),
+            );
+        }
+        csr
+    }
+
+    #[inline]
+    pub fn set_mode(csr: Mode) {
+        unsafe {
+            core::arch::asm!(
+                "ldmxcsr [{}]",
+                in(reg) &csr,
+                options(nostack, preserves_flags),
+            );
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod imp {
+    /// FPCR register.
+    pub type Mode = u64;
+    // FZ
+    pub const FLUSH: Mode = 1 << 24;
+
+    #[inline]
+    pub fn mode() -> Mode {
+        let fpcr: Mode;
+        unsafe {
+            core::arch::asm!(
+                "mrs {}, fpcr",
+                out(reg) fpcr,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        fpcr
+    }
+
+    #[inline]
+    pub fn set_mode(fpcr: Mode) {
+        unsafe {
+            core::arch::asm!(
+                "msr fpcr, {}",
+                in(reg) fpcr,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod imp {
+    pub type Mode = u32;
+    pub const FLUSH: Mode = 0;
+
+    #[inline]
+    pub fn mode() -> Mode {
+        0
+    }
+
+    #[inline]
+    pub fn set_mode(_: Mode) {}
+}
+
+/// Whether the current thread flushes denormal numbers to zero. Always `false` on targets other
+/// than x86_64 and aarch64.
+#[inline]
+pub fn thread_flushes_denormals() -> bool {
+    imp::FLUSH != 0 && imp::mode() & imp::FLUSH == imp::FLUSH
+}
+
+/// Flushes denormal numbers on the current thread until the guard is dropped, which restores its
+/// previous mode, including when the computation panics.
+pub struct FlushDenormalsGuard {
+    previous: Option<imp::Mode>,
+}
+
+impl FlushDenormalsGuard {
+    /// Flushes denormal numbers on the current thread if `enable` is true, and leaves its mode
+    /// as is otherwise.
+    #[inline]
+    pub fn new(enable: bool) -> Self {
+        let previous = if enable && !thread_flushes_denormals() {
+            let mode = imp::mode();
+            imp::set_mode(mode | imp::FLUSH);
+            Some(mode)
+        } else {
+            None
+        };
+        Self { previous }
+    }
+}
+
+impl Drop for FlushDenormalsGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(mode) = self.previous {
+            imp::set_mode(mode);
+        }
+    }
+}
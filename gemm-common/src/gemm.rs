@@ -1,12 +1,17 @@
 use crate::{
-    cache::{kernel_params, DivCeil, KernelParams, CACHE_INFO},
+    cache::{
+        get_kernel_params_override, kernel_params, override_kernel_params, DivCeil, KernelParams,
+        CACHE_INFO,
+    },
+    denormals::{thread_flushes_denormals, FlushDenormalsGuard},
     gemv, gevv,
     microkernel::MicroKernelFn,
-    pack_operands::{pack_lhs, pack_rhs},
+    numa::{get_numa_nodes, get_replicate_rhs},
+    pack_operands::{pack_lhs, pack_lhs_scaled, pack_rhs, pack_rhs_scaled},
     simd::MixedSimd,
     Parallelism, Ptr,
 };
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU8, AtomicUsize, Ordering};
 use dyn_stack::GlobalMemBuffer;
 use dyn_stack::{DynStack, StackReq};
 #[cfg(feature = "f16")]
@@ -56,10 +61,11 @@ pub const CACHELINE_ALIGN: usize = {
     }
 };
 
+// allocated and first touched by the thread that packs into it, so it lives on its NUMA node
 #[cfg(feature = "std")]
 thread_local! {
     pub static L2_SLAB: core::cell::RefCell<GlobalMemBuffer> = core::cell::RefCell::new(GlobalMemBuffer::new(
-        StackReq::new_aligned::<u8>(CACHE_INFO[1].cache_bytes, CACHELINE_ALIGN)
+        StackReq::new_aligned::<u8>(CACHE_INFO[1].bytes_per_thread(), CACHELINE_ALIGN)
     ));
 }
 
@@ -107,6 +113,8 @@ impl Conj for c64 {
     }
 }
 
+/// Work `m×n×k` of a block below which it's computed on a single thread, for real products with
+/// 256-bit vectors.
 pub const DEFAULT_THREADING_THRESHOLD: usize = 48 * 48 * 256;
 
 // we REALLY want to pack the rhs on aarch64 since we can use mul_add_lane
@@ -118,12 +126,34 @@ pub const DEFAULT_RHS_PACKING_THRESHOLD: usize = 128;
 pub const DEFAULT_LHS_PACKING_THRESHOLD_SINGLE_THREAD: usize = 8;
 pub const DEFAULT_LHS_PACKING_THRESHOLD_MULTI_THREAD: usize = 16;
 
+/// Whether the results may depend on the number of threads by default.
+pub const DEFAULT_DETERMINISTIC: bool = false;
+
+/// Derives the streaming stores threshold from the size of the l3.
+pub const DEFAULT_STREAMING_STORES_THRESHOLD: usize = 0;
+
+/// Whether the register blocking is selected from the shape of each problem by default.
+pub const DEFAULT_BLOCKING_SELECTION: bool = true;
+
 static THREADING_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_THREADING_THRESHOLD);
+// per-type thresholds, `usize::MAX` when derived from the global one
+#[cfg(feature = "f16")]
+static THREADING_THRESHOLD_F16: AtomicUsize = AtomicUsize::new(usize::MAX);
+static THREADING_THRESHOLD_F32: AtomicUsize = AtomicUsize::new(usize::MAX);
+static THREADING_THRESHOLD_F64: AtomicUsize = AtomicUsize::new(usize::MAX);
+static THREADING_THRESHOLD_C32: AtomicUsize = AtomicUsize::new(usize::MAX);
+static THREADING_THRESHOLD_C64: AtomicUsize = AtomicUsize::new(usize::MAX);
+static DETERMINISTIC: AtomicBool = AtomicBool::new(DEFAULT_DETERMINISTIC);
+static BLOCKING_SELECTION: AtomicBool = AtomicBool::new(DEFAULT_BLOCKING_SELECTION);
+static STREAMING_STORES_THRESHOLD: AtomicUsize =
+    AtomicUsize::new(DEFAULT_STREAMING_STORES_THRESHOLD);
 static RHS_PACKING_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_RHS_PACKING_THRESHOLD);
 static LHS_PACKING_THRESHOLD_SINGLE_THREAD: AtomicUsize =
     AtomicUsize::new(DEFAULT_LHS_PACKING_THRESHOLD_SINGLE_THREAD);
 static LHS_PACKING_THRESHOLD_MULTI_THREAD: AtomicUsize =
     AtomicUsize::new(DEFAULT_LHS_PACKING_THRESHOLD_MULTI_THREAD);
+static LHS_PACKING_POLICY: AtomicU8 = AtomicU8::new(PackingPolicy::Auto as u8);
+static RHS_PACKING_POLICY: AtomicU8 = AtomicU8::new(PackingPolicy::Auto as u8);
 
 #[inline]
 pub fn get_threading_threshold() -> usize {
@@ -134,6 +164,246 @@ pub fn set_threading_threshold(value: usize) {
     THREADING_THRESHOLD.store(value, Ordering::Relaxed);
 }
 
+fn threading_threshold_of<T: 'static>() -> Option<&'static AtomicUsize> {
+    use core::any::TypeId;
+    let ty = TypeId::of::<T>();
+    #[cfg(feature = "f16")]
+    if ty == TypeId::of::<f16>() {
+        return Some(&THREADING_THRESHOLD_F16);
+    }
+    if ty == TypeId::of::<f32>() {
+        Some(&THREADING_THRESHOLD_F32)
+    } else if ty == TypeId::of::<f64>() {
+        Some(&THREADING_THRESHOLD_F64)
+    } else if ty == TypeId::of::<c32>() {
+        Some(&THREADING_THRESHOLD_C32)
+    } else if ty == TypeId::of::<c64>() {
+        Some(&THREADING_THRESHOLD_C64)
+    } else {
+        None
+    }
+}
+
+/// Threading threshold set for products of `T` with [`set_type_threading_threshold`], or `None`
+/// if it's derived from the global one.
+#[inline]
+pub fn get_type_threading_threshold<T: 'static>() -> Option<usize> {
+    let value = threading_threshold_of::<T>()?.load(Ordering::Relaxed);
+    if value == usize::MAX {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Sets the threading threshold of the products of `T`, regardless of the vector width of the
+/// backend, or derives it from the global one again when `None`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `f16`, `c32`, or `c64`.
+#[inline]
+pub fn set_type_threading_threshold<T: 'static>(value: Option<usize>) {
+    threading_threshold_of::<T>()
+        .expect("threading thresholds can only be set for the supported scalar types")
+        .store(
+            value.map_or(usize::MAX, |value| Ord::min(value, usize::MAX - 1)),
+            Ordering::Relaxed,
+        );
+}
+
+/// Work `m×n×k` of a block of `T` below which it's computed on a single thread, for a backend with
+/// `simd_bytes`-byte vectors.
+///
+/// Unless set with [`set_type_threading_threshold`], it's the global threshold scaled by the
+/// vector width relative to 256 bits, since wider vectors finish a block sooner and need more
+/// work to make up for synchronizing the threads, and divided by 4 for `c32` and 16 for `c64`,
+/// whose products take 4 real ones.
+pub fn threading_threshold<T: 'static>(simd_bytes: usize) -> usize {
+    use core::any::TypeId;
+    if let Some(value) = get_type_threading_threshold::<T>() {
+        return value;
+    }
+    let threshold = get_threading_threshold().saturating_mul(simd_bytes) / 32;
+    if TypeId::of::<T>() == TypeId::of::<c32>() {
+        threshold / 4
+    } else if TypeId::of::<T>() == TypeId::of::<c64>() {
+        threshold / 16
+    } else {
+        threshold
+    }
+}
+
+/// Whether the products give bitwise-identical results regardless of the parallelism, which
+/// disables the strategies that change the order in which the products are accumulated, such as
+/// splitting `k` between the threads.
+///
+/// The order of the sums along `k` only depends on the register blocking and the depth blocking
+/// `kc`. In deterministic mode, neither depends on the number of threads, so that for fixed
+/// blocking parameters each element is accumulated in the same order on every run, for any
+/// parallelism. Single products can opt in or out with [`GemmConfig::deterministic`].
+#[inline]
+pub fn get_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
+#[inline]
+pub fn set_deterministic(enable: bool) {
+    DETERMINISTIC.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the backends with several register blockings pick the one of each product from its
+/// shape, with [`select_blocking`]. When disabled, they always use the blocking they report as
+/// their `mr×nr`.
+#[inline]
+pub fn get_blocking_selection() -> bool {
+    BLOCKING_SELECTION.load(Ordering::Relaxed)
+}
+#[inline]
+pub fn set_blocking_selection(enable: bool) {
+    BLOCKING_SELECTION.store(enable, Ordering::Relaxed);
+}
+
+// latency of a vector fma, in cycles
+const FMA_LATENCY: usize = 4;
+
+// cycles, doubled, of an `rows×cols` block of the destination, assuming two fma units and two
+// load units. each step of `k` issues an fma per accumulator and a load per lhs vector and rhs
+// element, and waits for the previous fma on each accumulator, so that blocks with few
+// accumulators are bound by the latency. those with fewer than
+// [`MIN_FMA_CHAINS`](crate::microkernel::MIN_FMA_CHAINS) alternate between two sets of
+// accumulators, which halves the wait.
+fn block_cycles(rows: usize, cols: usize, k: usize, lanes: usize) -> usize {
+    if rows == 0 || cols == 0 {
+        return 0;
+    }
+    let vecs = rows.msrv_div_ceil(lanes);
+    let accum_sets = if vecs * cols < crate::microkernel::MIN_FMA_CHAINS {
+        2
+    } else {
+        1
+    };
+    let step = Ord::max(
+        Ord::max(vecs * cols, vecs + cols),
+        2 * FMA_LATENCY / accum_sets,
+    );
+    // the destination is loaded and stored once per block
+    step.saturating_mul(k) + 2 * vecs * cols
+}
+
+/// Index in `blockings`, given as `(mr, nr)` pairs, of the register blocking that computes an
+/// `m×n×k` product with vectors of `lanes` elements in the fewest cycles, according to a model
+/// of the microkernels. The first blocking, which the backend reports as its `mr×nr`, is kept on
+/// ties, when the selection is disabled, and when `config` depends on it through its blocking
+/// parameters, scratch memory or packed operands.
+///
+/// Backends with 16 vector registers fit 12 accumulators, as a square `2N×6`, a tall `3N×4` or a
+/// wide `N×12` block. The tall one wastes less of the last column of blocks when `n` is small and
+/// the wide one less of the last row of blocks when `m` is, and both keep more accumulators busy
+/// than the square one when the product only spans a few vectors along the other dimension.
+///
+/// Products that are split between threads, according to `parallelism` and the threading
+/// threshold, can't use more threads than they have blocks, so that a small `N×6` blocking is
+/// faster for those with fewer blocks than threads, although it computes each element more
+/// slowly. The thread count is ignored in [deterministic](get_deterministic) mode, where the
+/// blocking, and with it the blocking parameters, must not depend on the parallelism.
+pub fn select_blocking<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    lanes: usize,
+    blockings: &[(usize, usize)],
+    parallelism: Parallelism<'_>,
+    config: &GemmConfig<'_, T>,
+) -> usize {
+    if blockings.len() <= 1
+        || !get_blocking_selection()
+        || config.kernel_params.is_some()
+        || config.stack.is_some()
+        || config.packed_lhs.is_some()
+        || config.packed_rhs.is_some()
+    {
+        return 0;
+    }
+
+    let threading_threshold = config
+        .threading_threshold
+        .unwrap_or_else(|| threading_threshold::<T>(lanes * core::mem::size_of::<T>()));
+    let deterministic = config.deterministic.unwrap_or_else(get_deterministic);
+    let n_threads =
+        if !deterministic && m.saturating_mul(n).saturating_mul(k) >= threading_threshold {
+            max_threads(parallelism)
+        } else {
+            1
+        };
+
+    let cycles = |(mr, nr): (usize, usize)| {
+        let (full_rows, last_rows) = (m / mr, m % mr);
+        let (full_cols, last_cols) = (n / nr, n % nr);
+        let total = (full_rows * full_cols)
+            .saturating_mul(block_cycles(mr, nr, k, lanes))
+            .saturating_add(full_rows.saturating_mul(block_cycles(mr, last_cols, k, lanes)))
+            .saturating_add(full_cols.saturating_mul(block_cycles(last_rows, nr, k, lanes)))
+            .saturating_add(block_cycles(last_rows, last_cols, k, lanes));
+        let n_blocks = m.msrv_div_ceil(mr) * n.msrv_div_ceil(nr);
+        total.msrv_div_ceil(Ord::max(Ord::min(n_threads, n_blocks), 1))
+    };
+
+    let mut best = (cycles(blockings[0]), 0);
+    for (idx, &blocking) in blockings.iter().enumerate().skip(1) {
+        let cycles = cycles(blocking);
+        if cycles < best.0 {
+            best = (cycles, idx);
+        }
+    }
+    best.1
+}
+
+/// Size in bytes of the destination from which it's written with non-temporal stores, which
+/// bypass the caches, when it's not read and computed in a single depth block. It's twice the
+/// size of the l3 unless overridden by [`set_streaming_stores_threshold`].
+///
+/// Streaming stores are only used on x86_64, for the blocks of a destination with unit row
+/// stride that aren't followed by an epilogue.
+#[inline]
+pub fn get_streaming_stores_threshold() -> usize {
+    match STREAMING_STORES_THRESHOLD.load(Ordering::Relaxed) {
+        0 => 2 * Ord::max(CACHE_INFO[2].cache_bytes, CACHE_INFO[1].cache_bytes),
+        value => value,
+    }
+}
+/// Overrides the streaming stores threshold, or restores the default one if `value` is `0`. A
+/// threshold of `usize::MAX` disables streaming stores.
+#[inline]
+pub fn set_streaming_stores_threshold(value: usize) {
+    STREAMING_STORES_THRESHOLD.store(value, Ordering::Relaxed);
+}
+
+/// Copies an `mr×nr` block with unit row stride and column stride `mr` from `src` to `dst` with
+/// non-temporal stores, falling back to regular stores for the columns that aren't 16-byte
+/// aligned.
+#[inline(always)]
+unsafe fn stream_block<T: Copy>(dst: *mut T, dst_cs: isize, src: *const T, mr: usize, nr: usize) {
+    for j in 0..nr {
+        let dst = dst.wrapping_offset(j as isize * dst_cs);
+        let src = src.add(j * mr);
+        #[cfg(target_arch = "x86_64")]
+        {
+            use core::arch::x86_64::*;
+            let bytes = mr * core::mem::size_of::<T>();
+            if bytes % 16 == 0 && dst as usize % 16 == 0 {
+                for i in 0..bytes / 16 {
+                    _mm_stream_si128(
+                        (dst as *mut __m128i).add(i),
+                        _mm_loadu_si128((src as *const __m128i).add(i)),
+                    );
+                }
+                continue;
+            }
+        }
+        core::ptr::copy_nonoverlapping(src, dst, mr);
+    }
+}
+
 #[inline]
 pub fn get_rhs_packing_threshold() -> usize {
     RHS_PACKING_THRESHOLD.load(Ordering::Relaxed)
@@ -161,14 +431,600 @@ pub fn set_lhs_packing_threshold_multi_thread(value: usize) {
     LHS_PACKING_THRESHOLD_MULTI_THREAD.store(value.min(256), Ordering::Relaxed);
 }
 
-#[cfg(feature = "rayon")]
-pub fn par_for_each(n_threads: usize, func: impl Fn(usize) + Send + Sync) {
-    fn inner(n_threads: usize, func: &(dyn Fn(usize) + Send + Sync)) {
-        use rayon::prelude::*;
-        (0..n_threads).into_par_iter().for_each(func);
+/// When the driver packs an operand that the microkernels could also read in place. Operands
+/// that they can't read in place, such as an lhs without unit row stride, a scaled operand, or
+/// an rhs on the backends that require a row-major one, are packed regardless.
+///
+/// The products computed by the matrix-vector and outer product kernels never pack their
+/// operands.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum PackingPolicy {
+    /// Packed when the heuristics of the driver expect it to pay off: the rhs when the product
+    /// has more than [`get_rhs_packing_threshold`] row blocks, or more than two with a rhs
+    /// without unit row stride, and each block of the lhs when its columns span more than
+    /// [`get_lhs_packing_threshold_single_thread`] column blocks, or
+    /// [`get_lhs_packing_threshold_multi_thread`] when it's split between threads.
+    #[default]
+    Auto,
+    /// Always packed.
+    Always,
+    /// Only packed when the microkernels can't read it in place.
+    Never,
+}
+
+impl PackingPolicy {
+    #[inline]
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => PackingPolicy::Always,
+            2 => PackingPolicy::Never,
+            _ => PackingPolicy::Auto,
+        }
+    }
+}
+
+/// Policy of the products whose config doesn't set [`GemmConfig::lhs_packing`]. The lhs and the
+/// rhs are those of the backend, which computes the transposed product when the destination is
+/// row-major.
+#[inline]
+pub fn get_lhs_packing_policy() -> PackingPolicy {
+    PackingPolicy::from_u8(LHS_PACKING_POLICY.load(Ordering::Relaxed))
+}
+#[inline]
+pub fn set_lhs_packing_policy(policy: PackingPolicy) {
+    LHS_PACKING_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Policy of the products whose config doesn't set [`GemmConfig::rhs_packing`], with the same
+/// lhs and rhs as [`get_lhs_packing_policy`].
+#[inline]
+pub fn get_rhs_packing_policy() -> PackingPolicy {
+    PackingPolicy::from_u8(RHS_PACKING_POLICY.load(Ordering::Relaxed))
+}
+#[inline]
+pub fn set_rhs_packing_policy(policy: PackingPolicy) {
+    RHS_PACKING_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Function applied to a block of the destination once its final value is stored, with the
+/// arguments `(data, dst, nrows, ncols, dst_cs, dst_rs, row, col)`, where `data` is the
+/// [`TileEpilogue::data`] pointer and `(row, col)` is the position of the block in the
+/// destination, as seen by the backend.
+pub type TileEpilogueFn<T> = unsafe fn(*const (), *mut T, usize, usize, isize, isize, usize, usize);
+
+/// Epilogue fused into the final store of each block of the destination.
+pub struct TileEpilogue<T> {
+    pub func: TileEpilogueFn<T>,
+    /// Parameters of the epilogue, which must stay valid for the whole call.
+    pub data: *const (),
+}
+
+unsafe impl<T> Send for TileEpilogue<T> {}
+unsafe impl<T> Sync for TileEpilogue<T> {}
+
+impl<T> Copy for TileEpilogue<T> {}
+impl<T> Clone for TileEpilogue<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
     }
+}
+
+impl<T> TileEpilogue<T> {
+    #[inline(always)]
+    pub unsafe fn apply(
+        self,
+        dst: *mut T,
+        nrows: usize,
+        ncols: usize,
+        dst_cs: isize,
+        dst_rs: isize,
+        row: usize,
+        col: usize,
+    ) {
+        (self.func)(self.data, dst, nrows, ncols, dst_cs, dst_rs, row, col)
+    }
+}
+
+/// Part of the destination that is computed and written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UpdateRegion {
+    /// Every element.
+    Full,
+    /// The elements on and below the diagonal, i.e. `row >= col`.
+    Lower,
+    /// The elements on and above the diagonal, i.e. `row <= col`.
+    Upper,
+}
+
+impl UpdateRegion {
+    /// Whether the element at `(row, col)` belongs to the region.
+    #[inline(always)]
+    pub fn contains(self, row: usize, col: usize) -> bool {
+        match self {
+            UpdateRegion::Full => true,
+            UpdateRegion::Lower => row >= col,
+            UpdateRegion::Upper => row <= col,
+        }
+    }
+
+    /// The same region of the transposed matrix.
+    #[inline]
+    pub fn transpose(self) -> Self {
+        match self {
+            UpdateRegion::Full => UpdateRegion::Full,
+            UpdateRegion::Lower => UpdateRegion::Upper,
+            UpdateRegion::Upper => UpdateRegion::Lower,
+        }
+    }
+}
+
+/// Largest number of columns (resp. rows) of a tall-skinny (resp. short-fat) product.
+const SKINNY_MAX_DIM: usize = 64;
+/// Smallest ratio between the long and the short dimension of a tall-skinny or short-fat
+/// product.
+const SKINNY_MIN_RATIO: usize = 16;
+/// Smallest number of microkernel tiles along the long dimension given to each thread of a
+/// tall-skinny or short-fat product.
+const SKINNY_MIN_TILES_PER_THREAD: usize = 4;
+/// Number of rectangles of microkernel tiles per thread that the work of each block is split
+/// into. The threads take the next rectangle as soon as they're done with the previous one, so
+/// that the ones that finish early, or start late, take over the remaining tiles.
+const TILE_BLOCKS_PER_THREAD: usize = 4;
+/// Largest dimension of the products whose operands are read in place by the microkernels,
+/// without packing them or blocking the product. Their operands fit in the l2, so the setup of
+/// the blocked path costs more than it saves.
+const DIRECT_MAX_DIM: usize = 128;
+/// Largest number of columns of the tall-skinny products whose rhs is read in place by the
+/// microkernels, from the l1, instead of being packed.
+const TALL_SKINNY_MAX_COLS: usize = 32;
+/// Largest number of rows of the short-fat products whose lhs is packed whole, once, and read
+/// from the l1 along with each tile of the rhs, which is read in place.
+const SHORT_FAT_MAX_ROWS: usize = 32;
+/// Smallest number of destination rows or columns given to each thread of a product with few
+/// columns or few rows, which are computed by [`gemv::mixed_gemv`] and [`gemv::mixed_gevm`].
+const GEMV_MIN_LEN_PER_THREAD: usize = 16;
+
+/// Shape of the destination, which selects how [`gemm_basic_generic`] splits the work between
+/// threads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Shape {
+    /// Both dimensions are comparable, or large enough to be split in blocks along each of them.
+    General,
+    /// Many more rows than columns, with few columns.
+    TallSkinny,
+    /// Many more columns than rows, with few rows.
+    ShortFat,
+}
+
+impl Shape {
+    /// Shape of an `m×n` destination.
+    #[inline]
+    pub fn of(m: usize, n: usize) -> Self {
+        if n <= SKINNY_MAX_DIM && m >= SKINNY_MIN_RATIO.saturating_mul(n) {
+            Shape::TallSkinny
+        } else if m <= SKINNY_MAX_DIM && n >= SKINNY_MIN_RATIO.saturating_mul(m) {
+            Shape::ShortFat
+        } else {
+            Shape::General
+        }
+    }
+}
+
+/// Optional settings for a single call to [`gemm_basic_generic`], which the `gemm` crate also
+/// takes from its callers in `gemm_with_config`.
+pub struct GemmConfig<'a, T> {
+    /// Blocking parameters to use instead of the ones derived from the cache sizes. In
+    /// `gemm_with_config`, the parameters that are `0` are still derived from the cache sizes,
+    /// and the others are adjusted as with
+    /// [`set_kernel_params_override`](crate::cache::set_kernel_params_override).
+    pub kernel_params: Option<KernelParams>,
+    /// Scratch memory for the packed operands, which must satisfy the backend's `gemm_req` for
+    /// the same problem. Allocated on each call when `None`.
+    pub stack: Option<DynStack<'a>>,
+    /// Lhs packed ahead of time by the backend's `pack_lhs`, with panels `k * MR` elements
+    /// apart. When set, the `lhs` argument and its strides are ignored.
+    pub packed_lhs: Option<*const T>,
+    /// Rhs packed ahead of time by the backend's `pack_rhs`, with panels `k * NR` elements
+    /// apart. When set, the `rhs` argument and its strides are ignored.
+    pub packed_rhs: Option<*const T>,
+    /// Applied to each block of the destination right after its final value is stored.
+    pub epilogue: Option<TileEpilogue<T>>,
+    /// Vector `(ptr, inc)` with `m` elements, `inc` elements apart, whose `i`-th element scales
+    /// the `i`-th row of `lhs` while it is packed, after it's conjugated if `conj_lhs` is true.
+    /// Incompatible with `packed_lhs`.
+    pub lhs_scale: Option<(*const T, isize)>,
+    /// Vector `(ptr, inc)` with `n` elements, `inc` elements apart, whose `j`-th element scales
+    /// the `j`-th column of `rhs` while it is packed. Incompatible with `packed_rhs`.
+    pub rhs_scale: Option<(*const T, isize)>,
+    /// `m×n` matrix `(ptr, cs, rs)` selecting the elements of the destination that are computed
+    /// and written. The other elements are neither read nor written, and the blocks of the
+    /// destination without any selected element are skipped, so that sparse selections only pay
+    /// for the blocks they touch.
+    pub mask: Option<(*const bool, isize, isize)>,
+    /// Part of the destination that is computed and written, with the same effect as a mask
+    /// selecting the elements of the region, e.g. a single triangle of a symmetric result. A
+    /// partial region requires a destination with nonnegative strides.
+    pub update_region: UpdateRegion,
+    /// Secondary `m×n` destination `(ptr, cs, rs)` that receives `+= beta×lhs×rhs`, written
+    /// along with each block of the destination, while the block is still in registers. It's
+    /// always read, neither scaled nor conjugated, and only the selected elements are updated.
+    pub accumulate: Option<(*mut T, isize, isize)>,
+    /// Work `m×n×k` of a block below which it's computed on a single thread, instead of
+    /// [`threading_threshold`].
+    pub threading_threshold: Option<usize>,
+    /// Whether the destination is written with non-temporal stores, where possible, instead of
+    /// comparing its size to [`get_streaming_stores_threshold`]. They're only used on x86_64,
+    /// for a destination with unit row or column stride that isn't read, when `k` fits in a
+    /// single depth block.
+    pub streaming_stores: Option<bool>,
+    /// Whether the results must be bitwise identical for any number of threads, instead of
+    /// [`get_deterministic`]. The register blocking then only depends on the shape of the
+    /// product, and the panels of a tall-skinny or short-fat product that are split between the
+    /// threads are blocked like the whole product.
+    pub deterministic: Option<bool>,
+    /// Whether denormal numbers are flushed to zero while the product is computed, instead of
+    /// [`get_flush_denormals`](crate::denormals::get_flush_denormals). Each thread of the product
+    /// restores its previous mode once its part is done. Flushing is only supported on x86_64 and
+    /// aarch64, and `false` leaves the mode of the threads as is.
+    pub flush_denormals: Option<bool>,
+    /// When the lhs is packed, instead of [`get_lhs_packing_policy`]. Operands that the
+    /// microkernels can't read in place are packed regardless.
+    pub lhs_packing: Option<PackingPolicy>,
+    /// When the rhs is packed, instead of [`get_rhs_packing_policy`].
+    pub rhs_packing: Option<PackingPolicy>,
+}
+
+impl<T> Default for GemmConfig<'_, T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            kernel_params: None,
+            stack: None,
+            packed_lhs: None,
+            packed_rhs: None,
+            epilogue: None,
+            lhs_scale: None,
+            rhs_scale: None,
+            mask: None,
+            update_region: UpdateRegion::Full,
+            accumulate: None,
+            threading_threshold: None,
+            streaming_stores: None,
+            deterministic: None,
+            flush_denormals: None,
+            lhs_packing: None,
+            rhs_packing: None,
+        }
+    }
+}
 
-    inner(n_threads, &func)
+pub type GemmReqFn = fn(usize, usize, usize, Parallelism<'_>) -> StackReq;
+pub type KernelParamsFn = fn(usize, usize, usize, Parallelism<'_>) -> KernelParams;
+pub type BlockingFn = fn(usize, usize, usize, Parallelism<'_>) -> (usize, usize, KernelParams);
+
+/// Entry points of a microkernel backend for one scalar type.
+pub struct Backend<F, P> {
+    pub name: &'static str,
+    pub gemm: F,
+    /// Scratch memory needed by `gemm` for an `m×n×k` problem.
+    pub gemm_req: GemmReqFn,
+    /// Blocking parameters used by `gemm` for an `m×n×k` problem.
+    pub kernel_params: KernelParamsFn,
+    /// Register blocking `(mr, nr)` and blocking parameters selected by `gemm` for an `m×n×k`
+    /// problem with the default [`GemmConfig`], which differ from `mr×nr` and `kernel_params`
+    /// when the backend has several register blockings.
+    pub blocking: BlockingFn,
+    /// Number of rows of a packed lhs panel.
+    pub mr: usize,
+    /// Number of columns of a packed rhs panel.
+    pub nr: usize,
+    /// Width in bytes of the vectors of the microkernels, from which [`threading_threshold`]
+    /// derives the work below which `gemm` runs on a single thread.
+    pub simd_bytes: usize,
+    /// Packs an `m×k` lhs into panels of `mr` rows, `(m, k, dst, src, src_cs, src_rs,
+    /// panel_stride)`. Within a panel, element `(i, depth)` is stored at `depth * mr + i`.
+    /// `None` if the backend doesn't support packing ahead of time.
+    pub pack_lhs: Option<P>,
+    /// Packs a `k×n` rhs into panels of `nr` columns, `(n, k, dst, src, src_cs, src_rs,
+    /// panel_stride)`. Within a panel, element `(depth, j)` is stored at `depth * nr + j`.
+    /// `None` if the backend doesn't support packing ahead of time.
+    pub pack_rhs: Option<P>,
+}
+
+/// Priority of the built-in backends in a [`BackendRegistry`].
+pub const DEFAULT_BACKEND_PRIORITY: i32 = 0;
+
+/// Backends available for one scalar type. The one registered with the highest priority is
+/// used, falling back to the fastest built-in backend supported by the current machine.
+pub struct BackendRegistry<F: 'static, P: 'static> {
+    active: AtomicPtr<Backend<F, P>>,
+    // only accessed while `lock` is held
+    priority: AtomicI32,
+    lock: AtomicBool,
+}
+
+impl<F: 'static, P: 'static> BackendRegistry<F, P> {
+    pub const fn new() -> Self {
+        Self {
+            active: AtomicPtr::new(core::ptr::null_mut()),
+            priority: AtomicI32::new(DEFAULT_BACKEND_PRIORITY),
+            lock: AtomicBool::new(false),
+        }
+    }
+
+    fn with_lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f();
+        self.lock.store(false, Ordering::Release);
+        result
+    }
+
+    /// Must be called with the lock held.
+    fn init_locked(&self, builtin: fn() -> &'static Backend<F, P>) -> &'static Backend<F, P> {
+        let active = self.active.load(Ordering::Acquire);
+        if active.is_null() {
+            let backend = builtin();
+            self.priority
+                .store(DEFAULT_BACKEND_PRIORITY, Ordering::Relaxed);
+            self.active
+                .store(backend as *const _ as *mut _, Ordering::Release);
+            backend
+        } else {
+            unsafe { &*active }
+        }
+    }
+
+    #[inline(never)]
+    fn init(&self, builtin: fn() -> &'static Backend<F, P>) -> &'static Backend<F, P> {
+        self.with_lock(|| self.init_locked(builtin))
+    }
+
+    /// Returns the active backend, selecting the built-in one on first use.
+    #[inline(always)]
+    pub fn get(&self, builtin: fn() -> &'static Backend<F, P>) -> &'static Backend<F, P> {
+        let active = self.active.load(Ordering::Acquire);
+        if active.is_null() {
+            self.init(builtin)
+        } else {
+            unsafe { &*active }
+        }
+    }
+
+    /// Makes `backend` the active backend if `priority` is at least as high as the priority of
+    /// the active one.
+    pub fn register(
+        &self,
+        backend: &'static Backend<F, P>,
+        priority: i32,
+        builtin: fn() -> &'static Backend<F, P>,
+    ) {
+        self.with_lock(|| {
+            self.init_locked(builtin);
+            if priority >= self.priority.load(Ordering::Relaxed) {
+                self.priority.store(priority, Ordering::Relaxed);
+                self.active
+                    .store(backend as *const _ as *mut _, Ordering::Release);
+            }
+        })
+    }
+
+    /// Forgets the registered backends, so that the built-in one is selected again.
+    pub fn reset(&self) {
+        self.with_lock(|| self.active.store(core::ptr::null_mut(), Ordering::Release))
+    }
+}
+
+impl<F: 'static, P: 'static> Default for BackendRegistry<F, P> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Blocking parameters used by [`gemm_basic_generic`] when none are provided by the caller.
+#[inline]
+pub fn gemm_kernel_params<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    parallelism: Parallelism<'_>,
+) -> KernelParams {
+    let KernelParams { kc, mc, nc } = if m <= 64 && n <= 64 {
+        // skip expensive kernel_params call for small sizes
+        let kc = k.clamp(1, 512);
+        let alloc = CACHE_INFO[1].bytes_per_thread() / core::mem::size_of::<T>();
+        let mc = (alloc / kc) / mr * mr;
+
+        KernelParams {
+            kc,
+            mc,
+            nc: n.msrv_next_multiple_of(nr),
+        }
+    } else {
+        #[cfg(feature = "std")]
+        let tuned = crate::tuning::tuned_kernel_params::<T>(m, n, k, mr, nr);
+        #[cfg(not(feature = "std"))]
+        let tuned = None;
+        tuned
+            .or_else(|| crate::presets::preset_kernel_params::<T>(m, n, k, mr, nr))
+            .unwrap_or_else(|| kernel_params(m, n, k, mr, nr, core::mem::size_of::<T>()))
+    };
+    let nc = if nc > 0 {
+        nc
+    } else {
+        match parallelism {
+            Parallelism::None => 128 * nr,
+            _ => n.msrv_next_multiple_of(nr),
+        }
+    };
+    override_kernel_params(
+        m,
+        n,
+        k,
+        mr,
+        nr,
+        KernelParams { kc, mc, nc },
+        get_kernel_params_override(),
+    )
+}
+
+/// Scratch memory needed by [`gemm_basic_generic`] for the packed operands of an `m×n×k`
+/// problem, using the blocking parameters `params`.
+#[inline]
+pub fn gemm_req_generic<T>(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    params: KernelParams,
+) -> StackReq {
+    if m == 0 || n == 0 || k == 0 {
+        return StackReq::empty();
+    }
+    let KernelParams { kc, mc, nc } = params;
+    let rhs_req = StackReq::new_aligned::<T>(kc * nr * (nc / nr), CACHELINE_ALIGN);
+    let lhs_req = StackReq::new_aligned::<T>(
+        if m <= 2 * mc {
+            kc * mr * (m.msrv_next_multiple_of(mr) / mr)
+        } else {
+            0
+        },
+        CACHELINE_ALIGN,
+    );
+    // short-fat products with few rows pack their whole lhs instead
+    let short_fat_req = if m <= SHORT_FAT_MAX_ROWS && Shape::of(m, n) == Shape::ShortFat {
+        StackReq::new_aligned::<T>(k * m.msrv_next_multiple_of(mr), CACHELINE_ALIGN)
+    } else {
+        StackReq::empty()
+    };
+    rhs_req.and(lhs_req).or(short_fat_req)
+}
+
+/// Number of threads that `parallelism` allows, where `Rayon(0)` stands for every thread of the
+/// current pool.
+#[inline]
+pub fn max_threads(parallelism: Parallelism<'_>) -> usize {
+    match parallelism {
+        Parallelism::None => 1,
+        #[cfg(feature = "rayon")]
+        Parallelism::Rayon(n_threads) => {
+            if n_threads == 0 {
+                // nested in a busy pool, where more jobs would only compete with the queued ones
+                if rayon_core::current_thread_has_pending_tasks() == Some(true) {
+                    return 1;
+                }
+                crate::spawner::ThreadSpawner::num_threads(&crate::spawner::RayonSpawner)
+            } else {
+                n_threads
+            }
+        }
+        Parallelism::Custom(spawner) => spawner.num_threads(),
+    }
+}
+
+/// Parallelism left to each of `n_tasks` tasks running at the same time under `parallelism`.
+/// The tasks of a custom pool always run on a single thread, since the pool may not support
+/// regions nested inside its own tasks.
+#[inline]
+pub fn inner_parallelism(parallelism: Parallelism<'_>, n_tasks: usize) -> Parallelism<'_> {
+    #[cfg(feature = "rayon")]
+    if let Parallelism::Rayon(_) = parallelism {
+        let n_threads = max_threads(parallelism) / Ord::max(n_tasks, 1);
+        if n_threads > 1 {
+            return Parallelism::Rayon(n_threads);
+        }
+    }
+    let _ = (parallelism, n_tasks);
+    Parallelism::None
+}
+
+/// Height and width, in tiles, of the rectangles that a grid of `row_tiles × col_tiles` tiles
+/// of size `mr × nr` is split into between `n_threads` threads. The rectangles are about
+/// square, so that each thread packs and reads few lhs and rhs panels for the tiles it computes.
+#[inline]
+pub fn tile_blocks(
+    n_threads: usize,
+    row_tiles: usize,
+    col_tiles: usize,
+    mr: usize,
+    nr: usize,
+) -> (usize, usize) {
+    let (row_tiles, col_tiles) = (Ord::max(row_tiles, 1), Ord::max(col_tiles, 1));
+    if n_threads <= 1 {
+        return (row_tiles, col_tiles);
+    }
+    let n_blocks = n_threads * TILE_BLOCKS_PER_THREAD;
+    let (rows, cols) = (row_tiles * mr, col_tiles * nr);
+
+    // the rectangles are square when col_groups² / n_blocks == cols / rows
+    let mut col_groups = 1;
+    while col_groups < col_tiles
+        && (col_groups * col_groups).saturating_mul(rows) < n_blocks.saturating_mul(cols)
+    {
+        col_groups += 1;
+    }
+    let row_groups = Ord::min(n_blocks.msrv_div_ceil(col_groups), row_tiles);
+    (
+        row_tiles.msrv_div_ceil(row_groups),
+        col_tiles.msrv_div_ceil(col_groups),
+    )
+}
+
+/// Calls `func(tid)` for each `tid` in `0..n_threads` on the pool selected by `parallelism`.
+///
+/// The threads flush denormal numbers while they run `func` if the calling thread does.
+pub fn par_for_each(
+    parallelism: Parallelism<'_>,
+    n_threads: usize,
+    func: impl Fn(usize) + Send + Sync,
+) {
+    fn inner(parallelism: Parallelism<'_>, n_threads: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+        match crate::spawner::spawner(parallelism) {
+            Some(spawner) if n_threads > 1 => {
+                let flush = thread_flushes_denormals();
+                spawner.for_each(n_threads, &|tid| {
+                    let _flush = FlushDenormalsGuard::new(flush);
+                    func(tid)
+                })
+            }
+            _ => (0..n_threads).for_each(func),
+        }
+    }
+
+    inner(parallelism, n_threads, &func)
+}
+
+/// Calls `func(start, len)` for each of the panels that `total` rows or columns are split into
+/// between up to `n_threads` threads, where each panel starts on a multiple of `align`.
+fn par_for_each_panel(
+    parallelism: Parallelism<'_>,
+    n_threads: usize,
+    total: usize,
+    align: usize,
+    func: impl Fn(usize, usize) + Send + Sync,
+) {
+    if total == 0 {
+        return;
+    }
+    let panel = total
+        .msrv_div_ceil(Ord::max(n_threads, 1))
+        .msrv_next_multiple_of(align);
+    let n_threads = total.msrv_div_ceil(panel);
+    par_for_each(parallelism, n_threads, |tid| {
+        let start = tid * panel;
+        func(start, Ord::min(panel, total - start))
+    });
 }
 
 #[inline(always)]
@@ -209,10 +1065,12 @@ pub unsafe fn gemm_basic_generic<
     conj_dst: bool,
     conj_lhs: bool,
     conj_rhs: bool,
-    mul_add: impl Copy + Fn(T, T, T) -> T,
+    mul_add: impl Copy + Send + Sync + Fn(T, T, T) -> T,
     dispatcher: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
     _requires_row_major_rhs: bool,
-    parallelism: Parallelism,
+    masked_edges: bool,
+    parallelism: Parallelism<'_>,
+    config: GemmConfig<'_, T>,
 ) {
     if m == 0 || n == 0 {
         return;
@@ -220,6 +1078,59 @@ pub unsafe fn gemm_basic_generic<
     if !read_dst {
         alpha.set_zero();
     }
+    let _flush = FlushDenormalsGuard::new(
+        config
+            .flush_denormals
+            .unwrap_or_else(crate::denormals::get_flush_denormals),
+    );
+
+    let epilogue = config.epilogue;
+    // applies the epilogue to the whole destination, for the paths that skip the microkernels
+    let finish = || {
+        if let Some(epilogue) = epilogue {
+            epilogue.apply(dst, m, n, dst_cs, dst_rs, 0, 0);
+        }
+    };
+
+    let mask = config
+        .mask
+        .map(|(ptr, cs, rs)| (Ptr(ptr as *mut bool), cs, rs));
+    let update_region = config.update_region;
+    let is_selected = |i: usize, j: usize| {
+        update_region.contains(i, j)
+            && match mask {
+                Some((ptr, cs, rs)) => *ptr.wrapping_offset(i as isize * rs + j as isize * cs).0,
+                None => true,
+            }
+    };
+    let is_masked = mask.is_some() || update_region != UpdateRegion::Full;
+    let accumulate = config.accumulate.map(|(ptr, cs, rs)| (Ptr(ptr), cs, rs));
+
+    if k == 0 {
+        record_stat!(record_fallback());
+    }
+
+    if k == 0 && is_masked {
+        for j in 0..n {
+            for i in 0..m {
+                if !is_selected(i, j) {
+                    continue;
+                }
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = if alpha.is_zero() {
+                    T::zero()
+                } else if conj_dst {
+                    alpha * (*dst).conj()
+                } else {
+                    alpha * *dst
+                };
+                if let Some(epilogue) = epilogue {
+                    epilogue.apply(dst, 1, 1, dst_cs, dst_rs, i, j);
+                }
+            }
+        }
+        return;
+    }
 
     if k == 0 {
         // dst = alpha * conj?(dst)
@@ -230,10 +1141,12 @@ pub unsafe fn gemm_basic_generic<
                     *dst.offset(i as isize * dst_rs + j as isize * dst_cs) = T::zero();
                 }
             }
+            finish();
             return;
         }
 
         if alpha.is_one() && !conj_dst {
+            finish();
             return;
         }
 
@@ -252,71 +1165,491 @@ pub unsafe fn gemm_basic_generic<
                 }
             }
         }
+        finish();
         return;
     }
 
-    if !conj_dst && !conj_lhs && !conj_rhs {
+    let lhs_is_packed = config.packed_lhs.is_some();
+    let rhs_is_packed = config.packed_rhs.is_some();
+    let lhs_scale = config.lhs_scale.map(|(ptr, inc)| (Ptr(ptr as *mut T), inc));
+    let rhs_scale = config.rhs_scale.map(|(ptr, inc)| (Ptr(ptr as *mut T), inc));
+    assert!(!lhs_is_packed || lhs_scale.is_none());
+    assert!(!rhs_is_packed || rhs_scale.is_none());
+    let is_scaled = lhs_scale.is_some() || rhs_scale.is_some();
+    let lhs_packing = config.lhs_packing.unwrap_or_else(get_lhs_packing_policy);
+    let rhs_packing = config.rhs_packing.unwrap_or_else(get_rhs_packing_policy);
+
+    if !conj_dst
+        && !conj_lhs
+        && !conj_rhs
+        && !lhs_is_packed
+        && !rhs_is_packed
+        && !is_scaled
+        && !is_masked
+        && accumulate.is_none()
+    {
         if k <= 2 {
+            trace_event!(debug, m, n, k, "outer product kernels");
+            record_stat!(record_fallback());
             gevv::gevv(
                 simd, m, n, k, dst, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
                 alpha, beta, mul_add,
             );
+            finish();
             return;
         }
 
-        if n <= 1 && lhs_rs == 1 && dst_rs == 1 {
-            gemv::mixed_gemv_colmajor(
-                simd, m, n, k, dst, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
-                alpha, beta,
-            );
+        // the gemv kernels load unit-stride operands, so broadcast operands with zero strides
+        // are read through the microkernels or the packed panels instead
+        let gemv_threads = |len: usize| {
+            let threading_threshold = config
+                .threading_threshold
+                .unwrap_or_else(|| threading_threshold::<T>(N * core::mem::size_of::<T>()));
+            if m.saturating_mul(n).saturating_mul(k) >= threading_threshold {
+                Ord::min(max_threads(parallelism), len / GEMV_MIN_LEN_PER_THREAD)
+            } else {
+                1
+            }
+        };
+        let (dst, lhs, rhs) = (Ptr(dst), Ptr(lhs as *mut T), Ptr(rhs as *mut T));
+
+        if n <= gemv::GEMV_MAX_COLS && lhs_rs == 1 && dst_rs == 1 {
+            trace_event!(debug, m, n, k, kernel = "gemv", "matrix-vector kernels");
+            record_stat!(record_fallback());
+            par_for_each_panel(parallelism, gemv_threads(m), m, N, |start, len| {
+                // capture the whole pointers, which are `Sync` unlike their fields
+                let (dst, lhs, rhs) = (dst, lhs, rhs);
+                gemv::mixed_gemv(
+                    simd,
+                    len,
+                    n,
+                    k,
+                    dst.wrapping_add(start).0,
+                    dst_cs,
+                    dst_rs,
+                    lhs.wrapping_add(start).0,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs.0,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                );
+            });
+            finish();
             return;
         }
-        if n <= 1 && lhs_cs == 1 && rhs_rs == 1 {
-            gemv::mixed_gemv_rowmajor(
-                simd, m, n, k, dst, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
-                alpha, beta,
-            );
+        if n <= gemv::GEVM_MAX_ROWS && lhs_cs == 1 && rhs_rs == 1 {
+            trace_event!(debug, m, n, k, kernel = "gevm", "matrix-vector kernels");
+            record_stat!(record_fallback());
+            par_for_each_panel(parallelism, gemv_threads(m), m, 1, |start, len| {
+                let (dst, lhs, rhs) = (dst, lhs, rhs);
+                gemv::mixed_gevm(
+                    simd,
+                    n,
+                    len,
+                    k,
+                    dst.wrapping_offset(start as isize * dst_rs).0,
+                    dst_rs,
+                    dst_cs,
+                    rhs.0,
+                    rhs_rs,
+                    rhs_cs,
+                    lhs.wrapping_offset(start as isize * lhs_rs).0,
+                    lhs_rs,
+                    lhs_cs,
+                    alpha,
+                    beta,
+                );
+            });
+            finish();
             return;
         }
-        if m <= 1 && rhs_cs == 1 && dst_cs == 1 {
-            gemv::mixed_gemv_colmajor(
-                simd, n, m, k, dst, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs,
-                alpha, beta,
+        if m <= gemv::GEMV_MAX_COLS && rhs_cs == 1 && dst_cs == 1 {
+            trace_event!(
+                debug,
+                m,
+                n,
+                k,
+                kernel = "transposed gemv",
+                "matrix-vector kernels"
             );
+            record_stat!(record_fallback());
+            par_for_each_panel(parallelism, gemv_threads(n), n, N, |start, len| {
+                let (dst, lhs, rhs) = (dst, lhs, rhs);
+                gemv::mixed_gemv(
+                    simd,
+                    len,
+                    m,
+                    k,
+                    dst.wrapping_add(start).0,
+                    dst_rs,
+                    dst_cs,
+                    rhs.wrapping_add(start).0,
+                    rhs_rs,
+                    rhs_cs,
+                    lhs.0,
+                    lhs_rs,
+                    lhs_cs,
+                    alpha,
+                    beta,
+                );
+            });
+            finish();
             return;
         }
-        if m <= 1 && rhs_rs == 1 && lhs_cs == 1 {
-            gemv::mixed_gemv_rowmajor(
-                simd, n, m, k, dst, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs,
-                alpha, beta,
+        if m <= gemv::GEVM_MAX_ROWS && rhs_rs == 1 && lhs_cs == 1 {
+            trace_event!(
+                debug,
+                m,
+                n,
+                k,
+                kernel = "transposed gevm",
+                "matrix-vector kernels"
             );
+            record_stat!(record_fallback());
+            par_for_each_panel(parallelism, gemv_threads(n), n, 1, |start, len| {
+                let (dst, lhs, rhs) = (dst, lhs, rhs);
+                gemv::mixed_gevm(
+                    simd,
+                    m,
+                    len,
+                    k,
+                    dst.wrapping_offset(start as isize * dst_cs).0,
+                    dst_cs,
+                    dst_rs,
+                    lhs.0,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs.wrapping_offset(start as isize * rhs_cs).0,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                );
+            });
+            finish();
             return;
         }
     }
 
-    let KernelParams { kc, mc, nc } = if m <= 64 && n <= 64 {
-        // skip expensive kernel_params call for small sizes
-        let kc = k.min(512);
-        let alloc = CACHE_INFO[1].cache_bytes / core::mem::size_of::<T>();
-        let mc = (alloc / kc) / MR * MR;
+    let threading_threshold = config
+        .threading_threshold
+        .unwrap_or_else(|| threading_threshold::<T>(N * core::mem::size_of::<T>()));
+
+    // the kernels can read both operands in place. the lhs is read one vector at a time, so its
+    // columns must be contiguous and fill whole vectors, unless the kernels mask the last one.
+    let is_direct = lhs_rs == 1
+        && (m % N == 0 || masked_edges)
+        && !_requires_row_major_rhs
+        && config.kernel_params.is_none()
+        && !lhs_is_packed
+        && !rhs_is_packed
+        && !is_scaled
+        && !is_masked
+        && accumulate.is_none()
+        && lhs_packing != PackingPolicy::Always
+        && rhs_packing != PackingPolicy::Always;
+    let (direct_dst, direct_lhs, direct_rhs) = (Ptr(dst), Ptr(lhs as *mut T), Ptr(rhs as *mut T));
+    // computes the rows `rows` and the columns `cols` of the destination, which start on a tile,
+    // over the depths `depth..depth + k_chunk`, with the kernels reading the rhs in place. the
+    // lhs is also read in place, unless it's given whole in `packed_lhs`, with panels `k * MR`
+    // elements apart.
+    let direct_tiles =
+        |rows: core::ops::Range<usize>,
+         cols: core::ops::Range<usize>,
+         depth: usize,
+         k_chunk: usize,
+         packed_lhs: Option<Ptr<T>>,
+         alpha: T,
+         conj_dst: bool,
+         is_last_depth: bool,
+         #[cfg(feature = "stats")] stats: &mut crate::stats::ThreadStats| {
+            // capture the whole pointers, which are `Sync` unlike their fields
+            let (dst, lhs, rhs) = (direct_dst, direct_lhs, direct_rhs);
+            let alpha_status = if alpha.is_zero() {
+                0
+            } else if alpha.is_one() {
+                1
+            } else {
+                2
+            };
+            for row in rows.clone().step_by(MR) {
+                let m_tile = Ord::min(MR, rows.end - row);
+                let (lhs, lhs_cs) = match packed_lhs {
+                    Some(packed_lhs) => {
+                        (packed_lhs.wrapping_add(row * k + depth * MR), MR as isize)
+                    }
+                    None => (
+                        lhs.wrapping_offset(row as isize + depth as isize * lhs_cs),
+                        lhs_cs,
+                    ),
+                };
+                for col in cols.clone().step_by(NR) {
+                    let n_tile = Ord::min(NR, cols.end - col);
+                    let dst = dst
+                        .wrapping_offset(row as isize * dst_rs + col as isize * dst_cs)
+                        .0;
+                    #[cfg(feature = "stats")]
+                    stats.record_kernel(m_tile < MR, n_tile < NR);
+                    dispatcher[(m_tile + (N - 1)) / N - 1][n_tile - 1](
+                        m_tile,
+                        n_tile,
+                        k_chunk,
+                        dst,
+                        lhs.0,
+                        rhs.wrapping_offset(depth as isize * rhs_rs + col as isize * rhs_cs)
+                            .0,
+                        dst_cs,
+                        dst_rs,
+                        lhs_cs,
+                        rhs_rs,
+                        rhs_cs,
+                        alpha,
+                        beta,
+                        alpha_status,
+                        conj_dst,
+                        conj_lhs,
+                        conj_rhs,
+                        core::ptr::null(),
+                    );
+                    if let Some(epilogue) = epilogue {
+                        if is_last_depth {
+                            epilogue.apply(dst, m_tile, n_tile, dst_cs, dst_rs, row, col);
+                        }
+                    }
+                }
+            }
+        };
+
+    // small products that run on a single thread are computed tile by tile, without packing or
+    // blocking them
+    if is_direct
+        && m <= DIRECT_MAX_DIM
+        && n <= DIRECT_MAX_DIM
+        && k <= DIRECT_MAX_DIM
+        && (max_threads(parallelism) == 1
+            || m.saturating_mul(n).saturating_mul(k) < threading_threshold)
+    {
+        trace_event!(debug, m, n, k, "direct kernels");
+        #[cfg(feature = "stats")]
+        let mut stats = crate::stats::ThreadStats::default();
+        #[cfg(feature = "stats")]
+        stats.record_job();
+        direct_tiles(
+            0..m,
+            0..n,
+            0,
+            k,
+            None,
+            alpha,
+            conj_dst,
+            true,
+            #[cfg(feature = "stats")]
+            &mut stats,
+        );
+        #[cfg(feature = "stats")]
+        stats.flush();
+        return;
+    }
 
-        KernelParams {
+    // tall-skinny products with few columns are split along their rows between the threads, and
+    // their rhs is never packed. each thread goes over its rows in chunks whose destination stays
+    // in the l2, and over the depth in blocks whose rhs stays in the l1, where it's read again by
+    // the kernels of each row tile of the chunk.
+    if is_direct && n <= TALL_SKINNY_MAX_COLS && Shape::of(m, n) == Shape::TallSkinny {
+        let sizeof = core::mem::size_of::<T>();
+        let kc = Ord::min(
+            Ord::max(CACHE_INFO[0].bytes_per_thread() / 2 / (n * sizeof), 1),
+            k,
+        );
+        let chunk = Ord::max(
+            CACHE_INFO[1].bytes_per_thread() / 2 / ((n + kc) * sizeof) / MR * MR,
+            MR,
+        );
+        let n_threads = if m.saturating_mul(n).saturating_mul(k) >= threading_threshold {
+            Ord::min(
+                max_threads(parallelism),
+                m / (MR * SKINNY_MIN_TILES_PER_THREAD),
+            )
+        } else {
+            1
+        };
+        trace_event!(
+            debug,
+            m,
+            n,
+            k,
             kc,
-            mc,
-            nc: n.msrv_next_multiple_of(NR),
-        }
-    } else {
-        kernel_params(m, n, k, MR, NR, core::mem::size_of::<T>())
-    };
-    let nc = if nc > 0 {
-        nc
-    } else {
-        match parallelism {
-            Parallelism::None => 128 * NR,
-            #[cfg(feature = "rayon")]
-            Parallelism::Rayon(_) => n.msrv_next_multiple_of(NR),
-        }
+            chunk,
+            threads = n_threads,
+            "tall-skinny driver"
+        );
+        par_for_each_panel(parallelism, n_threads, m, MR, |start, len| {
+            #[cfg(feature = "stats")]
+            let mut stats = crate::stats::ThreadStats::default();
+            #[cfg(feature = "stats")]
+            stats.record_job();
+            for row in (start..start + len).step_by(chunk) {
+                let rows = row..Ord::min(row + chunk, start + len);
+                for depth in (0..k).step_by(kc) {
+                    // dst is scaled and conjugated by the first block only
+                    direct_tiles(
+                        rows.clone(),
+                        0..n,
+                        depth,
+                        Ord::min(kc, k - depth),
+                        None,
+                        if depth == 0 { alpha } else { T::one() },
+                        conj_dst && depth == 0,
+                        depth + kc >= k,
+                        #[cfg(feature = "stats")]
+                        &mut stats,
+                    );
+                }
+            }
+            #[cfg(feature = "stats")]
+            stats.flush();
+        });
+        return;
+    }
+
+    // short-fat products with few rows have their lhs packed once, whole, and are split along
+    // their columns between the threads, which read the rhs in place. each thread goes over its
+    // columns in chunks whose destination stays in the l2, and over the depth in blocks whose
+    // packed lhs stays in the l1, along with the rhs of the tile it's multiplied with.
+    if m <= SHORT_FAT_MAX_ROWS
+        && Shape::of(m, n) == Shape::ShortFat
+        && !_requires_row_major_rhs
+        && config.kernel_params.is_none()
+        && !rhs_is_packed
+        && !is_scaled
+        && !is_masked
+        && accumulate.is_none()
+        && lhs_packing != PackingPolicy::Never
+        && rhs_packing != PackingPolicy::Always
+    {
+        let sizeof = core::mem::size_of::<T>();
+        let m_padded = m.msrv_next_multiple_of(MR);
+        let kc = Ord::min(
+            Ord::max(
+                CACHE_INFO[0].bytes_per_thread() / 2 / ((m_padded + NR) * sizeof),
+                1,
+            ),
+            k,
+        );
+        let chunk = Ord::max(
+            CACHE_INFO[1].bytes_per_thread() / 2 / ((m + kc) * sizeof) / NR * NR,
+            NR,
+        );
+        let n_threads = if m.saturating_mul(n).saturating_mul(k) >= threading_threshold {
+            Ord::min(
+                max_threads(parallelism),
+                n / (NR * SKINNY_MIN_TILES_PER_THREAD),
+            )
+        } else {
+            1
+        };
+        trace_event!(
+            debug,
+            m,
+            n,
+            k,
+            kc,
+            chunk,
+            threads = n_threads,
+            packed_lhs = lhs_is_packed,
+            "short-fat driver"
+        );
+
+        let req = StackReq::new_aligned::<T>(
+            if lhs_is_packed { 0 } else { m_padded * k },
+            CACHELINE_ALIGN,
+        );
+        let mut mem = if config.stack.is_none() && !lhs_is_packed {
+            #[cfg(feature = "std")]
+            let mem = crate::pool::alloc(req);
+            #[cfg(not(feature = "std"))]
+            let mem = GlobalMemBuffer::new(req);
+            Some(mem)
+        } else {
+            None
+        };
+        let stack = match config.stack {
+            Some(stack) => Some(stack),
+            None => mem.as_mut().map(|mem| DynStack::new(mem)),
+        };
+        let mut storage = stack.map(|stack| {
+            stack
+                .make_aligned_uninit::<T>(
+                    if lhs_is_packed { 0 } else { m_padded * k },
+                    CACHELINE_ALIGN,
+                )
+                .0
+        });
+        let packed_lhs = match (config.packed_lhs, storage.as_mut()) {
+            (Some(packed_lhs), _) => Ptr(packed_lhs as *mut T),
+            (None, Some(storage)) => {
+                let packed_lhs = Ptr(storage.as_mut_ptr() as *mut T);
+                record_stat!(record_packed_lhs(m * k * sizeof));
+                pack_lhs::<T, N, MR, _>(
+                    simd,
+                    m,
+                    k,
+                    packed_lhs,
+                    Ptr(lhs as *mut T),
+                    lhs_cs,
+                    lhs_rs,
+                    k * MR,
+                );
+                packed_lhs
+            }
+            (None, None) => unreachable!(),
+        };
+
+        par_for_each_panel(parallelism, n_threads, n, NR, |start, len| {
+            #[cfg(feature = "stats")]
+            let mut stats = crate::stats::ThreadStats::default();
+            #[cfg(feature = "stats")]
+            stats.record_job();
+            for col in (start..start + len).step_by(chunk) {
+                let end = Ord::min(col + chunk, start + len);
+                for depth in (0..k).step_by(kc) {
+                    // each rhs tile is multiplied with all the rows before moving on to the next
+                    for tile in (col..end).step_by(NR) {
+                        direct_tiles(
+                            0..m,
+                            tile..Ord::min(tile + NR, end),
+                            depth,
+                            Ord::min(kc, k - depth),
+                            Some(packed_lhs),
+                            if depth == 0 { alpha } else { T::one() },
+                            conj_dst && depth == 0,
+                            depth + kc >= k,
+                            #[cfg(feature = "stats")]
+                            &mut stats,
+                        );
+                    }
+                }
+            }
+            #[cfg(feature = "stats")]
+            stats.flush();
+        });
+        return;
+    }
+
+    let KernelParams { kc, mut mc, mut nc } = match config.kernel_params {
+        Some(params) => params,
+        None => gemm_kernel_params::<T>(m, n, k, MR, NR, parallelism),
     };
+    // prepacked panels are indexed by the position of the block, which must start on a panel
+    if lhs_is_packed {
+        mc = Ord::max(mc / MR, 1) * MR;
+    }
+    if rhs_is_packed {
+        nc = Ord::max(nc / NR, 1) * NR;
+    }
 
     let simd_align = CACHELINE_ALIGN;
 
@@ -327,42 +1660,173 @@ pub unsafe fn gemm_basic_generic<
     let lhs = Ptr(lhs as *mut T);
     let rhs = Ptr(rhs as *mut T);
 
-    #[cfg(feature = "rayon")]
-    let max_threads = match parallelism {
-        Parallelism::None => 1,
-        Parallelism::Rayon(n_threads) => {
-            if n_threads == 0 {
-                rayon::current_num_threads()
-            } else {
-                n_threads
-            }
-        }
-    };
-
-    #[cfg(feature = "rayon")]
-    let threading_threshold = {
-        use core::any::TypeId;
-        let is_c32 = TypeId::of::<c32>() == TypeId::of::<T>();
-        let is_c64 = TypeId::of::<c64>() == TypeId::of::<T>();
-        if is_c32 {
-            get_threading_threshold() / 4
-        } else if is_c64 {
-            get_threading_threshold() / 16
-        } else {
-            get_threading_threshold()
+    let max_threads = max_threads(parallelism);
+    trace_event!(
+        debug,
+        m,
+        n,
+        k,
+        kc,
+        mc,
+        nc,
+        mr = MR,
+        nr = NR,
+        max_threads,
+        "kernel params"
+    );
+
+    // a destination that is only written once and doesn't fit in the caches is streamed to
+    // memory, instead of evicting the operands on its way there
+    let streaming_stores = cfg!(target_arch = "x86_64")
+        && alpha.is_zero()
+        && k <= kc
+        && dst_rs == 1
+        && (MR * core::mem::size_of::<T>()) % 16 == 0
+        && !is_masked
+        && accumulate.is_none()
+        && epilogue.is_none()
+        && config.streaming_stores.unwrap_or_else(|| {
+            m.saturating_mul(n)
+                .saturating_mul(core::mem::size_of::<T>())
+                >= get_streaming_stores_threshold()
+        });
+
+    // tall-skinny and short-fat products are split once along their long dimension, and each
+    // thread computes its panel on its own. the shared path would pack the rhs and synchronize
+    // the threads for each depth block, while there are too few blocks along the short
+    // dimension to keep them busy.
+    if max_threads > 1
+        && config.kernel_params.is_none()
+        && !lhs_is_packed
+        && !rhs_is_packed
+        && !is_scaled
+        && !is_masked
+        && accumulate.is_none()
+        && epilogue.is_none()
+        && m.saturating_mul(n).saturating_mul(k) >= threading_threshold
+    {
+        let shape = Shape::of(m, n);
+        let (len, tile) = match shape {
+            Shape::General => (0, 1),
+            Shape::TallSkinny => (m, MR),
+            Shape::ShortFat => (n, NR),
+        };
+        let n_threads = Ord::min(max_threads, len / (tile * SKINNY_MIN_TILES_PER_THREAD));
+        if n_threads > 1 {
+            // the panels are blocked like the whole product in deterministic mode, since their
+            // own parameters, and with them the order of the sums along `k`, depend on their size
+            let kernel_params = config
+                .deterministic
+                .unwrap_or_else(get_deterministic)
+                .then(|| gemm_kernel_params::<T>(m, n, k, MR, NR, Parallelism::None));
+            let panel = len.msrv_div_ceil(n_threads).msrv_next_multiple_of(tile);
+            let n_threads = len.msrv_div_ceil(panel);
+            trace_event!(
+                debug,
+                ?shape,
+                panel,
+                threads = n_threads,
+                "split along the long dimension"
+            );
+            par_for_each(parallelism, n_threads, |tid| {
+                // capture the whole pointers, which are `Sync` unlike their fields
+                let (dst, lhs, rhs) = (dst, lhs, rhs);
+                let start = tid * panel;
+                let len = Ord::min(panel, len - start);
+                let (m, n, dst, lhs, rhs) = if shape == Shape::TallSkinny {
+                    (
+                        len,
+                        n,
+                        dst.wrapping_offset(start as isize * dst_rs),
+                        lhs.wrapping_offset(start as isize * lhs_rs),
+                        rhs,
+                    )
+                } else {
+                    (
+                        m,
+                        len,
+                        dst.wrapping_offset(start as isize * dst_cs),
+                        lhs,
+                        rhs.wrapping_offset(start as isize * rhs_cs),
+                    )
+                };
+                // alpha is already zero if dst isn't read
+                gemm_basic_generic::<S, T, N, MR, NR, MR_DIV_N>(
+                    simd,
+                    m,
+                    n,
+                    k,
+                    dst.0,
+                    dst_cs,
+                    dst_rs,
+                    true,
+                    lhs.0,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs.0,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                    conj_dst,
+                    conj_lhs,
+                    conj_rhs,
+                    mul_add,
+                    dispatcher,
+                    _requires_row_major_rhs,
+                    masked_edges,
+                    Parallelism::None,
+                    GemmConfig {
+                        kernel_params,
+                        streaming_stores: Some(streaming_stores),
+                        lhs_packing: Some(lhs_packing),
+                        rhs_packing: Some(rhs_packing),
+                        ..Default::default()
+                    },
+                );
+            });
+            return;
         }
-    };
+    }
 
     #[cfg(target_arch = "aarch64")]
-    let do_pack_rhs = _requires_row_major_rhs || m > get_rhs_packing_threshold() * MR;
+    let auto_pack_rhs = m > get_rhs_packing_threshold() * MR;
 
     // no need to pack if the lhs is already contiguous-ish
     #[cfg(not(target_arch = "aarch64"))]
-    let do_pack_rhs = (rhs_rs.unsigned_abs() != 1 && m > 2 * MR)
+    let auto_pack_rhs = (rhs_rs.unsigned_abs() != 1 && m > 2 * MR)
         || (rhs_rs.unsigned_abs() == 1 && m > get_rhs_packing_threshold() * MR);
-    let do_prepack_lhs = m <= 2 * mc && ((m % N != 0) || lhs_rs != 1);
-
-    let mut mem = if do_pack_rhs || do_prepack_lhs {
+    // the rhs of the backends that require a row-major one, and the scaled operands, are only
+    // read through the packed panels
+    let do_pack_rhs = !rhs_is_packed
+        && (_requires_row_major_rhs
+            || rhs_scale.is_some()
+            || match rhs_packing {
+                PackingPolicy::Auto => auto_pack_rhs,
+                PackingPolicy::Always => true,
+                PackingPolicy::Never => false,
+            });
+    // the kernels with masked edges read the rows of a partial vector in place
+    let do_prepack_lhs = !lhs_is_packed
+        && m <= 2 * mc
+        && ((m % N != 0 && !masked_edges)
+            || lhs_rs != 1
+            || lhs_scale.is_some()
+            || lhs_packing == PackingPolicy::Always);
+    trace_event!(
+        debug,
+        pack_rhs = do_pack_rhs,
+        prepack_lhs = do_prepack_lhs,
+        packed_lhs = lhs_is_packed,
+        packed_rhs = rhs_is_packed,
+        streaming_stores,
+        "packing"
+    );
+
+    let ext_lhs = Ptr(config.packed_lhs.unwrap_or(core::ptr::null()) as *mut T);
+    let ext_rhs = Ptr(config.packed_rhs.unwrap_or(core::ptr::null()) as *mut T);
+
+    let mut mem = if config.stack.is_none() && (do_pack_rhs || do_prepack_lhs) {
         let rhs_req = StackReq::new_aligned::<T>(
             if do_pack_rhs {
                 packed_rhs_stride * (nc / NR)
@@ -379,19 +1843,20 @@ pub unsafe fn gemm_basic_generic<
             },
             simd_align,
         );
-        Some(GlobalMemBuffer::new(rhs_req.and(lhs_req)))
+        #[cfg(feature = "std")]
+        let mem = crate::pool::alloc(rhs_req.and(lhs_req));
+        #[cfg(not(feature = "std"))]
+        let mem = GlobalMemBuffer::new(rhs_req.and(lhs_req));
+        Some(mem)
     } else {
         None
     };
+    let stack = match config.stack {
+        Some(stack) => Some(stack),
+        None => mem.as_mut().map(|mem| DynStack::new(mem)),
+    };
 
-    #[cfg(not(feature = "std"))]
-    let mut l2_slab = GlobalMemBuffer::new(StackReq::new_aligned::<T>(
-        packed_lhs_stride * (mc / MR),
-        simd_align,
-    ));
-
-    let mut packed_storage = mem.as_mut().map(|mem| {
-        let stack = DynStack::new(mem);
+    let mut packed_storage = stack.map(|stack| {
         let (rhs, stack) = stack.make_aligned_uninit::<T>(
             if do_pack_rhs {
                 packed_rhs_stride * (nc / NR)
@@ -429,8 +1894,97 @@ pub unsafe fn gemm_basic_generic<
     let packed_rhs = Ptr(packed_rhs);
     let prepacked_lhs = Ptr(prepacked_lhs);
 
-    let packed_rhs_rs = if do_pack_rhs { NR as isize } else { rhs_rs };
-    let packed_rhs_cs = if do_pack_rhs { 1 } else { rhs_cs };
+    let packed_rhs_rs = if do_pack_rhs || rhs_is_packed {
+        NR as isize
+    } else {
+        rhs_rs
+    };
+    let packed_rhs_cs = if do_pack_rhs || rhs_is_packed {
+        1
+    } else {
+        rhs_cs
+    };
+
+    // packs `ncols` columns of the rhs block starting at `(depth, col)`
+    let pack_rhs_block = |dst: Ptr<T>, ncols: usize, k_chunk: usize, depth: usize, col: usize| {
+        record_stat!(record_packed_rhs(
+            ncols * k_chunk * core::mem::size_of::<T>()
+        ));
+        let src = rhs.wrapping_offset(depth as isize * rhs_rs + col as isize * rhs_cs);
+        if let Some((scale, inc)) = rhs_scale {
+            pack_rhs_scaled::<T, NR, _>(
+                simd,
+                ncols,
+                k_chunk,
+                dst,
+                src,
+                rhs_cs,
+                rhs_rs,
+                packed_rhs_stride,
+                scale.wrapping_offset(col as isize * inc),
+                inc,
+                conj_rhs,
+            );
+            return;
+        }
+        // on aarch64 we want the registers to be fully initialized
+        // for use with neon/amx
+        #[cfg(target_arch = "aarch64")]
+        pack_rhs::<T, N, NR, _>(
+            simd,
+            ncols,
+            k_chunk,
+            dst,
+            src,
+            rhs_cs,
+            rhs_rs,
+            packed_rhs_stride,
+        );
+        #[cfg(not(target_arch = "aarch64"))]
+        pack_rhs::<T, 1, NR, _>(
+            simd,
+            ncols,
+            k_chunk,
+            dst,
+            src,
+            rhs_cs,
+            rhs_rs,
+            packed_rhs_stride,
+        );
+    };
+    // packs `nrows` rows of the lhs block starting at `(row, depth)`
+    let pack_lhs_block = |dst: Ptr<T>, nrows: usize, k_chunk: usize, row: usize, depth: usize| {
+        record_stat!(record_packed_lhs(
+            nrows * k_chunk * core::mem::size_of::<T>()
+        ));
+        let src = lhs.wrapping_offset(row as isize * lhs_rs + depth as isize * lhs_cs);
+        if let Some((scale, inc)) = lhs_scale {
+            pack_lhs_scaled::<T, MR, _>(
+                simd,
+                nrows,
+                k_chunk,
+                dst,
+                src,
+                lhs_cs,
+                lhs_rs,
+                packed_lhs_stride,
+                scale.wrapping_offset(row as isize * inc),
+                inc,
+                conj_lhs,
+            );
+        } else {
+            pack_lhs::<T, N, MR, _>(
+                simd,
+                nrows,
+                k_chunk,
+                dst,
+                src,
+                lhs_cs,
+                lhs_rs,
+                packed_lhs_stride,
+            );
+        }
+    };
 
     let mut did_pack_lhs = alloc::vec![false; mc / MR];
     let did_pack_lhs = Ptr((&mut *did_pack_lhs) as *mut _);
@@ -453,146 +2007,124 @@ pub unsafe fn gemm_basic_generic<
                 2
             };
 
-            let n_threads = match parallelism {
-                Parallelism::None => 1,
-                #[cfg(feature = "rayon")]
-                Parallelism::Rayon(_) => {
-                    let total_work = (m * n_chunk).saturating_mul(k_chunk);
-                    if total_work < threading_threshold {
-                        1
-                    } else {
-                        max_threads
-                    }
-                }
-            };
-
-            let packing_threshold = if n_threads == 1 {
-                get_lhs_packing_threshold_single_thread()
-            } else {
-                get_lhs_packing_threshold_multi_thread()
-            };
-
-            if do_pack_rhs {
-                if n_threads <= 1 {
-                    // on aarch64 we want the registers to be fully initialized
-                    // for use with neon/amx
-                    #[cfg(target_arch = "aarch64")]
-                    pack_rhs::<T, N, NR, _>(
-                        simd,
-                        n_chunk,
-                        k_chunk,
-                        packed_rhs,
-                        rhs.wrapping_offset(
-                            depth_outer as isize * rhs_rs + col_outer as isize * rhs_cs,
-                        ),
-                        rhs_cs,
-                        rhs_rs,
-                        packed_rhs_stride,
-                    );
-                    #[cfg(not(target_arch = "aarch64"))]
-                    pack_rhs::<T, 1, NR, _>(
-                        simd,
-                        n_chunk,
-                        k_chunk,
-                        packed_rhs,
-                        rhs.wrapping_offset(
-                            depth_outer as isize * rhs_rs + col_outer as isize * rhs_cs,
-                        ),
-                        rhs_cs,
-                        rhs_rs,
-                        packed_rhs_stride,
-                    );
+            let n_threads = {
+                let total_work = (m * n_chunk).saturating_mul(k_chunk);
+                if total_work < threading_threshold {
+                    1
                 } else {
-                    #[cfg(feature = "rayon")]
-                    {
-                        let n_tasks = n_chunk.msrv_div_ceil(NR);
-                        let base = n_tasks / n_threads;
-                        let rem = n_tasks % n_threads;
-
-                        let tid_to_col_inner = |tid: usize| {
-                            if tid == n_threads {
-                                return n_chunk;
-                            }
+                    max_threads
+                }
+            };
 
-                            let col = if tid < rem {
-                                NR * tid * (base + 1)
-                            } else {
-                                NR * (rem + tid * base)
-                            };
-                            col.min(n_chunk)
-                        };
+            let packing_threshold = if n_threads == 1 {
+                get_lhs_packing_threshold_single_thread()
+            } else {
+                get_lhs_packing_threshold_multi_thread()
+            };
+            // each thread packs the rhs panels it reads, instead of the shared packing below
+            let replicate_rhs = do_pack_rhs && n_threads > 1 && get_replicate_rhs();
 
-                        let func = |tid: usize| {
-                            let col_inner = tid_to_col_inner(tid);
-                            let ncols = tid_to_col_inner(tid + 1) - col_inner;
-                            let j = col_inner / NR;
+            if do_pack_rhs && !replicate_rhs {
+                if n_threads <= 1 {
+                    pack_rhs_block(packed_rhs, n_chunk, k_chunk, depth_outer, col_outer);
+                } else {
+                    // each thread packs a range of whole panels, and there are no more threads
+                    // than panels
+                    let n_tasks = n_chunk.msrv_div_ceil(NR);
+                    let n_threads = Ord::min(n_threads, n_tasks);
+                    let base = n_tasks / n_threads;
+                    let rem = n_tasks % n_threads;
+
+                    let tid_to_col_inner = |tid: usize| {
+                        if tid == n_threads {
+                            return n_chunk;
+                        }
 
-                            if ncols > 0 {
-                                #[cfg(target_arch = "aarch64")]
-                                pack_rhs::<T, N, NR, _>(
-                                    simd,
-                                    ncols,
-                                    k_chunk,
-                                    packed_rhs.wrapping_add(j * packed_rhs_stride),
-                                    rhs.wrapping_offset(
-                                        depth_outer as isize * rhs_rs
-                                            + (col_outer + col_inner) as isize * rhs_cs,
-                                    ),
-                                    rhs_cs,
-                                    rhs_rs,
-                                    packed_rhs_stride,
-                                );
-                                #[cfg(not(target_arch = "aarch64"))]
-                                pack_rhs::<T, 1, NR, _>(
-                                    simd,
-                                    ncols,
-                                    k_chunk,
-                                    packed_rhs.wrapping_add(j * packed_rhs_stride),
-                                    rhs.wrapping_offset(
-                                        depth_outer as isize * rhs_rs
-                                            + (col_outer + col_inner) as isize * rhs_cs,
-                                    ),
-                                    rhs_cs,
-                                    rhs_rs,
-                                    packed_rhs_stride,
-                                );
-                            }
+                        let col = if tid < rem {
+                            NR * tid * (base + 1)
+                        } else {
+                            NR * (rem + tid * base)
                         };
-                        par_for_each(n_threads, func);
-                    }
+                        col.min(n_chunk)
+                    };
 
-                    #[cfg(not(feature = "rayon"))]
-                    {
-                        unreachable!();
-                    }
+                    let func = |tid: usize| {
+                        let col_inner = tid_to_col_inner(tid);
+                        let ncols = tid_to_col_inner(tid + 1) - col_inner;
+                        let j = col_inner / NR;
+
+                        if ncols > 0 {
+                            pack_rhs_block(
+                                packed_rhs.wrapping_add(j * packed_rhs_stride),
+                                ncols,
+                                k_chunk,
+                                depth_outer,
+                                col_outer + col_inner,
+                            );
+                        }
+                    };
+                    par_for_each(parallelism, n_threads, func);
                 }
             }
             if do_prepack_lhs {
-                pack_lhs::<T, N, MR, _>(
-                    simd,
-                    m,
-                    k_chunk,
-                    prepacked_lhs,
-                    lhs.wrapping_offset(depth_outer as isize * lhs_cs),
-                    lhs_cs,
-                    lhs_rs,
-                    packed_lhs_stride,
-                );
+                if n_threads <= 1 || get_numa_nodes() <= 1 {
+                    pack_lhs_block(prepacked_lhs, m, k_chunk, 0, depth_outer);
+                } else {
+                    // the panels are read by every thread, so their pages are spread between
+                    // the nodes by packing them from every thread
+                    let n_panels = m.msrv_div_ceil(MR);
+                    par_for_each(parallelism, n_threads, |tid| {
+                        let first = n_panels * tid / n_threads;
+                        let last = n_panels * (tid + 1) / n_threads;
+                        let row = first * MR;
+                        let nrows = Ord::min(last * MR, m) - row;
+                        if nrows > 0 {
+                            pack_lhs_block(
+                                prepacked_lhs.wrapping_add(first * packed_lhs_stride),
+                                nrows,
+                                k_chunk,
+                                row,
+                                depth_outer,
+                            );
+                        }
+                    });
+                }
             }
 
             let n_col_mini_chunks = (n_chunk + (NR - 1)) / NR;
 
+            // each job is a rectangle of `block_rows × block_cols` tiles of a row block
+            let (block_rows, block_cols) =
+                tile_blocks(n_threads, m.msrv_div_ceil(MR), n_col_mini_chunks, MR, NR);
+            let n_col_groups = n_col_mini_chunks.msrv_div_ceil(block_cols);
+
             let mut n_jobs = 0;
             let mut row_outer = 0;
             while row_outer != m {
                 let mut m_chunk = mc.min(m - row_outer);
-                if m_chunk > N && !do_prepack_lhs {
+                if m_chunk > N && !do_prepack_lhs && !lhs_is_packed && !masked_edges {
                     m_chunk = m_chunk / N * N;
                 }
                 let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
-                n_jobs += n_col_mini_chunks * n_row_mini_chunks;
+                n_jobs += n_row_mini_chunks.msrv_div_ceil(block_rows) * n_col_groups;
                 row_outer += m_chunk;
             }
+            trace_event!(
+                trace,
+                col = col_outer,
+                n_chunk,
+                depth = depth_outer,
+                k_chunk,
+                threads = n_threads,
+                jobs = n_jobs,
+                replicate_rhs,
+                "block"
+            );
+
+            // the jobs are numbered by row block, then by column and row of the rectangle
+            // inside the block, and taken from `next_job` one at a time
+            let next_job = AtomicUsize::new(0);
+            let next_job = &next_job;
 
             let func = move |tid, packed_lhs: Ptr<T>| {
                 let mut did_pack_lhs_storage =
@@ -602,87 +2134,174 @@ pub unsafe fn gemm_basic_generic<
                 } else {
                     &mut *({ did_pack_lhs }.0)
                 };
+                // row block whose panels `did_pack_lhs` refers to
+                let mut packed_row_outer = usize::MAX;
 
-                let min_jobs_per_thread = n_jobs / n_threads;
-                let rem = n_jobs - n_threads * min_jobs_per_thread;
-
-                // thread `tid` takes min_jobs_per_thread or min_jobs_per_thread + 1
-                let (job_start, job_end) = if tid < rem {
-                    let start = tid * (min_jobs_per_thread + 1);
-                    (start, start + min_jobs_per_thread + 1)
+                let mut rhs_mem = if replicate_rhs {
+                    let req = StackReq::new_aligned::<T>(
+                        packed_rhs_stride * n_col_mini_chunks,
+                        simd_align,
+                    );
+                    #[cfg(feature = "std")]
+                    let mem = crate::pool::alloc(req);
+                    #[cfg(not(feature = "std"))]
+                    let mem = GlobalMemBuffer::new(req);
+                    Some(mem)
                 } else {
-                    // start = rem * (min_jobs_per_thread + 1) + (tid - rem) * min_jobs_per_thread;
-                    let start = tid * min_jobs_per_thread + rem;
-                    (start, start + min_jobs_per_thread)
+                    None
                 };
-
-                let mut row_outer = 0;
-                let mut job_id = 0;
-                while row_outer != m {
-                    let mut m_chunk = mc.min(m - row_outer);
-                    if m_chunk > N && !do_prepack_lhs {
-                        m_chunk = m_chunk / N * N;
+                let packed_rhs = match rhs_mem.as_mut() {
+                    Some(mem) => {
+                        let (mut storage, _) = DynStack::new(mem).make_aligned_uninit::<T>(
+                            packed_rhs_stride * n_col_mini_chunks,
+                            simd_align,
+                        );
+                        Ptr(storage.as_mut_ptr() as *mut T)
                     }
-                    let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
-
-                    let n_mini_jobs = n_col_mini_chunks * n_row_mini_chunks;
-
-                    if job_id >= job_end {
+                    None => packed_rhs,
+                };
+                let mut did_pack_rhs =
+                    alloc::vec![false; if replicate_rhs { n_col_mini_chunks } else { 0 }];
+                #[cfg(feature = "stats")]
+                let mut stats = crate::stats::ThreadStats::default();
+
+                loop {
+                    let job = next_job.fetch_add(1, Ordering::Relaxed);
+                    if job >= n_jobs {
+                        #[cfg(feature = "stats")]
+                        stats.flush();
+                        // the streaming stores are weakly ordered, so they're fenced before the
+                        // threads are joined
+                        #[cfg(target_arch = "x86_64")]
+                        if streaming_stores {
+                            core::arch::x86_64::_mm_sfence();
+                        }
                         return;
                     }
-                    if job_id + n_mini_jobs < job_start {
+                    #[cfg(feature = "stats")]
+                    stats.record_job();
+
+                    let mut row_outer = 0;
+                    let mut job_id = 0;
+                    let (m_chunk, n_row_mini_chunks, n_row_groups) = loop {
+                        let mut m_chunk = mc.min(m - row_outer);
+                        if m_chunk > N && !do_prepack_lhs && !lhs_is_packed && !masked_edges {
+                            m_chunk = m_chunk / N * N;
+                        }
+                        let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
+                        let n_row_groups = n_row_mini_chunks.msrv_div_ceil(block_rows);
+
+                        if job < job_id + n_row_groups * n_col_groups {
+                            break (m_chunk, n_row_mini_chunks, n_row_groups);
+                        }
                         row_outer += m_chunk;
-                        job_id += n_mini_jobs;
-                        continue;
-                    }
+                        job_id += n_row_groups * n_col_groups;
+                    };
+                    let row_group = (job - job_id) % n_row_groups;
+                    let col_group = (job - job_id) / n_row_groups;
 
                     let do_pack_lhs = !do_prepack_lhs
-                        && ((m_chunk % N != 0) || lhs_rs != 1 || n_chunk > packing_threshold * NR);
-                    let packed_lhs_cs = if do_prepack_lhs || do_pack_lhs {
+                        && !lhs_is_packed
+                        && ((m_chunk % N != 0 && !masked_edges)
+                            || lhs_rs != 1
+                            || lhs_scale.is_some()
+                            || match lhs_packing {
+                                PackingPolicy::Auto => n_chunk > packing_threshold * NR,
+                                PackingPolicy::Always => true,
+                                PackingPolicy::Never => false,
+                            });
+                    let packed_lhs_cs = if do_prepack_lhs || do_pack_lhs || lhs_is_packed {
                         MR as isize
                     } else {
                         lhs_cs
                     };
 
-                    let mut j = 0;
-                    did_pack_lhs.fill(false);
-                    while j < n_col_mini_chunks {
-                        let mut i = 0;
-                        while i < n_row_mini_chunks {
+                    if packed_row_outer != row_outer {
+                        did_pack_lhs.fill(false);
+                        packed_row_outer = row_outer;
+                        trace_event!(
+                            trace,
+                            tid,
+                            row = row_outer,
+                            m_chunk,
+                            pack_lhs = do_pack_lhs,
+                            "row block"
+                        );
+                    }
+                    let cols = col_group * block_cols
+                        ..Ord::min((col_group + 1) * block_cols, n_col_mini_chunks);
+                    let rows = row_group * block_rows
+                        ..Ord::min((row_group + 1) * block_rows, n_row_mini_chunks);
+                    for j in cols {
+                        for i in rows.clone() {
                             let col_inner = NR * j;
                             let n_chunk_inner = NR.min(n_chunk - col_inner);
 
                             let row_inner = MR * i;
                             let m_chunk_inner = MR.min(m_chunk - row_inner);
 
-                            if job_id < job_start || job_id >= job_end {
-                                job_id += 1;
-                                i += 1;
+                            let row = row_outer + row_inner;
+                            let col = col_outer + col_inner;
+                            let n_selected = if is_masked {
+                                (0..n_chunk_inner)
+                                    .map(|j| {
+                                        (0..m_chunk_inner)
+                                            .filter(|&i| is_selected(row + i, col + j))
+                                            .count()
+                                    })
+                                    .sum()
+                            } else {
+                                m_chunk_inner * n_chunk_inner
+                            };
+                            if n_selected == 0 {
                                 continue;
                             }
-                            job_id += 1;
+                            // partially selected blocks are computed out of place, then only
+                            // the selected elements are written to the destination. the same
+                            // goes for the blocks that are also added to the secondary
+                            // destination, which only receive the product.
+                            let is_partial =
+                                n_selected != m_chunk_inner * n_chunk_inner || accumulate.is_some();
+                            // full blocks are computed out of place, then streamed to the
+                            // destination
+                            let is_streamed =
+                                streaming_stores && m_chunk_inner == MR && n_chunk_inner == NR;
+                            let mut tmp = core::mem::MaybeUninit::<[[T; MR]; NR]>::uninit();
+                            if is_partial {
+                                tmp.write([[T::zero(); MR]; NR]);
+                            }
 
-                            let dst = dst.wrapping_offset(
-                                (row_outer + row_inner) as isize * dst_rs
-                                    + (col_outer + col_inner) as isize * dst_cs,
-                            );
+                            let dst =
+                                dst.wrapping_offset(row as isize * dst_rs + col as isize * dst_cs);
+                            let (tile_dst, tile_dst_cs, tile_dst_rs) = if is_partial || is_streamed
+                            {
+                                (tmp.as_mut_ptr() as *mut T, MR as isize, 1)
+                            } else {
+                                (dst.0, dst_cs, dst_rs)
+                            };
 
                             let func =
                                 dispatcher[(m_chunk_inner + (N - 1)) / N - 1][n_chunk_inner - 1];
+                            #[cfg(feature = "stats")]
+                            stats.record_kernel(m_chunk_inner < MR, n_chunk_inner < NR);
 
+                            if replicate_rhs && !did_pack_rhs[j] {
+                                pack_rhs_block(
+                                    packed_rhs.wrapping_add(j * packed_rhs_stride),
+                                    n_chunk_inner,
+                                    k_chunk,
+                                    depth_outer,
+                                    col,
+                                );
+                                did_pack_rhs[j] = true;
+                            }
                             if do_pack_lhs && !did_pack_lhs[i] {
-                                pack_lhs::<T, N, MR, _>(
-                                    simd,
+                                pack_lhs_block(
+                                    packed_lhs.wrapping_add(i * packed_lhs_stride),
                                     m_chunk_inner,
                                     k_chunk,
-                                    packed_lhs.wrapping_add(i * packed_lhs_stride),
-                                    lhs.wrapping_offset(
-                                        (row_outer + row_inner) as isize * lhs_rs
-                                            + depth_outer as isize * lhs_cs,
-                                    ),
-                                    lhs_cs,
-                                    lhs_rs,
-                                    packed_lhs_stride,
+                                    row_outer + row_inner,
+                                    depth_outer,
                                 );
                                 did_pack_lhs[i] = true;
                             }
@@ -691,13 +2310,19 @@ pub unsafe fn gemm_basic_generic<
                                 m_chunk_inner,
                                 n_chunk_inner,
                                 k_chunk,
-                                dst.0,
+                                tile_dst,
                                 if do_pack_lhs {
                                     packed_lhs.wrapping_add(i * packed_lhs_stride).0
                                 } else if do_prepack_lhs {
                                     packed_lhs
                                         .wrapping_add((i + row_outer / MR) * packed_lhs_stride)
                                         .0
+                                } else if lhs_is_packed {
+                                    ext_lhs
+                                        .wrapping_add(
+                                            (i + row_outer / MR) * k * MR + depth_outer * MR,
+                                        )
+                                        .0
                                 } else {
                                     lhs.wrapping_offset(
                                         (row_outer + row_inner) as isize * lhs_rs
@@ -707,6 +2332,12 @@ pub unsafe fn gemm_basic_generic<
                                 },
                                 if do_pack_rhs {
                                     packed_rhs.wrapping_add(j * packed_rhs_stride).0
+                                } else if rhs_is_packed {
+                                    ext_rhs
+                                        .wrapping_add(
+                                            (j + col_outer / NR) * k * NR + depth_outer * NR,
+                                        )
+                                        .0
                                 } else {
                                     rhs.wrapping_offset(
                                         depth_outer as isize * rhs_rs
@@ -714,39 +2345,88 @@ pub unsafe fn gemm_basic_generic<
                                     )
                                     .0
                                 },
-                                dst_cs,
-                                dst_rs,
+                                tile_dst_cs,
+                                tile_dst_rs,
                                 packed_lhs_cs,
                                 packed_rhs_rs,
                                 packed_rhs_cs,
-                                alpha,
+                                if is_partial { T::zero() } else { alpha },
                                 beta,
-                                alpha_status,
-                                conj_dst,
+                                if is_partial { 0 } else { alpha_status },
+                                conj_dst && !is_partial,
                                 conj_lhs,
                                 conj_rhs,
                                 core::ptr::null(),
                             );
-                            i += 1;
+
+                            let is_last_depth = depth_outer + k_chunk == k;
+                            if is_streamed {
+                                stream_block(dst.0, dst_cs, tmp.as_ptr() as *const T, MR, NR);
+                            } else if is_partial {
+                                let tmp = tmp.assume_init_ref();
+                                for (j, tmp) in tmp.iter().enumerate().take(n_chunk_inner) {
+                                    for (i, &value) in tmp.iter().enumerate().take(m_chunk_inner) {
+                                        if !is_selected(row + i, col + j) {
+                                            continue;
+                                        }
+                                        let dst = dst
+                                            .wrapping_offset(
+                                                i as isize * dst_rs + j as isize * dst_cs,
+                                            )
+                                            .0;
+                                        let old = if conj_dst { (*dst).conj() } else { *dst };
+                                        *dst = match alpha_status {
+                                            0 => value,
+                                            1 => old + value,
+                                            _ => alpha * old + value,
+                                        };
+                                        if let Some((acc, acc_cs, acc_rs)) = accumulate {
+                                            let acc = acc
+                                                .wrapping_offset(
+                                                    (row + i) as isize * acc_rs
+                                                        + (col + j) as isize * acc_cs,
+                                                )
+                                                .0;
+                                            *acc = *acc + value;
+                                        }
+                                        if let Some(epilogue) = epilogue {
+                                            if is_last_depth {
+                                                epilogue.apply(
+                                                    dst,
+                                                    1,
+                                                    1,
+                                                    dst_cs,
+                                                    dst_rs,
+                                                    row + i,
+                                                    col + j,
+                                                );
+                                            }
+                                        }
+                                    }
+                                }
+                            } else if let Some(epilogue) = epilogue {
+                                if is_last_depth {
+                                    epilogue.apply(
+                                        dst.0,
+                                        m_chunk_inner,
+                                        n_chunk_inner,
+                                        dst_cs,
+                                        dst_rs,
+                                        row,
+                                        col,
+                                    );
+                                }
+                            }
                         }
-                        j += 1;
                     }
-
-                    row_outer += m_chunk;
                 }
             };
 
             if do_prepack_lhs {
-                match parallelism {
-                    Parallelism::None => func(0, prepacked_lhs),
-                    #[cfg(feature = "rayon")]
-                    Parallelism::Rayon(_) => {
-                        if n_threads == 1 {
-                            func(0, prepacked_lhs);
-                        } else {
-                            par_for_each(n_threads, |tid| func(tid, prepacked_lhs));
-                        }
-                    }
+                if n_threads == 1 {
+                    func(0, prepacked_lhs);
+                } else {
+                    par_for_each(parallelism, n_threads, |tid| func(tid, prepacked_lhs));
                 }
             } else {
                 #[cfg(feature = "std")]
@@ -761,8 +2441,13 @@ pub unsafe fn gemm_basic_generic<
                     });
                 };
 
+                // without thread locals, each call allocates its own buffer
                 #[cfg(not(feature = "std"))]
-                let mut func = |tid: usize| {
+                let func = |tid: usize| {
+                    let mut l2_slab = GlobalMemBuffer::new(StackReq::new_aligned::<T>(
+                        packed_lhs_stride * (mc / MR),
+                        simd_align,
+                    ));
                     let stack = DynStack::new(&mut l2_slab);
                     let (mut packed_lhs_storage, _) =
                         stack.make_aligned_uninit::<T>(packed_lhs_stride * (mc / MR), simd_align);
@@ -770,16 +2455,10 @@ pub unsafe fn gemm_basic_generic<
                     func(tid, packed_lhs);
                 };
 
-                match parallelism {
-                    Parallelism::None => func(0),
-                    #[cfg(feature = "rayon")]
-                    Parallelism::Rayon(_) => {
-                        if n_threads == 1 {
-                            func(0);
-                        } else {
-                            par_for_each(n_threads, func);
-                        }
-                    }
+                if n_threads == 1 {
+                    func(0);
+                } else {
+                    par_for_each(parallelism, n_threads, func);
                 }
             }
 
@@ -794,7 +2473,7 @@ pub unsafe fn gemm_basic_generic<
 
 #[macro_export]
 macro_rules! __inject_mod {
-    ($module: ident, $ty: ident, $N: expr, $simd: ident, $requires_packed_rhs: expr) => {
+    ($module: ident, $ty: ident, $N: expr, $simd: ident, $requires_packed_rhs: expr $(, $masked_edges: expr $(, $shaped: ident)?)?) => {
         mod $module {
             use super::*;
             use crate::gemm_common::simd::MixedSimd;
@@ -821,9 +2500,76 @@ macro_rules! __inject_mod {
                 conj_dst: bool,
                 conj_lhs: bool,
                 conj_rhs: bool,
-                parallelism: $crate::Parallelism,
+                parallelism: $crate::Parallelism<'_>,
+                config: $crate::gemm::GemmConfig<'_, $ty>,
+            ) {
+                // shaped backends also provide tall, wide and small blockings, which are only used
+                // for products that don't depend on the reported `mr×nr`
+                $($(
+                    let _ = stringify!($shaped);
+                    let blockings = [
+                        (MR_DIV_N * N, NR),
+                        (TALL_MR_DIV_N * N, TALL_NR),
+                        (WIDE_MR_DIV_N * N, WIDE_NR),
+                        (SMALL_MR_DIV_N * N, SMALL_NR),
+                    ];
+                    match $crate::gemm::select_blocking(m, n, k, N, &blockings, parallelism, &config) {
+                        1 => {
+                            return gemm_with_blocking::<{ TALL_MR_DIV_N * N }, TALL_NR, TALL_MR_DIV_N>(
+                                &TALL_UKR, m, n, k, dst, dst_cs, dst_rs, read_dst, lhs, lhs_cs,
+                                lhs_rs, rhs, rhs_cs, rhs_rs, alpha, beta, conj_dst, conj_lhs,
+                                conj_rhs, parallelism, config,
+                            )
+                        }
+                        2 => {
+                            return gemm_with_blocking::<{ WIDE_MR_DIV_N * N }, WIDE_NR, WIDE_MR_DIV_N>(
+                                &WIDE_UKR, m, n, k, dst, dst_cs, dst_rs, read_dst, lhs, lhs_cs,
+                                lhs_rs, rhs, rhs_cs, rhs_rs, alpha, beta, conj_dst, conj_lhs,
+                                conj_rhs, parallelism, config,
+                            )
+                        }
+                        3 => {
+                            return gemm_with_blocking::<{ SMALL_MR_DIV_N * N }, SMALL_NR, SMALL_MR_DIV_N>(
+                                &SMALL_UKR, m, n, k, dst, dst_cs, dst_rs, read_dst, lhs, lhs_cs,
+                                lhs_rs, rhs, rhs_cs, rhs_rs, alpha, beta, conj_dst, conj_lhs,
+                                conj_rhs, parallelism, config,
+                            )
+                        }
+                        _ => {}
+                    }
+                )?)?
+                gemm_with_blocking::<{ MR_DIV_N * N }, NR, MR_DIV_N>(
+                    &UKR, m, n, k, dst, dst_cs, dst_rs, read_dst, lhs, lhs_cs, lhs_rs, rhs,
+                    rhs_cs, rhs_rs, alpha, beta, conj_dst, conj_lhs, conj_rhs, parallelism,
+                    config,
+                )
+            }
+
+            #[inline(always)]
+            unsafe fn gemm_with_blocking<const MR: usize, const NR_: usize, const MR_DIV_N_: usize>(
+                ukr: &[[$crate::microkernel::MicroKernelFn<$ty>; NR_]; MR_DIV_N_],
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut $ty,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const $ty,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                rhs: *const $ty,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                alpha: $ty,
+                beta: $ty,
+                conj_dst: bool,
+                conj_lhs: bool,
+                conj_rhs: bool,
+                parallelism: $crate::Parallelism<'_>,
+                config: $crate::gemm::GemmConfig<'_, $ty>,
             ) {
-                $crate::gemm::gemm_basic_generic::<_, $ty, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                $crate::gemm::gemm_basic_generic::<_, $ty, N, MR, NR_, MR_DIV_N_>(
                     <$crate::simd::$simd as MixedSimd<$ty, $ty, $ty, $ty>>::try_new().unwrap(),
                     m,
                     n,
@@ -844,11 +2590,120 @@ macro_rules! __inject_mod {
                     conj_lhs,
                     conj_rhs,
                     |a, b, c| a * b + c,
-                    &UKR,
+                    ukr,
                     $requires_packed_rhs,
+                    false $(|| $masked_edges)?,
                     parallelism,
+                    config,
+                );
+            }
+
+            pub fn kernel_params(
+                m: usize,
+                n: usize,
+                k: usize,
+                parallelism: $crate::Parallelism<'_>,
+            ) -> $crate::cache::KernelParams {
+                $crate::gemm::gemm_kernel_params::<$ty>(m, n, k, MR_DIV_N * N, NR, parallelism)
+            }
+
+            pub fn blocking(
+                m: usize,
+                n: usize,
+                k: usize,
+                parallelism: $crate::Parallelism<'_>,
+            ) -> (usize, usize, $crate::cache::KernelParams) {
+                let blockings: &[(usize, usize)] = &[(MR_DIV_N * N, NR)];
+                // same selection as `gemm_basic`
+                $($(
+                    let _ = stringify!($shaped);
+                    let blockings: &[(usize, usize)] = &[
+                        blockings[0],
+                        (TALL_MR_DIV_N * N, TALL_NR),
+                        (WIDE_MR_DIV_N * N, WIDE_NR),
+                        (SMALL_MR_DIV_N * N, SMALL_NR),
+                    ];
+                )?)?
+                let config = $crate::gemm::GemmConfig::<$ty>::default();
+                let (mr, nr) = blockings
+                    [$crate::gemm::select_blocking(m, n, k, N, blockings, parallelism, &config)];
+                (
+                    mr,
+                    nr,
+                    $crate::gemm::gemm_kernel_params::<$ty>(m, n, k, mr, nr, parallelism),
+                )
+            }
+
+            pub fn gemm_req(
+                m: usize,
+                n: usize,
+                k: usize,
+                parallelism: $crate::Parallelism<'_>,
+            ) -> $crate::dyn_stack::StackReq {
+                $crate::gemm::gemm_req_generic::<$ty>(
+                    m,
+                    n,
+                    k,
+                    MR_DIV_N * N,
+                    NR,
+                    kernel_params(m, n, k, parallelism),
+                )
+            }
+
+            pub unsafe fn pack_lhs(
+                m: usize,
+                k: usize,
+                dst: *mut $ty,
+                src: *const $ty,
+                src_cs: isize,
+                src_rs: isize,
+                panel_stride: usize,
+            ) {
+                $crate::pack_operands::pack_lhs::<$ty, N, { MR_DIV_N * N }, _>(
+                    <$crate::simd::$simd as MixedSimd<$ty, $ty, $ty, $ty>>::try_new().unwrap(),
+                    m,
+                    k,
+                    $crate::Ptr(dst),
+                    $crate::Ptr(src as *mut $ty),
+                    src_cs,
+                    src_rs,
+                    panel_stride,
+                );
+            }
+
+            pub unsafe fn pack_rhs(
+                n: usize,
+                k: usize,
+                dst: *mut $ty,
+                src: *const $ty,
+                src_cs: isize,
+                src_rs: isize,
+                panel_stride: usize,
+            ) {
+                $crate::pack_operands::pack_rhs::<$ty, 1, NR, _>(
+                    <$crate::simd::$simd as MixedSimd<$ty, $ty, $ty, $ty>>::try_new().unwrap(),
+                    n,
+                    k,
+                    $crate::Ptr(dst),
+                    $crate::Ptr(src as *mut $ty),
+                    src_cs,
+                    src_rs,
+                    panel_stride,
                 );
             }
+
+            pub static BACKEND: $crate::gemm::Backend<GemmTy, PackTy> = $crate::gemm::Backend {
+                name: stringify!($module),
+                gemm: gemm_basic,
+                gemm_req,
+                kernel_params,
+                blocking,
+                mr: MR_DIV_N * N,
+                nr: NR,
+                simd_bytes: N * core::mem::size_of::<$ty>(),
+                pack_lhs: Some(pack_lhs),
+                pack_rhs: Some(pack_rhs),
+            };
         }
     };
 }
@@ -883,7 +2738,8 @@ macro_rules! __inject_mod_cplx {
                     conj_dst: bool,
                     conj_lhs: bool,
                     conj_rhs: bool,
-                    parallelism: $crate::Parallelism,
+                    parallelism: $crate::Parallelism<'_>,
+                    config: $crate::gemm::GemmConfig<'_, num_complex::Complex<T>>,
                     ) {
                     $crate::gemm::gemm_basic_generic::<_, _, N, { CPLX_MR_DIV_N * N }, CPLX_NR, CPLX_MR_DIV_N>(
                         <$crate::simd::$simd as MixedSimd<T, T, T, T>>::try_new().unwrap(),
@@ -908,9 +2764,107 @@ macro_rules! __inject_mod_cplx {
                         |a, b, c| a * b + c,
                         &CPLX_UKR,
                         false,
+                        false,
                         parallelism,
+                        config,
                         );
                 }
+
+                pub fn kernel_params(
+                    m: usize,
+                    n: usize,
+                    k: usize,
+                    parallelism: $crate::Parallelism<'_>,
+                ) -> $crate::cache::KernelParams {
+                    $crate::gemm::gemm_kernel_params::<num_complex::Complex<T>>(
+                        m,
+                        n,
+                        k,
+                        CPLX_MR_DIV_N * N,
+                        CPLX_NR,
+                        parallelism,
+                    )
+                }
+
+                pub fn blocking(
+                    m: usize,
+                    n: usize,
+                    k: usize,
+                    parallelism: $crate::Parallelism<'_>,
+                ) -> (usize, usize, $crate::cache::KernelParams) {
+                    (CPLX_MR_DIV_N * N, CPLX_NR, kernel_params(m, n, k, parallelism))
+                }
+
+                pub fn gemm_req(
+                    m: usize,
+                    n: usize,
+                    k: usize,
+                    parallelism: $crate::Parallelism<'_>,
+                ) -> $crate::dyn_stack::StackReq {
+                    $crate::gemm::gemm_req_generic::<num_complex::Complex<T>>(
+                        m,
+                        n,
+                        k,
+                        CPLX_MR_DIV_N * N,
+                        CPLX_NR,
+                        kernel_params(m, n, k, parallelism),
+                    )
+                }
+
+                pub unsafe fn pack_lhs(
+                    m: usize,
+                    k: usize,
+                    dst: *mut num_complex::Complex<T>,
+                    src: *const num_complex::Complex<T>,
+                    src_cs: isize,
+                    src_rs: isize,
+                    panel_stride: usize,
+                ) {
+                    $crate::pack_operands::pack_lhs::<_, N, { CPLX_MR_DIV_N * N }, _>(
+                        <$crate::simd::$simd as MixedSimd<T, T, T, T>>::try_new().unwrap(),
+                        m,
+                        k,
+                        $crate::Ptr(dst),
+                        $crate::Ptr(src as *mut num_complex::Complex<T>),
+                        src_cs,
+                        src_rs,
+                        panel_stride,
+                    );
+                }
+
+                pub unsafe fn pack_rhs(
+                    n: usize,
+                    k: usize,
+                    dst: *mut num_complex::Complex<T>,
+                    src: *const num_complex::Complex<T>,
+                    src_cs: isize,
+                    src_rs: isize,
+                    panel_stride: usize,
+                ) {
+                    $crate::pack_operands::pack_rhs::<_, 1, CPLX_NR, _>(
+                        <$crate::simd::$simd as MixedSimd<T, T, T, T>>::try_new().unwrap(),
+                        n,
+                        k,
+                        $crate::Ptr(dst),
+                        $crate::Ptr(src as *mut num_complex::Complex<T>),
+                        src_cs,
+                        src_rs,
+                        panel_stride,
+                    );
+                }
+
+                pub static BACKEND: $crate::gemm::Backend<GemmTy, PackTy> = $crate::gemm::Backend {
+                    name: stringify!($module),
+                    gemm: gemm_basic_cplx,
+                    gemm_req,
+                    kernel_params,
+                    blocking,
+                    mr: CPLX_MR_DIV_N * N,
+                    nr: CPLX_NR,
+                    simd_bytes: N * core::mem::size_of::<num_complex::Complex<T>>(),
+                    pack_lhs: Some(pack_lhs),
+                    pack_rhs: Some(pack_rhs),
+                };
             }
         }
     };
@@ -939,20 +2893,22 @@ macro_rules! gemm_def {
             bool,
             bool,
             $crate::Parallelism,
+            $crate::gemm::GemmConfig<'_, T>,
         );
+        type PackTy = unsafe fn(usize, usize, *mut T, *const T, isize, isize, usize);
 
         #[inline]
-        fn init_gemm_fn() -> GemmTy {
+        fn init_backend() -> &'static $crate::gemm::Backend<GemmTy, PackTy> {
             #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
             {
                 #[cfg(feature = "nightly")]
                 if $crate::feature_detected!("avx512f") {
-                    return avx512f::gemm_basic;
+                    return &avx512f::BACKEND;
                 }
                 if $crate::feature_detected!("fma") {
-                    fma::gemm_basic
+                    &fma::BACKEND
                 } else {
-                    scalar::gemm_basic
+                    &scalar::BACKEND
                 }
             }
 
@@ -961,20 +2917,20 @@ macro_rules! gemm_def {
                 if $crate::feature_detected!("neon") {
                     #[cfg(feature = "experimental-apple-amx")]
                     if $crate::cache::HasAmx::get() {
-                        return amx::gemm_basic;
+                        return &amx::BACKEND;
                     }
-                    neon::gemm_basic
+                    &neon::BACKEND
                 } else {
-                    scalar::gemm_basic
+                    &scalar::BACKEND
                 }
             }
 
             #[cfg(target_arch = "wasm32")]
             {
                 if $crate::feature_detected!("simd128") {
-                    simd128::gemm_basic
+                    &simd128::BACKEND
                 } else {
-                    scalar::gemm_basic
+                    &scalar::BACKEND
                 }
             }
 
@@ -985,35 +2941,79 @@ macro_rules! gemm_def {
                 target_arch = "wasm32",
             )))]
             {
-                scalar::gemm_basic
+                &scalar::BACKEND
             }
         }
 
-        static GEMM_PTR: ::core::sync::atomic::AtomicPtr<()> =
-            ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut());
+        static REGISTRY: $crate::gemm::BackendRegistry<GemmTy, PackTy> =
+            $crate::gemm::BackendRegistry::new();
+
+        /// Returns the registered backend with the highest priority, by default the fastest
+        /// built-in backend supported by the current machine.
+        #[inline(always)]
+        pub fn get_backend() -> &'static $crate::gemm::Backend<GemmTy, PackTy> {
+            REGISTRY.get(init_backend)
+        }
+
+        /// Registers an additional backend, which becomes the active one if `priority` is at
+        /// least as high as the priority of the active backend. Built-in backends have priority
+        /// [`DEFAULT_BACKEND_PRIORITY`]($crate::gemm::DEFAULT_BACKEND_PRIORITY).
+        pub fn register_backend(
+            backend: &'static $crate::gemm::Backend<GemmTy, PackTy>,
+            priority: i32,
+        ) {
+            REGISTRY.register(backend, priority, init_backend)
+        }
+
+        /// Forgets the registered backends, so that the fastest built-in one is used again.
+        pub fn reset_backend() {
+            REGISTRY.reset()
+        }
+
+        /// Calls `f` with each built-in backend supported by the current machine, including the
+        /// ones that aren't selected by default, from the most portable to the fastest.
+        pub fn for_each_builtin_backend(
+            mut f: impl FnMut(&'static $crate::gemm::Backend<GemmTy, PackTy>),
+        ) {
+            f(&scalar::BACKEND);
+
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            {
+                if $crate::feature_detected!("fma") {
+                    f(&fma::BACKEND);
+                }
+                #[cfg(feature = "nightly")]
+                if $crate::feature_detected!("avx512f") {
+                    f(&avx512f::BACKEND);
+                }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            if $crate::feature_detected!("neon") {
+                f(&neon::BACKEND);
+                #[cfg(feature = "experimental-apple-amx")]
+                if $crate::cache::HasAmx::get() {
+                    f(&amx::BACKEND);
+                }
+            }
 
-        #[inline(never)]
-        fn init_gemm_ptr() -> GemmTy {
-            let gemm_fn = init_gemm_fn();
-            GEMM_PTR.store(gemm_fn as *mut (), ::core::sync::atomic::Ordering::Relaxed);
-            gemm_fn
+            #[cfg(target_arch = "wasm32")]
+            if $crate::feature_detected!("simd128") {
+                f(&simd128::BACKEND);
+            }
         }
 
         #[inline(always)]
         pub fn get_gemm_fn() -> GemmTy {
-            let mut gemm_fn = GEMM_PTR.load(::core::sync::atomic::Ordering::Relaxed);
-            if gemm_fn.is_null() {
-                gemm_fn = init_gemm_ptr() as *mut ();
-            }
-            unsafe { ::core::mem::transmute(gemm_fn) }
+            get_backend().gemm
         }
 
         $crate::__inject_mod!(scalar, $ty, 1, Scalar, false);
 
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        $crate::__inject_mod!(fma, $ty, 4 * $multiplier, V3, false);
+        $crate::__inject_mod!(fma, $ty, 4 * $multiplier, V3, false, false, shaped);
         #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
-        $crate::__inject_mod!(avx512f, $ty, 8 * $multiplier, V4, false);
+        $crate::__inject_mod!(avx512f, $ty, 8 * $multiplier, V4, false, true, shaped);
 
         #[cfg(target_arch = "aarch64")]
         $crate::__inject_mod!(neon, $ty, 2 * $multiplier, Scalar, false);
@@ -1049,17 +3049,28 @@ macro_rules! gemm_cplx_def {
             bool,
             bool,
             $crate::Parallelism,
+            $crate::gemm::GemmConfig<'_, num_complex::Complex<T>>,
+        );
+        type GemmTy = GemmCplxTy;
+        type PackTy = unsafe fn(
+            usize,
+            usize,
+            *mut num_complex::Complex<T>,
+            *const num_complex::Complex<T>,
+            isize,
+            isize,
+            usize,
         );
 
-        fn init_gemm_cplx_fn() -> GemmCplxTy {
+        fn init_backend() -> &'static $crate::gemm::Backend<GemmCplxTy, PackTy> {
             #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
             {
                 #[cfg(feature = "nightly")]
                 if $crate::feature_detected!("avx512f") {
-                    return avx512f_cplx::gemm_basic_cplx;
+                    return &avx512f_cplx::BACKEND;
                 }
                 if $crate::feature_detected!("fma") {
-                    return fma_cplx::gemm_basic_cplx;
+                    return &fma_cplx::BACKEND;
                 }
             }
 
@@ -1067,30 +3078,65 @@ macro_rules! gemm_cplx_def {
             {
                 #[cfg(target_arch = "aarch64")]
                 if $crate::feature_detected!("neon") && $crate::feature_detected!("fcma") {
-                    return neonfcma::gemm_basic;
+                    return &neonfcma::BACKEND;
                 }
             }
 
-            scalar_cplx::gemm_basic_cplx
+            &scalar_cplx::BACKEND
+        }
+
+        static REGISTRY: $crate::gemm::BackendRegistry<GemmCplxTy, PackTy> =
+            $crate::gemm::BackendRegistry::new();
+
+        /// Returns the registered backend with the highest priority, by default the fastest
+        /// built-in backend supported by the current machine.
+        #[inline(always)]
+        pub fn get_backend() -> &'static $crate::gemm::Backend<GemmCplxTy, PackTy> {
+            REGISTRY.get(init_backend)
+        }
+
+        /// Registers an additional backend, which becomes the active one if `priority` is at
+        /// least as high as the priority of the active backend. Built-in backends have priority
+        /// [`DEFAULT_BACKEND_PRIORITY`]($crate::gemm::DEFAULT_BACKEND_PRIORITY).
+        pub fn register_backend(
+            backend: &'static $crate::gemm::Backend<GemmCplxTy, PackTy>,
+            priority: i32,
+        ) {
+            REGISTRY.register(backend, priority, init_backend)
+        }
+
+        /// Forgets the registered backends, so that the fastest built-in one is used again.
+        pub fn reset_backend() {
+            REGISTRY.reset()
         }
 
-        static GEMM_PTR: ::core::sync::atomic::AtomicPtr<()> =
-            ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut());
+        /// Calls `f` with each built-in backend supported by the current machine, including the
+        /// ones that aren't selected by default, from the most portable to the fastest.
+        pub fn for_each_builtin_backend(
+            mut f: impl FnMut(&'static $crate::gemm::Backend<GemmCplxTy, PackTy>),
+        ) {
+            f(&scalar_cplx::BACKEND);
+
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            {
+                if $crate::feature_detected!("fma") {
+                    f(&fma_cplx::BACKEND);
+                }
+                #[cfg(feature = "nightly")]
+                if $crate::feature_detected!("avx512f") {
+                    f(&avx512f_cplx::BACKEND);
+                }
+            }
 
-        #[inline(never)]
-        fn init_gemm_ptr() -> GemmCplxTy {
-            let gemm_fn = init_gemm_cplx_fn();
-            GEMM_PTR.store(gemm_fn as *mut (), ::core::sync::atomic::Ordering::Relaxed);
-            gemm_fn
+            #[cfg(target_arch = "aarch64")]
+            if $crate::feature_detected!("neon") && $crate::feature_detected!("fcma") {
+                f(&neonfcma::BACKEND);
+            }
         }
 
         #[inline(always)]
         pub fn get_gemm_fn() -> GemmCplxTy {
-            let mut gemm_fn = GEMM_PTR.load(::core::sync::atomic::Ordering::Relaxed);
-            if gemm_fn.is_null() {
-                gemm_fn = init_gemm_ptr() as *mut ();
-            }
-            unsafe { ::core::mem::transmute(gemm_fn) }
+            get_backend().gemm
         }
 
         $crate::__inject_mod_cplx!(scalar, $ty, 1, Scalar);
@@ -585,3 +585,503 @@ pub unsafe fn mixed_gemv_rowmajor<
         );
     }
 }
+
+/// Largest number of dst columns for which a product is computed by [`mixed_gemv`] instead of
+/// the microkernels. It's the largest `nr` of the microkernels, so that the products that fit in
+/// a single column of tiles aren't packed.
+pub const GEMV_MAX_COLS: usize = 8;
+
+// number of accumulators per row or vector of rows for `n` columns or rows, so that there are
+// about 8 independent fma chains while the accumulators fit in 16 registers
+const fn unroll(n: usize) -> usize {
+    match n {
+        1 => 8,
+        2 => 4,
+        3 | 4 => 2,
+        _ => 1,
+    }
+}
+
+// computes `R` vectors of rows of `N` columns of dst. the accumulators stay in registers over the
+// whole depth, and each chunk of a lhs column is loaded once for all the columns.
+#[inline(always)]
+unsafe fn gemv_rows<
+    Lhs: Boilerplate + One + Zero,
+    Rhs: Boilerplate + One + Zero,
+    Dst: Boilerplate + One + Zero,
+    Acc: Boilerplate + One + Zero,
+    S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+    const N: usize,
+    const R: usize,
+>(
+    simd: S,
+    k: usize,
+    dst: *mut Dst,
+    dst_cs: isize,
+    lhs: *const Lhs,
+    lhs_cs: isize,
+    rhs: *const Rhs,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: Acc,
+    beta: Acc,
+) {
+    let lane = S::SIMD_WIDTH;
+
+    let mut acc = [[simd.simd_splat(Acc::zero()); R]; N];
+    for depth in 0..k {
+        let lhs = lhs.wrapping_offset(depth as isize * lhs_cs);
+        let rhs = rhs.wrapping_offset(depth as isize * rhs_rs);
+
+        let mut lhs_v = [simd.simd_splat(Acc::zero()); R];
+        for (r, lhs_v) in lhs_v.iter_mut().enumerate() {
+            *lhs_v = simd.simd_from_lhs(*(lhs.wrapping_add(r * lane) as *const S::LhsN));
+        }
+        for (col, acc) in acc.iter_mut().enumerate() {
+            let rhs = simd.simd_splat(simd.from_rhs(*rhs.wrapping_offset(col as isize * rhs_cs)));
+            for (acc, &lhs_v) in acc.iter_mut().zip(lhs_v.iter()) {
+                *acc = simd.simd_mult_add(lhs_v, rhs, *acc);
+            }
+        }
+    }
+
+    let alpha_v = simd.simd_splat(alpha);
+    let beta_v = simd.simd_splat(beta);
+    for (col, acc) in acc.iter().enumerate() {
+        let dst = dst.wrapping_offset(col as isize * dst_cs);
+        for (r, &acc) in acc.iter().enumerate() {
+            let dst = dst.wrapping_add(r * lane) as *mut S::DstN;
+            let acc = simd.simd_mul(acc, beta_v);
+            if alpha.is_zero() {
+                *dst = simd.simd_into_dst(acc);
+            } else {
+                *dst =
+                    simd.simd_into_dst(simd.simd_mult_add(simd.simd_from_dst(*dst), alpha_v, acc));
+            }
+        }
+    }
+}
+
+// computes the rows of `N` columns of dst, in blocks of `R` vectors, then single vectors, then
+// single rows
+#[inline(always)]
+unsafe fn gemv_cols<
+    Lhs: Boilerplate + One + Zero,
+    Rhs: Boilerplate + One + Zero,
+    Dst: Boilerplate + One + Zero,
+    Acc: Boilerplate + One + Zero,
+    S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+    const N: usize,
+    const R: usize,
+>(
+    simd: S,
+    m: usize,
+    k: usize,
+    dst: *mut Dst,
+    dst_cs: isize,
+    lhs: *const Lhs,
+    lhs_cs: isize,
+    rhs: *const Rhs,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: Acc,
+    beta: Acc,
+) {
+    let lane = S::SIMD_WIDTH;
+    let m_block = m / (R * lane) * (R * lane);
+    let m_lane = m / lane * lane;
+
+    let mut row = 0;
+    while row < m_block {
+        gemv_rows::<_, _, _, _, _, N, R>(
+            simd,
+            k,
+            dst.wrapping_add(row),
+            dst_cs,
+            lhs.wrapping_add(row),
+            lhs_cs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+        );
+        row += R * lane;
+    }
+    while row < m_lane {
+        gemv_rows::<_, _, _, _, _, N, 1>(
+            simd,
+            k,
+            dst.wrapping_add(row),
+            dst_cs,
+            lhs.wrapping_add(row),
+            lhs_cs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+        );
+        row += lane;
+    }
+    while row < m {
+        let lhs = lhs.wrapping_add(row);
+        let mut acc = [Acc::zero(); N];
+        for depth in 0..k {
+            let lhs0 = simd.from_lhs(*lhs.wrapping_offset(depth as isize * lhs_cs));
+            let rhs = rhs.wrapping_offset(depth as isize * rhs_rs);
+            for (col, acc) in acc.iter_mut().enumerate() {
+                let rhs0 = simd.from_rhs(*rhs.wrapping_offset(col as isize * rhs_cs));
+                *acc = simd.mult_add(lhs0, rhs0, *acc);
+            }
+        }
+        for (col, &acc) in acc.iter().enumerate() {
+            let dst = dst.wrapping_add(row).wrapping_offset(col as isize * dst_cs);
+            if alpha.is_zero() {
+                *dst = simd.into_dst(simd.mult(acc, beta));
+            } else {
+                *dst = simd.into_dst(
+                    simd.add(simd.mult(acc, beta), simd.mult(simd.from_dst(*dst), alpha)),
+                );
+            }
+        }
+        row += 1;
+    }
+}
+
+// dst, lhs are colmajor
+// n is small
+//
+// unlike `mixed_gemv_colmajor`, which reads and writes dst once per column of lhs, the
+// accumulators of up to 8 columns of dst stay in registers over the whole depth.
+#[inline(always)]
+pub unsafe fn mixed_gemv<
+    Lhs: Boilerplate + One + Zero,
+    Rhs: Boilerplate + One + Zero,
+    Dst: Boilerplate + One + Zero,
+    Acc: Boilerplate + One + Zero,
+    S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+>(
+    simd: S,
+
+    m: usize,
+    n: usize,
+    k: usize,
+
+    dst: *mut Dst,
+    dst_cs: isize,
+    dst_rs: isize,
+
+    lhs: *const Lhs,
+    lhs_cs: isize,
+    lhs_rs: isize,
+
+    rhs: *const Rhs,
+    rhs_cs: isize,
+    rhs_rs: isize,
+
+    alpha: Acc,
+    beta: Acc,
+) {
+    #[allow(dead_code)]
+    struct Impl<Lhs, Rhs, Dst, Acc, S> {
+        simd: S,
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: *mut Dst,
+        dst_cs: isize,
+        lhs: *const Lhs,
+        lhs_cs: isize,
+        rhs: *const Rhs,
+        rhs_cs: isize,
+        rhs_rs: isize,
+        alpha: Acc,
+        beta: Acc,
+    }
+    impl<
+            Lhs: Boilerplate + One + Zero,
+            Rhs: Boilerplate + One + Zero,
+            Dst: Boilerplate + One + Zero,
+            Acc: Boilerplate + One + Zero,
+            S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+        > pulp::NullaryFnOnce for Impl<Lhs, Rhs, Dst, Acc, S>
+    {
+        type Output = ();
+
+        #[inline(always)]
+        fn call(self) -> Self::Output {
+            unsafe {
+                let Self {
+                    simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    lhs,
+                    lhs_cs,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                } = self;
+
+                let mut col = 0;
+                while col < n {
+                    let dst = dst.wrapping_offset(col as isize * dst_cs);
+                    let rhs = rhs.wrapping_offset(col as isize * rhs_cs);
+                    let cols = Ord::min(n - col, GEMV_MAX_COLS);
+                    seq!(COLS in 1..=8 {
+                        match cols {
+                            #(
+                                COLS => gemv_cols::<_, _, _, _, _, COLS, { unroll(COLS) }>(
+                                    simd, m, k, dst, dst_cs, lhs, lhs_cs, rhs, rhs_cs, rhs_rs,
+                                    alpha, beta,
+                                ),
+                            )*
+                            _ => unreachable!(),
+                        }
+                    });
+                    col += cols;
+                }
+            }
+        }
+    }
+
+    assert_eq!(lhs_rs, 1);
+    assert_eq!(dst_rs, 1);
+
+    simd.vectorize(Impl {
+        simd,
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        lhs,
+        lhs_cs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+    })
+}
+
+/// Largest number of lhs rows for which a product is computed by [`mixed_gevm`] instead of the
+/// microkernels.
+pub const GEVM_MAX_ROWS: usize = 8;
+
+// computes `M` rows of a column of dst, loading each chunk of the rhs column once for all of them.
+// each row has `U` accumulators, so that there are enough independent fma chains to hide their
+// latency.
+#[inline(always)]
+unsafe fn gevm_rows<
+    Lhs: Boilerplate + One + Zero,
+    Rhs: Boilerplate + One + Zero,
+    Dst: Boilerplate + One + Zero,
+    Acc: Boilerplate + One + Zero,
+    S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+    const M: usize,
+    const U: usize,
+>(
+    simd: S,
+    k: usize,
+    dst: *mut Dst,
+    dst_rs: isize,
+    lhs: *const Lhs,
+    lhs_rs: isize,
+    rhs: *const Rhs,
+    alpha: Acc,
+    beta: Acc,
+) {
+    let lane = S::SIMD_WIDTH;
+    let lane_u = U * S::SIMD_WIDTH;
+
+    let k_lane = k / lane * lane;
+    let k_lane_u = k / lane_u * lane_u;
+
+    let mut depth = 0;
+
+    let mut acc = [[simd.simd_splat(Acc::zero()); U]; M];
+    while depth < k_lane_u {
+        for u in 0..U {
+            let rhs = simd.simd_from_rhs(*(rhs.wrapping_add(depth + lane * u) as *const S::RhsN));
+            for (row, acc) in acc.iter_mut().enumerate() {
+                let lhs = lhs.wrapping_offset(row as isize * lhs_rs);
+                let lhs = *(lhs.wrapping_add(depth + lane * u) as *const S::LhsN);
+                acc[u] = simd.simd_mult_add(simd.simd_from_lhs(lhs), rhs, acc[u]);
+            }
+        }
+        depth += lane_u;
+    }
+
+    let mut sum = [simd.simd_splat(Acc::zero()); M];
+    for (sum, acc) in sum.iter_mut().zip(acc.iter()) {
+        for &acc in acc {
+            *sum = simd.simd_add(*sum, acc);
+        }
+    }
+
+    while depth < k_lane {
+        let rhs = simd.simd_from_rhs(*(rhs.wrapping_add(depth) as *const S::RhsN));
+        for (row, sum) in sum.iter_mut().enumerate() {
+            let lhs = lhs.wrapping_offset(row as isize * lhs_rs);
+            let lhs = *(lhs.wrapping_add(depth) as *const S::LhsN);
+            *sum = simd.simd_mult_add(simd.simd_from_lhs(lhs), rhs, *sum);
+        }
+        depth += lane;
+    }
+
+    for (row, sum) in sum.iter().enumerate() {
+        let lhs = lhs.wrapping_offset(row as isize * lhs_rs);
+
+        let acc_ptr = sum as *const S::AccN as *const Acc;
+        let mut acc0 = *acc_ptr;
+        for x in 1..S::SIMD_WIDTH {
+            acc0 = simd.add(acc0, *acc_ptr.add(x));
+        }
+
+        for depth in depth..k {
+            let lhs0 = *lhs.wrapping_add(depth);
+            let rhs0 = *rhs.wrapping_add(depth);
+            acc0 = simd.mult_add(simd.from_lhs(lhs0), simd.from_rhs(rhs0), acc0);
+        }
+
+        let dst = dst.wrapping_offset(dst_rs * row as isize);
+        if alpha.is_zero() {
+            *dst = simd.into_dst(simd.mult(acc0, beta));
+        } else {
+            *dst = simd
+                .into_dst(simd.add(simd.mult(acc0, beta), simd.mult(simd.from_dst(*dst), alpha)));
+        }
+    }
+}
+
+// lhs is rowmajor
+// rhs is colmajor
+// m is small
+//
+// unlike `mixed_gemv_rowmajor` with the operands swapped, each chunk of a rhs column is loaded
+// once for up to 8 rows of lhs, which stay in the caches while the columns are streamed.
+#[inline(always)]
+pub unsafe fn mixed_gevm<
+    Lhs: Boilerplate + One + Zero,
+    Rhs: Boilerplate + One + Zero,
+    Dst: Boilerplate + One + Zero,
+    Acc: Boilerplate + One + Zero,
+    S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+>(
+    simd: S,
+
+    m: usize,
+    n: usize,
+    k: usize,
+
+    dst: *mut Dst,
+    dst_cs: isize,
+    dst_rs: isize,
+
+    lhs: *const Lhs,
+    lhs_cs: isize,
+    lhs_rs: isize,
+
+    rhs: *const Rhs,
+    rhs_cs: isize,
+    rhs_rs: isize,
+
+    alpha: Acc,
+    beta: Acc,
+) {
+    #[allow(dead_code)]
+    struct Impl<Lhs, Rhs, Dst, Acc, S> {
+        simd: S,
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: *mut Dst,
+        dst_cs: isize,
+        dst_rs: isize,
+        lhs: *const Lhs,
+        lhs_rs: isize,
+        rhs: *const Rhs,
+        rhs_cs: isize,
+        alpha: Acc,
+        beta: Acc,
+    }
+    impl<
+            Lhs: Boilerplate + One + Zero,
+            Rhs: Boilerplate + One + Zero,
+            Dst: Boilerplate + One + Zero,
+            Acc: Boilerplate + One + Zero,
+            S: MixedSimd<Lhs, Rhs, Dst, Acc>,
+        > pulp::NullaryFnOnce for Impl<Lhs, Rhs, Dst, Acc, S>
+    {
+        type Output = ();
+
+        #[inline(always)]
+        fn call(self) -> Self::Output {
+            unsafe {
+                let Self {
+                    simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    lhs,
+                    lhs_rs,
+                    rhs,
+                    rhs_cs,
+                    alpha,
+                    beta,
+                } = self;
+
+                for col in 0..n {
+                    let dst = dst.wrapping_offset(col as isize * dst_cs);
+                    let rhs = rhs.wrapping_offset(col as isize * rhs_cs);
+
+                    let mut row = 0;
+                    while row < m {
+                        let dst = dst.wrapping_offset(row as isize * dst_rs);
+                        let lhs = lhs.wrapping_offset(row as isize * lhs_rs);
+                        let rows = Ord::min(m - row, GEVM_MAX_ROWS);
+                        seq!(ROWS in 1..=8 {
+                            match rows {
+                                #(
+                                    ROWS => gevm_rows::<_, _, _, _, _, ROWS, { unroll(ROWS) }>(
+                                        simd, k, dst, dst_rs, lhs, lhs_rs, rhs, alpha, beta,
+                                    ),
+                                )*
+                                _ => unreachable!(),
+                            }
+                        });
+                        row += rows;
+                    }
+                }
+            }
+        }
+    }
+
+    assert_eq!(lhs_cs, 1);
+    assert_eq!(rhs_rs, 1);
+
+    simd.vectorize(Impl {
+        simd,
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        lhs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        alpha,
+        beta,
+    })
+}
@@ -26,21 +26,73 @@ pub fn set_wasm_simd128(enable: bool) {
 
 extern crate alloc;
 
+pub use dyn_stack;
+
+/// Emits a `tracing` event of the given level with the `tracing` feature, and nothing otherwise.
+macro_rules! trace_event {
+    ($level: ident, $($arg: tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)*);
+    };
+}
+
+/// Calls the given function of [`stats`] with the `stats` feature, and nothing otherwise.
+macro_rules! record_stat {
+    ($($f: ident)::+ ($($arg: expr),*)) => {
+        #[cfg(feature = "stats")]
+        crate::stats::$($f)::+($($arg),*);
+    };
+}
+
+#[cfg(feature = "std")]
+pub mod affinity;
 pub mod cache;
+pub mod denormals;
 
 pub mod gemm;
 pub mod gemv;
 pub mod gevv;
 
 pub mod microkernel;
+pub mod numa;
 pub mod pack_operands;
+#[cfg(feature = "std")]
+pub mod pool;
+pub mod presets;
 pub mod simd;
-
-#[derive(Copy, Clone, Debug)]
-pub enum Parallelism {
+pub mod spawner;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod tuning;
+
+/// Threads that the parallel regions of a multiplication run on. It's given explicitly to each
+/// call, so that single-threaded calls state it, and new kinds of pools can be added without
+/// changing the signatures.
+#[derive(Copy, Clone)]
+pub enum Parallelism<'a> {
+    /// Runs everything on the calling thread.
     None,
+    /// Runs the parallel regions on rayon, using up to the given number of threads, or up to all
+    /// the threads of the current pool if it's `0`. In the latter case, a call made from a rayon
+    /// task while other tasks are queued on the same worker, such as one item of a parallel
+    /// iterator, runs on that worker only, since the pool is already busy.
     #[cfg(feature = "rayon")]
     Rayon(usize),
+    /// Runs the parallel regions on a pool provided by the caller, using up to all of its
+    /// threads. The pool only needs to outlive the call, so it may be created for it.
+    Custom(&'a dyn spawner::ThreadSpawner),
+}
+
+impl core::fmt::Debug for Parallelism<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Parallelism::None => f.write_str("None"),
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(n_threads) => f.debug_tuple("Rayon").field(n_threads).finish(),
+            Parallelism::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
 }
 
 pub struct Ptr<T: ?Sized>(pub *mut T);
@@ -91,6 +91,18 @@ macro_rules! microkernel_fn_array {
         pub const UKR: [[$crate::microkernel::MicroKernelFn<T>; NR]; MR_DIV_N] =
             [ $([$($ukr,)*]),* ];
     };
+    // additional register blocking, selected by the drivers from the shape of the problem
+    ($mr_div_n: ident, $nr: ident, $name: ident; $([
+       $($ukr: ident,)*
+    ],)*) => {
+       pub const $mr_div_n: usize =
+           $crate::__microkernel_fn_array_helper!([$([$($ukr,)*],)*]);
+       pub const $nr: usize =
+           $crate::__microkernel_fn_array_helper_nr!($([$($ukr,)*],)*);
+
+        pub const $name: [[$crate::microkernel::MicroKernelFn<T>; $nr]; $mr_div_n] =
+            [ $([$($ukr,)*]),* ];
+    };
 }
 
 #[macro_export]
@@ -347,9 +359,103 @@ macro_rules! microkernel_amx {
     };
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __microkernel_has_flag {
+    ($flag: ident;) => {
+        false
+    };
+    (masked; masked $(, $rest: ident)*) => {
+        true
+    };
+    (aligned; aligned $(, $rest: ident)*) => {
+        true
+    };
+    ($flag: ident; $other: ident $(, $rest: ident)*) => {
+        $crate::__microkernel_has_flag!($flag; $($rest),*)
+    };
+}
+
+// loads and stores a vector of the block, where the kernels declared as `masked` only access the
+// lanes of `mask`, and the others access the whole vector
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __microkernel_load {
+    (masked $(, $rest: ident)*; $ptr: expr, $mask: expr) => {
+        masked_load($ptr, $mask)
+    };
+    ($other: ident $(, $rest: ident)*; $ptr: expr, $mask: expr) => {
+        $crate::__microkernel_load!($($rest),*; $ptr, $mask)
+    };
+    (; $ptr: expr, $mask: expr) => {{
+        let _ = $mask;
+        *($ptr as *const Pack)
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __microkernel_store {
+    (masked $(, $rest: ident)*; $ptr: expr, $mask: expr, $value: expr) => {
+        masked_store($ptr, $mask, $value)
+    };
+    ($other: ident $(, $rest: ident)*; $ptr: expr, $mask: expr, $value: expr) => {
+        $crate::__microkernel_store!($($rest),*; $ptr, $mask, $value)
+    };
+    (; $ptr: expr, $mask: expr, $value: expr) => {{
+        let _ = $mask;
+        ($ptr as *mut Pack).write_unaligned($value)
+    }};
+}
+
+// loads and stores a whole vector, with aligned accesses for the kernels declared as `aligned`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __microkernel_load_aligned {
+    (aligned $(, $rest: ident)*; $ptr: expr) => {
+        load_aligned($ptr)
+    };
+    ($other: ident $(, $rest: ident)*; $ptr: expr) => {
+        $crate::__microkernel_load_aligned!($($rest),*; $ptr)
+    };
+    (; $ptr: expr) => {
+        *($ptr as *const Pack)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __microkernel_store_aligned {
+    (aligned $(, $rest: ident)*; $ptr: expr, $value: expr) => {
+        store_aligned($ptr, $value)
+    };
+    ($other: ident $(, $rest: ident)*; $ptr: expr, $value: expr) => {
+        $crate::__microkernel_store_aligned!($($rest),*; $ptr, $value)
+    };
+    (; $ptr: expr, $value: expr) => {
+        ($ptr as *mut Pack).write_unaligned($value)
+    };
+}
+
+/// Number of accumulators below which the microkernels alternate between two sets of them along
+/// the depth. With two fma units and a latency of 4 cycles, 8 independent fma chains keep both
+/// units busy.
+pub const MIN_FMA_CHAINS: usize = 8;
+
+// kernels can be declared with a trailing list of flags, such as `; masked, aligned`.
+//
+// kernels declared as `masked` read the rows of a partial block with masked loads and write them
+// with masked stores, so that neither the lhs nor the destination needs to be padded to a whole
+// number of vectors. the module must then provide `masked_load(*const T, u32) -> Pack` and
+// `masked_store(*mut T, u32, Pack)`.
+//
+// kernels declared as `aligned` check on entry whether the columns of the lhs, and those of the
+// destination for a full block, start on a multiple of the size of a vector, as the packed lhs
+// panels do, and then access them with aligned loads and stores. the module must then provide
+// `load_aligned(*const T) -> Pack` and `store_aligned(*mut T, Pack)`.
 #[macro_export]
 macro_rules! microkernel {
-    ($([$target: tt])?, $unroll: tt, $name: ident, $mr_div_n: tt, $nr: tt $(, $nr_div_n: tt, $n: tt)?) => {
+    ($([$target: tt])?, $unroll: tt, $name: ident, $mr_div_n: tt, $nr: tt $(, $nr_div_n: tt, $n: tt)? $(; $($flag: ident),+)?) => {
         $(#[target_feature(enable = $target)])?
         // 0, 1, or 2 for generic alpha
         pub unsafe fn $name(
@@ -372,12 +478,32 @@ macro_rules! microkernel {
             _conj_rhs: bool,
             mut next_lhs: *const T,
         ) {
-            let mut accum_storage = [[splat(::core::mem::zeroed()); $mr_div_n]; $nr];
+            // blocks with few accumulators alternate between two sets of them along the depth,
+            // so that there are enough independent fma chains to hide the latency
+            const ACCUM_SETS: usize =
+                if $mr_div_n * $nr < $crate::microkernel::MIN_FMA_CHAINS { 2 } else { 1 };
+            const ACCUM_LEN: usize = $mr_div_n * $nr;
+
+            let mut accum_storage = [[[splat(::core::mem::zeroed()); $mr_div_n]; $nr]; ACCUM_SETS];
             let accum = accum_storage.as_mut_ptr() as *mut Pack;
 
             let mut lhs = [::core::mem::MaybeUninit::<Pack>::uninit(); $mr_div_n];
             let mut rhs = ::core::mem::MaybeUninit::<Pack>::uninit();
 
+            // lanes of each lhs vector that hold rows of the block
+            let mut lhs_masks = [0u32; $mr_div_n];
+            seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                let lanes = Ord::min(m.saturating_sub(M_ITER * N), N) as u32;
+                lhs_masks[M_ITER] = 1u32.checked_shl(lanes).map_or(u32::MAX, |bit| bit - 1);
+            }});
+
+            const PACK_BYTES: usize = ::core::mem::size_of::<Pack>();
+            // the masked loads of the lhs don't have an aligned variant
+            let lhs_aligned = $crate::__microkernel_has_flag!(aligned; $($($flag),+)?)
+                && !$crate::__microkernel_has_flag!(masked; $($($flag),+)?)
+                && packed_lhs as usize % PACK_BYTES == 0
+                && (lhs_cs as usize).wrapping_mul(::core::mem::size_of::<T>()) % PACK_BYTES == 0;
+
             #[derive(Copy, Clone)]
             struct KernelIter {
                 packed_lhs: *const T,
@@ -389,22 +515,30 @@ macro_rules! microkernel {
                 accum: *mut Pack,
                 lhs: *mut Pack,
                 rhs: *mut Pack,
+                lhs_masks: [u32; $mr_div_n],
             }
 
             impl KernelIter {
                 #[inline(always)]
-                unsafe fn execute(self, iter: usize) {
+                unsafe fn execute(self, iter: usize, lhs_aligned: bool) {
                     let packed_lhs = self.packed_lhs.wrapping_offset(iter as isize * self.lhs_cs);
                     let packed_rhs = self.packed_rhs.wrapping_offset(iter as isize * self.rhs_rs);
                     let next_lhs = self.next_lhs.wrapping_offset(iter as isize * self.lhs_cs);
 
                     seq_macro::seq!(M_ITER in 0..$mr_div_n {{
-                        *self.lhs.add(M_ITER) = *(packed_lhs.add(M_ITER * N) as *const Pack);
+                        let ptr = packed_lhs.add(M_ITER * N);
+                        *self.lhs.add(M_ITER) = if lhs_aligned {
+                            $crate::__microkernel_load_aligned!($($($flag),+)?; ptr)
+                        } else {
+                            $crate::__microkernel_load!($($($flag),+)?; ptr, self.lhs_masks[M_ITER])
+                        };
                     }});
 
+                    let set = if ACCUM_SETS == 2 { iter & 1 } else { 0 };
+                    let accum = self.accum.add(set * ACCUM_LEN);
                     seq_macro::seq!(N_ITER in 0..$nr {{
                         *self.rhs = splat(*packed_rhs.wrapping_offset(N_ITER * self.rhs_cs));
-                        let accum = self.accum.add(N_ITER * $mr_div_n);
+                        let accum = accum.add(N_ITER * $mr_div_n);
                         seq_macro::seq!(M_ITER in 0..$mr_div_n {{
                             let accum = &mut *accum.add(M_ITER);
                             *accum = mul_add(
@@ -427,12 +561,14 @@ macro_rules! microkernel {
 
                         load::<$mr_div_n>(self.lhs, packed_lhs);
 
+                        let set = if ACCUM_SETS == 2 { iter & 1 } else { 0 };
+                        let accum = self.accum.add(set * ACCUM_LEN);
                         seq_macro::seq!(N_ITER0 in 0..$nr_div_n {{
                             *self.rhs = *(packed_rhs.wrapping_offset(N_ITER0 * $n) as *const Pack);
 
                             seq_macro::seq!(N_ITER1 in 0..$n {{
                                 const N_ITER: usize = N_ITER0 * $n + N_ITER1;
-                                let accum = self.accum.add(N_ITER * $mr_div_n);
+                                let accum = accum.add(N_ITER * $mr_div_n);
                                 seq_macro::seq!(M_ITER in 0..$mr_div_n {{
                                     let accum = &mut *accum.add(M_ITER);
                                     *accum = mul_add_lane::<N_ITER1>(
@@ -452,7 +588,7 @@ macro_rules! microkernel {
 
             let mut main_loop = {
                 #[inline(always)]
-                || {
+                |lhs_aligned: bool| {
                     loop {
                         $(
                         let _ = $nr_div_n;
@@ -470,6 +606,7 @@ macro_rules! microkernel {
                                         accum,
                                         lhs: lhs.as_mut_ptr() as _,
                                         rhs: &mut rhs as *mut _ as _,
+                                        lhs_masks,
                                     };
 
                                     seq_macro::seq!(UNROLL_ITER in 0..$unroll {{
@@ -499,6 +636,7 @@ macro_rules! microkernel {
                                         accum,
                                         lhs: lhs.as_mut_ptr() as _,
                                         rhs: &mut rhs as *mut _ as _,
+                                        lhs_masks,
                                     }
                                     .execute_neon(0);
 
@@ -529,10 +667,11 @@ macro_rules! microkernel {
                                     accum,
                                     lhs: lhs.as_mut_ptr() as _,
                                     rhs: &mut rhs as *mut _ as _,
+                                    lhs_masks,
                                 };
 
                                 seq_macro::seq!(UNROLL_ITER in 0..$unroll {{
-                                    iter.execute(UNROLL_ITER);
+                                    iter.execute(UNROLL_ITER, lhs_aligned);
                                 }});
 
                                 packed_lhs = packed_lhs.wrapping_offset($unroll * lhs_cs);
@@ -558,8 +697,9 @@ macro_rules! microkernel {
                                     accum,
                                     lhs: lhs.as_mut_ptr() as _,
                                     rhs: &mut rhs as *mut _ as _,
+                                    lhs_masks,
                                 }
-                                .execute(0);
+                                .execute(0, lhs_aligned);
 
                                 packed_lhs = packed_lhs.wrapping_offset(lhs_cs);
                                 packed_rhs = packed_rhs.wrapping_offset(rhs_rs);
@@ -576,42 +716,110 @@ macro_rules! microkernel {
                 }
             };
 
-            if rhs_rs == 1 {
-                main_loop();
+            if lhs_aligned {
+                if rhs_rs == 1 {
+                    main_loop(true);
+                } else {
+                    main_loop(true);
+                }
+            } else if rhs_rs == 1 {
+                main_loop(false);
             } else {
-                main_loop();
+                main_loop(false);
+            }
+
+            if ACCUM_SETS == 2 {
+                seq_macro::seq!(N_ITER in 0..$nr {{
+                    seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                        let accum = accum.add(M_ITER + $mr_div_n * N_ITER);
+                        *accum = add(*accum, *accum.add(ACCUM_LEN));
+                    }});
+                }});
             }
 
             if m == $mr_div_n * N && n == $nr && dst_rs == 1  {
                 let alpha = splat(alpha);
                 let beta = splat(beta);
-                if alpha_status == 2 {
-                    seq_macro::seq!(N_ITER in 0..$nr {{
-                        seq_macro::seq!(M_ITER in 0..$mr_div_n {{
-                            let dst = dst.offset(M_ITER * N as isize + N_ITER * dst_cs) as *mut Pack;
-                            dst.write_unaligned(add(
-                                    mul(alpha, *dst),
-                                    mul(beta, *accum.offset(M_ITER + $mr_div_n * N_ITER)),
-                                    ));
-                        }});
-                    }});
-                } else if alpha_status == 1 {
-                    seq_macro::seq!(N_ITER in 0..$nr {{
-                        seq_macro::seq!(M_ITER in 0..$mr_div_n {{
-                            let dst = dst.offset(M_ITER * N as isize + N_ITER * dst_cs) as *mut Pack;
-                            dst.write_unaligned(mul_add(
-                                    beta,
-                                    *accum.offset(M_ITER + $mr_div_n * N_ITER),
-                                    *dst,
-                                    ));
-                        }});
-                    }});
+                let dst_aligned = $crate::__microkernel_has_flag!(aligned; $($($flag),+)?)
+                    && dst as usize % PACK_BYTES == 0
+                    && (dst_cs as usize).wrapping_mul(::core::mem::size_of::<T>()) % PACK_BYTES == 0;
+
+                let write_block = {
+                    #[inline(always)]
+                    |dst_aligned: bool| {
+                        let load = {
+                            #[inline(always)]
+                            |dst: *mut T| if dst_aligned {
+                                $crate::__microkernel_load_aligned!($($($flag),+)?; dst)
+                            } else {
+                                *(dst as *const Pack)
+                            }
+                        };
+                        let store = {
+                            #[inline(always)]
+                            |dst: *mut T, value: Pack| if dst_aligned {
+                                $crate::__microkernel_store_aligned!($($($flag),+)?; dst, value)
+                            } else {
+                                (dst as *mut Pack).write_unaligned(value)
+                            }
+                        };
+
+                        if alpha_status == 2 {
+                            seq_macro::seq!(N_ITER in 0..$nr {{
+                                seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                                    let dst = dst.offset(M_ITER * N as isize + N_ITER * dst_cs);
+                                    store(dst, add(
+                                            mul(alpha, load(dst)),
+                                            mul(beta, *accum.offset(M_ITER + $mr_div_n * N_ITER)),
+                                            ));
+                                }});
+                            }});
+                        } else if alpha_status == 1 {
+                            seq_macro::seq!(N_ITER in 0..$nr {{
+                                seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                                    let dst = dst.offset(M_ITER * N as isize + N_ITER * dst_cs);
+                                    store(dst, mul_add(
+                                            beta,
+                                            *accum.offset(M_ITER + $mr_div_n * N_ITER),
+                                            load(dst),
+                                            ));
+                                }});
+                            }});
+                        } else {
+                            seq_macro::seq!(N_ITER in 0..$nr {{
+                                seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                                    let dst = dst.offset(M_ITER * N as isize + N_ITER * dst_cs);
+                                    store(dst, mul(beta, *accum.offset(M_ITER + $mr_div_n * N_ITER)));
+                                }});
+                            }});
+                        }
+                    }
+                };
+
+                if dst_aligned {
+                    write_block(true);
                 } else {
-                    seq_macro::seq!(N_ITER in 0..$nr {{
-                        seq_macro::seq!(M_ITER in 0..$mr_div_n {{
-                            let dst = dst.offset(M_ITER * N as isize + N_ITER * dst_cs) as *mut Pack;
-                            dst.write_unaligned(mul(beta, *accum.offset(M_ITER + $mr_div_n * N_ITER)));
-                        }});
+                    write_block(false);
+                }
+            } else if $crate::__microkernel_has_flag!(masked; $($($flag),+)?) && dst_rs == 1 {
+                let alpha = splat(alpha);
+                let beta = splat(beta);
+                for j in 0..n {
+                    seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                        let dst = dst.offset(M_ITER * N as isize + j as isize * dst_cs);
+                        let mask = lhs_masks[M_ITER];
+                        let accum = *accum.add(M_ITER + $mr_div_n * j);
+                        let value = if alpha_status == 2 {
+                            add(
+                                mul(alpha, $crate::__microkernel_load!($($($flag),+)?; dst, mask)),
+                                mul(beta, accum),
+                            )
+                        } else if alpha_status == 1 {
+                            mul_add(beta, accum, $crate::__microkernel_load!($($($flag),+)?; dst, mask))
+                        } else {
+                            mul(beta, accum)
+                        };
+                        $crate::__microkernel_store!($($($flag),+)?; dst, mask, value);
                     }});
                 }
             } else {
@@ -0,0 +1,72 @@
+//! NUMA topology of the machine.
+//!
+//! On machines with several NUMA nodes, the panels that are shared by every thread are packed by
+//! all of them, so that their pages are spread between the nodes on first touch instead of all
+//! living on the node of the calling thread. The panels that are private to a thread are already
+//! packed by that thread, in a buffer that it allocated.
+//!
+//! The packed rhs panels are still shared, and read by every thread from the node they were
+//! packed on. With [`set_replicate_rhs`], each thread instead packs its own copy of the panels it
+//! reads, in its own buffer, which trades memory and packing work for local reads.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Detects the number of nodes.
+pub const DEFAULT_NUMA_NODES: usize = 0;
+
+static NUMA_NODES: AtomicUsize = AtomicUsize::new(DEFAULT_NUMA_NODES);
+
+pub const DEFAULT_REPLICATE_RHS: bool = false;
+
+static REPLICATE_RHS: AtomicBool = AtomicBool::new(DEFAULT_REPLICATE_RHS);
+
+/// Number of NUMA nodes the drivers assume, which is the detected one unless it was overridden
+/// by [`set_numa_nodes`].
+#[inline]
+pub fn get_numa_nodes() -> usize {
+    match NUMA_NODES.load(Ordering::Relaxed) {
+        0 => detected_numa_nodes(),
+        n => n,
+    }
+}
+/// Overrides the number of NUMA nodes, or restores the detected one if `value` is `0`.
+#[inline]
+pub fn set_numa_nodes(value: usize) {
+    NUMA_NODES.store(value, Ordering::Relaxed);
+}
+
+/// Whether each thread packs its own copy of the rhs panels it reads, instead of reading the
+/// panels packed once for all threads.
+#[inline]
+pub fn get_replicate_rhs() -> bool {
+    REPLICATE_RHS.load(Ordering::Relaxed)
+}
+/// Enables or disables the replication of the packed rhs panels between threads.
+#[inline]
+pub fn set_replicate_rhs(enable: bool) {
+    REPLICATE_RHS.store(enable, Ordering::Relaxed);
+}
+
+/// Number of NUMA nodes of the machine, or `1` if it can't be detected.
+pub fn detected_numa_nodes() -> usize {
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    {
+        static DETECTED: once_cell::race::OnceNonZeroUsize =
+            once_cell::race::OnceNonZeroUsize::new();
+        DETECTED
+            .get_or_init(|| {
+                let count = std::fs::read_to_string("/sys/devices/system/node/online")
+                    .ok()
+                    .and_then(|list| crate::affinity::parse_core_list(&list))
+                    .map(|nodes| nodes.len())
+                    .unwrap_or(1);
+                core::num::NonZeroUsize::new(count)
+                    .unwrap_or_else(|| core::num::NonZeroUsize::new(1).unwrap())
+            })
+            .get()
+    }
+    #[cfg(not(all(feature = "std", target_os = "linux")))]
+    {
+        1
+    }
+}
@@ -1,4 +1,7 @@
+use crate::gemm::Conj;
 use crate::simd::Simd;
+use core::ops::Mul;
+use num_traits::Zero;
 
 #[inline(always)]
 pub fn quick_zero<T: Copy>(slice: &mut [core::mem::MaybeUninit<T>]) {
@@ -301,3 +304,107 @@ pub unsafe fn pack_rhs<T: Copy, const N: usize, const NR: usize, S: Simd>(
         || pack_generic::<T, N, NR>(n, k, dst, src, src_rs, src_cs, dst_stride),
     );
 }
+
+/// Same as `pack_generic`, where the `i`-th row of `src` is multiplied by `scale[i * scale_inc]`,
+/// or by its conjugate if `conj_scale` is true.
+#[inline(always)]
+unsafe fn pack_scaled<T: Copy + Zero + Conj + Mul<Output = T>, const DST_WIDTH: usize>(
+    m: usize,
+    k: usize,
+    mut dst: *mut T,
+    mut src: *const T,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+    mut scale: *const T,
+    scale_inc: isize,
+    conj_scale: bool,
+) {
+    let mut i = 0;
+    while i < m {
+        let width = Ord::min(DST_WIDTH, m - i);
+
+        let mut factors = [T::zero(); DST_WIDTH];
+        for (j, factor) in factors[..width].iter_mut().enumerate() {
+            let value = *scale.offset(j as isize * scale_inc);
+            *factor = if conj_scale { value.conj() } else { value };
+        }
+
+        let mut src_col = src;
+        let mut dst_col = dst;
+        for _ in 0..k {
+            for (j, &factor) in factors[..width].iter().enumerate() {
+                *dst_col.add(j) = *src_col.offset(j as isize * src_rs) * factor;
+            }
+            quick_zero::<T>(core::slice::from_raw_parts_mut(
+                dst_col.add(width) as _,
+                DST_WIDTH - width,
+            ));
+            src_col = src_col.wrapping_offset(src_cs);
+            dst_col = dst_col.add(DST_WIDTH);
+        }
+
+        src = src.wrapping_offset(src_rs * DST_WIDTH as isize);
+        scale = scale.wrapping_offset(scale_inc * DST_WIDTH as isize);
+        dst = dst.wrapping_add(dst_stride);
+        i += width;
+    }
+}
+
+/// Same as [`pack_lhs`], where the `i`-th row is multiplied by `scale[i * scale_inc]`, conjugated
+/// if `conj_scale` is true.
+#[inline(never)]
+pub unsafe fn pack_lhs_scaled<T: Copy + Zero + Conj + Mul<Output = T>, const MR: usize, S: Simd>(
+    _: S,
+    m: usize,
+    k: usize,
+    dst: crate::Ptr<T>,
+    src: crate::Ptr<T>,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+    scale: crate::Ptr<T>,
+    scale_inc: isize,
+    conj_scale: bool,
+) {
+    let dst = dst.0;
+    let src = src.0;
+    let scale = scale.0;
+    S::vectorize(
+        #[inline(always)]
+        || {
+            pack_scaled::<T, MR>(
+                m, k, dst, src, src_cs, src_rs, dst_stride, scale, scale_inc, conj_scale,
+            )
+        },
+    );
+}
+
+/// Same as [`pack_rhs`], where the `j`-th column is multiplied by `scale[j * scale_inc]`,
+/// conjugated if `conj_scale` is true.
+#[inline(never)]
+pub unsafe fn pack_rhs_scaled<T: Copy + Zero + Conj + Mul<Output = T>, const NR: usize, S: Simd>(
+    _: S,
+    n: usize,
+    k: usize,
+    dst: crate::Ptr<T>,
+    src: crate::Ptr<T>,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+    scale: crate::Ptr<T>,
+    scale_inc: isize,
+    conj_scale: bool,
+) {
+    let dst = dst.0;
+    let src = src.0;
+    let scale = scale.0;
+    S::vectorize(
+        #[inline(always)]
+        || {
+            pack_scaled::<T, NR>(
+                n, k, dst, src, src_rs, src_cs, dst_stride, scale, scale_inc, conj_scale,
+            )
+        },
+    );
+}
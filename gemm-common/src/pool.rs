@@ -0,0 +1,167 @@
+//! Process-wide pool of packing buffers.
+//!
+//! When enabled with [`set_global_pool_enabled`], the scratch memory that the drivers allocate
+//! for packing is taken from, and returned to, a pool shared by every thread, instead of being
+//! allocated and freed on each call. Buffers are cacheline-aligned and grouped by power-of-two
+//! size classes, so calls with different shapes can still share them.
+
+use crate::gemm::CACHELINE_ALIGN;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
+use dyn_stack::{GlobalMemBuffer, StackReq};
+use std::sync::Mutex;
+use std::vec::Vec;
+
+pub const DEFAULT_GLOBAL_POOL_ENABLED: bool = false;
+
+/// Smallest size class, in bytes.
+const MIN_CLASS_BYTES: usize = 4096;
+/// Number of idle buffers kept per size class. Extra buffers are freed when released.
+const MAX_BUFFERS_PER_CLASS: usize = 16;
+
+static ENABLED: AtomicBool = AtomicBool::new(DEFAULT_GLOBAL_POOL_ENABLED);
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+// indexed by `log2(size in bytes)`
+static POOL: Mutex<Vec<Vec<GlobalMemBuffer>>> = Mutex::new(Vec::new());
+
+#[inline]
+pub fn get_global_pool_enabled() -> bool {
+    ENABLED.load(Relaxed)
+}
+#[inline]
+pub fn set_global_pool_enabled(enable: bool) {
+    ENABLED.store(enable, Relaxed);
+}
+
+/// Pool usage counters, as returned by [`pool_stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of requests served by an idle buffer.
+    pub hits: u64,
+    /// Number of requests that needed a new allocation.
+    pub misses: u64,
+    /// Number of idle buffers currently held by the pool.
+    pub cached_buffers: usize,
+    /// Total size of the idle buffers currently held by the pool.
+    pub cached_bytes: usize,
+}
+
+impl PoolStats {
+    /// Fraction of requests served by an idle buffer, or `0.0` if there were no requests.
+    #[inline]
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+fn lock_pool() -> std::sync::MutexGuard<'static, Vec<Vec<GlobalMemBuffer>>> {
+    // the pool only holds plain buffers, so it is still usable after a panic
+    POOL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn pool_stats() -> PoolStats {
+    let pool = lock_pool();
+    let mut cached_buffers = 0;
+    let mut cached_bytes = 0;
+    for (class, buffers) in pool.iter().enumerate() {
+        cached_buffers += buffers.len();
+        cached_bytes += buffers.len() << class;
+    }
+    PoolStats {
+        hits: HITS.load(Relaxed),
+        misses: MISSES.load(Relaxed),
+        cached_buffers,
+        cached_bytes,
+    }
+}
+
+pub fn reset_pool_stats() {
+    HITS.store(0, Relaxed);
+    MISSES.store(0, Relaxed);
+}
+
+/// Frees every idle buffer held by the pool.
+pub fn clear_pool() {
+    let buffers = core::mem::take(&mut *lock_pool());
+    drop(buffers);
+}
+
+/// Scratch buffer returned by [`alloc`]. It is handed back to the pool when dropped, if it came
+/// from the pool.
+pub struct PoolBuffer {
+    buf: Option<GlobalMemBuffer>,
+    class: Option<usize>,
+}
+
+/// Allocates a buffer satisfying `req`, from the pool if it is enabled, or from the global
+/// allocator otherwise.
+pub fn alloc(req: StackReq) -> PoolBuffer {
+    if !get_global_pool_enabled() {
+        return PoolBuffer {
+            buf: Some(GlobalMemBuffer::new(req)),
+            class: None,
+        };
+    }
+
+    let class = req
+        .unaligned_bytes_required()
+        .max(MIN_CLASS_BYTES)
+        .next_power_of_two()
+        .trailing_zeros() as usize;
+
+    let cached = lock_pool().get_mut(class).and_then(|buffers| buffers.pop());
+    let buf = match cached {
+        Some(buf) => {
+            HITS.fetch_add(1, Relaxed);
+            buf
+        }
+        None => {
+            MISSES.fetch_add(1, Relaxed);
+            GlobalMemBuffer::new(StackReq::new_aligned::<u8>(1 << class, CACHELINE_ALIGN))
+        }
+    };
+
+    PoolBuffer {
+        buf: Some(buf),
+        class: Some(class),
+    }
+}
+
+impl Drop for PoolBuffer {
+    fn drop(&mut self) {
+        if let (Some(buf), Some(class)) = (self.buf.take(), self.class) {
+            if !get_global_pool_enabled() {
+                return;
+            }
+            let mut pool = lock_pool();
+            if pool.len() <= class {
+                pool.resize_with(class + 1, Vec::new);
+            }
+            if pool[class].len() < MAX_BUFFERS_PER_CLASS {
+                pool[class].push(buf);
+            }
+        }
+    }
+}
+
+impl core::ops::Deref for PoolBuffer {
+    type Target = [MaybeUninit<u8>];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.buf.as_ref().unwrap()
+    }
+}
+
+impl core::ops::DerefMut for PoolBuffer {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buf.as_mut().unwrap()
+    }
+}
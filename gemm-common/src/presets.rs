@@ -0,0 +1,232 @@
+//! Blocking parameters of the built-in microkernels for common microarchitectures.
+//!
+//! The presets are a middle ground between the model of
+//! [`kernel_params`](crate::cache::kernel_params), which relies on the caches reported by the
+//! machine, and measuring the parameters with an autotuner. They're sized from the documented
+//! caches of each microarchitecture: the `kc×nr` rhs micropanel fills half of the L1, the `mc×kc`
+//! lhs block half of the L2 of a core, and the `kc×nc` rhs block half of the L3 shared by a CCX,
+//! a CCD or 8 cores of a mesh. They also hold on virtual machines, which often report misleading
+//! caches.
+//!
+//! The preset of the running microarchitecture is used when it's detected, unless disabled with
+//! [`set_presets_enabled`]. Parameters installed with
+//! [`set_tuned_kernel_params`](crate::tuning::set_tuned_kernel_params) take precedence over it.
+
+use crate::cache::{fit_kernel_params, KernelParams};
+use core::any::TypeId;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering::Relaxed};
+
+/// Microarchitectures with presets.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Microarch {
+    /// AMD Zen 2, with a 16MiB L3 per CCX of 4 cores.
+    Zen2,
+    /// AMD Zen 3, with a 32MiB L3 per CCD of 8 cores.
+    Zen3,
+    /// AMD Zen 4, with a 1MiB L2 and a 32MiB L3 per CCD of 8 cores.
+    Zen4,
+    /// Intel Skylake-SP, Cascade Lake and Cooper Lake, with a 1MiB L2.
+    SkylakeX,
+    /// Intel Ice Lake-SP, with a 48KiB L1 and a 1.25MiB L2.
+    IceLakeServer,
+    /// Intel Ice Lake client parts, with a 48KiB L1 and a 512KiB L2.
+    IceLakeClient,
+    /// Performance cores of the Apple M series, with a 128KiB L1 and an L2 shared by a cluster.
+    AppleM,
+}
+
+const MICROARCHS: [Microarch; 7] = [
+    Microarch::Zen2,
+    Microarch::Zen3,
+    Microarch::Zen4,
+    Microarch::SkylakeX,
+    Microarch::IceLakeServer,
+    Microarch::IceLakeClient,
+    Microarch::AppleM,
+];
+
+impl Microarch {
+    /// Microarchitecture of an x86 processor from the vendor string, family and model reported by
+    /// CPUID, or `None` if it has no preset.
+    pub fn from_cpuid(vendor: &str, family: u32, model: u32) -> Option<Self> {
+        match (vendor, family) {
+            // models below 0x30 are Zen and Zen+
+            ("AuthenticAMD", 0x17) if model >= 0x30 => Some(Microarch::Zen2),
+            ("AuthenticAMD", 0x19) => match model {
+                0x10..=0x1f | 0x60..=0xaf => Some(Microarch::Zen4),
+                _ => Some(Microarch::Zen3),
+            },
+            ("GenuineIntel", 6) => match model {
+                0x55 => Some(Microarch::SkylakeX),
+                0x6a | 0x6c => Some(Microarch::IceLakeServer),
+                0x7d | 0x7e => Some(Microarch::IceLakeClient),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn detect_microarch_impl() -> Option<Microarch> {
+    if cfg!(miri) {
+        return None;
+    }
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        let cpuid = raw_cpuid::CpuId::new();
+        let vendor = cpuid.get_vendor_info()?;
+        let features = cpuid.get_feature_info()?;
+        Microarch::from_cpuid(
+            vendor.as_str(),
+            features.family_id() as u32,
+            features.model_id() as u32,
+        )
+    }
+    #[cfg(all(
+        target_vendor = "apple",
+        feature = "std",
+        not(any(target_arch = "x86", target_arch = "x86_64")),
+    ))]
+    {
+        use sysctl::Ctl;
+        use sysctl::Sysctl;
+
+        let brand = Ctl::new("machdep.cpu.brand_string")
+            .and_then(|ctl| ctl.value_string())
+            .ok()?;
+        if brand.starts_with("Apple M") {
+            Some(Microarch::AppleM)
+        } else {
+            None
+        }
+    }
+    #[cfg(not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        all(target_vendor = "apple", feature = "std"),
+    )))]
+    {
+        None
+    }
+}
+
+/// Microarchitecture of the running machine, or `None` if it has no preset.
+pub fn detect_microarch() -> Option<Microarch> {
+    // index into `MICROARCHS`, `NONE` if there's no preset and `UNKNOWN` until detected
+    const UNKNOWN: u8 = u8::MAX;
+    const NONE: u8 = u8::MAX - 1;
+    static DETECTED: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    let mut detected = DETECTED.load(Relaxed);
+    if detected == UNKNOWN {
+        detected = match detect_microarch_impl() {
+            Some(arch) => MICROARCHS.iter().position(|&a| a == arch).unwrap() as u8,
+            None => NONE,
+        };
+        DETECTED.store(detected, Relaxed);
+    }
+    MICROARCHS.get(detected as usize).copied()
+}
+
+pub const DEFAULT_PRESETS_ENABLED: bool = true;
+
+static PRESETS_ENABLED: AtomicBool = AtomicBool::new(DEFAULT_PRESETS_ENABLED);
+
+#[inline]
+pub fn get_presets_enabled() -> bool {
+    PRESETS_ENABLED.load(Relaxed)
+}
+#[inline]
+pub fn set_presets_enabled(enable: bool) {
+    PRESETS_ENABLED.store(enable, Relaxed)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Scalar {
+    F32,
+    F64,
+}
+
+struct Preset {
+    arch: Microarch,
+    scalar: Scalar,
+    mr: usize,
+    nr: usize,
+    params: KernelParams,
+}
+
+macro_rules! presets {
+    ($($arch: ident, $ty: ident, $mr: expr, $nr: expr, $kc: expr, $mc: expr, $nc: expr;)*) => {
+        &[$(Preset {
+            arch: Microarch::$arch,
+            scalar: Scalar::$ty,
+            mr: $mr,
+            nr: $nr,
+            params: KernelParams { kc: $kc, mc: $mc, nc: $nc },
+        },)*]
+    };
+}
+
+// the fma microkernels are 16×6 for f32 and 8×6 for f64, the avx512f ones 64×6 and 32×6, and the
+// neon ones 16×4 and 8×4
+static PRESETS: &[Preset] = presets! {
+    Zen2, F32, 16, 6, 640, 96, 3276;
+    Zen2, F64, 8, 6, 320, 96, 3276;
+    Zen3, F32, 16, 6, 640, 96, 6552;
+    Zen3, F64, 8, 6, 320, 96, 6552;
+    Zen4, F32, 16, 6, 640, 192, 6552;
+    Zen4, F64, 8, 6, 320, 200, 6552;
+    Zen4, F32, 64, 6, 640, 192, 6552;
+    Zen4, F64, 32, 6, 320, 192, 6552;
+    SkylakeX, F32, 16, 6, 640, 192, 2250;
+    SkylakeX, F64, 8, 6, 320, 200, 2250;
+    SkylakeX, F32, 64, 6, 640, 192, 2250;
+    SkylakeX, F64, 32, 6, 320, 192, 2250;
+    IceLakeServer, F32, 16, 6, 1024, 160, 1536;
+    IceLakeServer, F64, 8, 6, 512, 160, 1536;
+    IceLakeServer, F32, 64, 6, 1024, 128, 1536;
+    IceLakeServer, F64, 32, 6, 512, 160, 1536;
+    IceLakeClient, F32, 16, 6, 1024, 64, 1020;
+    IceLakeClient, F64, 8, 6, 512, 64, 1020;
+    IceLakeClient, F32, 64, 6, 1024, 64, 1020;
+    IceLakeClient, F64, 32, 6, 512, 64, 1020;
+    AppleM, F32, 16, 4, 1024, 384, 1536;
+    AppleM, F64, 8, 4, 1024, 192, 768;
+};
+
+/// Preset of `arch` for `T` with an `mr×nr` microkernel, or `None` if there is none.
+pub fn get_preset_kernel_params<T: 'static>(
+    arch: Microarch,
+    mr: usize,
+    nr: usize,
+) -> Option<KernelParams> {
+    let ty = TypeId::of::<T>();
+    let scalar = if ty == TypeId::of::<f64>() {
+        Scalar::F64
+    } else if ty == TypeId::of::<f32>() {
+        Scalar::F32
+    } else {
+        return None;
+    };
+    PRESETS
+        .iter()
+        .find(|p| p.arch == arch && p.scalar == scalar && p.mr == mr && p.nr == nr)
+        .map(|p| p.params)
+}
+
+/// Preset of the running machine for `T` with an `mr×nr` microkernel, adjusted to an `m×n×k`
+/// problem, or `None` if there is none or the presets are disabled.
+#[inline]
+pub fn preset_kernel_params<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+) -> Option<KernelParams> {
+    if m == 0 || n == 0 || k == 0 || !get_presets_enabled() {
+        return None;
+    }
+    let params = get_preset_kernel_params::<T>(detect_microarch()?, mr, nr)?;
+    Some(fit_kernel_params(m, n, k, mr, nr, params))
+}
@@ -0,0 +1,132 @@
+//! Thread pools running the parallel regions of the drivers.
+//!
+//! Every parallel region goes through a [`ThreadSpawner`], selected by the [`Parallelism`] of the
+//! call. [`Parallelism::Rayon`] uses [`RayonSpawner`], and [`Parallelism::Custom`] hands the
+//! regions to a pool provided by the caller, so that applications which already manage their
+//! own threads don't need a second pool for the multiplications. Without rayon,
+//! [`ScopedSpawner`] only needs the standard library.
+
+use crate::Parallelism;
+
+/// Pool of threads that the parallel regions of the drivers run on.
+pub trait ThreadSpawner: Sync {
+    /// Number of threads of the pool, which is the most that a region is split into.
+    fn num_threads(&self) -> usize;
+
+    /// Calls `func(tid)` for each `tid` in `0..n_tasks`, potentially in parallel and in any
+    /// order, and returns once every call has returned. The tasks never wait on each other.
+    fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync));
+}
+
+/// Runs the parallel regions on the rayon pool the caller is running in. Outside of any pool,
+/// this is the global pool, or the pinned pool selected by the
+/// [`PIN_THREADS_ENV`](crate::affinity::PIN_THREADS_ENV) environment variable. The regions run on
+/// the global pool use at most [`available_cores`](crate::affinity::available_cores) threads, so
+/// that a pool sized for the whole machine doesn't exceed the CPU quota of a container.
+#[cfg(feature = "rayon")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RayonSpawner;
+
+#[cfg(feature = "rayon")]
+impl RayonSpawner {
+    #[inline]
+    fn pinned_pool() -> Option<&'static rayon::ThreadPool> {
+        if rayon::current_thread_index().is_none() {
+            crate::affinity::env_thread_pool()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl ThreadSpawner for RayonSpawner {
+    #[inline]
+    fn num_threads(&self) -> usize {
+        match Self::pinned_pool() {
+            Some(pool) => pool.current_num_threads(),
+            None if rayon::current_thread_index().is_none() => Ord::min(
+                rayon::current_num_threads(),
+                crate::affinity::available_cores(),
+            ),
+            None => rayon::current_num_threads(),
+        }
+    }
+
+    fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+        use rayon::prelude::*;
+        let run = || (0..n_tasks).into_par_iter().for_each(func);
+        match Self::pinned_pool() {
+            Some(pool) => pool.install(run),
+            None => run(),
+        }
+    }
+}
+
+/// Runs the parallel regions on a dedicated rayon pool, instead of the global one. The pool can
+/// be borrowed for a single call with [`Parallelism::Custom`].
+#[cfg(feature = "rayon")]
+impl ThreadSpawner for rayon::ThreadPool {
+    #[inline]
+    fn num_threads(&self) -> usize {
+        self.current_num_threads()
+    }
+
+    fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+        self.install(|| RayonSpawner.for_each(n_tasks, func))
+    }
+}
+
+/// Runs the parallel regions on threads spawned with [`std::thread::scope`], for builds without
+/// rayon or applications that don't want its global pool. The calling thread runs the first task
+/// of each region. Spawning threads costs more than waking those of a pool, so the threading
+/// threshold may need to be raised.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug)]
+pub struct ScopedSpawner {
+    n_threads: usize,
+}
+
+#[cfg(feature = "std")]
+impl ScopedSpawner {
+    /// Spawner using up to `n_threads` threads, or
+    /// [`available_cores`](crate::affinity::available_cores) if it's `0`.
+    #[inline]
+    pub const fn new(n_threads: usize) -> Self {
+        Self { n_threads }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ThreadSpawner for ScopedSpawner {
+    #[inline]
+    fn num_threads(&self) -> usize {
+        if self.n_threads == 0 {
+            crate::affinity::available_cores()
+        } else {
+            self.n_threads
+        }
+    }
+
+    fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+        std::thread::scope(|s| {
+            for tid in 1..n_tasks {
+                s.spawn(move || func(tid));
+            }
+            if n_tasks > 0 {
+                func(0);
+            }
+        });
+    }
+}
+
+/// Pool selected by `parallelism`, or `None` if it runs on the calling thread.
+#[inline]
+pub fn spawner<'a>(parallelism: Parallelism<'a>) -> Option<&'a dyn ThreadSpawner> {
+    match parallelism {
+        Parallelism::None => None,
+        #[cfg(feature = "rayon")]
+        Parallelism::Rayon(_) => Some(&RayonSpawner),
+        Parallelism::Custom(spawner) => Some(spawner),
+    }
+}
@@ -0,0 +1,156 @@
+//! Process-wide counters of the work done by the drivers, with the `stats` feature.
+//!
+//! The counters are updated by every call, from every thread, and read with [`driver_stats`],
+//! which makes it possible to see what a workload is made of, e.g. how much of it runs on the
+//! edges of the blocks, and to compare two versions of a program on the same workload. The
+//! threads count their jobs and kernel calls on their own, and add them to the counters when they
+//! are done with a block, so that the counters aren't written to by the microkernel loop.
+
+use core::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+static PACKED_LHS_BYTES: AtomicU64 = AtomicU64::new(0);
+static PACKED_RHS_BYTES: AtomicU64 = AtomicU64::new(0);
+// indexed by `KernelCalls::class`
+static KERNEL_CALLS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+static JOBS: AtomicU64 = AtomicU64::new(0);
+static THREAD_BLOCKS: AtomicU64 = AtomicU64::new(0);
+static MAX_JOBS_PER_THREAD: AtomicU64 = AtomicU64::new(0);
+static FALLBACKS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of microkernel calls by size class, relative to the `MR×NR` block of the kernels.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KernelCalls {
+    /// Calls on a whole `MR×NR` block.
+    pub full: u64,
+    /// Calls on the last rows of the destination, with fewer than `MR` rows.
+    pub partial_rows: u64,
+    /// Calls on the last columns of the destination, with fewer than `NR` columns.
+    pub partial_cols: u64,
+    /// Calls on the last rows and columns of the destination.
+    pub partial_both: u64,
+}
+
+impl KernelCalls {
+    #[inline]
+    fn class(partial_rows: bool, partial_cols: bool) -> usize {
+        partial_rows as usize | (partial_cols as usize) << 1
+    }
+
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.full + self.partial_rows + self.partial_cols + self.partial_both
+    }
+}
+
+/// Driver counters, as returned by [`driver_stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DriverStats {
+    /// Bytes of the lhs that were packed, counted once per element of the source.
+    pub packed_lhs_bytes: u64,
+    /// Bytes of the rhs that were packed, counted once per element of the source.
+    pub packed_rhs_bytes: u64,
+    /// Calls of the microkernels.
+    pub kernel_calls: KernelCalls,
+    /// Jobs of the blocks, each of which is a rectangle of microkernel calls taken by a thread.
+    pub jobs: u64,
+    /// Number of times a thread took part in a block.
+    pub thread_blocks: u64,
+    /// Largest number of jobs taken by a thread in a block.
+    pub max_jobs_per_thread: u64,
+    /// Products that were computed without the microkernels: the ones with `k == 0`, and the
+    /// outer products and matrix-vector products that have their own kernels.
+    pub fallbacks: u64,
+}
+
+impl DriverStats {
+    /// Average number of jobs taken by a thread in a block, or `0.0` if there were no blocks.
+    #[inline]
+    pub fn jobs_per_thread(&self) -> f64 {
+        if self.thread_blocks == 0 {
+            0.0
+        } else {
+            self.jobs as f64 / self.thread_blocks as f64
+        }
+    }
+}
+
+pub fn driver_stats() -> DriverStats {
+    let calls = |partial_rows, partial_cols| {
+        KERNEL_CALLS[KernelCalls::class(partial_rows, partial_cols)].load(Relaxed)
+    };
+    DriverStats {
+        packed_lhs_bytes: PACKED_LHS_BYTES.load(Relaxed),
+        packed_rhs_bytes: PACKED_RHS_BYTES.load(Relaxed),
+        kernel_calls: KernelCalls {
+            full: calls(false, false),
+            partial_rows: calls(true, false),
+            partial_cols: calls(false, true),
+            partial_both: calls(true, true),
+        },
+        jobs: JOBS.load(Relaxed),
+        thread_blocks: THREAD_BLOCKS.load(Relaxed),
+        max_jobs_per_thread: MAX_JOBS_PER_THREAD.load(Relaxed),
+        fallbacks: FALLBACKS.load(Relaxed),
+    }
+}
+
+pub fn reset_driver_stats() {
+    PACKED_LHS_BYTES.store(0, Relaxed);
+    PACKED_RHS_BYTES.store(0, Relaxed);
+    for calls in &KERNEL_CALLS {
+        calls.store(0, Relaxed);
+    }
+    JOBS.store(0, Relaxed);
+    THREAD_BLOCKS.store(0, Relaxed);
+    MAX_JOBS_PER_THREAD.store(0, Relaxed);
+    FALLBACKS.store(0, Relaxed);
+}
+
+#[inline]
+pub(crate) fn record_packed_lhs(bytes: usize) {
+    PACKED_LHS_BYTES.fetch_add(bytes as u64, Relaxed);
+}
+
+#[inline]
+pub(crate) fn record_packed_rhs(bytes: usize) {
+    PACKED_RHS_BYTES.fetch_add(bytes as u64, Relaxed);
+}
+
+#[inline]
+pub(crate) fn record_fallback() {
+    FALLBACKS.fetch_add(1, Relaxed);
+}
+
+/// Counters of a thread during a block, which are added to the global ones by
+/// [`ThreadStats::flush`].
+#[derive(Default)]
+pub(crate) struct ThreadStats {
+    jobs: u64,
+    kernel_calls: [u64; 4],
+}
+
+impl ThreadStats {
+    #[inline]
+    pub(crate) fn record_job(&mut self) {
+        self.jobs += 1;
+    }
+
+    #[inline]
+    pub(crate) fn record_kernel(&mut self, partial_rows: bool, partial_cols: bool) {
+        self.kernel_calls[KernelCalls::class(partial_rows, partial_cols)] += 1;
+    }
+
+    pub(crate) fn flush(&self) {
+        JOBS.fetch_add(self.jobs, Relaxed);
+        THREAD_BLOCKS.fetch_add(1, Relaxed);
+        MAX_JOBS_PER_THREAD.fetch_max(self.jobs, Relaxed);
+        for (total, &calls) in KERNEL_CALLS.iter().zip(&self.kernel_calls) {
+            total.fetch_add(calls, Relaxed);
+        }
+    }
+}
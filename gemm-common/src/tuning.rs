@@ -0,0 +1,402 @@
+//! Blocking parameters installed at runtime.
+//!
+//! [`kernel_params`](crate::cache::kernel_params) derives the blocking parameters from a model of
+//! the cache hierarchy, which doesn't fit every machine. Parameters measured on the running
+//! machine can be installed with [`set_tuned_kernel_params`], in which case the drivers use them
+//! instead for the problems with the same scalar type, microkernel and [`Shape`], adjusted to the
+//! dimensions of each problem. Small problems, whose blocking doesn't come from the model, are
+//! left alone.
+//!
+//! The installed parameters can be saved with [`save_tuning_cache`] to the tuning cache, a text
+//! file whose entries are keyed by the CPU model. The entries for the running CPU are installed on
+//! first use, so that the parameters are measured once per machine. The file is given by
+//! [`TUNING_CACHE_ENV`], and is `gemm/tuning` in the cache directory of the user otherwise.
+
+use crate::cache::{fit_kernel_params, KernelParams};
+use crate::gemm::Shape;
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, Once};
+use std::vec::Vec;
+
+/// Environment variable with the path of the tuning cache, which is disabled if it's empty.
+pub const TUNING_CACHE_ENV: &str = "GEMM_TUNING_CACHE";
+
+/// Version of the format of the tuning cache. Files with another version are ignored, and
+/// replaced when saving.
+pub const TUNING_CACHE_VERSION: u32 = 1;
+
+const TUNING_CACHE_HEADER: &str = "gemm tuning cache";
+
+struct Tuned {
+    // `core::any::type_name` of the scalar type, which is stable enough to be saved
+    ty: String,
+    mr: usize,
+    nr: usize,
+    shape: Shape,
+    backend: String,
+    params: KernelParams,
+}
+
+impl Tuned {
+    #[inline]
+    fn is(&self, ty: &str, mr: usize, nr: usize, shape: Shape) -> bool {
+        self.ty == ty && self.mr == mr && self.nr == nr && self.shape == shape
+    }
+}
+
+// lets the drivers skip the lock while nothing is installed
+static ANY_TUNED: AtomicBool = AtomicBool::new(false);
+static TUNED: Mutex<Vec<Tuned>> = Mutex::new(Vec::new());
+static LOADED: Once = Once::new();
+// `None` until overridden by `set_tuning_cache_path`
+static CACHE_PATH: Mutex<Option<Option<PathBuf>>> = Mutex::new(None);
+
+fn lock_tuned() -> std::sync::MutexGuard<'static, Vec<Tuned>> {
+    // the table only holds plain values, so it is still usable after a panic
+    TUNED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// installs the cached entries before anything else reads or changes the table
+#[inline]
+fn ensure_loaded() {
+    LOADED.call_once(|| {
+        if let Some(path) = get_tuning_cache_path() {
+            // a missing or unreadable cache only means that nothing was tuned yet
+            let _ = load_from(&path);
+        }
+    });
+}
+
+fn install(tuned: &mut Vec<Tuned>, entry: Tuned) {
+    tuned.retain(|t| !t.is(&entry.ty, entry.mr, entry.nr, entry.shape));
+    tuned.push(entry);
+    ANY_TUNED.store(true, Relaxed);
+}
+
+/// Parameters installed for `T` with an `mr×nr` microkernel, for problems of the given shape.
+pub fn get_tuned_kernel_params<T: 'static>(
+    mr: usize,
+    nr: usize,
+    shape: Shape,
+) -> Option<KernelParams> {
+    ensure_loaded();
+    if !ANY_TUNED.load(Relaxed) {
+        return None;
+    }
+    let ty = core::any::type_name::<T>();
+    lock_tuned()
+        .iter()
+        .find(|tuned| tuned.is(ty, mr, nr, shape))
+        .map(|tuned| tuned.params)
+}
+
+/// Name of the backend that the parameters installed for `T` with an `mr×nr` microkernel, for
+/// problems of the given shape, were measured with.
+pub fn get_tuned_backend<T: 'static>(mr: usize, nr: usize, shape: Shape) -> Option<String> {
+    ensure_loaded();
+    let ty = core::any::type_name::<T>();
+    lock_tuned()
+        .iter()
+        .find(|tuned| tuned.is(ty, mr, nr, shape))
+        .map(|tuned| tuned.backend.clone())
+}
+
+/// Installs `params`, measured with the backend named `backend`, for `T` with an `mr×nr`
+/// microkernel, for problems of the given shape, or removes the installed ones if `params` is
+/// `None`.
+pub fn set_tuned_kernel_params<T: 'static>(
+    backend: &str,
+    mr: usize,
+    nr: usize,
+    shape: Shape,
+    params: Option<KernelParams>,
+) {
+    ensure_loaded();
+    let ty = core::any::type_name::<T>();
+    let mut tuned = lock_tuned();
+    match params {
+        Some(params) => install(
+            &mut tuned,
+            Tuned {
+                ty: ty.to_string(),
+                mr,
+                nr,
+                shape,
+                backend: backend.to_string(),
+                params,
+            },
+        ),
+        None => {
+            tuned.retain(|tuned| !tuned.is(ty, mr, nr, shape));
+            ANY_TUNED.store(!tuned.is_empty(), Relaxed);
+        }
+    }
+}
+
+/// Removes every installed parameter, so that the drivers use the model again. The tuning cache
+/// is left untouched.
+pub fn clear_tuned_kernel_params() {
+    ensure_loaded();
+    let mut tuned = lock_tuned();
+    tuned.clear();
+    ANY_TUNED.store(false, Relaxed);
+}
+
+/// Parameters installed for `T` with an `mr×nr` microkernel, adjusted to an `m×n×k` problem.
+#[inline]
+pub fn tuned_kernel_params<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+) -> Option<KernelParams> {
+    if m == 0 || n == 0 || k == 0 {
+        return None;
+    }
+    let params = get_tuned_kernel_params::<T>(mr, nr, Shape::of(m, n))?;
+    Some(fit_kernel_params(m, n, k, mr, nr, params))
+}
+
+fn detect_cpu_model() -> Option<String> {
+    if cfg!(miri) {
+        return None;
+    }
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if let Some(brand) = raw_cpuid::CpuId::new().get_processor_brand_string() {
+            return Some(brand.as_str().trim().to_string());
+        }
+    }
+    #[cfg(target_vendor = "apple")]
+    {
+        use sysctl::Ctl;
+        use sysctl::Sysctl;
+
+        if let Ok(brand) = Ctl::new("machdep.cpu.brand_string").and_then(|ctl| ctl.value_string()) {
+            return Some(brand.trim().to_string());
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+            for key in ["model name", "Model", "cpu model", "cpu"] {
+                for line in cpuinfo.lines() {
+                    let mut fields = line.splitn(2, ':');
+                    if let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+                        if name.trim() == key && !value.trim().is_empty() {
+                            return Some(value.trim().to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Model of the running CPU, which keys the entries of the tuning cache, or the name of the
+/// architecture if it can't be detected.
+pub fn cpu_model() -> String {
+    static MODEL: once_cell::sync::OnceCell<String> = once_cell::sync::OnceCell::new();
+    MODEL
+        .get_or_init(|| {
+            let model = detect_cpu_model().unwrap_or_else(|| std::env::consts::ARCH.to_string());
+            // tabs and newlines delimit the fields of the tuning cache
+            model.replace(['\t', '\n', '\r'], " ")
+        })
+        .clone()
+}
+
+/// Path of the tuning cache given by [`TUNING_CACHE_ENV`], or in the cache directory of the user
+/// if it's unset, or `None` if it's empty or no cache directory is known.
+pub fn default_tuning_cache_path() -> Option<PathBuf> {
+    use std::env::var_os;
+
+    if let Some(path) = var_os(TUNING_CACHE_ENV) {
+        return if path.is_empty() {
+            None
+        } else {
+            Some(path.into())
+        };
+    }
+    let non_empty = |dir: std::ffi::OsString| if dir.is_empty() { None } else { Some(dir) };
+    let cache_dir = match var_os("XDG_CACHE_HOME").and_then(non_empty) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(var_os("LOCALAPPDATA").and_then(non_empty)?),
+        None if cfg!(target_vendor = "apple") => {
+            PathBuf::from(var_os("HOME").and_then(non_empty)?).join("Library/Caches")
+        }
+        None => PathBuf::from(var_os("HOME").and_then(non_empty)?).join(".cache"),
+    };
+    Some(cache_dir.join("gemm").join("tuning"))
+}
+
+/// Path of the tuning cache, which is the [default](default_tuning_cache_path) one unless it was
+/// overridden by [`set_tuning_cache_path`], or `None` if the cache is disabled.
+pub fn get_tuning_cache_path() -> Option<PathBuf> {
+    let path = CACHE_PATH.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    match path {
+        Some(path) => path,
+        None => default_tuning_cache_path(),
+    }
+}
+/// Overrides the path of the tuning cache, or disables the cache if `path` is `None`. The
+/// entries of the new file aren't installed until [`load_tuning_cache`] is called.
+pub fn set_tuning_cache_path(path: Option<PathBuf>) {
+    *CACHE_PATH.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+}
+
+fn shape_name(shape: Shape) -> &'static str {
+    match shape {
+        Shape::General => "general",
+        Shape::TallSkinny => "tall-skinny",
+        Shape::ShortFat => "short-fat",
+    }
+}
+
+fn parse_shape(name: &str) -> Option<Shape> {
+    match name {
+        "general" => Some(Shape::General),
+        "tall-skinny" => Some(Shape::TallSkinny),
+        "short-fat" => Some(Shape::ShortFat),
+        _ => None,
+    }
+}
+
+// `cpu	type	mr	nr	shape	backend	kc	mc	nc`
+fn format_entry(cpu: &str, tuned: &Tuned) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        cpu,
+        tuned.ty,
+        tuned.mr,
+        tuned.nr,
+        shape_name(tuned.shape),
+        tuned.backend,
+        tuned.params.kc,
+        tuned.params.mc,
+        tuned.params.nc,
+    )
+}
+
+fn parse_entry(line: &str) -> Option<(&str, Tuned)> {
+    let mut fields = line.split('\t');
+    let mut next = || fields.next();
+    let cpu = next()?;
+    let ty = next()?.to_string();
+    let mr = next()?.parse().ok()?;
+    let nr = next()?.parse().ok()?;
+    let shape = parse_shape(next()?)?;
+    let backend = next()?.to_string();
+    let kc = next()?.parse().ok()?;
+    let mc = next()?.parse().ok()?;
+    let nc = next()?.parse().ok()?;
+    if next().is_some() || mr == 0 || nr == 0 || kc == 0 || mc == 0 || nc == 0 {
+        return None;
+    }
+    Some((
+        cpu,
+        Tuned {
+            ty,
+            mr,
+            nr,
+            shape,
+            backend,
+            params: KernelParams { kc, mc, nc },
+        },
+    ))
+}
+
+// lines of the entries, or nothing if the file has another version
+fn read_entries(contents: &str) -> Vec<&str> {
+    let mut lines = contents.lines();
+    let version = lines
+        .next()
+        .and_then(|header| header.strip_prefix(TUNING_CACHE_HEADER))
+        .and_then(|version| version.trim().parse::<u32>().ok());
+    if version == Some(TUNING_CACHE_VERSION) {
+        lines.filter(|line| !line.trim().is_empty()).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn load_from(path: &std::path::Path) -> io::Result<usize> {
+    let contents = fs::read_to_string(path)?;
+    let cpu = cpu_model();
+    let mut tuned = lock_tuned();
+    let mut count = 0;
+    for line in read_entries(&contents) {
+        if let Some((entry_cpu, entry)) = parse_entry(line) {
+            if entry_cpu == cpu {
+                install(&mut tuned, entry);
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Installs the entries of the tuning cache for the running CPU, and returns their number. Does
+/// nothing if the cache is disabled or doesn't exist yet.
+pub fn load_tuning_cache() -> io::Result<usize> {
+    ensure_loaded();
+    match get_tuning_cache_path() {
+        Some(path) => match load_from(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            result => result,
+        },
+        None => Ok(0),
+    }
+}
+
+/// Saves the installed parameters to the tuning cache, replacing the entries of the running CPU
+/// with the same keys and keeping the others. Does nothing if the cache is disabled.
+pub fn save_tuning_cache() -> io::Result<()> {
+    ensure_loaded();
+    let path = match get_tuning_cache_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let cpu = cpu_model();
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let tuned = lock_tuned();
+    let mut out = format!("{} {}\n", TUNING_CACHE_HEADER, TUNING_CACHE_VERSION);
+    for line in read_entries(&contents) {
+        let replaced = match parse_entry(line) {
+            Some((entry_cpu, entry)) => {
+                entry_cpu == cpu
+                    && tuned
+                        .iter()
+                        .any(|t| t.is(&entry.ty, entry.mr, entry.nr, entry.shape))
+            }
+            None => true,
+        };
+        if !replaced {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    for entry in tuned.iter() {
+        out.push_str(&format_entry(&cpu, entry));
+        out.push('\n');
+    }
+    drop(tuned);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // written aside and renamed, so that concurrent readers never see a partial file
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, out)?;
+    fs::rename(&tmp, &path)
+}
@@ -1,12 +1,16 @@
-use dyn_stack::{DynStack, GlobalMemBuffer, StackReq};
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(feature = "std"))]
+use dyn_stack::GlobalMemBuffer;
+use dyn_stack::{DynStack, StackReq};
 #[cfg(feature = "std")]
 use gemm_common::gemm::L2_SLAB;
-#[cfg(feature = "rayon")]
-use gemm_common::gemm::{get_threading_threshold, par_for_each};
+use gemm_common::gemm::{max_threads, par_for_each, threading_threshold, tile_blocks};
 
 use gemm_common::{
-    cache::{kernel_params, DivCeil, KernelParams},
-    gemm::CACHELINE_ALIGN,
+    cache::{
+        get_kernel_params_override, kernel_params, override_kernel_params, DivCeil, KernelParams,
+    },
+    gemm::{gemm_req_generic, GemmConfig, UpdateRegion, CACHELINE_ALIGN},
     gemv, gevv,
     microkernel::MicroKernelFn,
     pack_operands::quick_zero,
@@ -259,6 +263,61 @@ pub unsafe fn pack_rhs<const N: usize, const NR: usize, S: MixedSimd<T, T, T, f3
     });
 }
 
+/// Blocking parameters used by [`gemm_basic_generic`] when none are provided by the caller.
+#[inline]
+pub fn gemm_kernel_params(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    parallelism: Parallelism<'_>,
+) -> KernelParams {
+    #[cfg(feature = "std")]
+    let tuned = gemm_common::tuning::tuned_kernel_params::<T>(m, n, k, mr, nr);
+    #[cfg(not(feature = "std"))]
+    let tuned = None;
+    let KernelParams { kc, mc, nc } =
+        tuned.unwrap_or_else(|| kernel_params(m, n, k, mr, nr, core::mem::size_of::<f32>()));
+    let nc = if nc > 0 {
+        nc
+    } else {
+        match parallelism {
+            Parallelism::None => 128 * nr,
+            _ => n.msrv_next_multiple_of(nr),
+        }
+    };
+    override_kernel_params(
+        m,
+        n,
+        k,
+        mr,
+        nr,
+        KernelParams { kc, mc, nc },
+        get_kernel_params_override(),
+    )
+}
+
+/// Scratch memory needed by [`gemm_basic_generic`] for an `m×n×k` problem.
+#[inline]
+pub fn gemm_req(
+    m: usize,
+    n: usize,
+    k: usize,
+    mr: usize,
+    nr: usize,
+    parallelism: Parallelism<'_>,
+) -> StackReq {
+    gemm_req_generic::<f32>(
+        m,
+        n,
+        k,
+        mr,
+        nr,
+        gemm_kernel_params(m, n, k, mr, nr, parallelism),
+    )
+}
+
 #[inline(always)]
 pub unsafe fn gemm_basic_generic<
     const N: usize,
@@ -284,14 +343,32 @@ pub unsafe fn gemm_basic_generic<
     mut alpha: T,
     beta: T,
     dispatcher: &[[MicroKernelFn<f32>; NR]; MR_DIV_N],
-    parallelism: Parallelism,
+    parallelism: Parallelism<'_>,
+    config: GemmConfig<'_, T>,
 ) {
+    assert!(config.packed_lhs.is_none() && config.packed_rhs.is_none());
+    assert!(config.lhs_scale.is_none() && config.rhs_scale.is_none());
+    assert!(config.mask.is_none() && config.update_region == UpdateRegion::Full);
+    assert!(config.accumulate.is_none());
     if m == 0 || n == 0 {
         return;
     }
     if !read_dst {
         alpha = T::ZERO;
     }
+    let _flush = gemm_common::denormals::FlushDenormalsGuard::new(
+        config
+            .flush_denormals
+            .unwrap_or_else(gemm_common::denormals::get_flush_denormals),
+    );
+
+    let epilogue = config.epilogue;
+    // applies the epilogue to the whole destination, for the paths that skip the microkernels
+    let finish = || {
+        if let Some(epilogue) = epilogue {
+            epilogue.apply(dst, m, n, dst_cs, dst_rs, 0, 0);
+        }
+    };
 
     if k == 0 {
         if alpha == T::ZERO {
@@ -300,9 +377,11 @@ pub unsafe fn gemm_basic_generic<
                     *dst.offset(i as isize * dst_rs + j as isize * dst_cs) = T::ZERO;
                 }
             }
+            finish();
             return;
         }
         if alpha == T::ONE {
+            finish();
             return;
         }
 
@@ -312,6 +391,7 @@ pub unsafe fn gemm_basic_generic<
                 *dst = alpha * *dst;
             }
         }
+        finish();
         return;
     }
 
@@ -341,6 +421,7 @@ pub unsafe fn gemm_basic_generic<
                     ))
                 },
             );
+            finish();
             return;
         }
 
@@ -351,6 +432,7 @@ pub unsafe fn gemm_basic_generic<
                 simd, m, n, k, dst, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
                 alpha, beta,
             );
+            finish();
             return;
         }
         if n <= 1 && lhs_cs == 1 && rhs_rs == 1 {
@@ -358,6 +440,7 @@ pub unsafe fn gemm_basic_generic<
                 simd, m, n, k, dst, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
                 alpha, beta,
             );
+            finish();
             return;
         }
 
@@ -366,6 +449,7 @@ pub unsafe fn gemm_basic_generic<
                 simd, n, m, k, dst, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs,
                 alpha, beta,
             );
+            finish();
             return;
         }
         if m <= 1 && rhs_rs == 1 && lhs_cs == 1 {
@@ -373,21 +457,21 @@ pub unsafe fn gemm_basic_generic<
                 simd, n, m, k, dst, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs,
                 alpha, beta,
             );
+            finish();
             return;
         }
     }
 
-    let KernelParams { kc, mc, nc } = kernel_params(m, n, k, MR, NR, core::mem::size_of::<f32>());
-    let nc = if nc > 0 {
-        nc
-    } else {
-        match parallelism {
-            Parallelism::None => 128 * NR,
-            #[cfg(feature = "rayon")]
-            Parallelism::Rayon(_) => n.msrv_next_multiple_of(NR),
-        }
+    let KernelParams { kc, mc, nc } = match config.kernel_params {
+        Some(params) => params,
+        None => gemm_kernel_params(m, n, k, MR, NR, parallelism),
     };
 
+    // the products are computed on f32 vectors
+    let threading_threshold = config
+        .threading_threshold
+        .unwrap_or_else(|| threading_threshold::<T>(N * core::mem::size_of::<f32>()));
+
     let simd_align = CACHELINE_ALIGN;
 
     let packed_rhs_stride = kc * NR;
@@ -409,14 +493,20 @@ pub unsafe fn gemm_basic_generic<
         simd_align,
     );
 
-    let mut mem = GlobalMemBuffer::new(rhs_req.and(lhs_req));
-    #[cfg(not(feature = "std"))]
-    let mut l2_slab = GlobalMemBuffer::new(StackReq::new_aligned::<f32>(
-        packed_lhs_stride * (mc / MR),
-        simd_align,
-    ));
+    let mut mem = if config.stack.is_none() {
+        #[cfg(feature = "std")]
+        let mem = gemm_common::pool::alloc(rhs_req.and(lhs_req));
+        #[cfg(not(feature = "std"))]
+        let mem = GlobalMemBuffer::new(rhs_req.and(lhs_req));
+        Some(mem)
+    } else {
+        None
+    };
 
-    let stack = DynStack::new(&mut mem);
+    let stack = match config.stack {
+        Some(stack) => stack,
+        None => DynStack::new(mem.as_mut().unwrap()),
+    };
     let (mut packed_rhs_storage, stack) =
         stack.make_aligned_uninit::<f32>(packed_rhs_stride * (nc / NR), simd_align);
 
@@ -454,21 +544,12 @@ pub unsafe fn gemm_basic_generic<
                 2
             };
 
-            let n_threads = match parallelism {
-                Parallelism::None => 1,
-                #[cfg(feature = "rayon")]
-                Parallelism::Rayon(n_threads) => {
-                    let threading_threshold = get_threading_threshold();
-                    let total_work = (m * n_chunk).saturating_mul(k_chunk);
-                    if total_work < threading_threshold {
-                        1
-                    } else {
-                        if n_threads == 0 {
-                            rayon::current_num_threads()
-                        } else {
-                            n_threads
-                        }
-                    }
+            let n_threads = {
+                let total_work = (m * n_chunk).saturating_mul(k_chunk);
+                if total_work < threading_threshold {
+                    1
+                } else {
+                    max_threads(parallelism)
                 }
             };
 
@@ -487,52 +568,49 @@ pub unsafe fn gemm_basic_generic<
                     packed_rhs_stride,
                 );
             } else {
-                #[cfg(feature = "rayon")]
-                {
-                    let n_tasks = n_chunk.msrv_div_ceil(NR);
-                    let base = n_tasks / n_threads;
-                    let rem = n_tasks % n_threads;
-
-                    let tid_to_col_inner = |tid: usize| {
-                        if tid == n_threads {
-                            return n_chunk;
-                        }
-
-                        let col = if tid < rem {
-                            NR * tid * (base + 1)
-                        } else {
-                            NR * (rem + tid * base)
-                        };
+                // each thread packs a range of whole panels, and there are no more threads than
+                // panels
+                let n_tasks = n_chunk.msrv_div_ceil(NR);
+                let n_threads = Ord::min(n_threads, n_tasks);
+                let base = n_tasks / n_threads;
+                let rem = n_tasks % n_threads;
+
+                let tid_to_col_inner = |tid: usize| {
+                    if tid == n_threads {
+                        return n_chunk;
+                    }
 
-                        col.min(n_chunk)
+                    let col = if tid < rem {
+                        NR * tid * (base + 1)
+                    } else {
+                        NR * (rem + tid * base)
                     };
 
-                    let func = |tid: usize| {
-                        let col_inner = tid_to_col_inner(tid);
-                        let ncols = tid_to_col_inner(tid + 1) - col_inner;
-                        let j = col_inner / NR;
+                    col.min(n_chunk)
+                };
 
-                        if ncols > 0 {
-                            pack_rhs::<N, NR, _>(
-                                simd,
-                                ncols,
-                                k_chunk,
-                                packed_rhs.wrapping_add(j * packed_rhs_stride),
-                                rhs.wrapping_offset(
-                                    depth_outer as isize * rhs_rs
-                                        + (col_outer + col_inner) as isize * rhs_cs,
-                                ),
-                                rhs_cs,
-                                rhs_rs,
-                                packed_rhs_stride,
-                            );
-                        }
-                    };
-                    par_for_each(n_threads, func);
-                }
+                let func = |tid: usize| {
+                    let col_inner = tid_to_col_inner(tid);
+                    let ncols = tid_to_col_inner(tid + 1) - col_inner;
+                    let j = col_inner / NR;
 
-                #[cfg(not(feature = "rayon"))]
-                unreachable!();
+                    if ncols > 0 {
+                        pack_rhs::<N, NR, _>(
+                            simd,
+                            ncols,
+                            k_chunk,
+                            packed_rhs.wrapping_add(j * packed_rhs_stride),
+                            rhs.wrapping_offset(
+                                depth_outer as isize * rhs_rs
+                                    + (col_outer + col_inner) as isize * rhs_cs,
+                            ),
+                            rhs_cs,
+                            rhs_rs,
+                            packed_rhs_stride,
+                        );
+                    }
+                };
+                par_for_each(parallelism, n_threads, func);
             }
             if do_prepack_lhs {
                 pack_lhs::<N, MR, _>(
@@ -549,6 +627,12 @@ pub unsafe fn gemm_basic_generic<
 
             let n_col_mini_chunks = (n_chunk + (NR - 1)) / NR;
 
+            // each job is a rectangle of `block_rows × block_cols` tiles of a row block, as in
+            // the generic driver
+            let (block_rows, block_cols) =
+                tile_blocks(n_threads, m.msrv_div_ceil(MR), n_col_mini_chunks, MR, NR);
+            let n_col_groups = n_col_mini_chunks.msrv_div_ceil(block_cols);
+
             let mut n_jobs = 0;
             let mut row_outer = 0;
             while row_outer != m {
@@ -557,56 +641,62 @@ pub unsafe fn gemm_basic_generic<
                     m_chunk = m_chunk / N * N;
                 }
                 let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
-                n_jobs += n_col_mini_chunks * n_row_mini_chunks;
+                n_jobs += n_row_mini_chunks.msrv_div_ceil(block_rows) * n_col_groups;
                 row_outer += m_chunk;
             }
 
             // use a single thread for small workloads
 
-            let func = move |tid, packed_lhs: Ptr<f32>| {
-                let min_jobs_per_thread = n_jobs / n_threads;
-                let rem = n_jobs - n_threads * min_jobs_per_thread;
+            let next_job = AtomicUsize::new(0);
+            let next_job = &next_job;
 
-                // thread `tid` takes min_jobs_per_thread or min_jobs_per_thread + 1
-                let (job_start, job_end) = if tid < rem {
-                    let start = tid * (min_jobs_per_thread + 1);
-                    (start, start + min_jobs_per_thread + 1)
-                } else {
-                    // start = rem * (min_jobs_per_thread + 1) + (tid - rem) * min_jobs_per_thread;
-                    let start = tid * min_jobs_per_thread + rem;
-                    (start, start + min_jobs_per_thread)
-                };
+            let func = move |_tid, packed_lhs: Ptr<f32>| {
+                // row block and group of rows that are packed in `packed_lhs`
+                let mut packed_rows = (usize::MAX, 0);
 
-                let mut row_outer = 0;
-                let mut job_id = 0;
-                while row_outer != m {
-                    let mut m_chunk = mc.min(m - row_outer);
-                    if m_chunk > N && !do_prepack_lhs {
-                        m_chunk = m_chunk / N * N;
+                loop {
+                    let job = next_job.fetch_add(1, Ordering::Relaxed);
+                    if job >= n_jobs {
+                        return;
                     }
-                    let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
 
-                    let n_mini_jobs = n_col_mini_chunks * n_row_mini_chunks;
+                    let mut row_outer = 0;
+                    let mut job_id = 0;
+                    let (m_chunk, n_row_mini_chunks, n_row_groups) = loop {
+                        let mut m_chunk = mc.min(m - row_outer);
+                        if m_chunk > N && !do_prepack_lhs {
+                            m_chunk = m_chunk / N * N;
+                        }
+                        let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
+                        let n_row_groups = n_row_mini_chunks.msrv_div_ceil(block_rows);
 
-                    if job_id >= job_end {
-                        return;
-                    }
-                    if job_id + n_mini_jobs < job_start {
+                        if job < job_id + n_row_groups * n_col_groups {
+                            break (m_chunk, n_row_mini_chunks, n_row_groups);
+                        }
                         row_outer += m_chunk;
-                        job_id += n_mini_jobs;
-                        continue;
-                    }
+                        job_id += n_row_groups * n_col_groups;
+                    };
+                    let row_group = (job - job_id) % n_row_groups;
+                    let col_group = (job - job_id) / n_row_groups;
 
                     let packed_lhs_cs = MR as isize;
 
-                    if !do_prepack_lhs {
+                    let cols = col_group * block_cols
+                        ..Ord::min((col_group + 1) * block_cols, n_col_mini_chunks);
+                    let rows = row_group * block_rows
+                        ..Ord::min((row_group + 1) * block_rows, n_row_mini_chunks);
+
+                    if !do_prepack_lhs && packed_rows != (row_outer, row_group) {
+                        packed_rows = (row_outer, row_group);
+                        let row_inner = rows.start * MR;
                         pack_lhs::<N, MR, _>(
                             simd,
-                            m_chunk,
+                            Ord::min(rows.end * MR, m_chunk) - row_inner,
                             k_chunk,
-                            packed_lhs,
+                            packed_lhs.wrapping_add(rows.start * packed_lhs_stride),
                             lhs.wrapping_offset(
-                                row_outer as isize * lhs_rs + depth_outer as isize * lhs_cs,
+                                (row_outer + row_inner) as isize * lhs_rs
+                                    + depth_outer as isize * lhs_cs,
                             ),
                             lhs_cs,
                             lhs_rs,
@@ -614,24 +704,14 @@ pub unsafe fn gemm_basic_generic<
                         );
                     }
 
-                    let mut j = 0;
-                    while j < n_col_mini_chunks {
-                        let mut i = 0;
-                        while i < n_row_mini_chunks {
+                    for j in cols {
+                        for i in rows.clone() {
                             let col_inner = NR * j;
                             let n_chunk_inner = NR.min(n_chunk - col_inner);
 
                             let row_inner = MR * i;
                             let m_chunk_inner = MR.min(m_chunk - row_inner);
 
-                            let inner_idx = &mut i;
-                            if job_id < job_start || job_id >= job_end {
-                                job_id += 1;
-                                *inner_idx += 1;
-                                continue;
-                            }
-                            job_id += 1;
-
                             let dst = dst.wrapping_offset(
                                 (row_outer + row_inner) as isize * dst_rs
                                     + (col_outer + col_inner) as isize * dst_cs,
@@ -705,27 +785,29 @@ pub unsafe fn gemm_basic_generic<
                                     }
                                 }
                             }
-
-                            i += 1;
+                            if let Some(epilogue) = epilogue {
+                                if depth_outer + k_chunk == k {
+                                    epilogue.apply(
+                                        dst.0,
+                                        m_chunk_inner,
+                                        n_chunk_inner,
+                                        dst_cs,
+                                        dst_rs,
+                                        row_outer + row_inner,
+                                        col_outer + col_inner,
+                                    );
+                                }
+                            }
                         }
-                        j += 1;
                     }
-
-                    row_outer += m_chunk;
                 }
             };
 
             if do_prepack_lhs {
-                match parallelism {
-                    Parallelism::None => func(0, prepacked_lhs),
-                    #[cfg(feature = "rayon")]
-                    Parallelism::Rayon(_) => {
-                        if n_threads == 1 {
-                            func(0, prepacked_lhs);
-                        } else {
-                            par_for_each(n_threads, |tid| func(tid, prepacked_lhs));
-                        }
-                    }
+                if n_threads == 1 {
+                    func(0, prepacked_lhs);
+                } else {
+                    par_for_each(parallelism, n_threads, |tid| func(tid, prepacked_lhs));
                 }
             } else {
                 #[cfg(feature = "std")]
@@ -740,8 +822,13 @@ pub unsafe fn gemm_basic_generic<
                     });
                 };
 
+                // without thread locals, each call allocates its own buffer
                 #[cfg(not(feature = "std"))]
-                let mut func = |tid: usize| {
+                let func = |tid: usize| {
+                    let mut l2_slab = GlobalMemBuffer::new(StackReq::new_aligned::<f32>(
+                        packed_lhs_stride * (mc / MR),
+                        simd_align,
+                    ));
                     let stack = DynStack::new(&mut l2_slab);
                     let (mut packed_lhs_storage, _) =
                         stack.make_aligned_uninit::<f32>(packed_lhs_stride * (mc / MR), simd_align);
@@ -749,16 +836,10 @@ pub unsafe fn gemm_basic_generic<
                     func(tid, packed_lhs);
                 };
 
-                match parallelism {
-                    Parallelism::None => func(0),
-                    #[cfg(feature = "rayon")]
-                    Parallelism::Rayon(_) => {
-                        if n_threads == 1 {
-                            func(0);
-                        } else {
-                            par_for_each(n_threads, func);
-                        }
-                    }
+                if n_threads == 1 {
+                    func(0);
+                } else {
+                    par_for_each(parallelism, n_threads, func);
                 }
             }
 
@@ -771,7 +852,10 @@ pub unsafe fn gemm_basic_generic<
 
 pub mod f16 {
     use super::gemm_basic_generic;
-    use gemm_common::Parallelism;
+    use gemm_common::{
+        gemm::{Backend, BackendRegistry, GemmConfig},
+        Parallelism,
+    };
 
     type T = half::f16;
     type GemmTy = unsafe fn(
@@ -794,19 +878,21 @@ pub mod f16 {
         bool,
         bool,
         Parallelism,
+        GemmConfig<'_, T>,
     );
+    type PackTy = unsafe fn(usize, usize, *mut T, *const T, isize, isize, usize);
 
-    fn init_gemm_fn() -> GemmTy {
+    fn init_backend() -> &'static Backend<GemmTy, PackTy> {
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
         {
             #[cfg(feature = "nightly")]
             if gemm_common::feature_detected!("avx512f") {
-                return avx512f::gemm_basic;
+                return &avx512f::BACKEND;
             }
             if gemm_common::feature_detected!("fma") {
-                fma::gemm_basic
+                &fma::BACKEND
             } else {
-                scalar::gemm_basic
+                &scalar::BACKEND
             }
         }
 
@@ -815,41 +901,77 @@ pub mod f16 {
             if gemm_common::feature_detected!("neon") {
                 #[cfg(feature = "experimental-apple-amx")]
                 if gemm_common::cache::HasAmx::get() {
-                    return amx::gemm_basic;
+                    return &amx::BACKEND;
                 }
                 if gemm_common::feature_detected!("fp16") {
-                    neonfp16::gemm_basic
+                    &neonfp16::BACKEND
                 } else {
-                    neon::gemm_basic
+                    &neon::BACKEND
                 }
             } else {
-                scalar::gemm_basic
+                &scalar::BACKEND
             }
         }
 
         #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
         {
-            scalar::gemm_basic
+            &scalar::BACKEND
         }
     }
 
-    static GEMM_PTR: ::core::sync::atomic::AtomicPtr<()> =
-        ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut());
+    static REGISTRY: BackendRegistry<GemmTy, PackTy> = BackendRegistry::new();
+
+    /// Returns the registered backend with the highest priority, by default the fastest built-in
+    /// backend supported by the current machine.
+    #[inline(always)]
+    pub fn get_backend() -> &'static Backend<GemmTy, PackTy> {
+        REGISTRY.get(init_backend)
+    }
+
+    /// Registers an additional backend, which becomes the active one if `priority` is at least
+    /// as high as the priority of the active backend. Built-in backends have priority
+    /// [`DEFAULT_BACKEND_PRIORITY`](gemm_common::gemm::DEFAULT_BACKEND_PRIORITY).
+    pub fn register_backend(backend: &'static Backend<GemmTy, PackTy>, priority: i32) {
+        REGISTRY.register(backend, priority, init_backend)
+    }
+
+    /// Forgets the registered backends, so that the fastest built-in one is used again.
+    pub fn reset_backend() {
+        REGISTRY.reset()
+    }
+
+    /// Calls `f` with each built-in backend supported by the current machine, including the ones
+    /// that aren't selected by default, from the most portable to the fastest.
+    pub fn for_each_builtin_backend(mut f: impl FnMut(&'static Backend<GemmTy, PackTy>)) {
+        f(&scalar::BACKEND);
 
-    #[inline(never)]
-    fn init_gemm_ptr() -> GemmTy {
-        let gemm_fn = init_gemm_fn();
-        GEMM_PTR.store(gemm_fn as *mut (), ::core::sync::atomic::Ordering::Relaxed);
-        gemm_fn
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if gemm_common::feature_detected!("fma") {
+                f(&fma::BACKEND);
+            }
+            #[cfg(feature = "nightly")]
+            if gemm_common::feature_detected!("avx512f") {
+                f(&avx512f::BACKEND);
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        if gemm_common::feature_detected!("neon") {
+            f(&neon::BACKEND);
+            if gemm_common::feature_detected!("fp16") {
+                f(&neonfp16::BACKEND);
+            }
+            #[cfg(feature = "experimental-apple-amx")]
+            if gemm_common::cache::HasAmx::get() {
+                f(&amx::BACKEND);
+            }
+        }
     }
 
     #[inline(always)]
     pub fn get_gemm_fn() -> GemmTy {
-        let mut gemm_fn = GEMM_PTR.load(::core::sync::atomic::Ordering::Relaxed);
-        if gemm_fn.is_null() {
-            gemm_fn = init_gemm_ptr() as *mut ();
-        }
-        unsafe { ::core::mem::transmute(gemm_fn) }
+        get_backend().gemm
     }
 
     mod scalar {
@@ -878,7 +1000,8 @@ pub mod f16 {
             _conj_dst: bool,
             _conj_lhs: bool,
             _conj_rhs: bool,
-            parallelism: gemm_common::Parallelism,
+            parallelism: gemm_common::Parallelism<'_>,
+            config: GemmConfig<'_, T>,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N, _>(
                 Scalar,
@@ -899,8 +1022,50 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                config,
             );
         }
+
+        pub fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            super::super::gemm_kernel_params(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (MR_DIV_N * N, NR, kernel_params(m, n, k, parallelism))
+        }
+
+        pub fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            super::super::gemm_req(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub static BACKEND: Backend<GemmTy, PackTy> = Backend {
+            name: "scalar",
+            gemm: gemm_basic,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: MR_DIV_N * N,
+            nr: NR,
+            simd_bytes: N * core::mem::size_of::<f32>(),
+            // packing ahead of time isn't supported for f16
+            pack_lhs: None,
+            pack_rhs: None,
+        };
     }
 
     #[cfg(target_arch = "aarch64")]
@@ -930,7 +1095,8 @@ pub mod f16 {
             _conj_dst: bool,
             _conj_lhs: bool,
             _conj_rhs: bool,
-            parallelism: gemm_common::Parallelism,
+            parallelism: gemm_common::Parallelism<'_>,
+            config: GemmConfig<'_, T>,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N, _>(
                 gemm_common::simd::Neon::try_new().unwrap(),
@@ -951,14 +1117,62 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                config,
             );
         }
+
+        pub fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            super::super::gemm_kernel_params(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (MR_DIV_N * N, NR, kernel_params(m, n, k, parallelism))
+        }
+
+        pub fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            super::super::gemm_req(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub static BACKEND: Backend<GemmTy, PackTy> = Backend {
+            name: "neon",
+            gemm: gemm_basic,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: MR_DIV_N * N,
+            nr: NR,
+            simd_bytes: N * core::mem::size_of::<f32>(),
+            // packing ahead of time isn't supported for f16
+            pack_lhs: None,
+            pack_rhs: None,
+        };
     }
 
     #[cfg(target_arch = "aarch64")]
     mod neonfp16 {
         use crate::microkernel::neonfp16::f16::*;
         use gemm_common::simd::{MixedSimd, NeonFp16};
+        use gemm_common::{
+            gemm::{Backend, GemmConfig},
+            Parallelism,
+        };
+        type GemmTy = super::GemmTy;
+        type PackTy = super::PackTy;
         type T = half::f16;
 
         #[inline(never)]
@@ -981,7 +1195,8 @@ pub mod f16 {
             _conj_dst: bool,
             _conj_lhs: bool,
             _conj_rhs: bool,
-            parallelism: gemm_common::Parallelism,
+            parallelism: gemm_common::Parallelism<'_>,
+            config: GemmConfig<'_, T>,
         ) {
             let simd = <NeonFp16 as MixedSimd<T, T, T, T>>::try_new().unwrap();
 
@@ -1008,9 +1223,59 @@ pub mod f16 {
                 move |a, b, c| <NeonFp16 as MixedSimd<T, T, T, T>>::mult_add(simd, a, b, c),
                 &UKR,
                 false,
+                false,
                 parallelism,
+                config,
             );
         }
+
+        pub fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            gemm_common::gemm::gemm_kernel_params::<T>(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (MR_DIV_N * N, NR, kernel_params(m, n, k, parallelism))
+        }
+
+        pub fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            gemm_common::gemm::gemm_req_generic::<T>(
+                m,
+                n,
+                k,
+                MR_DIV_N * N,
+                NR,
+                kernel_params(m, n, k, parallelism),
+            )
+        }
+
+        pub static BACKEND: Backend<GemmTy, PackTy> = Backend {
+            name: "neonfp16",
+            gemm: gemm_basic,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: MR_DIV_N * N,
+            nr: NR,
+            simd_bytes: N * core::mem::size_of::<T>(),
+            // packing ahead of time isn't supported for f16
+            pack_lhs: None,
+            pack_rhs: None,
+        };
     }
 
     #[cfg(target_arch = "aarch64")]
@@ -1018,6 +1283,12 @@ pub mod f16 {
     mod amx {
         use crate::microkernel::amx::f16::*;
         use gemm_common::simd::{MixedSimd, NeonFp16};
+        use gemm_common::{
+            gemm::{Backend, GemmConfig},
+            Parallelism,
+        };
+        type GemmTy = super::GemmTy;
+        type PackTy = super::PackTy;
         type T = half::f16;
 
         #[inline(never)]
@@ -1040,7 +1311,8 @@ pub mod f16 {
             _conj_dst: bool,
             _conj_lhs: bool,
             _conj_rhs: bool,
-            parallelism: gemm_common::Parallelism,
+            parallelism: gemm_common::Parallelism<'_>,
+            config: GemmConfig<'_, T>,
         ) {
             let simd = <NeonFp16 as MixedSimd<T, T, T, T>>::try_new().unwrap();
 
@@ -1067,9 +1339,59 @@ pub mod f16 {
                 move |a, b, c| <NeonFp16 as MixedSimd<T, T, T, T>>::mult_add(simd, a, b, c),
                 &UKR,
                 true,
+                false,
                 parallelism,
+                config,
             );
         }
+
+        pub fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            gemm_common::gemm::gemm_kernel_params::<T>(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (MR_DIV_N * N, NR, kernel_params(m, n, k, parallelism))
+        }
+
+        pub fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            gemm_common::gemm::gemm_req_generic::<T>(
+                m,
+                n,
+                k,
+                MR_DIV_N * N,
+                NR,
+                kernel_params(m, n, k, parallelism),
+            )
+        }
+
+        pub static BACKEND: Backend<GemmTy, PackTy> = Backend {
+            name: "amx",
+            gemm: gemm_basic,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: MR_DIV_N * N,
+            nr: NR,
+            simd_bytes: N * core::mem::size_of::<T>(),
+            // packing ahead of time isn't supported for f16
+            pack_lhs: None,
+            pack_rhs: None,
+        };
     }
 
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
@@ -1099,7 +1421,8 @@ pub mod f16 {
             _conj_dst: bool,
             _conj_lhs: bool,
             _conj_rhs: bool,
-            parallelism: gemm_common::Parallelism,
+            parallelism: gemm_common::Parallelism<'_>,
+            config: GemmConfig<'_, T>,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N, _>(
                 V3::try_new().unwrap(),
@@ -1120,8 +1443,50 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                config,
             );
         }
+
+        pub fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            super::super::gemm_kernel_params(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (MR_DIV_N * N, NR, kernel_params(m, n, k, parallelism))
+        }
+
+        pub fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            super::super::gemm_req(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub static BACKEND: Backend<GemmTy, PackTy> = Backend {
+            name: "fma",
+            gemm: gemm_basic,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: MR_DIV_N * N,
+            nr: NR,
+            simd_bytes: N * core::mem::size_of::<f32>(),
+            // packing ahead of time isn't supported for f16
+            pack_lhs: None,
+            pack_rhs: None,
+        };
     }
 
     #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
@@ -1151,7 +1516,8 @@ pub mod f16 {
             _conj_dst: bool,
             _conj_lhs: bool,
             _conj_rhs: bool,
-            parallelism: gemm_common::Parallelism,
+            parallelism: gemm_common::Parallelism<'_>,
+            config: GemmConfig<'_, T>,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N, _>(
                 V4::try_new().unwrap(),
@@ -1172,7 +1538,49 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                config,
             );
         }
+
+        pub fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            super::super::gemm_kernel_params(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (MR_DIV_N * N, NR, kernel_params(m, n, k, parallelism))
+        }
+
+        pub fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            super::super::gemm_req(m, n, k, MR_DIV_N * N, NR, parallelism)
+        }
+
+        pub static BACKEND: Backend<GemmTy, PackTy> = Backend {
+            name: "avx512f",
+            gemm: gemm_basic,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: MR_DIV_N * N,
+            nr: NR,
+            simd_bytes: N * core::mem::size_of::<f32>(),
+            // packing ahead of time isn't supported for f16
+            pack_lhs: None,
+            pack_rhs: None,
+        };
     }
 }
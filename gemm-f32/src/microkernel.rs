@@ -104,24 +104,71 @@ pub mod fma {
             gemm_common::simd::v3_fmaf(a, b, c)
         }
 
-        microkernel!(["fma"], 2, x1x1, 1, 1);
-        microkernel!(["fma"], 2, x1x2, 1, 2);
-        microkernel!(["fma"], 2, x1x3, 1, 3);
-        microkernel!(["fma"], 2, x1x4, 1, 4);
-        microkernel!(["fma"], 2, x1x5, 1, 5);
-        microkernel!(["fma"], 2, x1x6, 1, 6);
-
-        microkernel!(["fma"], 2, x2x1, 2, 1);
-        microkernel!(["fma"], 2, x2x2, 2, 2);
-        microkernel!(["fma"], 2, x2x3, 2, 3);
-        microkernel!(["fma"], 2, x2x4, 2, 4);
-        microkernel!(["fma"], 2, x2x5, 2, 5);
-        microkernel!(["fma"], 2, x2x6, 2, 6);
+        #[inline(always)]
+        unsafe fn load_aligned(ptr: *const T) -> Pack {
+            transmute(_mm256_load_ps(ptr))
+        }
+
+        #[inline(always)]
+        unsafe fn store_aligned(ptr: *mut T, value: Pack) {
+            _mm256_store_ps(ptr, transmute(value))
+        }
+
+        // the 16×6 kernel keeps 12 accumulators, 2 lhs vectors and a broadcast rhs element in the
+        // 16 ymm registers. the destination is column-major by the time it reaches the kernels, so
+        // this is the 6×16 blocking of row-major implementations such as BLIS and oneDNN, with the
+        // same reuse of each loaded vector
+        microkernel!(["fma"], 2, x1x1, 1, 1; aligned);
+        microkernel!(["fma"], 2, x1x2, 1, 2; aligned);
+        microkernel!(["fma"], 2, x1x3, 1, 3; aligned);
+        microkernel!(["fma"], 2, x1x4, 1, 4; aligned);
+        microkernel!(["fma"], 2, x1x5, 1, 5; aligned);
+        microkernel!(["fma"], 2, x1x6, 1, 6; aligned);
+
+        microkernel!(["fma"], 2, x2x1, 2, 1; aligned);
+        microkernel!(["fma"], 2, x2x2, 2, 2; aligned);
+        microkernel!(["fma"], 2, x2x3, 2, 3; aligned);
+        microkernel!(["fma"], 2, x2x4, 2, 4; aligned);
+        microkernel!(["fma"], 2, x2x5, 2, 5; aligned);
+        microkernel!(["fma"], 2, x2x6, 2, 6; aligned);
 
         microkernel_fn_array! {
             [x1x1, x1x2, x1x3, x1x4, x1x5, x1x6,],
             [x2x1, x2x2, x2x3, x2x4, x2x5, x2x6,],
         }
+
+        // the tall and wide blockings, for problems with few columns or few rows, keep as many
+        // accumulators as the square one in the 16 ymm registers
+        microkernel!(["fma"], 2, x1x7, 1, 7; aligned);
+        microkernel!(["fma"], 2, x1x8, 1, 8; aligned);
+        microkernel!(["fma"], 2, x1x9, 1, 9; aligned);
+        microkernel!(["fma"], 2, x1x10, 1, 10; aligned);
+        microkernel!(["fma"], 2, x1x11, 1, 11; aligned);
+        microkernel!(["fma"], 2, x1x12, 1, 12; aligned);
+
+        microkernel!(["fma"], 2, x3x1, 3, 1; aligned);
+        microkernel!(["fma"], 2, x3x2, 3, 2; aligned);
+        microkernel!(["fma"], 2, x3x3, 3, 3; aligned);
+        microkernel!(["fma"], 2, x3x4, 3, 4; aligned);
+
+        microkernel_fn_array! {
+            TALL_MR_DIV_N, TALL_NR, TALL_UKR;
+            [x1x1, x1x2, x1x3, x1x4,],
+            [x2x1, x2x2, x2x3, x2x4,],
+            [x3x1, x3x2, x3x3, x3x4,],
+        }
+
+        microkernel_fn_array! {
+            WIDE_MR_DIV_N, WIDE_NR, WIDE_UKR;
+            [x1x1, x1x2, x1x3, x1x4, x1x5, x1x6, x1x7, x1x8, x1x9, x1x10, x1x11, x1x12,],
+        }
+
+        // the small blocking splits products into twice as many blocks as the square one, for
+        // multithreaded products that have fewer blocks than threads
+        microkernel_fn_array! {
+            SMALL_MR_DIV_N, SMALL_NR, SMALL_UKR;
+            [x1x1, x1x2, x1x3, x1x4, x1x5, x1x6,],
+        }
     }
 }
 
@@ -173,33 +220,53 @@ pub mod avx512f {
             gemm_common::simd::v3_fmaf(a, b, c)
         }
 
-        microkernel!(["avx512f"], 4, x1x1, 1, 1);
-        microkernel!(["avx512f"], 4, x1x2, 1, 2);
-        microkernel!(["avx512f"], 4, x1x3, 1, 3);
-        microkernel!(["avx512f"], 4, x1x4, 1, 4);
-        microkernel!(["avx512f"], 4, x1x5, 1, 5);
-        microkernel!(["avx512f"], 4, x1x6, 1, 6);
-
-        microkernel!(["avx512f"], 4, x2x1, 2, 1);
-        microkernel!(["avx512f"], 4, x2x2, 2, 2);
-        microkernel!(["avx512f"], 4, x2x3, 2, 3);
-        microkernel!(["avx512f"], 4, x2x4, 2, 4);
-        microkernel!(["avx512f"], 4, x2x5, 2, 5);
-        microkernel!(["avx512f"], 4, x2x6, 2, 6);
-
-        microkernel!(["avx512f"], 4, x3x1, 3, 1);
-        microkernel!(["avx512f"], 4, x3x2, 3, 2);
-        microkernel!(["avx512f"], 4, x3x3, 3, 3);
-        microkernel!(["avx512f"], 4, x3x4, 3, 4);
-        microkernel!(["avx512f"], 4, x3x5, 3, 5);
-        microkernel!(["avx512f"], 4, x3x6, 3, 6);
-
-        microkernel!(["avx512f"], 4, x4x1, 4, 1);
-        microkernel!(["avx512f"], 4, x4x2, 4, 2);
-        microkernel!(["avx512f"], 4, x4x3, 4, 3);
-        microkernel!(["avx512f"], 4, x4x4, 4, 4);
-        microkernel!(["avx512f"], 4, x4x5, 4, 5);
-        microkernel!(["avx512f"], 4, x4x6, 4, 6);
+        #[inline(always)]
+        unsafe fn load_aligned(ptr: *const T) -> Pack {
+            transmute(_mm512_load_ps(ptr))
+        }
+
+        #[inline(always)]
+        unsafe fn store_aligned(ptr: *mut T, value: Pack) {
+            _mm512_store_ps(ptr, transmute(value))
+        }
+
+        #[inline(always)]
+        unsafe fn masked_load(ptr: *const T, mask: u32) -> Pack {
+            transmute(_mm512_maskz_loadu_ps(mask as __mmask16, ptr))
+        }
+
+        #[inline(always)]
+        unsafe fn masked_store(ptr: *mut T, mask: u32, value: Pack) {
+            _mm512_mask_storeu_ps(ptr, mask as __mmask16, transmute(value))
+        }
+
+        microkernel!(["avx512f"], 4, x1x1, 1, 1; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x2, 1, 2; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x3, 1, 3; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x4, 1, 4; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x5, 1, 5; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x6, 1, 6; masked, aligned);
+
+        microkernel!(["avx512f"], 4, x2x1, 2, 1; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x2, 2, 2; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x3, 2, 3; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x4, 2, 4; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x5, 2, 5; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x6, 2, 6; masked, aligned);
+
+        microkernel!(["avx512f"], 4, x3x1, 3, 1; masked, aligned);
+        microkernel!(["avx512f"], 4, x3x2, 3, 2; masked, aligned);
+        microkernel!(["avx512f"], 4, x3x3, 3, 3; masked, aligned);
+        microkernel!(["avx512f"], 4, x3x4, 3, 4; masked, aligned);
+        microkernel!(["avx512f"], 4, x3x5, 3, 5; masked, aligned);
+        microkernel!(["avx512f"], 4, x3x6, 3, 6; masked, aligned);
+
+        microkernel!(["avx512f"], 4, x4x1, 4, 1; masked, aligned);
+        microkernel!(["avx512f"], 4, x4x2, 4, 2; masked, aligned);
+        microkernel!(["avx512f"], 4, x4x3, 4, 3; masked, aligned);
+        microkernel!(["avx512f"], 4, x4x4, 4, 4; masked, aligned);
+        microkernel!(["avx512f"], 4, x4x5, 4, 5; masked, aligned);
+        microkernel!(["avx512f"], 4, x4x6, 4, 6; masked, aligned);
 
         microkernel_fn_array! {
             [x1x1, x1x2, x1x3, x1x4, x1x5, x1x6,],
@@ -207,6 +274,56 @@ pub mod avx512f {
             [x3x1, x3x2, x3x3, x3x4, x3x5, x3x6,],
             [x4x1, x4x2, x4x3, x4x4, x4x5, x4x6,],
         }
+
+        // the tall and wide blockings, for problems with few columns or few rows, keep as many
+        // accumulators as the square one in the 32 zmm registers
+        microkernel!(["avx512f"], 4, x1x7, 1, 7; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x8, 1, 8; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x9, 1, 9; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x10, 1, 10; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x11, 1, 11; masked, aligned);
+        microkernel!(["avx512f"], 4, x1x12, 1, 12; masked, aligned);
+
+        microkernel!(["avx512f"], 4, x2x7, 2, 7; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x8, 2, 8; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x9, 2, 9; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x10, 2, 10; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x11, 2, 11; masked, aligned);
+        microkernel!(["avx512f"], 4, x2x12, 2, 12; masked, aligned);
+
+        microkernel!(["avx512f"], 4, x5x1, 5, 1; masked, aligned);
+        microkernel!(["avx512f"], 4, x5x2, 5, 2; masked, aligned);
+        microkernel!(["avx512f"], 4, x5x3, 5, 3; masked, aligned);
+        microkernel!(["avx512f"], 4, x5x4, 5, 4; masked, aligned);
+
+        microkernel!(["avx512f"], 4, x6x1, 6, 1; masked, aligned);
+        microkernel!(["avx512f"], 4, x6x2, 6, 2; masked, aligned);
+        microkernel!(["avx512f"], 4, x6x3, 6, 3; masked, aligned);
+        microkernel!(["avx512f"], 4, x6x4, 6, 4; masked, aligned);
+
+        microkernel_fn_array! {
+            TALL_MR_DIV_N, TALL_NR, TALL_UKR;
+            [x1x1, x1x2, x1x3, x1x4,],
+            [x2x1, x2x2, x2x3, x2x4,],
+            [x3x1, x3x2, x3x3, x3x4,],
+            [x4x1, x4x2, x4x3, x4x4,],
+            [x5x1, x5x2, x5x3, x5x4,],
+            [x6x1, x6x2, x6x3, x6x4,],
+        }
+
+        microkernel_fn_array! {
+            WIDE_MR_DIV_N, WIDE_NR, WIDE_UKR;
+            [x1x1, x1x2, x1x3, x1x4, x1x5, x1x6, x1x7, x1x8, x1x9, x1x10, x1x11, x1x12,],
+            [x2x1, x2x2, x2x3, x2x4, x2x5, x2x6, x2x7, x2x8, x2x9, x2x10, x2x11, x2x12,],
+        }
+
+        // the small blocking splits products into twice as many blocks as the square one, for
+        // multithreaded products that have fewer blocks than threads
+        microkernel_fn_array! {
+            SMALL_MR_DIV_N, SMALL_NR, SMALL_UKR;
+            [x1x1, x1x2, x1x3, x1x4, x1x5, x1x6,],
+            [x2x1, x2x2, x2x3, x2x4, x2x5, x2x6,],
+        }
     }
 }
 
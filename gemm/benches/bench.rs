@@ -81,7 +81,17 @@ fn bench_gemm<T: One + Copy + 'static>(
     })
 }
 
-fn args() -> Vec<List![Parallelism, Layout, Layout, Layout, usize, usize, usize]> {
+fn args() -> Vec<
+    List![
+        Parallelism<'static>,
+        Layout,
+        Layout,
+        Layout,
+        usize,
+        usize,
+        usize
+    ],
+> {
     use itertools::Itertools;
     let pow2 = |i| 1usize << i;
     let halfway = |i| 3usize << (i - 1);
@@ -0,0 +1,169 @@
+//! Criterion benchmarks of the dispatch paths of `gemm`, with stable ids of the form
+//! `<path>/<dtype>/<shape>`, so that runs of different commits can be compared with
+//! `--save-baseline` and `--baseline`.
+//!
+//! ```text
+//! cargo bench --features criterion-bench --bench dispatch
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gemm::*;
+use num_traits::{One, Zero};
+
+/// Runs `dst := lhs×rhs` with column-major operands.
+fn run<T: 'static + Copy + One + Zero>(
+    dst: &mut [T],
+    lhs: &[T],
+    rhs: &[T],
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism,
+) {
+    unsafe {
+        gemm(
+            m,
+            n,
+            k,
+            dst.as_mut_ptr(),
+            m as isize,
+            1,
+            false,
+            lhs.as_ptr(),
+            m as isize,
+            1,
+            rhs.as_ptr(),
+            k as isize,
+            1,
+            T::zero(),
+            T::one(),
+            false,
+            false,
+            false,
+            parallelism,
+        )
+    }
+}
+
+fn bench_shapes<T: 'static + Copy + One + Zero>(
+    c: &mut Criterion,
+    path: &str,
+    dtype: &str,
+    shapes: &[(usize, usize, usize)],
+) {
+    let mut group = c.benchmark_group(format!("{path}/{dtype}"));
+    for &(m, n, k) in shapes {
+        let lhs = vec![T::one(); m * k];
+        let rhs = vec![T::one(); k * n];
+        let mut dst = vec![T::zero(); m * n];
+        group.throughput(Throughput::Elements((m * n * k) as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{m}x{n}x{k}")), |b| {
+            b.iter(|| run(&mut dst, &lhs, &rhs, m, n, k, Parallelism::None))
+        });
+    }
+    group.finish();
+}
+
+/// Matrix-vector products, computed by the gemv kernels.
+fn gemv(c: &mut Criterion) {
+    let shapes = [
+        (256, 1, 256),
+        (1024, 1, 1024),
+        (4096, 1, 4096),
+        (1024, 4, 1024),
+    ];
+    bench_shapes::<f32>(c, "gemv", "f32", &shapes);
+    bench_shapes::<f64>(c, "gemv", "f64", &shapes);
+}
+
+/// Vector-matrix products, computed by the gevm kernels.
+fn gevm(c: &mut Criterion) {
+    let shapes = [
+        (1, 256, 256),
+        (1, 1024, 1024),
+        (1, 4096, 4096),
+        (4, 1024, 1024),
+    ];
+    bench_shapes::<f32>(c, "gevm", "f32", &shapes);
+    bench_shapes::<f64>(c, "gevm", "f64", &shapes);
+}
+
+/// Outer products, whose depth `k == 1` is computed by the gevv kernels.
+fn outer(c: &mut Criterion) {
+    let shapes = [(256, 256, 1), (1024, 1024, 1), (1024, 1024, 2)];
+    bench_shapes::<f32>(c, "outer", "f32", &shapes);
+    bench_shapes::<f64>(c, "outer", "f64", &shapes);
+}
+
+/// Products computed by the microkernels, packing the operands on each call.
+fn blocked(c: &mut Criterion) {
+    let shapes = [(64, 64, 64), (256, 256, 256), (1024, 1024, 1024)];
+    bench_shapes::<f32>(c, "blocked", "f32", &shapes);
+    bench_shapes::<f64>(c, "blocked", "f64", &shapes);
+    bench_shapes::<c32>(c, "blocked", "c32", &shapes);
+    bench_shapes::<c64>(c, "blocked", "c64", &shapes);
+}
+
+/// Products whose operands were packed ahead of time.
+fn packed(c: &mut Criterion) {
+    fn bench<T: 'static + Copy + One + Zero>(c: &mut Criterion, dtype: &str) {
+        let mut group = c.benchmark_group(format!("packed/{dtype}"));
+        for size in [64, 256, 1024] {
+            let lhs = vec![T::one(); size * size];
+            let rhs = vec![T::one(); size * size];
+            let mut dst = vec![T::zero(); size * size];
+            let packed_lhs = pack_lhs(MatRef::from_col_major_slice(&lhs, size, size));
+            let packed_rhs = pack_rhs(MatRef::from_col_major_slice(&rhs, size, size));
+            group.throughput(Throughput::Elements((size * size * size) as u64));
+            group.bench_function(
+                BenchmarkId::from_parameter(format!("{size}x{size}x{size}")),
+                |b| {
+                    b.iter(|| {
+                        gemm_prepacked(
+                            MatMut::from_col_major_slice(&mut dst, size, size),
+                            false,
+                            &packed_lhs,
+                            &packed_rhs,
+                            T::zero(),
+                            T::one(),
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                        )
+                    })
+                },
+            );
+        }
+        group.finish();
+    }
+
+    bench::<f32>(c, "f32");
+    bench::<f64>(c, "f64");
+}
+
+/// Products computed by each built-in backend supported by the machine, forced with
+/// `register_backend`, whose ids are named after the backends.
+fn backends(c: &mut Criterion) {
+    fn bench<T: 'static + Copy + One + Zero>(c: &mut Criterion, dtype: &str) {
+        let mut backends = Vec::new();
+        for_each_builtin_backend::<T>(|backend| backends.push(backend));
+
+        for backend in backends {
+            register_backend::<T>(backend, i32::MAX);
+            bench_shapes::<T>(
+                c,
+                &format!("backend/{}", backend.name),
+                dtype,
+                &[(256, 256, 256), (1024, 1024, 1024)],
+            );
+            reset_backend::<T>();
+        }
+    }
+
+    bench::<f32>(c, "f32");
+    bench::<f64>(c, "f64");
+}
+
+criterion_group!(benches, gemv, gevm, outer, blocked, packed, backends);
+criterion_main!(benches);
@@ -0,0 +1,164 @@
+//! Criterion benchmarks of the microkernels in isolation, which call each `xMxN` function of the
+//! backends supported by the machine on panels packed ahead of time, without the drivers and the
+//! packing. The ids have the form `microkernel/<backend>/<dtype>/x<MR/N>x<NR>`, where `N` is the
+//! number of scalars in a vector of the backend, and the throughputs count floating-point
+//! operations, so that the reported `elem/s` are FLOPS.
+//!
+//! ```text
+//! cargo bench --features criterion-bench --bench microkernel
+//! cargo bench --features criterion-bench --bench microkernel -- microkernel/fma/f32
+//! ```
+
+use aligned_vec::{avec, AVec};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gemm_common::microkernel::MicroKernelFn;
+use num_traits::{One, Zero};
+
+/// Depth of the panels, which is the order of the depth of the blocks of the drivers.
+const KC: usize = 256;
+
+/// Microkernel computing an `mr×nr` block of the destination.
+struct Kernel<T> {
+    name: String,
+    mr: usize,
+    nr: usize,
+    ukr: MicroKernelFn<T>,
+}
+
+/// Appends the kernels of a table of `ukr`, whose rows hold `1..=MR_DIV_N` vectors of `n`
+/// scalars and columns `1..=NR` columns, except for the ones already in `kernels`, since the
+/// tables of the additional register blockings share their smaller kernels.
+fn push_kernels<T, const NR: usize, const MR_DIV_N: usize>(
+    kernels: &mut Vec<Kernel<T>>,
+    n: usize,
+    ukr: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
+) {
+    for (i, row) in ukr.iter().enumerate() {
+        for (j, &ukr) in row.iter().enumerate() {
+            let name = format!("x{}x{}", i + 1, j + 1);
+            if kernels.iter().all(|kernel| kernel.name != name) {
+                kernels.push(Kernel {
+                    name,
+                    mr: (i + 1) * n,
+                    nr: j + 1,
+                    ukr,
+                });
+            }
+        }
+    }
+}
+
+/// Times each of `kernels` on an `mr×KC` lhs panel and a `KC×nr` rhs panel, accumulating into an
+/// `mr×nr` column-major destination, as the drivers do for the full blocks.
+fn bench_kernels<T: 'static + Copy + One + Zero>(
+    c: &mut Criterion,
+    backend: &str,
+    dtype: &str,
+    kernels: &[Kernel<T>],
+) {
+    let mut group = c.benchmark_group(format!("microkernel/{backend}/{dtype}"));
+    for kernel in kernels {
+        let &Kernel { mr, nr, ukr, .. } = kernel;
+        // the panels are aligned to a cache line, as the packing buffers of the drivers, and the
+        // zero lhs keeps the accumulated destination from overflowing
+        let lhs: AVec<T> = avec![T::zero(); mr * KC];
+        let rhs: AVec<T> = avec![T::one(); KC * nr];
+        let mut dst: AVec<T> = avec![T::zero(); mr * nr];
+
+        group.throughput(Throughput::Elements((2 * mr * nr * KC) as u64));
+        group.bench_function(BenchmarkId::from_parameter(&kernel.name), |b| {
+            b.iter(|| unsafe {
+                ukr(
+                    mr,
+                    nr,
+                    KC,
+                    dst.as_mut_ptr(),
+                    lhs.as_ptr(),
+                    rhs.as_ptr(),
+                    mr as isize,
+                    1,
+                    mr as isize,
+                    nr as isize,
+                    1,
+                    T::one(),
+                    T::one(),
+                    1,
+                    false,
+                    false,
+                    false,
+                    core::ptr::null(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn f32(c: &mut Criterion) {
+    use gemm_f32::microkernel::*;
+
+    let mut kernels = Vec::new();
+    push_kernels(&mut kernels, 1, &scalar::f32::UKR);
+    bench_kernels(c, "scalar", "f32", &kernels);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("fma") {
+        let mut kernels = Vec::new();
+        push_kernels(&mut kernels, 8, &fma::f32::UKR);
+        push_kernels(&mut kernels, 8, &fma::f32::TALL_UKR);
+        push_kernels(&mut kernels, 8, &fma::f32::WIDE_UKR);
+        bench_kernels(c, "fma", "f32", &kernels);
+    }
+
+    #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
+    if is_x86_feature_detected!("avx512f") {
+        let mut kernels = Vec::new();
+        push_kernels(&mut kernels, 16, &avx512f::f32::UKR);
+        push_kernels(&mut kernels, 16, &avx512f::f32::TALL_UKR);
+        push_kernels(&mut kernels, 16, &avx512f::f32::WIDE_UKR);
+        bench_kernels(c, "avx512f", "f32", &kernels);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        let mut kernels = Vec::new();
+        push_kernels(&mut kernels, 4, &neon::f32::UKR);
+        bench_kernels(c, "neon", "f32", &kernels);
+    }
+}
+
+fn f64(c: &mut Criterion) {
+    use gemm_f64::microkernel::*;
+
+    let mut kernels = Vec::new();
+    push_kernels(&mut kernels, 1, &scalar::f64::UKR);
+    bench_kernels(c, "scalar", "f64", &kernels);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("fma") {
+        let mut kernels = Vec::new();
+        push_kernels(&mut kernels, 4, &fma::f64::UKR);
+        push_kernels(&mut kernels, 4, &fma::f64::TALL_UKR);
+        push_kernels(&mut kernels, 4, &fma::f64::WIDE_UKR);
+        bench_kernels(c, "fma", "f64", &kernels);
+    }
+
+    #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
+    if is_x86_feature_detected!("avx512f") {
+        let mut kernels = Vec::new();
+        push_kernels(&mut kernels, 8, &avx512f::f64::UKR);
+        push_kernels(&mut kernels, 8, &avx512f::f64::TALL_UKR);
+        push_kernels(&mut kernels, 8, &avx512f::f64::WIDE_UKR);
+        bench_kernels(c, "avx512f", "f64", &kernels);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        let mut kernels = Vec::new();
+        push_kernels(&mut kernels, 2, &neon::f64::UKR);
+        bench_kernels(c, "neon", "f64", &kernels);
+    }
+}
+
+criterion_group!(benches, f32, f64);
+criterion_main!(benches);
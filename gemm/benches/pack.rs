@@ -0,0 +1,173 @@
+//! Criterion benchmarks of the packing of the operands in isolation, which call `pack_lhs` and
+//! `pack_rhs` on blocks of the shapes of the drivers with different strides of the source. The
+//! ids have the form `pack/<lhs|rhs>/<backend>/<dtype>/<pattern>/<len>x<depth>`, where `len` is
+//! the number of rows of an lhs block or columns of an rhs block, and `pattern` is one of
+//! - `unit`, where the packed dimension is contiguous in the source, as the columns of a
+//!   column-major lhs or the rows of a row-major rhs,
+//! - `large`, which is the same with a power-of-two leading dimension several times larger than
+//!   the block, as for blocks of a large matrix,
+//! - `transposed`, where the depth is contiguous in the source, so that each packed element is
+//!   gathered from a different row or column.
+//!
+//! The throughputs count the bytes that are read from the source and written to the panels.
+//!
+//! ```text
+//! cargo bench --features criterion-bench --bench pack
+//! cargo bench --features criterion-bench --bench pack -- pack/rhs/fma/f64/transposed
+//! ```
+
+use aligned_vec::{avec, AVec};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gemm_common::pack_operands::{pack_lhs, pack_rhs};
+use gemm_common::simd::Simd;
+use gemm_common::Ptr;
+use num_traits::One;
+
+/// `pack_lhs` or `pack_rhs` of a block of `len` rows or columns and `depth` columns or rows.
+type PackFn<S, T> = unsafe fn(S, usize, usize, Ptr<T>, Ptr<T>, isize, isize, usize);
+
+/// `(len, depth)` of the packed blocks: a square block, and the blocks of the skinny products
+/// with a small depth or a small number of rows or columns.
+const SHAPES: &[(usize, usize)] = &[(256, 256), (4096, 16), (16, 4096)];
+
+const PATTERNS: &[&str] = &["unit", "large", "transposed"];
+
+/// Strides of the source along the packed dimension and along the depth.
+fn strides(pattern: &str, len: usize, depth: usize) -> (isize, isize) {
+    match pattern {
+        "unit" => (1, len as isize),
+        "large" => (1, (4 * len).next_power_of_two() as isize),
+        "transposed" => (depth as isize, 1),
+        _ => unreachable!(),
+    }
+}
+
+/// Times `pack` on the blocks of [`SHAPES`] for each of [`PATTERNS`], where the panels are
+/// `width` rows or columns wide.
+fn bench_pack<S: Simd, T: 'static + Copy + One>(
+    c: &mut Criterion,
+    operand: &str,
+    backend: &str,
+    dtype: &str,
+    simd: S,
+    pack: PackFn<S, T>,
+    width: usize,
+) {
+    let mut group = c.benchmark_group(format!("pack/{operand}/{backend}/{dtype}"));
+    for &(len, depth) in SHAPES {
+        let panel_stride = depth * width;
+        let mut dst: AVec<T> = avec![T::one(); len.div_ceil(width) * panel_stride];
+
+        for &pattern in PATTERNS {
+            let (inner, outer) = strides(pattern, len, depth);
+            let src: AVec<T> =
+                avec![T::one(); (len - 1) * inner as usize + (depth - 1) * outer as usize + 1];
+            // `pack_lhs` takes the column stride then the row stride of an lhs block, and
+            // `pack_rhs` the ones of an rhs block, whose packed dimensions are the rows and the
+            // columns
+            let (src_cs, src_rs) = if operand == "lhs" {
+                (outer, inner)
+            } else {
+                (inner, outer)
+            };
+
+            group.throughput(Throughput::BytesDecimal(
+                (2 * len * depth * core::mem::size_of::<T>()) as u64,
+            ));
+            group.bench_function(
+                BenchmarkId::from_parameter(format!("{pattern}/{len}x{depth}")),
+                |b| {
+                    b.iter(|| unsafe {
+                        pack(
+                            simd,
+                            len,
+                            depth,
+                            Ptr(dst.as_mut_ptr()),
+                            Ptr(src.as_ptr() as *mut T),
+                            src_cs,
+                            src_rs,
+                            panel_stride,
+                        )
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+/// Packs with the widths of the panels of the scalar backend.
+fn scalar(c: &mut Criterion) {
+    use gemm_common::simd::Scalar;
+
+    {
+        use gemm_f32::microkernel::scalar::f32::{MR_DIV_N, NR};
+        bench_pack(
+            c,
+            "lhs",
+            "scalar",
+            "f32",
+            Scalar,
+            pack_lhs::<f32, 1, MR_DIV_N, _>,
+            MR_DIV_N,
+        );
+        bench_pack(
+            c,
+            "rhs",
+            "scalar",
+            "f32",
+            Scalar,
+            pack_rhs::<f32, 1, NR, _>,
+            NR,
+        );
+    }
+    {
+        use gemm_f64::microkernel::scalar::f64::{MR_DIV_N, NR};
+        bench_pack(
+            c,
+            "lhs",
+            "scalar",
+            "f64",
+            Scalar,
+            pack_lhs::<f64, 1, MR_DIV_N, _>,
+            MR_DIV_N,
+        );
+        bench_pack(
+            c,
+            "rhs",
+            "scalar",
+            "f64",
+            Scalar,
+            pack_rhs::<f64, 1, NR, _>,
+            NR,
+        );
+    }
+}
+
+/// Packs with the widths of the panels of the fma backend, as the drivers do on x86.
+fn fma(c: &mut Criterion) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("fma") {
+        use gemm_common::simd::Fma;
+
+        {
+            use gemm_f32::microkernel::fma::f32::{MR_DIV_N, NR};
+            const N: usize = 8;
+            const MR: usize = MR_DIV_N * N;
+            bench_pack(c, "lhs", "fma", "f32", Fma, pack_lhs::<f32, N, MR, _>, MR);
+            bench_pack(c, "rhs", "fma", "f32", Fma, pack_rhs::<f32, 1, NR, _>, NR);
+        }
+        {
+            use gemm_f64::microkernel::fma::f64::{MR_DIV_N, NR};
+            const N: usize = 4;
+            const MR: usize = MR_DIV_N * N;
+            bench_pack(c, "lhs", "fma", "f64", Fma, pack_lhs::<f64, N, MR, _>, MR);
+            bench_pack(c, "rhs", "fma", "f64", Fma, pack_rhs::<f64, 1, NR, _>, NR);
+        }
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let _ = c;
+}
+
+criterion_group!(benches, scalar, fma);
+criterion_main!(benches);
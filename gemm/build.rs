@@ -0,0 +1,19 @@
+//! Links the system BLAS into `bench-gemm` when the `blas` feature is enabled.
+
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=GEMM_BLAS_LIB");
+    println!("cargo:rerun-if-env-changed=GEMM_BLAS_DIR");
+
+    if env::var_os("CARGO_FEATURE_BLAS").is_none() {
+        return;
+    }
+
+    let lib = env::var("GEMM_BLAS_LIB").unwrap_or_else(|_| "openblas".into());
+    if let Ok(dir) = env::var("GEMM_BLAS_DIR") {
+        println!("cargo:rustc-link-arg-bin=bench-gemm=-L{dir}");
+    }
+    println!("cargo:rustc-link-arg-bin=bench-gemm=-l{lib}");
+}
@@ -0,0 +1,216 @@
+#ifndef BENCH_GEMM_H
+#define BENCH_GEMM_H
+
+/* Generated with cbindgen from gemm/src/capi.rs. Do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Scalar types accepted by [`bench_gemm_req`].
+ */
+#define BENCH_GEMM_F32 0
+
+#define BENCH_GEMM_F64 1
+
+/**
+ * Returned when the call succeeded.
+ */
+#define BENCH_GEMM_OK 0
+
+/**
+ * Returned when the scratch memory is too small for the problem.
+ */
+#define BENCH_GEMM_SCRATCH_TOO_SMALL -1
+
+/**
+ * Thread pool owned by the caller, which `bench_gemm_f32_on`/`bench_gemm_f64_on` run the
+ * parallel regions on. It only needs to outlive the call.
+ */
+typedef struct BenchGemmPool {
+  /**
+   * Passed back to `for_each`.
+   */
+  void *ctx;
+  /**
+   * Number of threads of the pool, which is the most that a region is split into.
+   */
+  size_t num_threads;
+  /**
+   * Calls `task(task_ctx, i)` for each `i` in `0..n_tasks`, potentially in parallel and in
+   * any order, and returns once every call has returned. It may be called from the threads
+   * of the pool.
+   */
+  void (*for_each)(void *ctx,
+                   size_t n_tasks,
+                   void (*task)(const void *task_ctx, size_t i),
+                   const void *task_ctx);
+} BenchGemmPool;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Number of bytes of scratch memory needed by `bench_gemm_f32`/`bench_gemm_f64` for the given
+ * problem, or `0` if `dtype` is unknown. The scratch memory doesn't need to be aligned.
+ */
+size_t bench_gemm_req(int dtype,
+                      size_t m,
+                      size_t n,
+                      size_t k,
+                      ptrdiff_t dst_cs,
+                      ptrdiff_t dst_rs,
+                      size_t n_threads);
+
+/**
+ * Same as `bench_gemm_req`, for `bench_gemm_f32_on`/`bench_gemm_f64_on` with the same `pool`.
+ *
+ * # Safety
+ *
+ * `pool` must be null or point to a valid `BenchGemmPool`.
+ */
+size_t bench_gemm_req_on(int dtype,
+                         size_t m,
+                         size_t n,
+                         size_t k,
+                         ptrdiff_t dst_cs,
+                         ptrdiff_t dst_rs,
+                         const struct BenchGemmPool *pool);
+
+/**
+ * dst := alpha×dst + beta×lhs×rhs
+ *
+ * Strides are given in elements. `scratch` may be null, in which case the scratch memory
+ * is allocated by the call, otherwise it must point to `scratch_len` writable bytes,
+ * where `scratch_len` is at least the value returned by `bench_gemm_req`. `n_threads`
+ * is `0` to use every thread of the global pool, or the number of threads to use.
+ *
+ * Returns `BENCH_GEMM_OK`, or `BENCH_GEMM_SCRATCH_TOO_SMALL` without touching `dst`.
+ *
+ * # Safety
+ *
+ * Same requirements as [`gemm`], and `scratch` must be null or valid for writes of
+ * `scratch_len` bytes.
+ */
+int bench_gemm_f32(size_t m,
+                   size_t n,
+                   size_t k,
+                   float *dst,
+                   ptrdiff_t dst_cs,
+                   ptrdiff_t dst_rs,
+                   bool read_dst,
+                   const float *lhs,
+                   ptrdiff_t lhs_cs,
+                   ptrdiff_t lhs_rs,
+                   const float *rhs,
+                   ptrdiff_t rhs_cs,
+                   ptrdiff_t rhs_rs,
+                   float alpha,
+                   float beta,
+                   size_t n_threads,
+                   uint8_t *scratch,
+                   size_t scratch_len);
+
+/**
+ * dst := alpha×dst + beta×lhs×rhs
+ *
+ * Same as the function without the `_on` suffix, except that the parallel regions run
+ * on `pool`, or on the calling thread if it's null. `scratch_len` must then be at least
+ * the value returned by `bench_gemm_req_on`.
+ *
+ * # Safety
+ *
+ * Same requirements as [`gemm`], `scratch` must be null or valid for writes of
+ * `scratch_len` bytes, and `pool` must be null or point to a valid `BenchGemmPool`.
+ */
+int bench_gemm_f32_on(size_t m,
+                      size_t n,
+                      size_t k,
+                      float *dst,
+                      ptrdiff_t dst_cs,
+                      ptrdiff_t dst_rs,
+                      bool read_dst,
+                      const float *lhs,
+                      ptrdiff_t lhs_cs,
+                      ptrdiff_t lhs_rs,
+                      const float *rhs,
+                      ptrdiff_t rhs_cs,
+                      ptrdiff_t rhs_rs,
+                      float alpha,
+                      float beta,
+                      const struct BenchGemmPool *pool,
+                      uint8_t *scratch,
+                      size_t scratch_len);
+
+/**
+ * dst := alpha×dst + beta×lhs×rhs
+ *
+ * Strides are given in elements. `scratch` may be null, in which case the scratch memory
+ * is allocated by the call, otherwise it must point to `scratch_len` writable bytes,
+ * where `scratch_len` is at least the value returned by `bench_gemm_req`. `n_threads`
+ * is `0` to use every thread of the global pool, or the number of threads to use.
+ *
+ * Returns `BENCH_GEMM_OK`, or `BENCH_GEMM_SCRATCH_TOO_SMALL` without touching `dst`.
+ *
+ * # Safety
+ *
+ * Same requirements as [`gemm`], and `scratch` must be null or valid for writes of
+ * `scratch_len` bytes.
+ */
+int bench_gemm_f64(size_t m,
+                   size_t n,
+                   size_t k,
+                   double *dst,
+                   ptrdiff_t dst_cs,
+                   ptrdiff_t dst_rs,
+                   bool read_dst,
+                   const double *lhs,
+                   ptrdiff_t lhs_cs,
+                   ptrdiff_t lhs_rs,
+                   const double *rhs,
+                   ptrdiff_t rhs_cs,
+                   ptrdiff_t rhs_rs,
+                   double alpha,
+                   double beta,
+                   size_t n_threads,
+                   uint8_t *scratch,
+                   size_t scratch_len);
+
+/**
+ * dst := alpha×dst + beta×lhs×rhs
+ *
+ * Same as the function without the `_on` suffix, except that the parallel regions run
+ * on `pool`, or on the calling thread if it's null. `scratch_len` must then be at least
+ * the value returned by `bench_gemm_req_on`.
+ *
+ * # Safety
+ *
+ * Same requirements as [`gemm`], `scratch` must be null or valid for writes of
+ * `scratch_len` bytes, and `pool` must be null or point to a valid `BenchGemmPool`.
+ */
+int bench_gemm_f64_on(size_t m,
+                      size_t n,
+                      size_t k,
+                      double *dst,
+                      ptrdiff_t dst_cs,
+                      ptrdiff_t dst_rs,
+                      bool read_dst,
+                      const double *lhs,
+                      ptrdiff_t lhs_cs,
+                      ptrdiff_t lhs_rs,
+                      const double *rhs,
+                      ptrdiff_t rhs_cs,
+                      ptrdiff_t rhs_rs,
+                      double alpha,
+                      double beta,
+                      const struct BenchGemmPool *pool,
+                      uint8_t *scratch,
+                      size_t scratch_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BENCH_GEMM_H */
@@ -0,0 +1,148 @@
+use crate::describe::DType;
+use crate::gemm::{gemm_with_backend, get_backend};
+use crate::{c32, c64, Parallelism};
+use gemm_common::cache::{fit_kernel_params, DivCeil, KernelParams};
+use gemm_common::gemm::{GemmConfig, Shape};
+use gemm_common::tuning::{
+    get_tuned_backend, get_tuned_kernel_params, save_tuning_cache, set_tuned_kernel_params,
+};
+use std::time::{Duration, Instant};
+use std::vec;
+use std::vec::Vec;
+
+/// Candidates for each blocking parameter, as fractions `(num, den)` of the one derived from the
+/// cache sizes.
+const SCALES: [(usize, usize); 3] = [(1, 2), (1, 1), (2, 1)];
+/// Number of timed runs per candidate, after an untimed one. The fastest run is kept.
+const RUNS: usize = 3;
+
+fn candidates(base: usize, align: usize, dim: usize) -> Vec<usize> {
+    let max = dim.msrv_next_multiple_of(align);
+    let mut values: Vec<usize> = SCALES
+        .iter()
+        .map(|&(num, den)| {
+            let value = (base * num / den).msrv_next_multiple_of(align);
+            Ord::min(Ord::max(value, align), max)
+        })
+        .collect();
+    values.dedup();
+    values
+}
+
+fn autotune_impl<T: 'static + Copy + Default>(
+    m: usize,
+    n: usize,
+    k: usize,
+    one: T,
+) -> KernelParams {
+    let backend = get_backend::<T>();
+    let (mr, nr) = (backend.mr, backend.nr);
+    let shape = Shape::of(m, n);
+
+    // measured before, possibly by another process on the same machine
+    if let Some(params) = get_tuned_kernel_params::<T>(mr, nr, shape) {
+        if get_tuned_backend::<T>(mr, nr, shape).as_deref() == Some(backend.name) {
+            return fit_kernel_params(m, n, k, mr, nr, params);
+        }
+    }
+
+    // measure around the model, not around the previously installed parameters
+    set_tuned_kernel_params::<T>(backend.name, mr, nr, shape, None);
+    let base = (backend.kernel_params)(m, n, k, Parallelism::None);
+    if m == 0 || n == 0 || k == 0 {
+        return base;
+    }
+
+    let lhs = vec![one; m * k];
+    let rhs = vec![one; k * n];
+    let mut dst = vec![T::default(); m * n];
+
+    let mut best = (Duration::MAX, base);
+    let mut measured = Vec::new();
+    for &kc in &candidates(base.kc, 1, k) {
+        for &mc in &candidates(base.mc, mr, m) {
+            for &nc in &candidates(base.nc, nr, n) {
+                // measure the blocks that the installed parameters lead to for this problem
+                let params = fit_kernel_params(m, n, k, mr, nr, KernelParams { kc, mc, nc });
+                if measured.contains(&(params.kc, params.mc, params.nc)) {
+                    continue;
+                }
+                measured.push((params.kc, params.mc, params.nc));
+
+                let mut time = Duration::MAX;
+                for run in 0..RUNS + 1 {
+                    let start = Instant::now();
+                    unsafe {
+                        gemm_with_backend(
+                            backend,
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            false,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            T::default(),
+                            one,
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                            |_| GemmConfig {
+                                kernel_params: Some(params),
+                                ..Default::default()
+                            },
+                        );
+                    }
+                    if run > 0 {
+                        time = Ord::min(time, start.elapsed());
+                    }
+                }
+                if time < best.0 {
+                    best = (time, params);
+                }
+            }
+        }
+    }
+
+    set_tuned_kernel_params::<T>(backend.name, mr, nr, shape, Some(best.1));
+    // the cache only saves time, so failing to write it isn't an error
+    let _ = save_tuning_cache();
+    best.1
+}
+
+/// Measures a small grid of blocking parameters around the ones derived from the cache sizes,
+/// on a single-threaded `m×n×k` product of `dtype` matrices, and installs the fastest.
+///
+/// The installed parameters are then used by every product of `dtype` matrices that isn't small
+/// and has the same [`Shape`], adjusted to its dimensions, until [`clear_tuned_kernel_params`] is
+/// called. This helps on machines whose caches don't fit the model, such as server parts with
+/// large private L2.
+///
+/// The parameters are also saved to the [tuning cache](gemm_common::tuning), from which they're
+/// installed by the next processes running on the same CPU model. If parameters measured with the
+/// active backend are already installed for the same shape, they're returned without measuring.
+///
+/// Each of the up to 27 candidates is run 4 times, so the shape should be representative of the
+/// workload while taking at most a few milliseconds.
+///
+/// The parameters are measured for the backend that is active when this is called, and apply
+/// to the backends with the same microkernel size only.
+///
+/// [`clear_tuned_kernel_params`]: crate::clear_tuned_kernel_params
+pub fn autotune(m: usize, n: usize, k: usize, dtype: DType) -> KernelParams {
+    match dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => autotune_impl(m, n, k, crate::f16::ONE),
+        DType::F32 => autotune_impl(m, n, k, 1.0f32),
+        DType::F64 => autotune_impl(m, n, k, 1.0f64),
+        DType::C32 => autotune_impl(m, n, k, c32::new(1.0, 0.0)),
+        DType::C64 => autotune_impl(m, n, k, c64::new(1.0, 0.0)),
+    }
+}
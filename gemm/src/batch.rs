@@ -0,0 +1,350 @@
+use crate::gemm::{gemm_with_backend, get_backend};
+use crate::Parallelism;
+use gemm_common::{
+    cache::DivCeil,
+    gemm::{inner_parallelism, max_threads, par_for_each, threading_threshold, GemmConfig},
+    Ptr,
+};
+
+/// Calls `run(i, parallelism)` for each of the `n_problems` problems, each of which needs `work`
+/// multiply-adds. Batches needing less than `threshold` in total run on the calling thread.
+/// Otherwise, whole problems are distributed between the threads allowed by `parallelism`,
+/// and `run` receives the threads left to each problem, if there are more threads than problems.
+pub(crate) fn for_each_problem(
+    n_problems: usize,
+    work: usize,
+    threshold: usize,
+    parallelism: Parallelism<'_>,
+    run: impl Fn(usize, Parallelism<'_>) + Send + Sync,
+) {
+    let max_threads = max_threads(parallelism);
+    if max_threads <= 1 || n_problems <= 1 || work.saturating_mul(n_problems) < threshold {
+        (0..n_problems).for_each(|i| run(i, parallelism));
+        return;
+    }
+
+    let n_threads = Ord::min(max_threads, n_problems);
+    let inner = inner_parallelism(parallelism, n_threads);
+    par_for_each(parallelism, n_threads, |tid| {
+        let start = n_problems * tid / n_threads;
+        let end = n_problems * (tid + 1) / n_threads;
+        (start..end).for_each(|i| run(i, inner));
+    });
+}
+
+/// dst[i] := alpha×dst[i] + beta×lhs[i]×rhs[i]
+///
+/// Computes a batch of independent products sharing the same dimensions and strides. Unlike
+/// calling [`gemm`](crate::gemm) in a loop, whole problems are distributed between the threads,
+/// which suits batches of small problems.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`, or if `dst`,
+/// `lhs` and `rhs` have different lengths.
+///
+/// # Safety
+///
+/// Each problem must satisfy the requirements of [`gemm`](crate::gemm), and the destinations
+/// must not overlap each other.
+#[track_caller]
+pub unsafe fn gemm_batched<T: 'static + Copy + Send + Sync>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: &[*mut T],
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: &[*const T],
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: &[*const T],
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    assert!(
+        dst.len() == lhs.len() && dst.len() == rhs.len(),
+        "batch size mismatch: {} destinations, {} lhs operands, {} rhs operands",
+        dst.len(),
+        lhs.len(),
+        rhs.len(),
+    );
+
+    let backend = get_backend::<T>();
+    let batch_size = dst.len();
+    let dst = Ptr(dst.as_ptr() as *mut *mut T);
+    let lhs = Ptr(lhs.as_ptr() as *mut *const T);
+    let rhs = Ptr(rhs.as_ptr() as *mut *const T);
+
+    for_each_problem(
+        batch_size,
+        m.saturating_mul(n).saturating_mul(k),
+        threading_threshold::<T>(backend.simd_bytes),
+        parallelism,
+        |i, parallelism| {
+            gemm_with_backend(
+                backend,
+                m,
+                n,
+                k,
+                *dst.wrapping_add(i).0,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                *lhs.wrapping_add(i).0,
+                lhs_cs,
+                lhs_rs,
+                *rhs.wrapping_add(i).0,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                conj_dst,
+                conj_lhs,
+                conj_rhs,
+                parallelism,
+                |_| GemmConfig::default(),
+            )
+        },
+    );
+}
+
+/// dst[i] := alpha×dst[i] + beta×lhs[i]×rhs[i]
+///
+/// Same as [`gemm_batched`], where the `i`-th operands start `i` batch strides after the first
+/// ones, e.g. `dst[i]` starts at `dst.offset(i * dst_bs)`. Strides are given in elements.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Each problem must satisfy the requirements of [`gemm`](crate::gemm), and the destinations
+/// must not overlap each other.
+#[track_caller]
+pub unsafe fn gemm_strided_batched<T: 'static + Copy + Send + Sync>(
+    m: usize,
+    n: usize,
+    k: usize,
+    batch_size: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    dst_bs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_bs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    rhs_bs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let backend = get_backend::<T>();
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    for_each_problem(
+        batch_size,
+        m.saturating_mul(n).saturating_mul(k),
+        threading_threshold::<T>(backend.simd_bytes),
+        parallelism,
+        |i, parallelism| {
+            let i = i as isize;
+            gemm_with_backend(
+                backend,
+                m,
+                n,
+                k,
+                dst.wrapping_offset(i * dst_bs).0,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs.wrapping_offset(i * lhs_bs).0,
+                lhs_cs,
+                lhs_rs,
+                rhs.wrapping_offset(i * rhs_bs).0,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                conj_dst,
+                conj_lhs,
+                conj_rhs,
+                parallelism,
+                |_| GemmConfig::default(),
+            )
+        },
+    );
+}
+
+/// One entry of a [`gemm_grouped`] call, with the same meaning as the corresponding arguments of
+/// [`gemm`](crate::gemm).
+#[derive(Debug)]
+pub struct GemmProblem<T> {
+    pub m: usize,
+    pub n: usize,
+    pub k: usize,
+    pub dst: *mut T,
+    pub dst_cs: isize,
+    pub dst_rs: isize,
+    pub lhs: *const T,
+    pub lhs_cs: isize,
+    pub lhs_rs: isize,
+    pub rhs: *const T,
+    pub rhs_cs: isize,
+    pub rhs_rs: isize,
+}
+
+unsafe impl<T: Send> Send for GemmProblem<T> {}
+unsafe impl<T: Sync> Sync for GemmProblem<T> {}
+
+impl<T> Copy for GemmProblem<T> {}
+impl<T> Clone for GemmProblem<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> GemmProblem<T> {
+    /// Number of multiply-adds needed by the problem.
+    #[inline]
+    pub fn work(&self) -> usize {
+        self.m.saturating_mul(self.n).saturating_mul(self.k)
+    }
+}
+
+/// Calls `run(i, parallelism)` for each of the `n_problems` problems, where the `i`-th problem
+/// needs `work(i)` multiply-adds.
+///
+/// Problems needing at least a thread's share of the total work are run one after another, each
+/// split between all the threads allowed by `parallelism`. The remaining problems are split into
+/// contiguous chunks of similar work, each of which runs on a single thread.
+pub(crate) fn for_each_problem_grouped(
+    n_problems: usize,
+    work: impl Fn(usize) -> usize + Send + Sync,
+    threshold: usize,
+    parallelism: Parallelism<'_>,
+    run: impl Fn(usize, Parallelism<'_>) + Send + Sync,
+) {
+    let max_threads = max_threads(parallelism);
+    let total_work = (0..n_problems).fold(0usize, |acc, i| acc.saturating_add(work(i)));
+    if max_threads <= 1 || n_problems <= 1 || total_work < threshold {
+        (0..n_problems).for_each(|i| run(i, parallelism));
+        return;
+    }
+
+    let share = total_work.msrv_div_ceil(max_threads);
+    let is_large = |i: usize| work(i) >= share;
+
+    (0..n_problems)
+        .filter(|&i| is_large(i))
+        .for_each(|i| run(i, parallelism));
+
+    let (n_small, small_work) = (0..n_problems)
+        .filter(|&i| !is_large(i))
+        .fold((0usize, 0usize), |(n_small, small_work), i| {
+            (n_small + 1, small_work.saturating_add(work(i)))
+        });
+    if n_small == 0 {
+        return;
+    }
+
+    let n_threads = Ord::min(max_threads, n_small);
+    // the problem is assigned to the thread that owns the middle of its work range
+    let owner = |work_before: usize, work: usize| -> usize {
+        let mid = work_before as u128 + work as u128 / 2;
+        Ord::min(
+            (mid * n_threads as u128 / Ord::max(small_work, 1) as u128) as usize,
+            n_threads - 1,
+        )
+    };
+    par_for_each(parallelism, n_threads, |tid| {
+        let mut work_before = 0usize;
+        for i in (0..n_problems).filter(|&i| !is_large(i)) {
+            let work = work(i);
+            if owner(work_before, work) == tid {
+                run(i, Parallelism::None);
+            }
+            work_before = work_before.saturating_add(work);
+        }
+    });
+}
+
+/// dst[i] := alpha×dst[i] + beta×lhs[i]×rhs[i]
+///
+/// Computes a group of independent products, each with its own dimensions and strides. Small
+/// problems are gathered onto single threads, while large ones are split between the threads
+/// allowed by `parallelism`, which suits ragged batches.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Each problem must satisfy the requirements of [`gemm`](crate::gemm), and the destinations
+/// must not overlap each other.
+#[track_caller]
+pub unsafe fn gemm_grouped<T: 'static + Copy + Send + Sync>(
+    problems: &[GemmProblem<T>],
+    read_dst: bool,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let backend = get_backend::<T>();
+
+    for_each_problem_grouped(
+        problems.len(),
+        |i| problems[i].work(),
+        threading_threshold::<T>(backend.simd_bytes),
+        parallelism,
+        |i, parallelism| {
+            let p = problems[i];
+            gemm_with_backend(
+                backend,
+                p.m,
+                p.n,
+                p.k,
+                p.dst,
+                p.dst_cs,
+                p.dst_rs,
+                read_dst,
+                p.lhs,
+                p.lhs_cs,
+                p.lhs_rs,
+                p.rhs,
+                p.rhs_cs,
+                p.rhs_rs,
+                alpha,
+                beta,
+                conj_dst,
+                conj_lhs,
+                conj_rhs,
+                parallelism,
+                |_| GemmConfig::default(),
+            )
+        },
+    );
+}
@@ -0,0 +1,85 @@
+//! Baselines of the throughput of the runs, saved per machine as comma-separated values, which
+//! later runs are compared against to detect regressions.
+//!
+//! A baseline `NAME` of the machine `MACHINE` is stored in `DIR/MACHINE/NAME.csv`, with one line
+//! `dtype,m,n,k,threads,layout,gflops` per run. The runs are identified by their problem, so that
+//! a baseline should be compared with runs that use the same options, e.g. `--cold`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HEADER: &str = "dtype,m,n,k,threads,layout,gflops";
+
+/// Throughput in GFLOPS of each run, identified by `dtype,m,n,k,threads,layout`.
+#[derive(Default)]
+pub struct Baseline(HashMap<String, f64>);
+
+/// Name of the current machine, which is its host name.
+pub fn machine() -> String {
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_default();
+    let hostname = hostname.trim();
+    if hostname.is_empty() {
+        "default".into()
+    } else {
+        hostname.into()
+    }
+}
+
+/// Path of the baseline `name` of `machine` in `dir`.
+pub fn path(dir: &Path, machine: &str, name: &str) -> PathBuf {
+    // keeps the names from escaping `dir`
+    let sanitize = |name: &str| -> String {
+        name.chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect()
+    };
+    dir.join(sanitize(machine))
+        .join(format!("{}.csv", sanitize(name)))
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read the baseline {}: {e}", path.display()))?;
+        let mut baseline = Baseline::default();
+        for (i, line) in contents.lines().enumerate().skip(1) {
+            let invalid = || format!("invalid line {} of {}", i + 1, path.display());
+            let (key, gflops) = line.rsplit_once(',').ok_or_else(invalid)?;
+            let gflops = gflops.trim().parse().map_err(|_| invalid())?;
+            baseline.0.insert(key.into(), gflops);
+        }
+        Ok(baseline)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut lines: Vec<String> = self
+            .0
+            .iter()
+            .map(|(key, gflops)| format!("{key},{gflops}"))
+            .collect();
+        lines.sort();
+        let contents = format!("{HEADER}\n{}\n", lines.join("\n"));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        }
+        fs::write(path, contents)
+            .map_err(|e| format!("failed to write the baseline {}: {e}", path.display()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<f64> {
+        self.0.get(key).copied()
+    }
+
+    pub fn insert(&mut self, key: String, gflops: f64) {
+        self.0.insert(key, gflops);
+    }
+}
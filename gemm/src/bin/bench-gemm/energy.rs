@@ -0,0 +1,127 @@
+//! Energy of the runs, read from the RAPL counters of the powercap interface of Linux.
+//!
+//! The package domains count the energy of the cores and the caches of each socket, and their
+//! DRAM subdomains the energy of the memory, on the machines that report it. The counters are
+//! updated about every millisecond, so they're read around all the timed runs of a problem rather
+//! than around each one.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const POWERCAP: &str = "/sys/class/powercap";
+
+/// Energy per run, in joules.
+#[derive(Copy, Clone, Debug)]
+pub struct Energy {
+    /// Sum of the package domains.
+    pub package: f64,
+    /// Sum of the DRAM domains, or `None` if the machine doesn't report any.
+    pub dram: Option<f64>,
+}
+
+impl Energy {
+    pub fn total(&self) -> f64 {
+        self.package + self.dram.unwrap_or(0.0)
+    }
+}
+
+/// RAPL domain, whose counter wraps around at `range` microjoules.
+struct Domain {
+    energy_uj: PathBuf,
+    dram: bool,
+    range: u64,
+}
+
+/// RAPL domains of the machine.
+pub struct Domains(Vec<Domain>);
+
+fn read_u64(path: &PathBuf) -> Result<u64, String> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "failed to read {}: {e}, which is usually restricted to root",
+            path.display()
+        )
+    })?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid counter in {}", path.display()))
+}
+
+/// RAPL domains of the machine, which are listed on the first call.
+pub fn domains() -> Result<&'static Domains, String> {
+    static DOMAINS: OnceLock<Result<Domains, String>> = OnceLock::new();
+    DOMAINS
+        .get_or_init(Domains::open)
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+impl Domains {
+    /// Lists the package and DRAM domains, and fails if their counters can't be read.
+    pub fn open() -> Result<Self, String> {
+        let entries = fs::read_dir(POWERCAP)
+            .map_err(|e| format!("failed to list the RAPL domains in {POWERCAP}: {e}"))?;
+
+        let mut domains = Vec::new();
+        for entry in entries.flatten() {
+            // `intel-rapl-mmio` duplicates the package domains of `intel-rapl` on some machines
+            let dir = entry.file_name();
+            if !dir.to_string_lossy().starts_with("intel-rapl:") {
+                continue;
+            }
+            let path = entry.path();
+            let Ok(name) = fs::read_to_string(path.join("name")) else {
+                continue;
+            };
+            let name = name.trim();
+            let dram = name == "dram";
+            if !dram && !name.starts_with("package") {
+                continue;
+            }
+            let domain = Domain {
+                energy_uj: path.join("energy_uj"),
+                dram,
+                range: read_u64(&path.join("max_energy_range_uj")).unwrap_or(u64::MAX),
+            };
+            read_u64(&domain.energy_uj)?;
+            domains.push(domain);
+        }
+
+        if domains.is_empty() {
+            return Err(format!("no RAPL package domain was found in {POWERCAP}"));
+        }
+        Ok(Domains(domains))
+    }
+
+    /// Counters of the domains, in microjoules.
+    pub fn read(&self) -> Vec<u64> {
+        self.0
+            .iter()
+            .map(|domain| read_u64(&domain.energy_uj).unwrap_or(0))
+            .collect()
+    }
+
+    /// Energy between two readings of the counters, divided between `runs` runs.
+    pub fn energy(&self, before: &[u64], after: &[u64], runs: usize) -> Energy {
+        let mut energy = Energy {
+            package: 0.0,
+            dram: None,
+        };
+        for ((domain, &before), &after) in self.0.iter().zip(before).zip(after) {
+            let microjoules = if after >= before {
+                after - before
+            } else {
+                domain.range - before + after
+            };
+            let joules = microjoules as f64 * 1e-6 / runs as f64;
+            if domain.dram {
+                *energy.dram.get_or_insert(0.0) += joules;
+            } else {
+                energy.package += joules;
+            }
+        }
+        energy
+    }
+}
@@ -0,0 +1,374 @@
+//! Fuzz benchmarking, which times random products with random shapes, strides and scaling
+//! factors, and checks each result against [`gemm_reference`], so that the same sweep finds the
+//! performance cliffs and the bugs of the edge cases.
+
+use crate::{flops, measure, Sampling, Scalar};
+use gemm::{c32, c64, error_bound, gemm, gemm_reference, DType, Parallelism};
+
+/// Scalar that can be sampled and compared in `f64` arithmetic, as `(re, im)`.
+trait Value: Scalar {
+    fn from_parts(re: f64, im: f64) -> Self;
+    fn parts(self) -> (f64, f64);
+}
+
+impl Value for f32 {
+    fn from_parts(re: f64, _: f64) -> Self {
+        re as f32
+    }
+    fn parts(self) -> (f64, f64) {
+        (self as f64, 0.0)
+    }
+}
+impl Value for f64 {
+    fn from_parts(re: f64, _: f64) -> Self {
+        re
+    }
+    fn parts(self) -> (f64, f64) {
+        (self, 0.0)
+    }
+}
+impl Value for c32 {
+    fn from_parts(re: f64, im: f64) -> Self {
+        c32::new(re as f32, im as f32)
+    }
+    fn parts(self) -> (f64, f64) {
+        (self.re as f64, self.im as f64)
+    }
+}
+impl Value for c64 {
+    fn from_parts(re: f64, im: f64) -> Self {
+        c64::new(re, im)
+    }
+    fn parts(self) -> (f64, f64) {
+        (self.re, self.im)
+    }
+}
+#[cfg(feature = "f16")]
+impl Value for gemm::f16 {
+    fn from_parts(re: f64, _: f64) -> Self {
+        gemm::f16::from_f64(re)
+    }
+    fn parts(self) -> (f64, f64) {
+        (self.to_f64(), 0.0)
+    }
+}
+
+/// splitmix64 generator.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Uniform sample in `[-1, 1)`.
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// Dimension up to `max`: zero, a size of the edge tiles of the microkernels, or a size that's
+    /// uniform on a logarithmic scale.
+    fn dim(&mut self, max: usize) -> usize {
+        match self.below(20) {
+            0 => 0,
+            1..=9 => 1 + self.below(Ord::min(max, 17)),
+            _ => {
+                let log = self.uniform().abs() * (max as f64).ln();
+                Ord::min(log.exp().round() as usize, max)
+            }
+        }
+    }
+
+    /// Scaling factor, which is zero, one or uniform in `[-1, 1)`.
+    fn factor<T: Value>(&mut self) -> T {
+        match self.below(4) {
+            0 => T::zero(),
+            1 => T::one(),
+            _ => {
+                let re = self.uniform();
+                let im = self.uniform();
+                T::from_parts(re, im)
+            }
+        }
+    }
+}
+
+/// Strided `rows×cols` matrix, stored at `offset` in a buffer of `len` elements.
+#[derive(Copy, Clone, Debug)]
+struct Strided {
+    cs: isize,
+    rs: isize,
+    offset: usize,
+    len: usize,
+}
+
+impl Strided {
+    /// Random strides of a `rows×cols` matrix whose elements don't overlap: column-major or
+    /// row-major, with padded columns or rows, non-unit strides and negative strides.
+    fn sample(rng: &mut Rng, rows: usize, cols: usize) -> Self {
+        let inner = if rng.below(4) == 0 { 2 } else { 1 };
+        let pad = rng.below(4) as isize;
+        let (mut cs, mut rs) = if rng.below(2) == 0 {
+            (inner * rows as isize + pad, inner)
+        } else {
+            (inner, inner * cols as isize + pad)
+        };
+        if rng.below(4) == 0 {
+            cs = -cs;
+        }
+        if rng.below(4) == 0 {
+            rs = -rs;
+        }
+
+        let extent = |count: usize, stride: isize| count.saturating_sub(1) * stride.unsigned_abs();
+        let (col_extent, row_extent) = (extent(cols, cs), extent(rows, rs));
+        Strided {
+            cs,
+            rs,
+            offset: if cs < 0 { col_extent } else { 0 } + if rs < 0 { row_extent } else { 0 },
+            len: col_extent + row_extent + 1,
+        }
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        (self.offset as isize + i as isize * self.rs + j as isize * self.cs) as usize
+    }
+}
+
+impl std::fmt::Display for Strided {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.cs, self.rs)
+    }
+}
+
+/// Result of one random product.
+struct Case {
+    m: usize,
+    n: usize,
+    k: usize,
+    strides: [Strided; 3],
+    read_dst: bool,
+    conj: [bool; 3],
+    elapsed: f64,
+    /// Largest ratio of the error of an element to its bound, which is above one for a wrong
+    /// result.
+    error: f64,
+    /// Whether an element of the buffer of the destination outside of the matrix was written.
+    out_of_bounds: bool,
+}
+
+fn fuzz_case<T: Value>(
+    rng: &mut Rng,
+    dtype: DType,
+    max_size: usize,
+    parallelism: Parallelism<'_>,
+    sampling: Sampling,
+) -> Case {
+    let (m, n, k) = (rng.dim(max_size), rng.dim(max_size), rng.dim(max_size));
+    let dst = Strided::sample(rng, m, n);
+    let lhs = Strided::sample(rng, m, k);
+    let rhs = Strided::sample(rng, k, n);
+    let alpha = rng.factor::<T>();
+    let beta = rng.factor::<T>();
+    let read_dst = rng.below(2) == 0;
+    let conj = [rng.below(2) == 0, rng.below(2) == 0, rng.below(2) == 0];
+
+    let mut sample = |len: usize| -> Vec<T> {
+        (0..len)
+            .map(|_| {
+                let re = rng.uniform();
+                let im = rng.uniform();
+                T::from_parts(re, im)
+            })
+            .collect()
+    };
+    let init = sample(dst.len);
+    let lhs_buf = sample(lhs.len);
+    let rhs_buf = sample(rhs.len);
+
+    let run = |out: *mut T, reference: bool| unsafe {
+        let f = if reference {
+            gemm_reference::<T>
+        } else {
+            gemm::<T>
+        };
+        f(
+            m,
+            n,
+            k,
+            out.add(dst.offset),
+            dst.cs,
+            dst.rs,
+            read_dst,
+            lhs_buf.as_ptr().add(lhs.offset),
+            lhs.cs,
+            lhs.rs,
+            rhs_buf.as_ptr().add(rhs.offset),
+            rhs.cs,
+            rhs.rs,
+            alpha,
+            beta,
+            conj[0],
+            conj[1],
+            conj[2],
+            parallelism,
+        )
+    };
+
+    let mut actual = init.clone();
+    let mut expected = init.clone();
+    run(actual.as_mut_ptr(), false);
+    run(expected.as_mut_ptr(), true);
+
+    // the reference is within the same bound of the exact result as the backend
+    let bound = 2.0 * error_bound(m, n, k, dtype, parallelism).relative_any_blocking;
+    let abs = |(re, im): (f64, f64)| f64::hypot(re, im);
+    let mut error = 0.0f64;
+    let mut in_matrix = vec![false; dst.len];
+    for j in 0..n {
+        for i in 0..m {
+            let index = dst.index(i, j);
+            in_matrix[index] = true;
+            let mut scale = 0.0;
+            for depth in 0..k {
+                let a = abs(lhs_buf[lhs.index(i, depth)].parts());
+                let b = abs(rhs_buf[rhs.index(depth, j)].parts());
+                scale += a * b;
+            }
+            scale *= abs(beta.parts());
+            if read_dst {
+                scale += abs(alpha.parts()) * abs(init[index].parts());
+            }
+            let (re, im) = actual[index].parts();
+            let (expected_re, expected_im) = expected[index].parts();
+            let diff = abs((re - expected_re, im - expected_im));
+            let ratio = diff / f64::max(bound * scale, f64::MIN_POSITIVE);
+            // propagates the NaNs
+            if ratio.is_nan() || ratio > error {
+                error = ratio;
+            }
+        }
+    }
+    let out_of_bounds =
+        (0..dst.len).any(|index| !in_matrix[index] && actual[index].parts() != init[index].parts());
+
+    // restores the destination before each run, so that the runs that read it don't drift
+    let mut timed = init.clone();
+    let timed = timed.as_mut_ptr();
+    let elapsed = measure(
+        || run(timed, false),
+        || unsafe { std::ptr::copy_nonoverlapping(init.as_ptr(), timed, dst.len) },
+        sampling,
+    )
+    .elapsed
+    .as_secs_f64();
+
+    Case {
+        m,
+        n,
+        k,
+        strides: [dst, lhs, rhs],
+        read_dst,
+        conj,
+        elapsed,
+        error,
+        out_of_bounds,
+    }
+}
+
+/// Times and checks `cases` random products with dimensions up to `max_size`, whose scalar types
+/// and numbers of threads are picked among `dtypes` and `threads`, and returns the number of
+/// wrong results.
+pub fn run(
+    cases: usize,
+    seed: u64,
+    max_size: usize,
+    dtypes: &[DType],
+    threads: &[usize],
+    sampling: Sampling,
+    csv: bool,
+) -> usize {
+    if csv {
+        println!("case,dtype,m,n,k,threads,dst_strides,lhs_strides,rhs_strides,read_dst,conj,seconds,gflops,error_ratio,status");
+    } else {
+        println!(
+            "{:>5} {:>5} {:>5} {:>5} {:>5} {:>7} {:>13} {:>13} {:>13} {:>4} {:>4} {:>12} {:>9} {:>8} {:>6}",
+            "case", "dtype", "m", "n", "k", "threads", "dst cs:rs", "lhs cs:rs", "rhs cs:rs", "read", "conj", "time", "GFLOPS", "error", "status",
+        );
+    }
+
+    let mut rng = Rng(seed);
+    let mut failures = 0;
+    for case in 0..cases {
+        let dtype = dtypes[rng.below(dtypes.len())];
+        let threads = threads[rng.below(threads.len())];
+        let parallelism = if threads == 1 {
+            Parallelism::None
+        } else {
+            Parallelism::Rayon(threads)
+        };
+        let n_threads = gemm_common::gemm::max_threads(parallelism);
+        let rng = &mut rng;
+        let result = match dtype {
+            #[cfg(feature = "f16")]
+            DType::F16 => fuzz_case::<gemm::f16>(rng, dtype, max_size, parallelism, sampling),
+            DType::F32 => fuzz_case::<f32>(rng, dtype, max_size, parallelism, sampling),
+            DType::F64 => fuzz_case::<f64>(rng, dtype, max_size, parallelism, sampling),
+            DType::C32 => fuzz_case::<c32>(rng, dtype, max_size, parallelism, sampling),
+            DType::C64 => fuzz_case::<c64>(rng, dtype, max_size, parallelism, sampling),
+        };
+
+        let Case {
+            m,
+            n,
+            k,
+            strides: [dst, lhs, rhs],
+            read_dst,
+            conj,
+            elapsed,
+            error,
+            out_of_bounds,
+        } = result;
+        let status = if out_of_bounds {
+            "OOB"
+        } else if error.is_nan() || error > 1.0 {
+            "WRONG"
+        } else {
+            "ok"
+        };
+        if status != "ok" {
+            failures += 1;
+        }
+        let name = format!("{dtype:?}").to_lowercase();
+        let gflops = if elapsed > 0.0 {
+            flops(m, n, k, dtype) / elapsed / 1e9
+        } else {
+            0.0
+        };
+        let conj: String = conj.iter().map(|&c| if c { 'y' } else { 'n' }).collect();
+        if csv {
+            println!(
+                "{case},{name},{m},{n},{k},{n_threads},{dst},{lhs},{rhs},{read_dst},{conj},{elapsed},{gflops:.3},{error:.3e},{status}"
+            );
+        } else {
+            println!(
+                "{case:>5} {name:>5} {m:>5} {n:>5} {k:>5} {n_threads:>7} {:>13} {:>13} {:>13} {:>4} {conj:>4} {:>12} {gflops:>9.2} {error:>8.2e} {status:>6}",
+                dst.to_string(),
+                lhs.to_string(),
+                rhs.to_string(),
+                if read_dst { "y" } else { "n" },
+                format!("{:.2?}", std::time::Duration::from_secs_f64(elapsed)),
+            );
+        }
+    }
+    failures
+}
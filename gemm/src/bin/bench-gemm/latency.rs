@@ -0,0 +1,171 @@
+//! Latency of single calls on tiny products, whose cost is dominated by the fixed costs of a call
+//! rather than by the kernels: the dispatch to the backend, the lazy initialization of the
+//! information of the caches, and the computation of the kernel parameters.
+
+use crate::{flops, Layout, Sampling, Scalar};
+use gemm::{gemm, DType, Parallelism};
+use std::time::{Duration, Instant};
+
+/// Largest number of timed calls of each problem, which bounds the memory taken by their
+/// latencies.
+const MAX_CALLS: usize = 100_000;
+
+/// Latencies of the calls of a problem.
+struct Latencies {
+    /// First call of the problem, without warming up.
+    first: Duration,
+    /// Following calls, each timed on its own, sorted.
+    calls: Vec<Duration>,
+}
+
+impl Latencies {
+    fn quantile(&self, q: f64) -> Duration {
+        self.calls[((self.calls.len() - 1) as f64 * q).round() as usize]
+    }
+}
+
+/// Shortest time between two reads of the clock, which is subtracted from the latencies.
+fn clock_overhead() -> Duration {
+    (0..1000)
+        .map(|_| {
+            let now = Instant::now();
+            now.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// Times the calls of `dst := lhs×rhs` on `n×n` operands with `layout`, one by one, for at least
+/// `samples` calls and `min_time`.
+fn latencies<T: Scalar>(
+    n: usize,
+    layout: [Layout; 3],
+    sampling: Sampling,
+    overhead: Duration,
+) -> Latencies {
+    let mut dst = vec![T::zero(); n * n];
+    let lhs = vec![T::one(); n * n];
+    let rhs = vec![T::one(); n * n];
+    let [(dst_cs, dst_rs), (lhs_cs, lhs_rs), (rhs_cs, rhs_rs)] =
+        layout.map(|layout| layout.strides(n, n));
+
+    let mut call = || {
+        let now = Instant::now();
+        unsafe {
+            gemm(
+                n,
+                n,
+                n,
+                dst.as_mut_ptr(),
+                dst_cs,
+                dst_rs,
+                false,
+                lhs.as_ptr(),
+                lhs_cs,
+                lhs_rs,
+                rhs.as_ptr(),
+                rhs_cs,
+                rhs_rs,
+                T::zero(),
+                T::one(),
+                false,
+                false,
+                false,
+                Parallelism::None,
+            )
+        };
+        now.elapsed().saturating_sub(overhead)
+    };
+
+    let first = call();
+    let start = Instant::now();
+    let mut calls = Vec::new();
+    while calls.len() < Ord::max(sampling.samples, 1)
+        || (start.elapsed() < sampling.min_time && calls.len() < MAX_CALLS)
+    {
+        calls.push(call());
+    }
+    calls.sort_unstable();
+    Latencies { first, calls }
+}
+
+fn latencies_dtype(
+    dtype: DType,
+    n: usize,
+    layout: [Layout; 3],
+    sampling: Sampling,
+    overhead: Duration,
+) -> Latencies {
+    match dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => latencies::<gemm::f16>(n, layout, sampling, overhead),
+        DType::F32 => latencies::<f32>(n, layout, sampling, overhead),
+        DType::F64 => latencies::<f64>(n, layout, sampling, overhead),
+        DType::C32 => latencies::<gemm::c32>(n, layout, sampling, overhead),
+        DType::C64 => latencies::<gemm::c64>(n, layout, sampling, overhead),
+    }
+}
+
+/// Times single calls of the square products of `sizes` on one thread, and prints the latency
+/// of the first call of each problem and the distribution of the latencies of the next ones, in
+/// nanoseconds, with the throughput of the median call.
+///
+/// The first call of the first problem also pays for the initialization of `gemm` that's shared
+/// by every scalar type, such as the detection of the features of the CPU and of its caches.
+pub fn run(
+    sizes: &[usize],
+    dtypes: &[DType],
+    layouts: &[([Layout; 3], &str)],
+    sampling: Sampling,
+    csv: bool,
+) {
+    let overhead = clock_overhead();
+    eprintln!(
+        "clock overhead: {} ns, subtracted from the latencies",
+        overhead.as_nanos()
+    );
+
+    if csv {
+        println!("dtype,m,n,k,layout,first_ns,min_ns,median_ns,p99_ns,calls,gflops");
+    } else {
+        println!(
+            "{:>5} {:>4} {:>6} {:>10} {:>10} {:>10} {:>10} {:>8} {:>9}",
+            "dtype",
+            "size",
+            "layout",
+            "first ns",
+            "min ns",
+            "median ns",
+            "p99 ns",
+            "calls",
+            "GFLOPS",
+        );
+    }
+
+    for &dtype in dtypes {
+        let name = format!("{dtype:?}").to_lowercase();
+        for &(layout, spec) in layouts {
+            for &n in sizes {
+                let latencies = latencies_dtype(dtype, n, layout, sampling, overhead);
+                let [first, min, median, p99] = [
+                    latencies.first,
+                    latencies.quantile(0.0),
+                    latencies.quantile(0.5),
+                    latencies.quantile(0.99),
+                ]
+                .map(|latency| latency.as_nanos());
+                let calls = latencies.calls.len();
+                // floating-point operations per nanosecond are GFLOPS
+                let gflops = flops(n, n, n, dtype) / (median as f64).max(1.0);
+
+                if csv {
+                    println!("{name},{n},{n},{n},{spec},{first},{min},{median},{p99},{calls},{gflops:.3}");
+                } else {
+                    println!(
+                        "{name:>5} {n:>4} {spec:>6} {first:>10} {min:>10} {median:>10} {p99:>10} {calls:>8} {gflops:>9.2}"
+                    );
+                }
+            }
+        }
+    }
+}
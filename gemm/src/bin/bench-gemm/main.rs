@@ -0,0 +1,1312 @@
+//! Benchmarks `gemm` over sweeps of problem sizes, scalar types, thread counts and layouts, and
+//! reports the throughput of each run in GFLOPS and as a percentage of the peak of the machine.
+//! With `--roofline`, each run is also placed on the roofline of the machine, which tells the
+//! products that are bound by the memory bandwidth apart from the ones that underuse the kernels.
+//!
+//! ```text
+//! cargo run --release --features cli --bin bench-gemm -- --size 64:1024 --dtype f32,f64 --threads 1,0
+//! cargo run --release --features cli --bin bench-gemm -- -m 4096 -n 1:64 -k 4096 --roofline
+//! cargo run --release --features cli --bin bench-gemm -- --size 16:256 --cold clflush
+//! cargo run --release --features cli --bin bench-gemm -- --preset bert-mlp,llama-decode --threads 0
+//! cargo run --release --features cli --bin bench-gemm -- --size 256:4096 --scaling
+//! cargo run --release --features plot --bin bench-gemm -- -m 64:1024 -n 64:1024 -k 256 --plot plots
+//! cargo run --release --features cli --bin bench-gemm -- --latency 2:64 --dtype f32,f64 --csv
+//! cargo run --release --features cli --bin bench-gemm -- --fuzz 1000 --seed 7 --dtype f32,c64 --threads 1,4
+//! cargo run --release --features cli --bin bench-gemm -- --save-baseline main
+//! cargo run --release --features cli --bin bench-gemm -- --baseline main --threshold 3
+//! cargo run --release --features matrixmultiply --bin bench-gemm -- --compare matrixmultiply
+//! OPENBLAS_NUM_THREADS=1 cargo run --release --features blas --bin bench-gemm -- --compare blas
+//! ```
+//!
+//! The `perf` feature adds `--counters`, which reports the hardware counters of the runs on Linux,
+//! read with `perf_event_open`.
+//!
+//! The `energy` feature adds `--energy`, which reports the energy of the runs on Linux, read from
+//! `/sys/class/powercap`, whose counters are usually only readable by root.
+//!
+//! The `plot` feature adds `--plot`, which draws SVG plots of the results.
+//!
+//! The `blas` feature links the CBLAS library named by the `GEMM_BLAS_LIB` environment variable,
+//! `openblas` by default (e.g. `mkl_rt` for MKL), searched in `GEMM_BLAS_DIR` if it's set. It
+//! can't be combined with the `capi` feature, whose `cblas_*gemm` symbols would be linked in
+//! place of the library's, so that the comparison would measure `gemm` against itself.
+
+// the crate's own CBLAS symbols may resolve ahead of the system library
+#[cfg(all(feature = "blas", feature = "capi"))]
+compile_error!("the `blas` and `capi` features are mutually exclusive");
+
+use baseline::Baseline;
+use clap::Parser;
+use gemm::{describe, gemm, DType, Parallelism};
+use gemm_common::cache::CACHE_INFO;
+use num_traits::{One, Zero};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+mod baseline;
+#[cfg(feature = "energy")]
+mod energy;
+mod fuzz;
+mod latency;
+#[cfg(feature = "perf")]
+mod perf;
+#[cfg(feature = "plot")]
+mod plot;
+mod presets;
+mod scaling;
+
+/// Benchmarks `gemm` over sweeps of problem sizes, scalar types, thread counts and layouts.
+///
+/// Sizes are given as comma-separated lists whose items are either a single size `N`, a range
+/// `START:END` of the powers of two times `START` up to `END`, or a range `START:END:STEP` of
+/// the multiples of `STEP` after `START` up to `END`.
+#[derive(Parser, Debug)]
+#[command(name = "bench-gemm")]
+struct Args {
+    /// Sizes of the square problems, `m = n = k`. Ignored for the dimensions given by `-m`, `-n`
+    /// or `-k`.
+    #[arg(long, default_value = "64:1024")]
+    size: String,
+    /// Number of rows of the destination.
+    #[arg(short)]
+    m: Option<String>,
+    /// Number of columns of the destination.
+    #[arg(short)]
+    n: Option<String>,
+    /// Depth of the product.
+    #[arg(short)]
+    k: Option<String>,
+    /// Named sets of shapes of machine learning workloads, which replace the sizes: the attention
+    /// and feed-forward layers of BERT-base (`bert-attention`, `bert-mlp`), the convolutions of
+    /// ResNet-50 lowered with im2col (`resnet50`) and the generation of a token by LLaMA-2 7B
+    /// (`llama-decode`).
+    #[arg(long, value_delimiter = ',')]
+    preset: Vec<String>,
+    /// Scalar types, among `f16`, `f32`, `f64`, `c32` and `c64`.
+    #[arg(long, value_delimiter = ',', default_value = "f32")]
+    dtype: Vec<String>,
+    /// Numbers of threads, where `0` stands for every thread of the machine.
+    #[arg(long, value_delimiter = ',', default_value = "1")]
+    threads: Vec<usize>,
+    /// Times each problem with every number of threads from one up to this one, where `0` (or no
+    /// value) stands for every thread of the machine, instead of the numbers of `--threads`, and
+    /// reports the speedups and the parallel efficiencies over one thread, with the number of
+    /// threads after which an additional thread brings less than half of its share. The other
+    /// reports are disabled.
+    #[arg(long, value_name = "MAX_THREADS", num_args = 0..=1, default_missing_value = "0")]
+    scaling: Option<usize>,
+    /// Times this number of random products instead of the sweep, with random dimensions up to
+    /// `--max-size`, strides, scaling factors, `read_dst` and conjugations, and with a scalar type
+    /// and number of threads picked among the ones of `--dtype` and `--threads`. Each result is
+    /// checked against `gemm_reference`, and the run exits with a nonzero status if one of them
+    /// is wrong or wrote outside of the destination.
+    #[arg(long, value_name = "CASES")]
+    fuzz: Option<usize>,
+    /// Seed of the random products of `--fuzz`.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Largest dimension of the random products of `--fuzz`.
+    #[arg(long, default_value_t = 256)]
+    max_size: usize,
+    /// Times single calls of the square problems of these sizes, `2:64` by default, instead of
+    /// the sweep, and reports their latencies in nanoseconds: the one of the first call, which
+    /// includes the lazy initialization of `gemm`, and the minimum, median and 99th percentile of
+    /// the next calls, each timed on its own. The products run on one thread, and the other
+    /// reports are disabled.
+    #[arg(long, value_name = "SIZES", num_args = 0..=1, default_missing_value = "2:64")]
+    latency: Option<String>,
+    /// Layouts of the destination, the lhs and the rhs, as three letters among `c` for
+    /// column-major and `r` for row-major, e.g. `ccc,rcr`.
+    #[arg(long, value_delimiter = ',', default_value = "ccc")]
+    layout: Vec<String>,
+    /// Minimum number of timed runs of each problem, whose fastest one is reported.
+    #[arg(long, default_value_t = 3)]
+    samples: usize,
+    /// Minimum time spent timing each problem, in milliseconds.
+    #[arg(long, default_value_t = 200)]
+    min_time: u64,
+    /// Flushes the caches before each timed run, to measure the first-touch performance of the
+    /// products instead of the one with operands that are already in the caches. `sweep` writes a
+    /// buffer twice as large as the last level cache, which evicts the operands from the caches
+    /// of the current core and the shared caches, and `clflush` (on x86_64) evicts the lines of
+    /// the operands from every cache of the machine, including the private caches of the other
+    /// threads. The energy of `--energy` includes the flushes.
+    #[arg(long)]
+    cold: Option<String>,
+    /// Peak of one thread in GFLOPS, for each scalar type. By default, it's estimated from the
+    /// throughput of the backend on a small product that stays in the caches.
+    #[arg(long)]
+    peak: Option<f64>,
+    /// Libraries whose throughput is reported next to the one of `gemm`, among `matrixmultiply`
+    /// (with the `matrixmultiply` feature) and `blas` (the system BLAS, with the `blas` feature).
+    /// They're only timed for single-threaded runs, so the BLAS should be limited to one thread,
+    /// e.g. with `OPENBLAS_NUM_THREADS=1` or `MKL_NUM_THREADS=1`.
+    #[arg(long, value_delimiter = ',')]
+    compare: Vec<String>,
+    /// Reports the arithmetic intensity of each problem and its throughput relative to the
+    /// roofline of the machine, bounded by the peak and by the memory bandwidth.
+    #[arg(long)]
+    roofline: bool,
+    /// Sustained memory bandwidth in GB/s for each number of threads, used by `--roofline`. By
+    /// default, it's measured by reading a buffer that's larger than the last level cache.
+    #[arg(long)]
+    bandwidth: Option<f64>,
+    /// Reports the hardware counters of the runs of `gemm`, per run: the instructions per cycle,
+    /// cycles, instructions, L1 data cache misses, last level cache references and misses, and
+    /// frontend and backend stalls, for the events supported by the machine.
+    #[cfg(feature = "perf")]
+    #[arg(long)]
+    counters: bool,
+    /// Reports the energy of the runs of `gemm` from the RAPL counters of Linux, per run, for the
+    /// packages and for the DRAM if the machine reports it, and the GFLOPS per watt of both.
+    #[cfg(feature = "energy")]
+    #[arg(long)]
+    energy: bool,
+    /// Saves the throughput of the runs as the baseline with this name of the current machine.
+    #[arg(long, value_name = "NAME")]
+    save_baseline: Option<String>,
+    /// Compares the throughput of the runs with the ones of the baseline with this name of the
+    /// current machine, and exits with a nonzero status if one of them regressed by more than
+    /// `--threshold`.
+    #[arg(long, value_name = "NAME")]
+    baseline: Option<String>,
+    /// Largest slowdown from the baseline that isn't reported as a regression, in percent.
+    #[arg(long, default_value_t = 5.0)]
+    threshold: f64,
+    /// Directory of the baselines, which are stored in `DIR/MACHINE/NAME.csv`.
+    #[arg(long, value_name = "DIR", default_value = "target/bench-gemm")]
+    baseline_dir: std::path::PathBuf,
+    /// Name of the current machine in the baselines, its host name by default.
+    #[arg(long)]
+    machine: Option<String>,
+    /// Draws SVG plots of the throughput in this directory: against the size of the problems,
+    /// against the number of threads, and over the grid of `m` and `n` of the sweeps of both.
+    #[cfg(feature = "plot")]
+    #[arg(long, value_name = "DIR")]
+    plot: Option<std::path::PathBuf>,
+    /// Prints the results as comma-separated values.
+    #[arg(long)]
+    csv: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Layout {
+    Col,
+    Row,
+}
+
+impl Layout {
+    /// Strides `(cs, rs)` of a contiguous `rows×cols` matrix.
+    fn strides(self, rows: usize, cols: usize) -> (isize, isize) {
+        match self {
+            Layout::Col => (rows as isize, 1),
+            Layout::Row => (1, cols as isize),
+        }
+    }
+}
+
+fn parse_sizes(spec: &str) -> Result<Vec<usize>, String> {
+    let mut sizes = Vec::new();
+    for item in spec.split(',') {
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid size `{s}` in `{spec}`"))
+        };
+        let parts: Vec<&str> = item.split(':').collect();
+        match parts[..] {
+            [size] => sizes.push(parse(size)?),
+            [start, end] => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start == 0 {
+                    return Err(format!("geometric range `{item}` must start above zero"));
+                }
+                let mut size = start;
+                while size <= end {
+                    sizes.push(size);
+                    size *= 2;
+                }
+            }
+            [start, end, step] => {
+                let (start, end, step) = (parse(start)?, parse(end)?, parse(step)?);
+                if step == 0 {
+                    return Err(format!("range `{item}` must have a nonzero step"));
+                }
+                sizes.extend((start..=end).step_by(step));
+            }
+            _ => return Err(format!("invalid size range `{item}`")),
+        }
+    }
+    Ok(sizes)
+}
+
+fn parse_dtype(name: &str) -> Result<DType, String> {
+    match name {
+        #[cfg(feature = "f16")]
+        "f16" => Ok(DType::F16),
+        "f32" => Ok(DType::F32),
+        "f64" => Ok(DType::F64),
+        "c32" => Ok(DType::C32),
+        "c64" => Ok(DType::C64),
+        _ => Err(format!("unsupported scalar type `{name}`")),
+    }
+}
+
+fn parse_layout(spec: &str) -> Result<[Layout; 3], String> {
+    let layouts: Vec<Layout> = spec
+        .chars()
+        .map(|c| match c {
+            'c' => Ok(Layout::Col),
+            'r' => Ok(Layout::Row),
+            _ => Err(format!("invalid layout `{spec}`")),
+        })
+        .collect::<Result<_, _>>()?;
+    layouts
+        .try_into()
+        .map_err(|_| format!("layout `{spec}` must have three letters"))
+}
+
+/// How the caches are flushed before the timed runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Cold {
+    /// Writes a buffer that's larger than the last level cache.
+    Sweep,
+    /// Evicts the lines of the operands with `clflush`.
+    #[cfg(target_arch = "x86_64")]
+    Clflush,
+}
+
+fn parse_cold(name: &str) -> Result<Cold, String> {
+    match name {
+        "sweep" => Ok(Cold::Sweep),
+        #[cfg(target_arch = "x86_64")]
+        "clflush" => Ok(Cold::Clflush),
+        _ => Err(format!("unsupported cache flush `{name}`")),
+    }
+}
+
+/// Evicts the previous data from the caches of the current core and from the last level cache,
+/// by writing each line of a buffer twice as large as the last level cache.
+fn sweep_caches() {
+    static BUFFER: OnceLock<Mutex<Vec<u8>>> = OnceLock::new();
+    let mut buffer = BUFFER
+        .get_or_init(|| {
+            let bytes = Ord::max(2 * CACHE_INFO[2].cache_bytes, 32 << 20);
+            Mutex::new(vec![0; bytes])
+        })
+        .lock()
+        .unwrap();
+    let line = Ord::max(CACHE_INFO[0].cache_line_bytes, 1);
+    for byte in buffer.iter_mut().step_by(line) {
+        *byte = byte.wrapping_add(1);
+    }
+    std::hint::black_box(&mut *buffer);
+}
+
+/// Evicts the lines of the `len` elements at `ptr` from every cache of the machine.
+#[cfg(target_arch = "x86_64")]
+unsafe fn clflush<T>(ptr: *const T, len: usize) {
+    use core::arch::x86_64::{_mm_clflush, _mm_mfence};
+
+    let line = Ord::max(CACHE_INFO[0].cache_line_bytes, 1);
+    let end = ptr as usize + len * core::mem::size_of::<T>();
+    let mut addr = ptr as usize / line * line;
+    while addr < end {
+        _mm_clflush(addr as *const u8);
+        addr += line;
+    }
+    _mm_mfence();
+}
+
+/// Floating point operations of a product, counting a complex multiply-add as eight.
+fn flops(m: usize, n: usize, k: usize, dtype: DType) -> f64 {
+    let per_term = match dtype {
+        DType::C32 | DType::C64 => 8.0,
+        _ => 2.0,
+    };
+    per_term * m as f64 * n as f64 * k as f64
+}
+
+/// Size in bytes of a scalar of `dtype`.
+fn scalar_bytes(dtype: DType) -> usize {
+    match dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => 2,
+        DType::F32 => 4,
+        DType::F64 | DType::C32 => 8,
+        DType::C64 => 16,
+    }
+}
+
+/// Floating point operations per byte of a product, for the minimal traffic where each operand
+/// is read once and the destination is written once.
+fn intensity(m: usize, n: usize, k: usize, dtype: DType) -> f64 {
+    let elements = m as f64 * k as f64 + k as f64 * n as f64 + m as f64 * n as f64;
+    flops(m, n, k, dtype) / (elements * scalar_bytes(dtype) as f64)
+}
+
+/// Library computing the products.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Library {
+    Gemm,
+    #[cfg(feature = "matrixmultiply")]
+    MatrixMultiply,
+    #[cfg(feature = "blas")]
+    Blas,
+}
+
+impl Library {
+    fn name(self) -> &'static str {
+        match self {
+            Library::Gemm => "gemm",
+            #[cfg(feature = "matrixmultiply")]
+            Library::MatrixMultiply => "matrixmultiply",
+            #[cfg(feature = "blas")]
+            Library::Blas => "blas",
+        }
+    }
+}
+
+fn parse_library(name: &str) -> Result<Library, String> {
+    match name {
+        #[cfg(feature = "matrixmultiply")]
+        "matrixmultiply" => Ok(Library::MatrixMultiply),
+        #[cfg(feature = "blas")]
+        "blas" => Ok(Library::Blas),
+        _ => Err(format!(
+            "unsupported library `{name}`, which may need to be enabled with its cargo feature"
+        )),
+    }
+}
+
+/// Operands of `dst := lhs×rhs`, in contiguous storage with the given layouts.
+#[derive(Copy, Clone)]
+#[cfg_attr(
+    not(any(feature = "matrixmultiply", feature = "blas")),
+    allow(dead_code)
+)]
+struct Operands<T> {
+    m: usize,
+    n: usize,
+    k: usize,
+    layout: [Layout; 3],
+    dst: *mut T,
+    lhs: *const T,
+    rhs: *const T,
+}
+
+/// Scalar type that can be benchmarked, with the entry points of the comparison libraries that
+/// support it, which return `false` if they don't support the type.
+trait Scalar: 'static + Copy + One + Zero {
+    #[cfg(feature = "matrixmultiply")]
+    unsafe fn matrixmultiply(_operands: Operands<Self>) -> bool {
+        false
+    }
+    #[cfg(feature = "blas")]
+    unsafe fn blas(_operands: Operands<Self>) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "matrixmultiply")]
+macro_rules! matrixmultiply {
+    ($gemm: ident, $one: expr, $zero: expr $(, $flag: expr)?) => {
+        unsafe fn matrixmultiply(operands: Operands<Self>) -> bool {
+            let Operands { m, n, k, layout: [dst_layout, lhs_layout, rhs_layout], dst, lhs, rhs } =
+                operands;
+            let (dst_cs, dst_rs) = dst_layout.strides(m, n);
+            let (lhs_cs, lhs_rs) = lhs_layout.strides(m, k);
+            let (rhs_cs, rhs_rs) = rhs_layout.strides(k, n);
+            // the complex numbers of matrixmultiply are `[re, im]` arrays, laid out like
+            // `num_complex::Complex`
+            matrixmultiply::$gemm(
+                $($flag, $flag,)?
+                m,
+                k,
+                n,
+                $one,
+                lhs as *const _,
+                lhs_rs,
+                lhs_cs,
+                rhs as *const _,
+                rhs_rs,
+                rhs_cs,
+                $zero,
+                dst as *mut _,
+                dst_rs,
+                dst_cs,
+            );
+            true
+        }
+    };
+}
+
+/// CBLAS interface of the system BLAS, which is linked by the build script.
+#[cfg(feature = "blas")]
+mod cblas {
+    use core::ffi::{c_int, c_void};
+
+    pub const ROW_MAJOR: c_int = 101;
+    pub const COL_MAJOR: c_int = 102;
+    pub const NO_TRANS: c_int = 111;
+    pub const TRANS: c_int = 112;
+
+    extern "C" {
+        pub fn cblas_sgemm(
+            layout: c_int,
+            trans_a: c_int,
+            trans_b: c_int,
+            m: c_int,
+            n: c_int,
+            k: c_int,
+            alpha: f32,
+            a: *const f32,
+            lda: c_int,
+            b: *const f32,
+            ldb: c_int,
+            beta: f32,
+            c: *mut f32,
+            ldc: c_int,
+        );
+        pub fn cblas_dgemm(
+            layout: c_int,
+            trans_a: c_int,
+            trans_b: c_int,
+            m: c_int,
+            n: c_int,
+            k: c_int,
+            alpha: f64,
+            a: *const f64,
+            lda: c_int,
+            b: *const f64,
+            ldb: c_int,
+            beta: f64,
+            c: *mut f64,
+            ldc: c_int,
+        );
+        pub fn cblas_cgemm(
+            layout: c_int,
+            trans_a: c_int,
+            trans_b: c_int,
+            m: c_int,
+            n: c_int,
+            k: c_int,
+            alpha: *const c_void,
+            a: *const c_void,
+            lda: c_int,
+            b: *const c_void,
+            ldb: c_int,
+            beta: *const c_void,
+            c: *mut c_void,
+            ldc: c_int,
+        );
+        pub fn cblas_zgemm(
+            layout: c_int,
+            trans_a: c_int,
+            trans_b: c_int,
+            m: c_int,
+            n: c_int,
+            k: c_int,
+            alpha: *const c_void,
+            a: *const c_void,
+            lda: c_int,
+            b: *const c_void,
+            ldb: c_int,
+            beta: *const c_void,
+            c: *mut c_void,
+            ldc: c_int,
+        );
+    }
+}
+
+/// Arguments `(layout, trans_a, trans_b, m, n, k, lda, ldb, ldc)` of the CBLAS call computing
+/// `operands`, in the layout of the destination, or `None` if the dimensions overflow a C `int`.
+#[cfg(feature = "blas")]
+fn blas_args<T>(operands: &Operands<T>) -> Option<[core::ffi::c_int; 9]> {
+    let Operands {
+        m,
+        n,
+        k,
+        layout: [dst_layout, lhs_layout, rhs_layout],
+        ..
+    } = *operands;
+    let int = |x: usize| core::ffi::c_int::try_from(x).ok();
+    // the leading dimension is the distance between the rows or the columns, and an operand
+    // stored in the other layout is the transpose of one stored in the layout of the call
+    let leading = |layout: Layout, rows: usize, cols: usize| {
+        let leading = match layout {
+            Layout::Col => rows,
+            Layout::Row => cols,
+        };
+        Ord::max(leading, 1)
+    };
+    let trans = |layout: Layout| {
+        if layout == dst_layout {
+            cblas::NO_TRANS
+        } else {
+            cblas::TRANS
+        }
+    };
+    let order = match dst_layout {
+        Layout::Col => cblas::COL_MAJOR,
+        Layout::Row => cblas::ROW_MAJOR,
+    };
+    Some([
+        order,
+        trans(lhs_layout),
+        trans(rhs_layout),
+        int(m)?,
+        int(n)?,
+        int(k)?,
+        int(leading(lhs_layout, m, k))?,
+        int(leading(rhs_layout, k, n))?,
+        int(leading(dst_layout, m, n))?,
+    ])
+}
+
+#[cfg(feature = "blas")]
+macro_rules! blas {
+    ($gemm: ident, |$x: ident| $arg: expr) => {
+        unsafe fn blas(operands: Operands<Self>) -> bool {
+            let Some([order, trans_a, trans_b, m, n, k, lda, ldb, ldc]) = blas_args(&operands)
+            else {
+                return false;
+            };
+            let (one, zero) = (Self::one(), Self::zero());
+            let arg = |$x: &Self| $arg;
+            cblas::$gemm(
+                order,
+                trans_a,
+                trans_b,
+                m,
+                n,
+                k,
+                arg(&one),
+                operands.lhs as *const _,
+                lda,
+                operands.rhs as *const _,
+                ldb,
+                arg(&zero),
+                operands.dst as *mut _,
+                ldc,
+            );
+            true
+        }
+    };
+}
+
+impl Scalar for f32 {
+    #[cfg(feature = "matrixmultiply")]
+    matrixmultiply!(sgemm, 1.0, 0.0);
+    #[cfg(feature = "blas")]
+    blas!(cblas_sgemm, |x| *x);
+}
+impl Scalar for f64 {
+    #[cfg(feature = "matrixmultiply")]
+    matrixmultiply!(dgemm, 1.0, 0.0);
+    #[cfg(feature = "blas")]
+    blas!(cblas_dgemm, |x| *x);
+}
+impl Scalar for gemm::c32 {
+    #[cfg(feature = "matrixmultiply")]
+    matrixmultiply!(
+        cgemm,
+        [1.0, 0.0],
+        [0.0, 0.0],
+        matrixmultiply::CGemmOption::Standard
+    );
+    // the complex scalars are passed by pointer
+    #[cfg(feature = "blas")]
+    blas!(cblas_cgemm, |x| x as *const Self
+        as *const core::ffi::c_void);
+}
+impl Scalar for gemm::c64 {
+    #[cfg(feature = "matrixmultiply")]
+    matrixmultiply!(
+        zgemm,
+        [1.0, 0.0],
+        [0.0, 0.0],
+        matrixmultiply::CGemmOption::Standard
+    );
+    #[cfg(feature = "blas")]
+    blas!(cblas_zgemm, |x| x as *const Self
+        as *const core::ffi::c_void);
+}
+#[cfg(feature = "f16")]
+impl Scalar for gemm::f16 {}
+
+/// Product to time.
+#[derive(Copy, Clone)]
+struct Problem {
+    m: usize,
+    n: usize,
+    k: usize,
+    dtype: DType,
+    layout: [Layout; 3],
+    parallelism: Parallelism<'static>,
+}
+
+/// How the runs of each problem are measured.
+#[derive(Copy, Clone)]
+struct Sampling {
+    /// Minimum number of timed runs.
+    samples: usize,
+    /// Minimum time spent timing the runs.
+    min_time: Duration,
+    /// How the caches are flushed before each run, if they are.
+    cold: Option<Cold>,
+    /// Whether the hardware counters of `samples` more runs are read.
+    #[cfg(feature = "perf")]
+    counters: bool,
+    /// Whether the energy of the timed runs is read.
+    #[cfg(feature = "energy")]
+    energy: bool,
+}
+
+impl Sampling {
+    fn new(samples: usize, min_time: Duration) -> Self {
+        Sampling {
+            samples,
+            min_time,
+            cold: None,
+            #[cfg(feature = "perf")]
+            counters: false,
+            #[cfg(feature = "energy")]
+            energy: false,
+        }
+    }
+}
+
+/// Measurements of the runs of a problem.
+#[derive(Copy, Clone)]
+struct Measurement {
+    /// Fastest run.
+    elapsed: Duration,
+    /// Hardware counters per run.
+    #[cfg(feature = "perf")]
+    counts: Option<perf::Counts>,
+    /// Energy per run, or `None` if the counters didn't advance during the runs.
+    #[cfg(feature = "energy")]
+    energy: Option<energy::Energy>,
+}
+
+/// Fastest of at least `samples` runs of `run`, timed for at least `min_time`, where `flush` is
+/// called before each run, and isn't timed.
+fn measure(mut run: impl FnMut(), mut flush: impl FnMut(), sampling: Sampling) -> Measurement {
+    // warm up the caches and the thread pool
+    run();
+    #[cfg(feature = "energy")]
+    let domains = sampling.energy.then(energy::domains).and_then(Result::ok);
+    #[cfg(feature = "energy")]
+    let before = domains.map(energy::Domains::read);
+    let start = Instant::now();
+    let mut best = Duration::MAX;
+    let mut runs = 0;
+    while runs < sampling.samples || start.elapsed() < sampling.min_time {
+        flush();
+        let now = Instant::now();
+        run();
+        best = Ord::min(best, now.elapsed());
+        runs += 1;
+    }
+    Measurement {
+        elapsed: best,
+        #[cfg(feature = "energy")]
+        energy: domains
+            .zip(before)
+            .map(|(domains, before)| domains.energy(&before, &domains.read(), runs))
+            // the counters may not have been updated during short runs
+            .filter(|energy| energy.total() > 0.0),
+        // the counters are read over separate runs, so that opening them isn't timed
+        #[cfg(feature = "perf")]
+        counts: if sampling.counters {
+            perf::count(run, flush, Ord::max(sampling.samples, 1)).ok()
+        } else {
+            None
+        },
+    }
+}
+
+/// Measurements of `dst := lhs×rhs` with `library`, or `None` if it doesn't support the problem.
+fn time<T: Scalar>(problem: Problem, library: Library, sampling: Sampling) -> Option<Measurement> {
+    let Problem {
+        m,
+        n,
+        k,
+        layout,
+        parallelism,
+        ..
+    } = problem;
+    let mut dst = vec![T::zero(); m * n];
+    let lhs = vec![T::one(); m * k];
+    let rhs = vec![T::one(); k * n];
+    let operands = Operands {
+        m,
+        n,
+        k,
+        layout,
+        dst: dst.as_mut_ptr(),
+        lhs: lhs.as_ptr(),
+        rhs: rhs.as_ptr(),
+    };
+
+    let flush = || match sampling.cold {
+        None => {}
+        Some(Cold::Sweep) => sweep_caches(),
+        #[cfg(target_arch = "x86_64")]
+        Some(Cold::Clflush) => unsafe {
+            clflush(operands.dst, m * n);
+            clflush(operands.lhs, m * k);
+            clflush(operands.rhs, k * n);
+        },
+    };
+
+    // the comparison libraries are timed on one thread
+    #[cfg(any(feature = "matrixmultiply", feature = "blas"))]
+    let other = |run: unsafe fn(Operands<T>) -> bool| {
+        if !matches!(parallelism, Parallelism::None) || !unsafe { run(operands) } {
+            return None;
+        }
+        Some(measure(
+            || {
+                unsafe { run(operands) };
+            },
+            flush,
+            sampling,
+        ))
+    };
+
+    match library {
+        Library::Gemm => {
+            let [dst_layout, lhs_layout, rhs_layout] = layout;
+            let (dst_cs, dst_rs) = dst_layout.strides(m, n);
+            let (lhs_cs, lhs_rs) = lhs_layout.strides(m, k);
+            let (rhs_cs, rhs_rs) = rhs_layout.strides(k, n);
+            Some(measure(
+                || unsafe {
+                    gemm(
+                        m,
+                        n,
+                        k,
+                        operands.dst,
+                        dst_cs,
+                        dst_rs,
+                        false,
+                        operands.lhs,
+                        lhs_cs,
+                        lhs_rs,
+                        operands.rhs,
+                        rhs_cs,
+                        rhs_rs,
+                        T::zero(),
+                        T::one(),
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                    )
+                },
+                flush,
+                sampling,
+            ))
+        }
+        #[cfg(feature = "matrixmultiply")]
+        Library::MatrixMultiply => other(T::matrixmultiply),
+        #[cfg(feature = "blas")]
+        Library::Blas => other(T::blas),
+    }
+}
+
+fn time_dtype(problem: Problem, library: Library, sampling: Sampling) -> Option<Measurement> {
+    match problem.dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => time::<gemm::f16>(problem, library, sampling),
+        DType::F32 => time::<f32>(problem, library, sampling),
+        DType::F64 => time::<f64>(problem, library, sampling),
+        DType::C32 => time::<gemm::c32>(problem, library, sampling),
+        DType::C64 => time::<gemm::c64>(problem, library, sampling),
+    }
+}
+
+/// Count with an SI prefix, e.g. `1.23G`, or `-` if it's unknown.
+#[cfg(feature = "perf")]
+fn si(count: Option<f64>) -> String {
+    let Some(count) = count else {
+        return "-".into();
+    };
+    let mut scaled = count;
+    for prefix in ["", "k", "M", "G", "T"] {
+        if scaled.abs() < 1000.0 || prefix == "T" {
+            return format!("{scaled:.2}{prefix}");
+        }
+        scaled /= 1000.0;
+    }
+    unreachable!()
+}
+
+/// Estimates the peak of one thread in GFLOPS from the throughput of the backend on a
+/// single-threaded product of a few microkernel tiles, which stays in the caches.
+fn estimate_peak(dtype: DType) -> f64 {
+    let description = describe(256, 256, 256, dtype);
+    let (m, n, k) = (2 * description.mr, 8 * description.nr, 256);
+    let problem = Problem {
+        m,
+        n,
+        k,
+        dtype,
+        layout: [Layout::Col; 3],
+        parallelism: Parallelism::None,
+    };
+    let sampling = Sampling::new(100, Duration::from_millis(100));
+    let elapsed = time_dtype(problem, Library::Gemm, sampling)
+        .unwrap()
+        .elapsed;
+    flops(m, n, k, dtype) / elapsed.as_secs_f64() / 1e9
+}
+
+/// Sustained bandwidth in GB/s of reads from memory by `n_threads` threads, from the fastest of a
+/// few sums of a buffer that's four times as large as the last level cache.
+fn measure_bandwidth(n_threads: usize) -> f64 {
+    let llc = gemm_common::cache::CACHE_INFO[2].cache_bytes;
+    let bytes = Ord::max(4 * llc, 64 << 20);
+    let words = vec![1u64; bytes / 8];
+    let chunk = words.len().div_ceil(n_threads);
+
+    let sampling = Sampling::new(5, Duration::ZERO);
+    let elapsed = measure(
+        || {
+            std::thread::scope(|scope| {
+                for words in words.chunks(chunk) {
+                    scope.spawn(move || {
+                        let sum = words.iter().fold(0u64, |sum, &word| sum.wrapping_add(word));
+                        std::hint::black_box(sum);
+                    });
+                }
+            })
+        },
+        || {},
+        sampling,
+    )
+    .elapsed;
+    bytes as f64 / elapsed.as_secs_f64() / 1e9
+}
+
+fn main() {
+    let args = Args::parse();
+    let exit = |message: String| -> ! {
+        eprintln!("error: {message}");
+        std::process::exit(2)
+    };
+
+    let sizes = parse_sizes(&args.size).unwrap_or_else(|e| exit(e));
+    let dims = |spec: &Option<String>| match spec {
+        Some(spec) => parse_sizes(spec).unwrap_or_else(|e| exit(e)),
+        None => sizes.clone(),
+    };
+    // shapes, named after their preset
+    let shapes: Vec<(usize, usize, usize, String)> = if !args.preset.is_empty() {
+        let mut shapes = Vec::new();
+        for name in &args.preset {
+            for shape in presets::parse_preset(name).unwrap_or_else(|e| exit(e)) {
+                let label = format!("{name}/{}", shape.name);
+                shapes.push((shape.m, shape.n, shape.k, label));
+            }
+        }
+        shapes
+    } else if args.m.is_none() && args.n.is_none() && args.k.is_none() {
+        sizes
+            .iter()
+            .map(|&size| (size, size, size, String::new()))
+            .collect()
+    } else {
+        let (ms, ns, ks) = (dims(&args.m), dims(&args.n), dims(&args.k));
+        let mut shapes = Vec::new();
+        for &m in &ms {
+            for &n in &ns {
+                for &k in &ks {
+                    shapes.push((m, n, k, String::new()));
+                }
+            }
+        }
+        shapes
+    };
+    let dtypes: Vec<DType> = args
+        .dtype
+        .iter()
+        .map(|name| parse_dtype(name).unwrap_or_else(|e| exit(e)))
+        .collect();
+    let layouts: Vec<[Layout; 3]> = args
+        .layout
+        .iter()
+        .map(|spec| parse_layout(spec).unwrap_or_else(|e| exit(e)))
+        .collect();
+    let mut sampling = Sampling::new(args.samples, Duration::from_millis(args.min_time));
+    sampling.cold = args
+        .cold
+        .as_deref()
+        .map(|name| parse_cold(name).unwrap_or_else(|e| exit(e)));
+    #[cfg(feature = "perf")]
+    if args.counters {
+        perf::check().unwrap_or_else(|e| exit(e));
+        sampling.counters = true;
+    }
+    #[cfg(feature = "energy")]
+    if args.energy {
+        energy::domains().unwrap_or_else(|e| exit(e));
+        sampling.energy = true;
+    }
+    #[cfg(feature = "plot")]
+    let mut records = Vec::new();
+    #[cfg(feature = "plot")]
+    let draw = |records: &[plot::Record]| {
+        if let Some(dir) = &args.plot {
+            for path in plot::draw(dir, records).unwrap_or_else(|e| exit(e)) {
+                eprintln!("drew {path}");
+            }
+        }
+    };
+
+    if let Some(cases) = args.fuzz {
+        let failures = fuzz::run(
+            cases,
+            args.seed,
+            args.max_size,
+            &dtypes,
+            &args.threads,
+            sampling,
+            args.csv,
+        );
+        if failures != 0 {
+            eprintln!("{failures} of {cases} products were wrong");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(spec) = &args.latency {
+        let sizes = parse_sizes(spec).unwrap_or_else(|e| exit(e));
+        let layouts: Vec<([Layout; 3], &str)> = layouts
+            .iter()
+            .copied()
+            .zip(args.layout.iter().map(String::as_str))
+            .collect();
+        latency::run(&sizes, &dtypes, &layouts, sampling, args.csv);
+        return;
+    }
+
+    if let Some(max_threads) = args.scaling {
+        let max_threads = match max_threads {
+            0 => gemm_common::gemm::max_threads(Parallelism::Rayon(0)),
+            max_threads => max_threads,
+        };
+        let layouts: Vec<([Layout; 3], &str)> = layouts
+            .iter()
+            .copied()
+            .zip(args.layout.iter().map(String::as_str))
+            .collect();
+        scaling::sweep(
+            &shapes,
+            &dtypes,
+            &layouts,
+            max_threads,
+            sampling,
+            args.csv,
+            #[cfg(feature = "plot")]
+            &mut records,
+        );
+        #[cfg(feature = "plot")]
+        draw(&records);
+        return;
+    }
+
+    let compare: Vec<Library> = args
+        .compare
+        .iter()
+        .map(|name| parse_library(name).unwrap_or_else(|e| exit(e)))
+        .collect();
+    let machine = args.machine.clone().unwrap_or_else(baseline::machine);
+    let baseline = args.baseline.as_ref().map(|name| {
+        Baseline::load(&baseline::path(&args.baseline_dir, &machine, name))
+            .unwrap_or_else(|e| exit(e))
+    });
+    let mut saved = Baseline::default();
+    let mut regressions = Vec::new();
+
+    let mut peaks = HashMap::new();
+    let mut bandwidths = HashMap::new();
+    if args.csv {
+        print!("dtype,m,n,k,threads,layout,seconds,gflops,peak_percent");
+        if args.roofline {
+            print!(",intensity,roofline_gflops,roofline_percent,bound");
+        }
+        #[cfg(feature = "energy")]
+        if args.energy {
+            print!(",package_joules,dram_joules,gflops_per_watt");
+        }
+        #[cfg(feature = "perf")]
+        if args.counters {
+            print!(",ipc");
+            for (event, ..) in perf::EVENTS {
+                print!(",{event}");
+            }
+        }
+        if baseline.is_some() {
+            print!(",baseline_gflops,change_percent");
+        }
+        for library in &compare {
+            print!(",{}_gflops", library.name());
+        }
+        if !args.preset.is_empty() {
+            print!(",shape");
+        }
+        println!();
+    } else {
+        print!(
+            "{:>5} {:>6} {:>6} {:>6} {:>7} {:>6} {:>12} {:>9} {:>6}",
+            "dtype", "m", "n", "k", "threads", "layout", "time", "GFLOPS", "%peak",
+        );
+        if args.roofline {
+            print!(
+                " {:>7} {:>9} {:>6} {:>7}",
+                "flop/B", "roofline", "%roof", "bound"
+            );
+        }
+        #[cfg(feature = "energy")]
+        if args.energy {
+            print!(" {:>10} {:>10} {:>8}", "package J", "DRAM J", "GFLOPS/W");
+        }
+        #[cfg(feature = "perf")]
+        if args.counters {
+            print!(" {:>5}", "IPC");
+            for (event, ..) in perf::EVENTS {
+                print!(" {event:>15}");
+            }
+        }
+        if baseline.is_some() {
+            print!(" {:>9} {:>7}", "baseline", "change");
+        }
+        for library in &compare {
+            print!(" {:>16} {:>7}", library.name(), "speedup");
+        }
+        if !args.preset.is_empty() {
+            print!(" shape");
+        }
+        println!();
+    }
+
+    for &dtype in &dtypes {
+        let peak = *peaks
+            .entry(dtype)
+            .or_insert_with(|| args.peak.unwrap_or_else(|| estimate_peak(dtype)));
+
+        for &threads in &args.threads {
+            let parallelism = if threads == 1 {
+                Parallelism::None
+            } else {
+                Parallelism::Rayon(threads)
+            };
+            let n_threads = gemm_common::gemm::max_threads(parallelism);
+            let bandwidth = if args.roofline {
+                *bandwidths.entry(n_threads).or_insert_with(|| {
+                    let bandwidth = args
+                        .bandwidth
+                        .unwrap_or_else(|| measure_bandwidth(n_threads));
+                    eprintln!("memory bandwidth with {n_threads} threads: {bandwidth:.1} GB/s");
+                    bandwidth
+                })
+            } else {
+                0.0
+            };
+
+            for (layout, spec) in layouts.iter().zip(&args.layout) {
+                for (m, n, k, label) in &shapes {
+                    let (m, n, k) = (*m, *n, *k);
+                    let problem = Problem {
+                        m,
+                        n,
+                        k,
+                        dtype,
+                        layout: *layout,
+                        parallelism,
+                    };
+                    let gflops =
+                        |elapsed: Duration| flops(m, n, k, dtype) / elapsed.as_secs_f64() / 1e9;
+                    let measurement = time_dtype(problem, Library::Gemm, sampling).unwrap();
+                    let elapsed = measurement.elapsed;
+                    let ours = gflops(elapsed);
+                    let percent = 100.0 * ours / (peak * n_threads as f64);
+                    let name = format!("{dtype:?}").to_lowercase();
+                    let key = format!("{name},{m},{n},{k},{n_threads},{spec}");
+                    let base = baseline.as_ref().and_then(|baseline| baseline.get(&key));
+                    let change = base.map(|base| 100.0 * (ours / base - 1.0));
+                    if let (Some(base), Some(change)) = (base, change) {
+                        if change < -args.threshold {
+                            regressions.push(format!(
+                                "{name} {m}x{n}x{k} with {n_threads} threads and layout {spec}: \
+                                 {base:.2} -> {ours:.2} GFLOPS ({change:+.1}%)"
+                            ));
+                        }
+                    }
+                    saved.insert(key, ours);
+                    #[cfg(feature = "plot")]
+                    records.push(plot::Record {
+                        dtype: name.clone(),
+                        m,
+                        n,
+                        k,
+                        threads: n_threads,
+                        layout: spec.clone(),
+                        gflops: ours,
+                    });
+                    // the attainable throughput is bounded by the peak, or by the bandwidth for
+                    // the products that move too few flops per byte
+                    let intensity = intensity(m, n, k, dtype);
+                    let compute_roof = peak * n_threads as f64;
+                    let memory_roof = intensity * bandwidth;
+                    let (roof, bound) = if memory_roof < compute_roof {
+                        (memory_roof, "memory")
+                    } else {
+                        (compute_roof, "compute")
+                    };
+                    let roof_percent = 100.0 * ours / roof;
+                    // GFLOPS per watt, from the joules per run
+                    #[cfg(feature = "energy")]
+                    let efficiency =
+                        |energy: energy::Energy| flops(m, n, k, dtype) / energy.total() / 1e9;
+                    let others: Vec<Option<f64>> = compare
+                        .iter()
+                        .map(|&library| {
+                            time_dtype(problem, library, sampling)
+                                .map(|measurement| gflops(measurement.elapsed))
+                        })
+                        .collect();
+
+                    if args.csv {
+                        print!(
+                            "{name},{m},{n},{k},{n_threads},{spec},{},{ours:.3},{percent:.1}",
+                            elapsed.as_secs_f64(),
+                        );
+                        if args.roofline {
+                            print!(",{intensity:.3},{roof:.3},{roof_percent:.1},{bound}");
+                        }
+                        #[cfg(feature = "energy")]
+                        if args.energy {
+                            match measurement.energy {
+                                Some(energy) => print!(
+                                    ",{:.6e},{},{:.3}",
+                                    energy.package,
+                                    energy
+                                        .dram
+                                        .map(|dram| format!("{dram:.6e}"))
+                                        .unwrap_or_default(),
+                                    efficiency(energy),
+                                ),
+                                None => print!(",,,"),
+                            }
+                        }
+                        #[cfg(feature = "perf")]
+                        if args.counters {
+                            let counts = measurement.counts.unwrap_or_default();
+                            let value = |value: Option<f64>| {
+                                value.map(|value| format!("{value:.3}")).unwrap_or_default()
+                            };
+                            print!(",{}", value(counts.ipc()));
+                            for count in counts.0 {
+                                print!(",{}", value(count));
+                            }
+                        }
+                        if baseline.is_some() {
+                            match base.zip(change) {
+                                Some((base, change)) => print!(",{base:.3},{change:.1}"),
+                                None => print!(",,"),
+                            }
+                        }
+                        for other in &others {
+                            match other {
+                                Some(other) => print!(",{other:.3}"),
+                                None => print!(","),
+                            }
+                        }
+                        if !args.preset.is_empty() {
+                            print!(",{label}");
+                        }
+                    } else {
+                        print!(
+                            "{name:>5} {m:>6} {n:>6} {k:>6} {n_threads:>7} {spec:>6} {:>12} {ours:>9.2} {percent:>6.1}",
+                            format!("{elapsed:.2?}"),
+                        );
+                        if args.roofline {
+                            print!(" {intensity:>7.2} {roof:>9.2} {roof_percent:>6.1} {bound:>7}");
+                        }
+                        #[cfg(feature = "energy")]
+                        if args.energy {
+                            match measurement.energy {
+                                Some(energy) => print!(
+                                    " {:>10.3e} {:>10} {:>8.2}",
+                                    energy.package,
+                                    energy
+                                        .dram
+                                        .map(|dram| format!("{dram:.3e}"))
+                                        .unwrap_or_else(|| "-".into()),
+                                    efficiency(energy),
+                                ),
+                                None => print!(" {:>10} {:>10} {:>8}", "-", "-", "-"),
+                            }
+                        }
+                        #[cfg(feature = "perf")]
+                        if args.counters {
+                            let counts = measurement.counts.unwrap_or_default();
+                            match counts.ipc() {
+                                Some(ipc) => print!(" {ipc:>5.2}"),
+                                None => print!(" {:>5}", "-"),
+                            }
+                            for count in counts.0 {
+                                print!(" {:>15}", si(count));
+                            }
+                        }
+                        if baseline.is_some() {
+                            match base.zip(change) {
+                                Some((base, change)) => {
+                                    print!(" {base:>9.2} {:>7}", format!("{change:+.1}%"))
+                                }
+                                None => print!(" {:>9} {:>7}", "-", "-"),
+                            }
+                        }
+                        for other in &others {
+                            match other {
+                                Some(other) => {
+                                    print!(" {other:>16.2} {:>7.2}", ours / other)
+                                }
+                                None => print!(" {:>16} {:>7}", "-", "-"),
+                            }
+                        }
+                        if !args.preset.is_empty() {
+                            print!(" {label}");
+                        }
+                    }
+                    println!();
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "plot")]
+    draw(&records);
+    if let Some(name) = &args.save_baseline {
+        let path = baseline::path(&args.baseline_dir, &machine, name);
+        saved.save(&path).unwrap_or_else(|e| exit(e));
+        eprintln!("saved the baseline {}", path.display());
+    }
+    if !regressions.is_empty() {
+        eprintln!(
+            "{} runs regressed by more than {}% from the baseline:",
+            regressions.len(),
+            args.threshold,
+        );
+        for regression in &regressions {
+            eprintln!("  {regression}");
+        }
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,236 @@
+//! Hardware counters of the runs, read with `perf_event_open` on Linux.
+//!
+//! The counters are opened for each thread of the process, including the ones of the thread
+//! pool, and only count the user space instructions, which is allowed to unprivileged users up to
+//! `perf_event_paranoid = 2`. Each event is opened on its own and scaled by the fraction of the
+//! time it was scheduled, when the kernel has to multiplex more events than the machine has
+//! counters.
+
+/// Counted events, as their name, `perf_event_attr::type` and `perf_event_attr::config`.
+pub const EVENTS: [(&str, u32, u64); 7] = [
+    ("cycles", PERF_TYPE_HARDWARE, 0),
+    ("instructions", PERF_TYPE_HARDWARE, 1),
+    // L1D | READ << 8 | MISS << 16
+    ("l1d_misses", PERF_TYPE_HW_CACHE, 1 << 16),
+    // the requests that reach the last level cache, which missed the L2 on most machines
+    ("llc_references", PERF_TYPE_HARDWARE, 2),
+    ("llc_misses", PERF_TYPE_HARDWARE, 3),
+    ("frontend_stalls", PERF_TYPE_HARDWARE, 7),
+    ("backend_stalls", PERF_TYPE_HARDWARE, 8),
+];
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_HW_CACHE: u32 = 3;
+
+/// Counts of [`EVENTS`] per run, or `None` for the events that aren't supported by the machine.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Counts(pub [Option<f64>; EVENTS.len()]);
+
+impl Counts {
+    pub fn get(&self, name: &str) -> Option<f64> {
+        let index = EVENTS.iter().position(|&(event, ..)| event == name)?;
+        self.0[index]
+    }
+
+    /// Instructions per cycle.
+    pub fn ipc(&self) -> Option<f64> {
+        Some(self.get("instructions")? / self.get("cycles")?)
+    }
+}
+
+/// Fails if the counters can't be opened, e.g. if `perf_event_paranoid` is above `2` or if the
+/// machine doesn't expose its hardware counters.
+pub fn check() -> Result<(), String> {
+    count(|| {}, || {}, 1).map(drop)
+}
+
+/// Counts of [`EVENTS`] per run of `runs` runs of `run`, where `flush` is called before each run,
+/// and isn't counted.
+#[cfg(target_os = "linux")]
+pub fn count(
+    mut run: impl FnMut(),
+    mut flush: impl FnMut(),
+    runs: usize,
+) -> Result<Counts, String> {
+    let counters = linux::Counters::open()?;
+    for _ in 0..runs {
+        flush();
+        counters.enable();
+        run();
+        counters.disable();
+    }
+    let mut counts = counters.read();
+    for count in counts.0.iter_mut().flatten() {
+        *count /= runs as f64;
+    }
+    Ok(counts)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn count(_run: impl FnMut(), _flush: impl FnMut(), _runs: usize) -> Result<Counts, String> {
+    Err("hardware counters are only supported on linux".into())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{Counts, EVENTS};
+    use std::io;
+
+    const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+    const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+    const PERF_EVENT_IOC_ENABLE: u64 = 0x2400;
+    const PERF_EVENT_IOC_DISABLE: u64 = 0x2401;
+
+    /// `struct perf_event_attr`, up to `config2` (`PERF_ATTR_SIZE_VER1`).
+    #[repr(C)]
+    #[derive(Default)]
+    struct PerfEventAttr {
+        type_: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        // disabled: 1, inherit: 1, pinned: 1, exclusive: 1, exclude_user: 1,
+        // exclude_kernel: 1, exclude_hv: 1, ...
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+        config2: u64,
+    }
+
+    const DISABLED: u64 = 1 << 0;
+    const EXCLUDE_KERNEL: u64 = 1 << 5;
+    const EXCLUDE_HV: u64 = 1 << 6;
+
+    /// File descriptors of each event, for each thread of the process.
+    pub struct Counters {
+        fds: Vec<[Option<libc::c_int>; EVENTS.len()]>,
+    }
+
+    fn open(tid: libc::pid_t, type_: u32, config: u64) -> io::Result<libc::c_int> {
+        let attr = PerfEventAttr {
+            type_,
+            size: core::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            read_format: PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING,
+            flags: DISABLED | EXCLUDE_KERNEL | EXCLUDE_HV,
+            ..Default::default()
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                tid,
+                -1 as libc::c_int,
+                -1 as libc::c_int,
+                0 as libc::c_ulong,
+            )
+        };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(fd as libc::c_int)
+        }
+    }
+
+    impl Counters {
+        pub fn open() -> Result<Self, String> {
+            let tasks = std::fs::read_dir("/proc/self/task")
+                .map_err(|e| format!("failed to list the threads of the process: {e}"))?;
+
+            let mut counters = Counters { fds: Vec::new() };
+            let mut error = None;
+            for task in tasks {
+                let tid = match task
+                    .ok()
+                    .and_then(|task| task.file_name().to_str()?.parse().ok())
+                {
+                    Some(tid) => tid,
+                    None => continue,
+                };
+                let mut fds = [None; EVENTS.len()];
+                for (fd, &(_, type_, config)) in fds.iter_mut().zip(&EVENTS) {
+                    match open(tid, type_, config) {
+                        Ok(opened) => *fd = Some(opened),
+                        // the event isn't supported by the machine
+                        Err(e)
+                            if matches!(
+                                e.raw_os_error(),
+                                Some(libc::ENOENT | libc::EOPNOTSUPP | libc::EINVAL)
+                            ) =>
+                        {
+                            error.get_or_insert(e);
+                        }
+                        // the thread exited since it was listed
+                        Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+                        Err(e) => {
+                            return Err(format!(
+                                "failed to open the hardware counters: {e}, which may require \
+                                 lowering /proc/sys/kernel/perf_event_paranoid to 2"
+                            ))
+                        }
+                    }
+                }
+                counters.fds.push(fds);
+            }
+
+            if counters.fds.iter().flatten().all(Option::is_none) {
+                return Err(match error {
+                    Some(e) => format!("no hardware counter is supported by the machine: {e}"),
+                    None => "no hardware counter could be opened".into(),
+                });
+            }
+            Ok(counters)
+        }
+
+        fn ioctl(&self, request: u64) {
+            for &fd in self.fds.iter().flatten().flatten() {
+                unsafe { libc::ioctl(fd, request as _, 0) };
+            }
+        }
+
+        pub fn enable(&self) {
+            self.ioctl(PERF_EVENT_IOC_ENABLE);
+        }
+
+        pub fn disable(&self) {
+            self.ioctl(PERF_EVENT_IOC_DISABLE);
+        }
+
+        /// Sums of the counts of the threads, scaled by the fraction of the time each event was
+        /// counted.
+        pub fn read(&self) -> Counts {
+            let mut counts = Counts::default();
+            for fds in &self.fds {
+                for (count, fd) in counts.0.iter_mut().zip(fds) {
+                    let Some(fd) = *fd else { continue };
+                    // value, time enabled, time running
+                    let mut values = [0u64; 3];
+                    let len = core::mem::size_of_val(&values);
+                    let read = unsafe { libc::read(fd, values.as_mut_ptr() as *mut _, len) };
+                    if read != len as isize {
+                        continue;
+                    }
+                    let [value, enabled, running] = values;
+                    let scaled = if running == 0 {
+                        0.0
+                    } else {
+                        value as f64 * enabled as f64 / running as f64
+                    };
+                    *count = Some(count.unwrap_or(0.0) + scaled);
+                }
+            }
+            counts
+        }
+    }
+
+    impl Drop for Counters {
+        fn drop(&mut self) {
+            for &fd in self.fds.iter().flatten().flatten() {
+                unsafe { libc::close(fd) };
+            }
+        }
+    }
+}
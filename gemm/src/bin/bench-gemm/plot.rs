@@ -0,0 +1,205 @@
+//! SVG plots of the results of a sweep, drawn with `plotters`.
+
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Throughput of one run.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub dtype: String,
+    pub m: usize,
+    pub n: usize,
+    pub k: usize,
+    pub threads: usize,
+    pub layout: String,
+    pub gflops: f64,
+}
+
+const SIZE: (u32, u32) = (960, 640);
+
+fn error(path: &Path, e: impl std::fmt::Display) -> String {
+    format!("failed to draw {}: {e}", path.display())
+}
+
+/// Draws one line per series of `(x, GFLOPS)` points, where `x` is on a logarithmic scale.
+fn lines(
+    path: &Path,
+    caption: &str,
+    x_desc: &str,
+    series: &BTreeMap<String, Vec<(f64, f64)>>,
+) -> Result<(), String> {
+    let points = series.values().flatten();
+    let x_min = points.clone().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let x_max = points.clone().map(|p| p.0).fold(0.0, f64::max);
+    let y_max = points.map(|p| p.1).fold(0.0, f64::max);
+    // widens the ranges of single points, which can't be drawn on a logarithmic scale
+    let (x_min, x_max) = if x_min < x_max {
+        (x_min, x_max)
+    } else {
+        (x_min / 2.0, x_max * 2.0)
+    };
+
+    let root = SVGBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| error(path, e))?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(64)
+        .build_cartesian_2d((x_min..x_max).log_scale(), 0.0..y_max * 1.1)
+        .map_err(|e| error(path, e))?;
+    chart
+        .configure_mesh()
+        .x_desc(x_desc)
+        .y_desc("GFLOPS")
+        .x_label_formatter(&|x| format!("{x:.0}"))
+        .draw()
+        .map_err(|e| error(path, e))?;
+
+    for (i, (name, points)) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        chart
+            .draw_series(LineSeries::new(
+                points.iter().copied(),
+                color.stroke_width(2),
+            ))
+            .map_err(|e| error(path, e))?
+            .label(name)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 16, y)], color.stroke_width(2)));
+        chart
+            .draw_series(
+                points
+                    .iter()
+                    .map(|&point| Circle::new(point, 3, color.filled())),
+            )
+            .map_err(|e| error(path, e))?;
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .position(SeriesLabelPosition::LowerRight)
+        .draw()
+        .map_err(|e| error(path, e))?;
+    root.present().map_err(|e| error(path, e))
+}
+
+/// Draws the throughput of the `(m, n)` grid of a sweep, with one cell per problem.
+fn heatmap(
+    path: &Path,
+    caption: &str,
+    cells: &BTreeMap<(usize, usize), f64>,
+) -> Result<(), String> {
+    let mut ms: Vec<usize> = cells.keys().map(|&(m, _)| m).collect();
+    let mut ns: Vec<usize> = cells.keys().map(|&(_, n)| n).collect();
+    ms.sort_unstable();
+    ms.dedup();
+    ns.sort_unstable();
+    ns.dedup();
+    let max = cells.values().copied().fold(0.0, f64::max);
+
+    let root = SVGBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| error(path, e))?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(64)
+        .build_cartesian_2d(0..ns.len(), 0..ms.len())
+        .map_err(|e| error(path, e))?;
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("n")
+        .y_desc("m")
+        .x_labels(ns.len() + 1)
+        .y_labels(ms.len() + 1)
+        .x_label_formatter(&|&i| ns.get(i).map(|n| n.to_string()).unwrap_or_default())
+        .y_label_formatter(&|&i| ms.get(i).map(|m| m.to_string()).unwrap_or_default())
+        .draw()
+        .map_err(|e| error(path, e))?;
+
+    chart
+        .draw_series(cells.iter().map(|(&(m, n), &gflops)| {
+            let x = ns.binary_search(&n).unwrap();
+            let y = ms.binary_search(&m).unwrap();
+            let color = ViridisRGB::get_color(gflops / max);
+            Rectangle::new([(x, y), (x + 1, y + 1)], color.filled())
+        }))
+        .map_err(|e| error(path, e))?;
+    chart
+        .draw_series(cells.iter().map(|(&(m, n), &gflops)| {
+            let x = ns.binary_search(&n).unwrap();
+            let y = ms.binary_search(&m).unwrap();
+            let color = if gflops / max < 0.6 { WHITE } else { BLACK };
+            Text::new(
+                format!("{gflops:.0}"),
+                (x, y + 1),
+                ("sans-serif", 12).into_font().color(&color),
+            )
+        }))
+        .map_err(|e| error(path, e))?;
+    root.present().map_err(|e| error(path, e))
+}
+
+/// Draws the plots of `records` in `dir`:
+/// - `size.svg`, the throughput against the geometric mean of `m`, `n` and `k`, with one line per
+///   scalar type, number of threads and layout,
+/// - `threads.svg`, the throughput against the number of threads, with one line per scalar type,
+///   layout and shape, if several numbers of threads were timed,
+/// - `heatmap-<dtype>-<threads>-<layout>-k<k>.svg`, the throughput over the `(m, n)` grid, if
+///   several values of both `m` and `n` were timed.
+///
+/// Returns the paths of the plots.
+pub fn draw(dir: &Path, records: &[Record]) -> Result<Vec<String>, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    let mut drawn = Vec::new();
+
+    let mut by_size = BTreeMap::<String, Vec<(f64, f64)>>::new();
+    let mut by_threads = BTreeMap::<String, Vec<(f64, f64)>>::new();
+    let mut grids = BTreeMap::<String, BTreeMap<(usize, usize), f64>>::new();
+    for r in records {
+        let size = (r.m as f64 * r.n as f64 * r.k as f64).cbrt();
+        by_size
+            .entry(format!("{} {}t {}", r.dtype, r.threads, r.layout))
+            .or_default()
+            .push((size, r.gflops));
+        by_threads
+            .entry(format!("{} {} {}x{}x{}", r.dtype, r.layout, r.m, r.n, r.k))
+            .or_default()
+            .push((r.threads as f64, r.gflops));
+        grids
+            .entry(format!("{}-{}-{}-k{}", r.dtype, r.threads, r.layout, r.k))
+            .or_default()
+            .insert((r.m, r.n), r.gflops);
+    }
+    for points in by_size.values_mut().chain(by_threads.values_mut()) {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    let path = dir.join("size.svg");
+    lines(&path, "GFLOPS by size", "(m×n×k)^(1/3)", &by_size)?;
+    drawn.push(path.display().to_string());
+
+    if by_threads.values().any(|points| points.len() > 1) {
+        let path = dir.join("threads.svg");
+        lines(&path, "GFLOPS by number of threads", "threads", &by_threads)?;
+        drawn.push(path.display().to_string());
+    }
+
+    for (name, cells) in &grids {
+        let distinct = |f: fn(&(usize, usize)) -> usize| {
+            let mut values: Vec<usize> = cells.keys().map(f).collect();
+            values.sort_unstable();
+            values.dedup();
+            values.len()
+        };
+        if distinct(|&(m, _)| m) > 1 && distinct(|&(_, n)| n) > 1 {
+            let path = dir.join(format!("heatmap-{name}.svg"));
+            heatmap(&path, &format!("GFLOPS of {name}"), cells)?;
+            drawn.push(path.display().to_string());
+        }
+    }
+    Ok(drawn)
+}
@@ -0,0 +1,89 @@
+//! Named sets of the products of common machine learning workloads, so that the results of
+//! different machines are measured on the same shapes.
+//!
+//! The linear layers compute `dst := lhs×rhs` with one row of `lhs` and `dst` per token (or per
+//! output pixel for the convolutions) and one column of `rhs` and `dst` per output feature, so
+//! that `m` is the number of tokens, `n` the number of output features and `k` the number of
+//! input features.
+
+/// Product `dst (m×n) := lhs (m×k) × rhs (k×n)` of a workload.
+#[derive(Copy, Clone, Debug)]
+pub struct Shape {
+    pub name: &'static str,
+    pub m: usize,
+    pub n: usize,
+    pub k: usize,
+}
+
+const fn shape(name: &'static str, m: usize, n: usize, k: usize) -> Shape {
+    Shape { name, m, n, k }
+}
+
+/// Self-attention of BERT-base on a sequence of 384 tokens: hidden size 768, 12 heads of 64.
+const BERT_ATTENTION: &[Shape] = &[
+    shape("qkv", 384, 2304, 768),
+    shape("scores", 384, 384, 64),
+    shape("context", 384, 64, 384),
+    shape("output", 384, 768, 768),
+];
+
+/// Feed-forward layers of BERT-base on a sequence of 384 tokens: hidden size 768, intermediate
+/// size 3072.
+const BERT_MLP: &[Shape] = &[shape("up", 384, 3072, 768), shape("down", 384, 768, 3072)];
+
+/// Convolutions of ResNet-50 on one 224×224 image, lowered with im2col: `m` is the number of
+/// output pixels, `n` the number of output channels and `k` the number of input channels times
+/// the size of the kernel.
+const RESNET50: &[Shape] = &[
+    shape("conv1", 12544, 64, 147),
+    shape("conv2.1x1a", 3136, 64, 64),
+    shape("conv2.3x3", 3136, 64, 576),
+    shape("conv2.1x1b", 3136, 256, 64),
+    shape("conv2.reduce", 3136, 64, 256),
+    shape("conv3.1x1a", 784, 128, 256),
+    shape("conv3.3x3", 784, 128, 1152),
+    shape("conv3.1x1b", 784, 512, 128),
+    shape("conv3.reduce", 784, 128, 512),
+    shape("conv4.1x1a", 196, 256, 512),
+    shape("conv4.3x3", 196, 256, 2304),
+    shape("conv4.1x1b", 196, 1024, 256),
+    shape("conv4.reduce", 196, 256, 1024),
+    shape("conv5.1x1a", 49, 512, 1024),
+    shape("conv5.3x3", 49, 512, 4608),
+    shape("conv5.1x1b", 49, 2048, 512),
+    shape("conv5.reduce", 49, 512, 2048),
+    shape("fc", 1, 1000, 2048),
+];
+
+/// Generation of one token by LLaMA-2 7B with a context of 2048 tokens: hidden size 4096, 32
+/// heads of 128, intermediate size 11008 and a vocabulary of 32000 tokens.
+const LLAMA_DECODE: &[Shape] = &[
+    shape("qkv", 1, 12288, 4096),
+    shape("scores", 1, 2048, 128),
+    shape("context", 1, 128, 2048),
+    shape("output", 1, 4096, 4096),
+    shape("gate_up", 1, 22016, 4096),
+    shape("down", 1, 4096, 11008),
+    shape("lm_head", 1, 32000, 4096),
+];
+
+pub const PRESETS: &[(&str, &[Shape])] = &[
+    ("bert-attention", BERT_ATTENTION),
+    ("bert-mlp", BERT_MLP),
+    ("resnet50", RESNET50),
+    ("llama-decode", LLAMA_DECODE),
+];
+
+pub fn parse_preset(name: &str) -> Result<&'static [Shape], String> {
+    PRESETS
+        .iter()
+        .find(|&&(preset, _)| preset == name)
+        .map(|&(_, shapes)| shapes)
+        .ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|&(preset, _)| preset).collect();
+            format!(
+                "unknown preset `{name}`, expected one of {}",
+                names.join(", ")
+            )
+        })
+}
@@ -0,0 +1,109 @@
+//! Thread-scaling sweep, which times each problem with every number of threads up to a maximum
+//! and reports how far the speedups are from linear.
+
+use crate::{flops, time_dtype, Layout, Library, Problem, Sampling};
+use gemm::{DType, Parallelism};
+
+/// Smallest speedup that an additional thread has to bring for the scaling to be considered
+/// worthwhile, as a fraction of the one of a perfectly parallel product.
+const MIN_MARGINAL_SPEEDUP: f64 = 0.5;
+
+/// Number of threads after which the marginal speedups drop below [`MIN_MARGINAL_SPEEDUP`], given
+/// the speedups of `1..=speedups.len()` threads.
+fn knee(speedups: &[f64]) -> usize {
+    speedups
+        .windows(2)
+        .position(|pair| pair[1] - pair[0] < MIN_MARGINAL_SPEEDUP)
+        .map_or(speedups.len(), |i| i + 1)
+}
+
+/// Times each problem of `shapes` with `1..=max_threads` threads, and prints their speedups and
+/// parallel efficiencies over one thread, with the number of threads after which the additional
+/// threads bring less than half of their share.
+pub fn sweep(
+    shapes: &[(usize, usize, usize, String)],
+    dtypes: &[DType],
+    layouts: &[([Layout; 3], &str)],
+    max_threads: usize,
+    sampling: Sampling,
+    csv: bool,
+    #[cfg(feature = "plot")] records: &mut Vec<crate::plot::Record>,
+) {
+    if csv {
+        println!("dtype,m,n,k,threads,layout,seconds,gflops,speedup,efficiency_percent,knee_threads,shape");
+    } else {
+        println!(
+            "{:>5} {:>6} {:>6} {:>6} {:>7} {:>6} {:>12} {:>9} {:>7} {:>6}",
+            "dtype", "m", "n", "k", "threads", "layout", "time", "GFLOPS", "speedup", "%eff",
+        );
+    }
+
+    for &dtype in dtypes {
+        let name = format!("{dtype:?}").to_lowercase();
+        for &(layout, spec) in layouts {
+            for (m, n, k, label) in shapes {
+                let (m, n, k) = (*m, *n, *k);
+                let elapsed: Vec<f64> = (1..=max_threads)
+                    .map(|threads| {
+                        let parallelism = if threads == 1 {
+                            Parallelism::None
+                        } else {
+                            Parallelism::Rayon(threads)
+                        };
+                        let problem = Problem {
+                            m,
+                            n,
+                            k,
+                            dtype,
+                            layout,
+                            parallelism,
+                        };
+                        time_dtype(problem, Library::Gemm, sampling)
+                            .unwrap()
+                            .elapsed
+                            .as_secs_f64()
+                    })
+                    .collect();
+                let speedups: Vec<f64> = elapsed.iter().map(|&time| elapsed[0] / time).collect();
+                let knee = knee(&speedups);
+
+                for (i, (&time, &speedup)) in elapsed.iter().zip(&speedups).enumerate() {
+                    let threads = i + 1;
+                    let gflops = flops(m, n, k, dtype) / time / 1e9;
+                    let efficiency = 100.0 * speedup / threads as f64;
+                    #[cfg(feature = "plot")]
+                    records.push(crate::plot::Record {
+                        dtype: name.clone(),
+                        m,
+                        n,
+                        k,
+                        threads,
+                        layout: spec.into(),
+                        gflops,
+                    });
+                    if csv {
+                        println!(
+                            "{name},{m},{n},{k},{threads},{spec},{time},{gflops:.3},{speedup:.3},{efficiency:.1},{knee},{label}"
+                        );
+                    } else {
+                        println!(
+                            "{name:>5} {m:>6} {n:>6} {k:>6} {threads:>7} {spec:>6} {:>12} {gflops:>9.2} {speedup:>7.2} {efficiency:>6.1}",
+                            format!("{:.2?}", std::time::Duration::from_secs_f64(time)),
+                        );
+                    }
+                }
+                if !csv {
+                    let best = speedups
+                        .iter()
+                        .enumerate()
+                        .max_by(|a, b| a.1.total_cmp(b.1))
+                        .map_or(1, |(i, _)| i + 1);
+                    println!(
+                        "  {label}{}diminishing returns after {knee} threads, fastest with {best} threads",
+                        if label.is_empty() { "" } else { ": " },
+                    );
+                }
+            }
+        }
+    }
+}
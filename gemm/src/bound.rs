@@ -0,0 +1,142 @@
+use crate::describe::DType;
+use crate::gemm::{get_backend, GemmBackend};
+use crate::{c32, c64, Parallelism};
+use gemm_common::{cache::DivCeil, gemm::get_deterministic};
+
+/// Forward error bound of a product, as returned by [`error_bound`].
+///
+/// Each element of the computed destination `d̂` satisfies
+///
+/// `|d̂ - d| ≤ relative × (|alpha|×|dst| + |beta|×(|lhs|×|rhs|))`
+///
+/// where `d` is the exact result, `dst` is the destination before the call and `|lhs|×|rhs|` is
+/// the product of the absolute values of the operands, or of the moduli for the complex types.
+/// The bound assumes that no intermediate result overflows or underflows.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ErrorBound {
+    /// Whether the microkernels accumulate the products with fused multiply-adds, which round
+    /// each step once instead of twice. It only tightens the error of the last terms of each sum,
+    /// so that the bound is the same either way.
+    pub fused: bool,
+    /// Depth blocking `kc`, which is the length of the longest sum accumulated in the registers of
+    /// the microkernels before it's added to the destination.
+    pub kc: usize,
+    /// Number of slices of `k` computed by different threads, whose partial products are then
+    /// added to the destination.
+    pub n_splits: usize,
+    /// Largest number of roundings applied to one term of the sum of an element.
+    pub roundings: usize,
+    /// Unit roundoff of the accumulators.
+    pub unit_roundoff: f64,
+    /// Coefficient of the bound, for the blocking parameters reported by
+    /// [`describe`](crate::describe).
+    pub relative: f64,
+    /// Coefficient of the bound for any blocking parameters, including the ones of the other
+    /// register blockings that the backend may select for the shape of the product, and the ones
+    /// given to [`gemm_with_config`](crate::gemm_with_config).
+    pub relative_any_blocking: f64,
+}
+
+/// `γₙ = n×u / (1 - n×u)`, which bounds the relative error `|θ|` of `n` roundings
+/// `(1 + δ₁)…(1 + δₙ) = 1 + θ` with `|δᵢ| ≤ u`.
+fn gamma(n: usize, u: f64) -> f64 {
+    let nu = n as f64 * u;
+    if nu < 1.0 {
+        nu / (1.0 - nu)
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// Relative error of `acc` roundings with unit roundoff `u_acc` followed by `dst` roundings with
+/// unit roundoff `u_dst`, for the real types, or of the same steps in complex arithmetic, where
+/// each multiply-add is bounded by `√2×γ₂`.
+fn combined(acc: usize, u_acc: f64, dst: usize, u_dst: f64, complex: bool) -> f64 {
+    let (acc, scale) = if complex {
+        (acc + 2, core::f64::consts::SQRT_2)
+    } else {
+        (acc, 1.0)
+    };
+    let (acc, dst) = (gamma(acc, u_acc), gamma(dst, u_dst));
+    scale * (acc + dst + acc * dst)
+}
+
+fn bound_backend<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    u_acc: f64,
+    u_dst: f64,
+    complex: bool,
+    parallelism: Parallelism<'_>,
+) -> ErrorBound {
+    let n_splits = if get_deterministic() {
+        1
+    } else {
+        crate::split_k::n_splits(backend, m, n, k, parallelism, None)
+    };
+    let depth = k.msrv_div_ceil(n_splits);
+
+    // the backend sees the transposed problem for row-major destinations, which may be blocked
+    // differently
+    let kc = |m, n| Ord::max((backend.blocking)(m, n, depth, Parallelism::None).2.kc, 1);
+    let (max_kc, min_kc) = (Ord::max(kc(m, n), kc(n, m)), Ord::min(kc(m, n), kc(n, m)));
+    let kc = Ord::min(max_kc, depth);
+
+    // each term is rounded along the sum of its depth block, by the scaling with beta and by
+    // the addition to the destination, which is then rounded to the destination type, and once
+    // more by the addition of each later depth block and partial product
+    let acc = kc + 2;
+    let dst = depth.msrv_div_ceil(min_kc) + n_splits - 1;
+    // the longest sum and the number of depth blocks add up to at most `depth + 1` for any
+    // depth blocking
+    let any = depth + 2 + n_splits;
+
+    ErrorBound {
+        fused: backend.name != "scalar",
+        kc,
+        n_splits,
+        roundings: acc + dst,
+        unit_roundoff: u_acc,
+        relative: combined(acc, u_acc, dst, u_dst, complex),
+        relative_any_blocking: combined(any, f64::max(u_acc, u_dst), 0, u_dst, complex),
+    }
+}
+
+/// Returns a rigorous forward error bound of an `m×n×k` product of `dtype` matrices computed by
+/// [`gemm`](crate::gemm) on the current machine.
+///
+/// The bound follows the accumulation scheme of the backend. Each element is computed as a sum
+/// of at most `kc` products in the registers of the microkernels, whose result is scaled and
+/// added to the destination, once per depth block and per slice of `k` if the product is split
+/// between the threads allowed by `parallelism`. It's much tighter than the bound of a single
+/// sum of length `k` when `k` is large.
+///
+/// `gemm::f16` is accumulated in `f32` and rounded to `f16` after each depth block, except by
+/// the `neonfp16` backend, which accumulates in `f16`. The bound doesn't cover the
+/// Strassen-Winograd recursion of the `strassen` feature, whose error grows faster.
+pub fn error_bound(
+    m: usize,
+    n: usize,
+    k: usize,
+    dtype: DType,
+    parallelism: Parallelism<'_>,
+) -> ErrorBound {
+    const F16: f64 = 1.0 / (1u64 << 11) as f64;
+    const F32: f64 = f32::EPSILON as f64 / 2.0;
+    const F64: f64 = f64::EPSILON / 2.0;
+
+    match dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => {
+            let backend = get_backend::<crate::f16>();
+            let u_acc = if backend.name == "neonfp16" { F16 } else { F32 };
+            bound_backend(backend, m, n, k, u_acc, F16, false, parallelism)
+        }
+        DType::F32 => bound_backend(get_backend::<f32>(), m, n, k, F32, F32, false, parallelism),
+        DType::F64 => bound_backend(get_backend::<f64>(), m, n, k, F64, F64, false, parallelism),
+        DType::C32 => bound_backend(get_backend::<c32>(), m, n, k, F32, F32, true, parallelism),
+        DType::C64 => bound_backend(get_backend::<c64>(), m, n, k, F64, F64, true, parallelism),
+    }
+}
@@ -0,0 +1,485 @@
+//! cblas-compatible entry points.
+//!
+//! Exports `cblas_sgemm` and `cblas_dgemm` with the standard cblas signatures, so that C and C++
+//! programs can use this crate as a drop-in replacement for that subset of BLAS, e.g. when built
+//! with `cargo rustc -p gemm --features capi --crate-type cdylib`.
+//!
+//! Invalid arguments are reported the way the reference cblas does it before calling `xerbla`:
+//! the call returns without touching `C`.
+//!
+//! Also exports the raw `bench_gemm_*` functions, which expose the same arguments as [`gemm`]
+//! to non-Rust benchmark harnesses, running either on the global pool or on a [`BenchGemmPool`]
+//! owned by the caller. Their declarations are in `include/bench_gemm.h`, which is generated
+//! with `cbindgen --config cbindgen.toml --output include/bench_gemm.h` from the `gemm`
+//! directory.
+
+use crate::gemm::{gemm_dispatch, get_backend};
+use crate::{gemm, Parallelism, ThreadSpawner};
+use core::ffi::{c_int, c_void};
+use dyn_stack::DynStack;
+use gemm_common::gemm::GemmConfig;
+
+pub const CBLAS_ROW_MAJOR: c_int = 101;
+pub const CBLAS_COL_MAJOR: c_int = 102;
+
+pub const CBLAS_NO_TRANS: c_int = 111;
+pub const CBLAS_TRANS: c_int = 112;
+pub const CBLAS_CONJ_TRANS: c_int = 113;
+
+/// Row and column strides of an operand of `op(X)`, or `None` if the arguments are invalid.
+fn op_strides(
+    order: c_int,
+    trans: c_int,
+    nrows: usize,
+    ncols: usize,
+    ld: c_int,
+) -> Option<(isize, isize)> {
+    let transposed = match trans {
+        CBLAS_NO_TRANS => false,
+        // conjugation is a no-op for real matrices
+        CBLAS_TRANS | CBLAS_CONJ_TRANS => true,
+        _ => return None,
+    };
+    // dimensions of the matrix as it is stored
+    let (nrows, ncols) = if transposed {
+        (ncols, nrows)
+    } else {
+        (nrows, ncols)
+    };
+    let ld = ld as isize;
+    let (rs, cs) = match order {
+        CBLAS_COL_MAJOR if ld >= Ord::max(nrows, 1) as isize => (1, ld),
+        CBLAS_ROW_MAJOR if ld >= Ord::max(ncols, 1) as isize => (ld, 1),
+        _ => return None,
+    };
+    Some(if transposed { (cs, rs) } else { (rs, cs) })
+}
+
+/// Dimensions and strides of `op(A)`, `op(B)` and `C`, or `None` if the arguments are invalid.
+#[allow(clippy::type_complexity)]
+fn check_args(
+    order: c_int,
+    trans_a: c_int,
+    trans_b: c_int,
+    m: c_int,
+    n: c_int,
+    k: c_int,
+    lda: c_int,
+    ldb: c_int,
+    ldc: c_int,
+) -> Option<(
+    usize,
+    usize,
+    usize,
+    isize,
+    isize,
+    isize,
+    isize,
+    isize,
+    isize,
+)> {
+    let m = usize::try_from(m).ok()?;
+    let n = usize::try_from(n).ok()?;
+    let k = usize::try_from(k).ok()?;
+    let (a_rs, a_cs) = op_strides(order, trans_a, m, k, lda)?;
+    let (b_rs, b_cs) = op_strides(order, trans_b, k, n, ldb)?;
+    let (c_rs, c_cs) = op_strides(order, CBLAS_NO_TRANS, m, n, ldc)?;
+    Some((m, n, k, a_rs, a_cs, b_rs, b_cs, c_rs, c_cs))
+}
+
+/// Every thread of the global pool, or of the machine if rayon is disabled.
+#[inline]
+fn parallelism() -> Parallelism<'static> {
+    #[cfg(feature = "rayon")]
+    {
+        Parallelism::Rayon(0)
+    }
+    #[cfg(all(not(feature = "rayon"), feature = "std"))]
+    {
+        static SPAWNER: crate::ScopedSpawner = crate::ScopedSpawner::new(0);
+        Parallelism::Custom(&SPAWNER)
+    }
+    #[cfg(not(any(feature = "rayon", feature = "std")))]
+    {
+        Parallelism::None
+    }
+}
+
+macro_rules! cblas_gemm {
+    ($name: ident, $ty: ty) => {
+        /// C := alpha×op(A)×op(B) + beta×C
+        ///
+        /// # Safety
+        ///
+        /// The pointers and leading dimensions must describe valid matrices, as required by
+        /// cblas. `C` must not alias `A` or `B`, and is not read when `beta` is zero.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(
+            order: c_int,
+            trans_a: c_int,
+            trans_b: c_int,
+            m: c_int,
+            n: c_int,
+            k: c_int,
+            alpha: $ty,
+            a: *const $ty,
+            lda: c_int,
+            b: *const $ty,
+            ldb: c_int,
+            beta: $ty,
+            c: *mut $ty,
+            ldc: c_int,
+        ) {
+            let (m, n, k, a_rs, a_cs, b_rs, b_cs, c_rs, c_cs) =
+                match check_args(order, trans_a, trans_b, m, n, k, lda, ldb, ldc) {
+                    Some(args) => args,
+                    None => return,
+                };
+            // like the reference implementation, skip the product entirely when alpha is zero,
+            // so that NaNs in A or B don't propagate
+            let k = if alpha == 0.0 { 0 } else { k };
+
+            // cblas scales the product by alpha and the destination by beta, which is the
+            // other way around from `gemm`
+            gemm(
+                m,
+                n,
+                k,
+                c,
+                c_cs,
+                c_rs,
+                beta != 0.0,
+                a,
+                a_cs,
+                a_rs,
+                b,
+                b_cs,
+                b_rs,
+                beta,
+                alpha,
+                false,
+                false,
+                false,
+                parallelism(),
+            );
+        }
+    };
+}
+
+cblas_gemm!(cblas_sgemm, f32);
+cblas_gemm!(cblas_dgemm, f64);
+
+/// Scalar types accepted by [`bench_gemm_req`].
+pub const BENCH_GEMM_F32: c_int = 0;
+pub const BENCH_GEMM_F64: c_int = 1;
+
+/// Returned when the call succeeded.
+pub const BENCH_GEMM_OK: c_int = 0;
+/// Returned when the scratch memory is too small for the problem.
+pub const BENCH_GEMM_SCRATCH_TOO_SMALL: c_int = -1;
+
+/// `0` uses all the threads of the global pool, `1` runs on the calling thread. Without rayon,
+/// any other count uses every thread of the machine, as [`parallelism`] does.
+#[inline]
+fn threads_to_parallelism(n_threads: usize) -> Parallelism<'static> {
+    match n_threads {
+        1 => Parallelism::None,
+        #[cfg(feature = "rayon")]
+        n_threads => Parallelism::Rayon(n_threads),
+        #[cfg(not(feature = "rayon"))]
+        _ => parallelism(),
+    }
+}
+
+/// Thread pool owned by the caller, which `bench_gemm_f32_on`/`bench_gemm_f64_on` run the
+/// parallel regions on. It only needs to outlive the call.
+#[repr(C)]
+pub struct BenchGemmPool {
+    /// Passed back to `for_each`.
+    pub ctx: *mut c_void,
+    /// Number of threads of the pool, which is the most that a region is split into.
+    pub num_threads: usize,
+    /// Calls `task(task_ctx, i)` for each `i` in `0..n_tasks`, potentially in parallel and in
+    /// any order, and returns once every call has returned. It may be called from the threads
+    /// of the pool.
+    pub for_each: unsafe extern "C" fn(
+        ctx: *mut c_void,
+        n_tasks: usize,
+        task: unsafe extern "C" fn(task_ctx: *const c_void, i: usize),
+        task_ctx: *const c_void,
+    ),
+}
+
+// `for_each` is required to be callable from any thread
+unsafe impl Sync for BenchGemmPool {}
+
+unsafe extern "C" fn run_task(task_ctx: *const c_void, i: usize) {
+    let func = &*(task_ctx as *const &(dyn Fn(usize) + Send + Sync));
+    func(i)
+}
+
+impl ThreadSpawner for BenchGemmPool {
+    #[inline]
+    fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+        // a panic in `func` aborts, since it can't unwind through the caller's frames
+        unsafe {
+            (self.for_each)(
+                self.ctx,
+                n_tasks,
+                run_task,
+                &func as *const &(dyn Fn(usize) + Send + Sync) as *const c_void,
+            )
+        }
+    }
+}
+
+/// A null `pool` runs on the calling thread.
+#[inline]
+unsafe fn pool_to_parallelism<'a>(pool: *const BenchGemmPool) -> Parallelism<'a> {
+    match pool.as_ref() {
+        Some(pool) => Parallelism::Custom(pool),
+        None => Parallelism::None,
+    }
+}
+
+fn scratch_req(
+    dtype: c_int,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    parallelism: Parallelism<'_>,
+) -> usize {
+    let req = match dtype {
+        BENCH_GEMM_F32 => crate::gemm_req::<f32>(m, n, k, dst_cs, dst_rs, parallelism),
+        BENCH_GEMM_F64 => crate::gemm_req::<f64>(m, n, k, dst_cs, dst_rs, parallelism),
+        _ => return 0,
+    };
+    req.unaligned_bytes_required()
+}
+
+/// Number of bytes of scratch memory needed by `bench_gemm_f32`/`bench_gemm_f64` for the given
+/// problem, or `0` if `dtype` is unknown. The scratch memory doesn't need to be aligned.
+#[no_mangle]
+pub extern "C" fn bench_gemm_req(
+    dtype: c_int,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    n_threads: usize,
+) -> usize {
+    scratch_req(
+        dtype,
+        m,
+        n,
+        k,
+        dst_cs,
+        dst_rs,
+        threads_to_parallelism(n_threads),
+    )
+}
+
+/// Same as `bench_gemm_req`, for `bench_gemm_f32_on`/`bench_gemm_f64_on` with the same `pool`.
+///
+/// # Safety
+///
+/// `pool` must be null or point to a valid `BenchGemmPool`.
+#[no_mangle]
+pub unsafe extern "C" fn bench_gemm_req_on(
+    dtype: c_int,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    pool: *const BenchGemmPool,
+) -> usize {
+    scratch_req(dtype, m, n, k, dst_cs, dst_rs, pool_to_parallelism(pool))
+}
+
+unsafe fn bench_gemm_impl<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism<'_>,
+    scratch: *mut u8,
+    scratch_len: usize,
+) -> c_int {
+    let backend = get_backend::<T>();
+    let stack = if scratch.is_null() {
+        None
+    } else {
+        let stack = DynStack::new(core::slice::from_raw_parts_mut(
+            scratch as *mut core::mem::MaybeUninit<u8>,
+            scratch_len,
+        ));
+        let req = crate::gemm_req::<T>(m, n, k, dst_cs, dst_rs, parallelism);
+        if !stack.can_hold(req) {
+            return BENCH_GEMM_SCRATCH_TOO_SMALL;
+        }
+        Some(stack)
+    };
+
+    gemm_dispatch(
+        backend,
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        false,
+        false,
+        false,
+        parallelism,
+        GemmConfig {
+            stack,
+            ..Default::default()
+        },
+    );
+    BENCH_GEMM_OK
+}
+
+macro_rules! bench_gemm {
+    ($name: ident, $name_on: ident, $ty: ty) => {
+        /// dst := alpha×dst + beta×lhs×rhs
+        ///
+        /// Strides are given in elements. `scratch` may be null, in which case the scratch memory
+        /// is allocated by the call, otherwise it must point to `scratch_len` writable bytes,
+        /// where `scratch_len` is at least the value returned by `bench_gemm_req`. `n_threads`
+        /// is `0` to use every thread of the global pool, or the number of threads to use.
+        ///
+        /// Returns `BENCH_GEMM_OK`, or `BENCH_GEMM_SCRATCH_TOO_SMALL` without touching `dst`.
+        ///
+        /// # Safety
+        ///
+        /// Same requirements as [`gemm`], and `scratch` must be null or valid for writes of
+        /// `scratch_len` bytes.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(
+            m: usize,
+            n: usize,
+            k: usize,
+            dst: *mut $ty,
+            dst_cs: isize,
+            dst_rs: isize,
+            read_dst: bool,
+            lhs: *const $ty,
+            lhs_cs: isize,
+            lhs_rs: isize,
+            rhs: *const $ty,
+            rhs_cs: isize,
+            rhs_rs: isize,
+            alpha: $ty,
+            beta: $ty,
+            n_threads: usize,
+            scratch: *mut u8,
+            scratch_len: usize,
+        ) -> c_int {
+            bench_gemm_impl(
+                m,
+                n,
+                k,
+                dst,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs,
+                lhs_cs,
+                lhs_rs,
+                rhs,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                threads_to_parallelism(n_threads),
+                scratch,
+                scratch_len,
+            )
+        }
+
+        /// dst := alpha×dst + beta×lhs×rhs
+        ///
+        /// Same as the function without the `_on` suffix, except that the parallel regions run
+        /// on `pool`, or on the calling thread if it's null. `scratch_len` must then be at least
+        /// the value returned by `bench_gemm_req_on`.
+        ///
+        /// # Safety
+        ///
+        /// Same requirements as [`gemm`], `scratch` must be null or valid for writes of
+        /// `scratch_len` bytes, and `pool` must be null or point to a valid `BenchGemmPool`.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name_on(
+            m: usize,
+            n: usize,
+            k: usize,
+            dst: *mut $ty,
+            dst_cs: isize,
+            dst_rs: isize,
+            read_dst: bool,
+            lhs: *const $ty,
+            lhs_cs: isize,
+            lhs_rs: isize,
+            rhs: *const $ty,
+            rhs_cs: isize,
+            rhs_rs: isize,
+            alpha: $ty,
+            beta: $ty,
+            pool: *const BenchGemmPool,
+            scratch: *mut u8,
+            scratch_len: usize,
+        ) -> c_int {
+            bench_gemm_impl(
+                m,
+                n,
+                k,
+                dst,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs,
+                lhs_cs,
+                lhs_rs,
+                rhs,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                pool_to_parallelism(pool),
+                scratch,
+                scratch_len,
+            )
+        }
+    };
+}
+
+bench_gemm!(bench_gemm_f32, bench_gemm_f32_on, f32);
+bench_gemm!(bench_gemm_f64, bench_gemm_f64_on, f64);
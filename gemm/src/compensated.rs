@@ -0,0 +1,281 @@
+use crate::gemm::{c32, c64, gemm, get_backend, is_complex, is_transposed};
+use crate::Parallelism;
+use core::any::TypeId;
+use core::ops::{Add, Mul, Sub};
+use gemm_common::{
+    gemm::{max_threads, par_for_each, threading_threshold},
+    Ptr,
+};
+use num_traits::Zero;
+
+/// Scalar whose products are accumulated with compensated summation, in `Acc`.
+trait Scalar: 'static + Copy + Send + Sync {
+    type Acc: Copy
+        + Zero
+        + Add<Output = Self::Acc>
+        + Sub<Output = Self::Acc>
+        + Mul<Output = Self::Acc>;
+
+    fn widen(self) -> Self::Acc;
+    fn narrow(acc: Self::Acc) -> Self;
+    fn conj(acc: Self::Acc) -> Self::Acc;
+}
+
+macro_rules! impl_scalar {
+    ($ty: ty, $conj: expr) => {
+        impl Scalar for $ty {
+            type Acc = $ty;
+
+            #[inline(always)]
+            fn widen(self) -> $ty {
+                self
+            }
+            #[inline(always)]
+            fn narrow(acc: $ty) -> $ty {
+                acc
+            }
+            #[inline(always)]
+            fn conj(acc: $ty) -> $ty {
+                $conj(acc)
+            }
+        }
+    };
+}
+
+impl_scalar!(f32, |x| x);
+impl_scalar!(f64, |x| x);
+impl_scalar!(c32, |x: c32| x.conj());
+impl_scalar!(c64, |x: c64| x.conj());
+
+// like the backend, f16 products are accumulated in f32
+#[cfg(feature = "f16")]
+impl Scalar for crate::f16 {
+    type Acc = f32;
+
+    #[inline(always)]
+    fn widen(self) -> f32 {
+        self.to_f32()
+    }
+    #[inline(always)]
+    fn narrow(acc: f32) -> Self {
+        crate::f16::from_f32(acc)
+    }
+    #[inline(always)]
+    fn conj(acc: f32) -> f32 {
+        acc
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`](crate::gemm), where `compensated` selects whether the products along `k` are
+/// accumulated with Kahan summation. Each element then carries a compensation term that recovers
+/// the low-order bits lost by each addition, so that its error no longer grows with `k`, which
+/// keeps the digits that a plain sum loses for very deep products.
+///
+/// The compensated product is computed element by element, with the columns (or the rows, for
+/// a row-major destination) split between the threads allowed by `parallelism`, and is several
+/// times slower than the backend. The complex types compensate their real and imaginary parts
+/// separately, and `gemm::f16` accumulates in `f32`, like the backend.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm).
+#[track_caller]
+pub unsafe fn gemm_with_compensated_summation<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    compensated: bool,
+) {
+    if !compensated {
+        return gemm(
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        );
+    }
+
+    let (conj_dst, conj_lhs, conj_rhs) = if is_complex::<T>() {
+        (conj_dst, conj_lhs, conj_rhs)
+    } else {
+        (false, false, false)
+    };
+
+    macro_rules! dispatch {
+        ($($ty: ty),*) => {$(
+            if TypeId::of::<T>() == TypeId::of::<$ty>() {
+                return kahan::<$ty>(
+                    m,
+                    n,
+                    k,
+                    Ptr(dst as *mut $ty),
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    Ptr(lhs as *mut $ty),
+                    lhs_cs,
+                    lhs_rs,
+                    Ptr(rhs as *mut $ty),
+                    rhs_cs,
+                    rhs_rs,
+                    core::mem::transmute_copy(&alpha),
+                    core::mem::transmute_copy(&beta),
+                    conj_dst,
+                    conj_lhs,
+                    conj_rhs,
+                    parallelism,
+                );
+            }
+        )*};
+    }
+
+    #[cfg(feature = "f16")]
+    dispatch!(crate::f16);
+    dispatch!(f64, f32, c64, c32);
+    panic!("{}", crate::GemmError::UnsupportedType)
+}
+
+unsafe fn kahan<T: Scalar>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: Ptr<T>,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: Ptr<T>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let element = |i: usize, j: usize| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, lhs, rhs) = (dst, lhs, rhs);
+        let lhs = lhs.wrapping_offset(i as isize * lhs_rs).0;
+        let rhs = rhs.wrapping_offset(j as isize * rhs_cs).0;
+
+        let mut sum = T::Acc::zero();
+        let mut compensation = T::Acc::zero();
+        for depth in 0..k as isize {
+            let mut a = (*lhs.offset(depth * lhs_cs)).widen();
+            let mut b = (*rhs.offset(depth * rhs_rs)).widen();
+            if conj_lhs {
+                a = T::conj(a);
+            }
+            if conj_rhs {
+                b = T::conj(b);
+            }
+            let y = a * b - compensation;
+            let t = sum + y;
+            compensation = (t - sum) - y;
+            sum = t;
+        }
+
+        let dst = dst
+            .wrapping_offset(i as isize * dst_rs + j as isize * dst_cs)
+            .0;
+        let mut value = beta.widen() * sum;
+        if read_dst {
+            let mut old = (*dst).widen();
+            if conj_dst {
+                old = T::conj(old);
+            }
+            value = alpha.widen() * old + value;
+        }
+        *dst = T::narrow(value);
+    };
+
+    for_each_element::<T>(m, n, k, dst_cs, dst_rs, parallelism, element);
+}
+
+/// Calls `element(i, j)` for each element of an `m×n` destination, with the columns (or the rows,
+/// for a row-major destination) split between the threads allowed by `parallelism`, for the
+/// products computed element by element. Products of `T` below its threading threshold run on
+/// the calling thread.
+pub(crate) fn for_each_element<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    parallelism: Parallelism<'_>,
+    element: impl Fn(usize, usize) + Send + Sync,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    // the threads split the outer dimension of the destination, so that each one writes
+    // contiguous elements
+    let transposed = is_transposed(dst_cs, dst_rs);
+    let (n_outer, n_inner) = if transposed { (m, n) } else { (n, m) };
+    let threshold = threading_threshold::<T>(get_backend::<T>().simd_bytes);
+    let n_threads = if m.saturating_mul(n).saturating_mul(k) < threshold {
+        1
+    } else {
+        Ord::min(max_threads(parallelism), n_outer)
+    };
+
+    let outer = |tid: usize| {
+        for outer in n_outer * tid / n_threads..n_outer * (tid + 1) / n_threads {
+            for inner in 0..n_inner {
+                if transposed {
+                    element(outer, inner);
+                } else {
+                    element(inner, outer);
+                }
+            }
+        }
+    };
+
+    if n_threads <= 1 {
+        outer(0);
+    } else {
+        par_for_each(parallelism, n_threads, outer);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::batch::for_each_problem;
+use crate::pack::packing_backend;
+use crate::Parallelism;
+use dyn_stack::{GlobalMemBuffer, StackReq};
+use gemm_common::{
+    cache::DivCeil,
+    gemm::{threading_threshold, GemmConfig, CACHELINE_ALIGN},
+    Ptr,
+};
+
+/// Smallest number of output pixels whose im2col rows are packed at once by [`conv2d`].
+const CONV_ROW_BLOCK: usize = 256;
+
+/// Dimensions of a 2D convolution computed by [`conv2d`]. Pairs are given as `(vertical,
+/// horizontal)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Conv2dShape {
+    pub in_channels: usize,
+    pub out_channels: usize,
+    /// Height and width of the input image.
+    pub input: (usize, usize),
+    /// Height and width of the kernel.
+    pub kernel: (usize, usize),
+    pub stride: (usize, usize),
+    /// Number of implicit zeros added on each side of the input.
+    pub padding: (usize, usize),
+    /// Distance between two consecutive kernel taps.
+    pub dilation: (usize, usize),
+}
+
+impl Conv2dShape {
+    /// Height and width of the output image.
+    #[inline]
+    pub fn output(&self) -> (usize, usize) {
+        let dim = |input: usize, kernel: usize, stride: usize, padding: usize, dilation: usize| {
+            let extent = dilation * (kernel - 1) + 1;
+            (input + 2 * padding - extent) / stride + 1
+        };
+        (
+            dim(
+                self.input.0,
+                self.kernel.0,
+                self.stride.0,
+                self.padding.0,
+                self.dilation.0,
+            ),
+            dim(
+                self.input.1,
+                self.kernel.1,
+                self.stride.1,
+                self.padding.1,
+                self.dilation.1,
+            ),
+        )
+    }
+
+    /// Depth of the equivalent product, i.e. the number of weights of each output channel.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.kernel.0 * self.kernel.1 * self.in_channels
+    }
+
+    #[track_caller]
+    fn check(&self) {
+        assert!(
+            self.kernel.0 > 0
+                && self.kernel.1 > 0
+                && self.stride.0 > 0
+                && self.stride.1 > 0
+                && self.dilation.0 > 0
+                && self.dilation.1 > 0,
+            "kernel, stride and dilation must be nonzero: {:?}",
+            self,
+        );
+        assert!(
+            self.input.0 + 2 * self.padding.0 > self.dilation.0 * (self.kernel.0 - 1)
+                && self.input.1 + 2 * self.padding.1 > self.dilation.1 * (self.kernel.1 - 1),
+            "the kernel doesn't fit in the padded input: {:?}",
+            self,
+        );
+    }
+}
+
+/// Writes the im2col rows of the output pixels `first..first + nrows` into `ceil(nrows / mr)`
+/// lhs panels of `mr` rows, `depth * mr` elements apart, in the layout of
+/// [`PackedLhs`](crate::PackedLhs).
+unsafe fn pack_im2col<T: Copy>(
+    shape: &Conv2dShape,
+    input: *const T,
+    first: usize,
+    nrows: usize,
+    mr: usize,
+    dst: *mut T,
+) {
+    let (_, out_width) = shape.output();
+    let (height, width) = shape.input;
+    let in_channels = shape.in_channels;
+    let depth = shape.depth();
+
+    for row in 0..nrows.msrv_div_ceil(mr) * mr {
+        let panel = dst.add(row / mr * depth * mr + row % mr);
+
+        if row >= nrows {
+            for d in 0..depth {
+                *panel.add(d * mr) = core::mem::zeroed();
+            }
+            continue;
+        }
+
+        let pixel = first + row;
+        let (out_i, out_j) = (pixel / out_width, pixel % out_width);
+
+        for ki in 0..shape.kernel.0 {
+            let i = (out_i * shape.stride.0 + ki * shape.dilation.0).wrapping_sub(shape.padding.0);
+            for kj in 0..shape.kernel.1 {
+                let j =
+                    (out_j * shape.stride.1 + kj * shape.dilation.1).wrapping_sub(shape.padding.1);
+                let tap = panel.add((ki * shape.kernel.1 + kj) * in_channels * mr);
+
+                // out of bounds taps read the implicit zero padding
+                if i < height && j < width {
+                    let src = input.add((i * width + j) * in_channels);
+                    for c in 0..in_channels {
+                        *tap.add(c * mr) = *src.add(c);
+                    }
+                } else {
+                    for c in 0..in_channels {
+                        *tap.add(c * mr) = core::mem::zeroed();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×conv2d(input, weights)
+///
+/// Computes a 2D convolution as an implicit product: the rows of the `im2col` matrix, with one
+/// row per output pixel and `shape.depth()` columns, are written directly in the packed lhs
+/// layout used by the microkernels, one block of output pixels at a time. Unlike an explicit
+/// `im2col` followed by [`gemm`](crate::gemm), the whole `im2col` matrix is never stored.
+///
+/// The layouts are as follows:
+/// - `input` is a contiguous `height×width×in_channels` image, with the channels innermost.
+/// - `weights` is a `depth×out_channels` matrix with strides `weights_cs` and `weights_rs`,
+///   where the row of the weight for kernel tap `(ki, kj)` and input channel `c` is
+///   `(ki * kernel_width + kj) * in_channels + c`.
+/// - `dst` is an `(out_height * out_width)×out_channels` matrix with strides `dst_cs` and
+///   `dst_rs`, with the output pixels in row-major order.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::c32`, or `gemm::c64`, or if the shape is invalid.
+///
+/// # Safety
+///
+/// `input` must be valid for reads of `height * width * in_channels` elements, `weights` must be
+/// valid for reads of the weight matrix, and `dst` must satisfy the requirements of
+/// [`gemm`](crate::gemm) for the output matrix.
+#[track_caller]
+pub unsafe fn conv2d<T: 'static + Copy + Send + Sync>(
+    shape: Conv2dShape,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    input: *const T,
+    weights: *const T,
+    weights_cs: isize,
+    weights_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism<'_>,
+) {
+    shape.check();
+    let backend = packing_backend::<T>();
+
+    let (out_height, out_width) = shape.output();
+    let m = out_height * out_width;
+    let n = shape.out_channels;
+    let k = shape.depth();
+    let mr = backend.mr;
+
+    let block = CONV_ROW_BLOCK.msrv_div_ceil(mr) * mr;
+    let n_blocks = m.msrv_div_ceil(block);
+
+    let dst = Ptr(dst);
+    let input = Ptr(input as *mut T);
+    let weights = Ptr(weights as *mut T);
+
+    for_each_problem(
+        n_blocks,
+        block.saturating_mul(n).saturating_mul(k),
+        threading_threshold::<T>(backend.simd_bytes),
+        parallelism,
+        |b, parallelism| {
+            // capture the whole pointers, which are `Sync` unlike their fields
+            let (dst, input, weights) = (dst, input, weights);
+            let first = b * block;
+            let nrows = Ord::min(block, m - first);
+
+            let mut mem = GlobalMemBuffer::new(StackReq::new_aligned::<T>(
+                nrows.msrv_div_ceil(mr) * mr * k,
+                CACHELINE_ALIGN,
+            ));
+            let packed = mem.as_mut_ptr() as *mut T;
+            pack_im2col(&shape, input.0, first, nrows, mr, packed);
+
+            // the packed panels only fit on the lhs side, so the problem is never transposed
+            (backend.gemm)(
+                nrows,
+                n,
+                k,
+                dst.wrapping_offset(first as isize * dst_rs).0,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                packed,
+                0,
+                0,
+                weights.0,
+                weights_cs,
+                weights_rs,
+                alpha,
+                beta,
+                false,
+                false,
+                false,
+                parallelism,
+                GemmConfig {
+                    packed_lhs: Some(packed),
+                    ..Default::default()
+                },
+            )
+        },
+    );
+}
@@ -0,0 +1,73 @@
+use crate::gemm::{get_backend, GemmBackend};
+use crate::{c32, c64, Parallelism};
+use core::fmt;
+use gemm_common::cache::KernelParams;
+
+/// Scalar types supported by [`gemm`](crate::gemm).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DType {
+    #[cfg(feature = "f16")]
+    F16,
+    F32,
+    F64,
+    C32,
+    C64,
+}
+
+/// Backend and blocking parameters chosen for a problem, as returned by [`describe`].
+#[derive(Copy, Clone, Debug)]
+pub struct GemmDescription {
+    /// Name of the backend, which is the instruction set it targets for the built-in backends.
+    pub backend: &'static str,
+    /// Number of rows of the microkernel.
+    pub mr: usize,
+    /// Number of columns of the microkernel.
+    pub nr: usize,
+    pub kernel_params: KernelParams,
+}
+
+impl fmt::Display for GemmDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}×{} microkernel), kc={}, mc={}, nc={}",
+            self.backend,
+            self.mr,
+            self.nr,
+            self.kernel_params.kc,
+            self.kernel_params.mc,
+            self.kernel_params.nc,
+        )
+    }
+}
+
+fn describe_backend<T>(backend: &GemmBackend<T>, m: usize, n: usize, k: usize) -> GemmDescription {
+    let (mr, nr, kernel_params) = (backend.blocking)(m, n, k, Parallelism::None);
+    GemmDescription {
+        backend: backend.name,
+        mr,
+        nr,
+        kernel_params,
+    }
+}
+
+/// Describes how a single-threaded `m×n×k` product of `dtype` matrices is computed on the
+/// current machine.
+///
+/// The microkernel is the register blocking selected for the shape of the problem, which may
+/// differ from the one the backend uses for packing ahead of time, and the blocking parameters
+/// are the ones derived from it.
+///
+/// The problem is described as it is handed to the backend, i.e. for a column-major destination.
+/// [`gemm`](crate::gemm) transposes the problem for row-major destinations, in which case the
+/// description of the `n×m×k` problem applies.
+pub fn describe(m: usize, n: usize, k: usize, dtype: DType) -> GemmDescription {
+    match dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => describe_backend(get_backend::<crate::f16>(), m, n, k),
+        DType::F32 => describe_backend(get_backend::<f32>(), m, n, k),
+        DType::F64 => describe_backend(get_backend::<f64>(), m, n, k),
+        DType::C32 => describe_backend(get_backend::<c32>(), m, n, k),
+        DType::C64 => describe_backend(get_backend::<c64>(), m, n, k),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::compensated::for_each_element;
+use crate::Parallelism;
+use gemm_common::Ptr;
+
+/// Unevaluated sum `hi + lo` of two `f64`, with `|lo| ≤ ulp(hi) / 2`.
+type DoubleDouble = (f64, f64);
+
+/// `a + b` as a double-double, for any `a` and `b`.
+#[inline(always)]
+fn two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// `a + b` as a double-double, for `|a| ≥ |b|`.
+#[inline(always)]
+fn fast_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// Splits `a` into two halves of 26 bits, whose products are exact.
+#[inline(always)]
+fn split(a: f64) -> DoubleDouble {
+    const SPLITTER: f64 = (1u64 << 27) as f64 + 1.0;
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+/// `a × b` as a double-double, with Dekker's product, which doesn't need a fused multiply-add.
+#[inline(always)]
+fn two_prod(a: f64, b: f64) -> DoubleDouble {
+    let p = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    (
+        p,
+        ((a_hi * b_hi - p) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo,
+    )
+}
+
+#[inline(always)]
+fn add(a: DoubleDouble, b: DoubleDouble) -> DoubleDouble {
+    let (hi, lo) = two_sum(a.0, b.0);
+    let (t_hi, t_lo) = two_sum(a.1, b.1);
+    let (hi, lo) = fast_two_sum(hi, lo + t_hi);
+    fast_two_sum(hi, lo + t_lo)
+}
+
+#[inline(always)]
+fn mul(a: DoubleDouble, b: f64) -> DoubleDouble {
+    let (hi, lo) = two_prod(a.0, b);
+    fast_two_sum(hi, lo + a.1 * b)
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`](crate::gemm) for `f64`, where the products are computed exactly and
+/// accumulated along `k` in double-double arithmetic, which carries about 106 bits, or 32
+/// significant digits, through the sums. The result is only rounded to `f64` when it's stored,
+/// so that it's accurate even for ill-conditioned products whose terms cancel each other out.
+///
+/// Like [`gemm_with_compensated_summation`](crate::gemm_with_compensated_summation), the product
+/// is computed element by element, with the columns (or the rows, for a row-major destination)
+/// split between the threads allowed by `parallelism`. It's meant for verification and for the
+/// products that need the extra digits, rather than for speed.
+///
+/// The exact products are computed with Dekker's algorithm, which requires the elements of the
+/// operands to be below `2^996` in magnitude.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm).
+pub unsafe fn gemm_double_double(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut f64,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const f64,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const f64,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: f64,
+    beta: f64,
+    parallelism: Parallelism<'_>,
+) {
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut f64);
+    let rhs = Ptr(rhs as *mut f64);
+
+    for_each_element::<f64>(m, n, k, dst_cs, dst_rs, parallelism, |i, j| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, lhs, rhs) = (dst, lhs, rhs);
+        let lhs = lhs.wrapping_offset(i as isize * lhs_rs).0;
+        let rhs = rhs.wrapping_offset(j as isize * rhs_cs).0;
+
+        let mut sum = (0.0, 0.0);
+        for depth in 0..k as isize {
+            sum = add(
+                sum,
+                two_prod(*lhs.offset(depth * lhs_cs), *rhs.offset(depth * rhs_rs)),
+            );
+        }
+
+        let dst = dst
+            .wrapping_offset(i as isize * dst_rs + j as isize * dst_cs)
+            .0;
+        let mut value = mul(sum, beta);
+        if read_dst {
+            value = add(value, two_prod(alpha, *dst));
+        }
+        *dst = value.0 + value.1;
+    });
+}
@@ -0,0 +1,462 @@
+use crate::gemm::{gemm_with_backend, get_backend};
+use crate::Parallelism;
+use core::any::TypeId;
+use gemm_common::gemm::{GemmConfig, TileEpilogue, TileEpilogueFn};
+
+/// Element-wise activation applied by [`gemm_epilogue`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Activation {
+    /// max(x, 0)
+    Relu,
+    /// x/2 × (1 + tanh(√(2/π) × (x + 0.044715×x³))), the tanh approximation of GELU.
+    #[cfg(feature = "std")]
+    Gelu,
+    /// 1 / (1 + exp(-x))
+    #[cfg(feature = "std")]
+    Sigmoid,
+}
+
+/// Element-wise operations fused into the final store of [`gemm_epilogue`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Epilogue {
+    pub activation: Option<Activation>,
+    /// Range `(lo, hi)` the values are clamped to, after the activation. `NaN` is kept.
+    pub clamp: Option<(f64, f64)>,
+}
+
+/// Real scalar types supported by the epilogues.
+trait Real: Copy + 'static {
+    fn clamp(self, lo: f64, hi: f64) -> Self;
+    fn relu(self) -> Self;
+    #[cfg(feature = "std")]
+    fn gelu(self) -> Self;
+    #[cfg(feature = "std")]
+    fn sigmoid(self) -> Self;
+}
+
+macro_rules! impl_real {
+    ($ty: ident) => {
+        impl Real for $ty {
+            #[inline(always)]
+            fn clamp(self, lo: f64, hi: f64) -> Self {
+                if self < lo as $ty {
+                    lo as $ty
+                } else if self > hi as $ty {
+                    hi as $ty
+                } else {
+                    self
+                }
+            }
+            #[inline(always)]
+            fn relu(self) -> Self {
+                // keeps NaN
+                if self < 0.0 {
+                    0.0
+                } else {
+                    self
+                }
+            }
+            #[cfg(feature = "std")]
+            #[inline(always)]
+            fn gelu(self) -> Self {
+                use core::$ty::consts::{FRAC_1_SQRT_2, FRAC_2_SQRT_PI};
+                const SQRT_2_OVER_PI: $ty = FRAC_2_SQRT_PI * FRAC_1_SQRT_2;
+                let inner = SQRT_2_OVER_PI * (self + 0.044715 * self * self * self);
+                0.5 * self * (1.0 + inner.tanh())
+            }
+            #[cfg(feature = "std")]
+            #[inline(always)]
+            fn sigmoid(self) -> Self {
+                1.0 / (1.0 + (-self).exp())
+            }
+        }
+    };
+}
+
+impl_real!(f32);
+impl_real!(f64);
+
+#[cfg(feature = "f16")]
+impl Real for crate::f16 {
+    #[inline(always)]
+    fn clamp(self, lo: f64, hi: f64) -> Self {
+        if self < Self::from_f64(lo) {
+            Self::from_f64(lo)
+        } else if self > Self::from_f64(hi) {
+            Self::from_f64(hi)
+        } else {
+            self
+        }
+    }
+    #[inline(always)]
+    fn relu(self) -> Self {
+        Self::from_f32(self.to_f32().relu())
+    }
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn gelu(self) -> Self {
+        Self::from_f32(self.to_f32().gelu())
+    }
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn sigmoid(self) -> Self {
+        Self::from_f32(self.to_f32().sigmoid())
+    }
+}
+
+/// Activation known at compile time, so that each one gets its own tile function.
+trait ActivationFn {
+    fn apply<T: Real>(x: T) -> T;
+}
+
+struct Identity;
+struct Relu;
+#[cfg(feature = "std")]
+struct Gelu;
+#[cfg(feature = "std")]
+struct Sigmoid;
+
+impl ActivationFn for Identity {
+    #[inline(always)]
+    fn apply<T: Real>(x: T) -> T {
+        x
+    }
+}
+impl ActivationFn for Relu {
+    #[inline(always)]
+    fn apply<T: Real>(x: T) -> T {
+        x.relu()
+    }
+}
+#[cfg(feature = "std")]
+impl ActivationFn for Gelu {
+    #[inline(always)]
+    fn apply<T: Real>(x: T) -> T {
+        x.gelu()
+    }
+}
+#[cfg(feature = "std")]
+impl ActivationFn for Sigmoid {
+    #[inline(always)]
+    fn apply<T: Real>(x: T) -> T {
+        x.sigmoid()
+    }
+}
+
+unsafe fn apply_tile<T: Real, A: ActivationFn, const CLAMP: bool>(
+    data: *const (),
+    dst: *mut T,
+    nrows: usize,
+    ncols: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    _row: usize,
+    _col: usize,
+) {
+    let epilogue = &*(data as *const Epilogue);
+    let (lo, hi) = epilogue.clamp.unwrap_or((0.0, 0.0));
+
+    for j in 0..ncols {
+        for i in 0..nrows {
+            let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+            let value = A::apply(*dst);
+            *dst = if CLAMP { value.clamp(lo, hi) } else { value };
+        }
+    }
+}
+
+fn activation_tile_fn<T: Real, const CLAMP: bool>(
+    activation: Option<Activation>,
+) -> TileEpilogueFn<T> {
+    match activation {
+        None => apply_tile::<T, Identity, CLAMP>,
+        Some(Activation::Relu) => apply_tile::<T, Relu, CLAMP>,
+        #[cfg(feature = "std")]
+        Some(Activation::Gelu) => apply_tile::<T, Gelu, CLAMP>,
+        #[cfg(feature = "std")]
+        Some(Activation::Sigmoid) => apply_tile::<T, Sigmoid, CLAMP>,
+    }
+}
+
+fn tile_fn<T: Real>(epilogue: &Epilogue) -> TileEpilogueFn<T> {
+    if epilogue.clamp.is_some() {
+        activation_tile_fn::<T, true>(epilogue.activation)
+    } else {
+        activation_tile_fn::<T, false>(epilogue.activation)
+    }
+}
+
+#[inline(always)]
+unsafe fn cast_tile_fn<T: 'static, U: 'static>(func: TileEpilogueFn<U>) -> TileEpilogueFn<T> {
+    core::mem::transmute::<TileEpilogueFn<U>, TileEpilogueFn<T>>(func)
+}
+
+/// Returns the tile function applying `epilogue` to `T`, or `None` if `T` is not `f32`, `f64`,
+/// or `gemm::f16`.
+fn try_get_tile_fn<T: 'static>(epilogue: &Epilogue) -> Option<TileEpilogueFn<T>> {
+    unsafe {
+        #[cfg(feature = "f16")]
+        if TypeId::of::<T>() == TypeId::of::<crate::f16>() {
+            return Some(cast_tile_fn(tile_fn::<crate::f16>(epilogue)));
+        }
+
+        if TypeId::of::<T>() == TypeId::of::<f64>() {
+            Some(cast_tile_fn(tile_fn::<f64>(epilogue)))
+        } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+            Some(cast_tile_fn(tile_fn::<f32>(epilogue)))
+        } else {
+            None
+        }
+    }
+}
+
+/// dst := epilogue(alpha×dst + beta×lhs×rhs)
+///
+/// Same as [`gemm`](crate::gemm), where `epilogue` is applied element-wise to each block of the
+/// destination right after its final value is stored by the microkernel, while it is still in
+/// cache, instead of in a separate pass over the whole destination.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, or `gemm::f16`, or if the clamping range is empty.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm).
+#[track_caller]
+pub unsafe fn gemm_epilogue<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    epilogue: Epilogue,
+    parallelism: Parallelism<'_>,
+) {
+    if let Some((lo, hi)) = epilogue.clamp {
+        assert!(lo <= hi, "empty clamping range: [{}, {}]", lo, hi);
+    }
+    let func = match try_get_tile_fn::<T>(&epilogue) {
+        Some(func) => func,
+        None => panic!("epilogues are only supported for real types"),
+    };
+
+    gemm_with_backend(
+        get_backend::<T>(),
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        false,
+        false,
+        false,
+        parallelism,
+        |_| GemmConfig {
+            epilogue: Some(TileEpilogue {
+                func,
+                data: &epilogue as *const Epilogue as *const (),
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+/// Block of the destination given to the callback of [`gemm_tile_epilogue`].
+pub struct Tile<T> {
+    /// Pointer to the element of the block at `(row, col)`.
+    pub ptr: *mut T,
+    pub nrows: usize,
+    pub ncols: usize,
+    pub cs: isize,
+    pub rs: isize,
+    /// Position of the block in the destination.
+    pub row: usize,
+    pub col: usize,
+}
+
+impl<T> Copy for Tile<T> {}
+impl<T> Clone for Tile<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Tile<T> {
+    /// Pointer to the element at `(i, j)` within the block.
+    ///
+    /// # Safety
+    ///
+    /// `i` and `j` must be smaller than `nrows` and `ncols`.
+    #[inline(always)]
+    pub unsafe fn get(&self, i: usize, j: usize) -> *mut T {
+        self.ptr.offset(i as isize * self.rs + j as isize * self.cs)
+    }
+}
+
+/// Callback of [`gemm_tile_epilogue`], along with the layout of the destination as given by the
+/// caller, to map the blocks seen by the backend back to it.
+struct TileClosure<'a, F> {
+    func: &'a F,
+    m: usize,
+    n: usize,
+    transposed: bool,
+    flip_rows: bool,
+    flip_cols: bool,
+}
+
+unsafe fn call_tile<T, F: Fn(Tile<T>) + Sync>(
+    data: *const (),
+    dst: *mut T,
+    nrows: usize,
+    ncols: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    row: usize,
+    col: usize,
+) {
+    let closure = &*(data as *const TileClosure<'_, F>);
+    let mut tile = if closure.transposed {
+        Tile {
+            ptr: dst,
+            nrows: ncols,
+            ncols: nrows,
+            cs: dst_rs,
+            rs: dst_cs,
+            row: col,
+            col: row,
+        }
+    } else {
+        Tile {
+            ptr: dst,
+            nrows,
+            ncols,
+            cs: dst_cs,
+            rs: dst_rs,
+            row,
+            col,
+        }
+    };
+
+    // the backend walks reversed dimensions forward, so the block starts at its last row
+    // (resp. column) in the caller's order
+    if closure.flip_rows && tile.nrows > 0 {
+        tile.row = closure.m - tile.row - tile.nrows;
+        tile.ptr = tile.ptr.offset((tile.nrows - 1) as isize * tile.rs);
+        tile.rs = -tile.rs;
+    }
+    if closure.flip_cols && tile.ncols > 0 {
+        tile.col = closure.n - tile.col - tile.ncols;
+        tile.ptr = tile.ptr.offset((tile.ncols - 1) as isize * tile.cs);
+        tile.cs = -tile.cs;
+    }
+
+    (closure.func)(tile)
+}
+
+/// dst := epilogue(alpha×dst + beta×lhs×rhs)
+///
+/// Same as [`gemm`](crate::gemm), where `epilogue` is called on each block of the destination
+/// right after its final value is stored by the microkernel, while it is still in cache. The
+/// blocks are given in the layout of `dst`, with their position in it, so the callback can
+/// apply position-dependent operations such as a bias or a causal mask.
+///
+/// The blocks partition the destination, and are at most as large as the register tile of the
+/// microkernel, except for the shapes that don't go through the microkernels, where the whole
+/// destination may be given at once. The callback may be called from several threads at once.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::c32`, `gemm::c64`, or `gemm::f16`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm). The callback may read and write any element of
+/// the block it is given.
+#[track_caller]
+pub unsafe fn gemm_tile_epilogue<T: 'static, F: Fn(Tile<T>) + Sync>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    epilogue: &F,
+    parallelism: Parallelism<'_>,
+) {
+    let mut closure = TileClosure {
+        func: epilogue,
+        m,
+        n,
+        transposed: false,
+        flip_rows: dst_rs < 0,
+        flip_cols: dst_cs < 0,
+    };
+    let closure = &mut closure;
+
+    gemm_with_backend(
+        get_backend::<T>(),
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+        |transposed| {
+            closure.transposed = transposed;
+            GemmConfig {
+                epilogue: Some(TileEpilogue {
+                    func: call_tile::<T, F>,
+                    data: closure as *const TileClosure<'_, F> as *const (),
+                }),
+                ..Default::default()
+            }
+        },
+    )
+}
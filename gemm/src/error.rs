@@ -0,0 +1,88 @@
+use core::fmt;
+
+/// Reason why [`try_gemm`](crate::try_gemm) rejected its arguments.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GemmError {
+    /// No backend is available for the scalar type.
+    UnsupportedType,
+    /// The offset of some element of the matrix doesn't fit in an `isize`.
+    OffsetOverflow {
+        operand: &'static str,
+        nrows: usize,
+        ncols: usize,
+        row_stride: isize,
+        col_stride: isize,
+    },
+    /// Some element of the matrix lies outside of its slice.
+    OutOfBounds {
+        operand: &'static str,
+        nrows: usize,
+        ncols: usize,
+        row_stride: isize,
+        col_stride: isize,
+        len: usize,
+    },
+    /// Distinct elements of the destination share the same memory location.
+    DstOverlap {
+        row_stride: isize,
+        col_stride: isize,
+    },
+    /// Some element of the destination shares its memory location with an element of `lhs` or
+    /// `rhs`. Only detected by the raw-pointer functions in builds with debug assertions, since
+    /// the slices of [`try_gemm`](crate::try_gemm) can't alias.
+    DstAliasing { operand: &'static str },
+    /// The scratch memory can't hold the packed operands.
+    ScratchTooSmall { required: usize, available: usize },
+}
+
+impl fmt::Display for GemmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            GemmError::UnsupportedType => write!(
+                f,
+                "scalar type is not one of `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`",
+            ),
+            GemmError::OffsetOverflow {
+                operand,
+                nrows,
+                ncols,
+                row_stride,
+                col_stride,
+            } => write!(
+                f,
+                "{operand}: {nrows}×{ncols} matrix with row stride {row_stride} and column stride {col_stride} has offsets that overflow isize",
+            ),
+            GemmError::OutOfBounds {
+                operand,
+                nrows,
+                ncols,
+                row_stride,
+                col_stride,
+                len,
+            } => write!(
+                f,
+                "{operand}: {nrows}×{ncols} matrix with row stride {row_stride} and column stride {col_stride} does not fit in a slice of length {len}",
+            ),
+            GemmError::DstOverlap {
+                row_stride,
+                col_stride,
+            } => write!(
+                f,
+                "dst: row stride {row_stride} and column stride {col_stride} make distinct elements overlap",
+            ),
+            GemmError::DstAliasing { operand } => {
+                write!(f, "dst shares memory with {operand}, which is read while dst is written")
+            }
+            GemmError::ScratchTooSmall {
+                required,
+                available,
+            } => write!(
+                f,
+                "scratch memory holds {available} bytes, but {required} bytes are required",
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GemmError {}
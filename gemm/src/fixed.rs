@@ -0,0 +1,43 @@
+use core::ops::{Add, Mul};
+use num_traits::Zero;
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Multiplies small matrices whose dimensions are known at compile time. The matrices are stored
+/// in column-major order, as arrays of columns: `dst` is `M×N`, `lhs` is `M×K` and `rhs` is `K×N`.
+///
+/// Unlike [`gemm`](crate::gemm), there is no backend selection, blocking, packing or threading:
+/// the loops have constant bounds, so they are fully unrolled and vectorized by the compiler,
+/// which is much faster for products up to around 16×16.
+///
+/// If `read_dst` is false, `alpha` and the initial contents of `dst` are ignored.
+#[inline(always)]
+pub fn gemm_fixed<T, const M: usize, const N: usize, const K: usize>(
+    dst: &mut [[T; M]; N],
+    read_dst: bool,
+    lhs: &[[T; M]; K],
+    rhs: &[[T; K]; N],
+    alpha: T,
+    beta: T,
+) where
+    T: Copy + Zero + Add<Output = T> + Mul<Output = T>,
+{
+    for (dst, rhs) in dst.iter_mut().zip(rhs.iter()) {
+        let mut acc = [T::zero(); M];
+        for (lhs, &rhs) in lhs.iter().zip(rhs.iter()) {
+            for (acc, &lhs) in acc.iter_mut().zip(lhs.iter()) {
+                *acc = *acc + lhs * rhs;
+            }
+        }
+
+        if read_dst {
+            for (dst, &acc) in dst.iter_mut().zip(acc.iter()) {
+                *dst = alpha * *dst + beta * acc;
+            }
+        } else {
+            for (dst, &acc) in dst.iter_mut().zip(acc.iter()) {
+                *dst = beta * acc;
+            }
+        }
+    }
+}
@@ -1,5 +1,10 @@
-use crate::Parallelism;
+use crate::{GemmError, Parallelism};
 use core::any::TypeId;
+#[cfg(feature = "std")]
+use dyn_stack::GlobalMemBuffer;
+use dyn_stack::{DynStack, StackReq};
+use gemm_common::cache::{override_kernel_params, DivCeil};
+use gemm_common::gemm::{get_deterministic, Backend, GemmConfig, PackingPolicy, UpdateRegion};
 
 #[allow(non_camel_case_types)]
 pub type c32 = num_complex::Complex32;
@@ -9,151 +14,242 @@ pub type c64 = num_complex::Complex64;
 #[allow(non_camel_case_types)]
 pub type f16 = gemm_f16::f16;
 
-unsafe fn gemm_dispatch<T: 'static>(
-    m: usize,
-    n: usize,
-    k: usize,
-    dst: *mut T,
-    dst_cs: isize,
-    dst_rs: isize,
-    read_dst: bool,
-    lhs: *const T,
-    lhs_cs: isize,
-    lhs_rs: isize,
-    rhs: *const T,
-    rhs_cs: isize,
-    rhs_rs: isize,
-    alpha: T,
-    beta: T,
-    conj_dst: bool,
-    conj_lhs: bool,
-    conj_rhs: bool,
-    parallelism: Parallelism,
-) {
+/// Signature of [`Backend::gemm`] for the scalar type `T`.
+pub type GemmFn<T> = unsafe fn(
+    usize,
+    usize,
+    usize,
+    *mut T,
+    isize,
+    isize,
+    bool,
+    *const T,
+    isize,
+    isize,
+    *const T,
+    isize,
+    isize,
+    T,
+    T,
+    bool,
+    bool,
+    bool,
+    Parallelism<'_>,
+    GemmConfig<'_, T>,
+);
+/// Signature of [`Backend::pack_lhs`] and [`Backend::pack_rhs`] for the scalar type `T`.
+pub type PackFn<T> = unsafe fn(usize, usize, *mut T, *const T, isize, isize, usize);
+/// Backend that can be registered with [`register_backend`].
+pub type GemmBackend<T> = Backend<GemmFn<T>, PackFn<T>>;
+
+#[inline(always)]
+unsafe fn cast_backend<T: 'static, F, P>(
+    backend: &'static Backend<F, P>,
+) -> &'static GemmBackend<T> {
+    &*(backend as *const Backend<F, P> as *const GemmBackend<T>)
+}
+
+/// Returns the backend selected for `T` on the current machine, or `None` if `T` is not `f32`,
+/// `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+#[inline]
+pub(crate) fn try_get_backend<T: 'static>() -> Option<&'static GemmBackend<T>> {
+    unsafe {
+        #[cfg(feature = "f16")]
+        if TypeId::of::<T>() == TypeId::of::<f16>() {
+            return Some(cast_backend::<T, _, _>(gemm_f16::gemm::f16::get_backend()));
+        }
+
+        if TypeId::of::<T>() == TypeId::of::<f64>() {
+            Some(cast_backend::<T, _, _>(gemm_f64::gemm::f64::get_backend()))
+        } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+            Some(cast_backend::<T, _, _>(gemm_f32::gemm::f32::get_backend()))
+        } else if TypeId::of::<T>() == TypeId::of::<c64>() {
+            Some(cast_backend::<T, _, _>(gemm_c64::gemm::f64::get_backend()))
+        } else if TypeId::of::<T>() == TypeId::of::<c32>() {
+            Some(cast_backend::<T, _, _>(gemm_c32::gemm::f32::get_backend()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the backend selected for `T` on the current machine.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+#[inline]
+#[track_caller]
+pub(crate) fn get_backend<T: 'static>() -> &'static GemmBackend<T> {
+    match try_get_backend::<T>() {
+        Some(backend) => backend,
+        None => panic!("{}", GemmError::UnsupportedType),
+    }
+}
+
+/// Registers an additional backend for `T`, e.g. an experimental kernel set, which is then used
+/// by every call that doesn't pass an explicit backend, if `priority` is at least as high as the
+/// priority of the active backend. The built-in backends have priority
+/// [`DEFAULT_BACKEND_PRIORITY`](gemm_common::gemm::DEFAULT_BACKEND_PRIORITY).
+///
+/// Operands that were already packed keep using the backend they were packed for.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+#[track_caller]
+pub fn register_backend<T: 'static>(backend: &'static GemmBackend<T>, priority: i32) {
+    let backend = backend as *const GemmBackend<T>;
+    unsafe {
+        #[cfg(feature = "f16")]
+        if TypeId::of::<T>() == TypeId::of::<f16>() {
+            return gemm_f16::gemm::f16::register_backend(&*(backend as *const _), priority);
+        }
+
+        if TypeId::of::<T>() == TypeId::of::<f64>() {
+            gemm_f64::gemm::f64::register_backend(&*(backend as *const _), priority)
+        } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+            gemm_f32::gemm::f32::register_backend(&*(backend as *const _), priority)
+        } else if TypeId::of::<T>() == TypeId::of::<c64>() {
+            gemm_c64::gemm::f64::register_backend(&*(backend as *const _), priority)
+        } else if TypeId::of::<T>() == TypeId::of::<c32>() {
+            gemm_c32::gemm::f32::register_backend(&*(backend as *const _), priority)
+        } else {
+            panic!("{}", GemmError::UnsupportedType)
+        }
+    }
+}
+
+/// Forgets the backends registered for `T`, so that the fastest built-in backend is used again.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+#[track_caller]
+pub fn reset_backend<T: 'static>() {
     #[cfg(feature = "f16")]
     if TypeId::of::<T>() == TypeId::of::<f16>() {
-        return gemm_f16::gemm::f16::get_gemm_fn()(
-            m,
-            n,
-            k,
-            dst as *mut f16,
-            dst_cs,
-            dst_rs,
-            read_dst,
-            lhs as *mut f16,
-            lhs_cs,
-            lhs_rs,
-            rhs as *mut f16,
-            rhs_cs,
-            rhs_rs,
-            *(&alpha as *const T as *const f16),
-            *(&beta as *const T as *const f16),
-            false,
-            false,
-            false,
-            parallelism,
-        );
+        return gemm_f16::gemm::f16::reset_backend();
     }
 
     if TypeId::of::<T>() == TypeId::of::<f64>() {
-        gemm_f64::gemm::f64::get_gemm_fn()(
-            m,
-            n,
-            k,
-            dst as *mut f64,
-            dst_cs,
-            dst_rs,
-            read_dst,
-            lhs as *mut f64,
-            lhs_cs,
-            lhs_rs,
-            rhs as *mut f64,
-            rhs_cs,
-            rhs_rs,
-            *(&alpha as *const T as *const f64),
-            *(&beta as *const T as *const f64),
-            false,
-            false,
-            false,
-            parallelism,
-        )
+        gemm_f64::gemm::f64::reset_backend()
     } else if TypeId::of::<T>() == TypeId::of::<f32>() {
-        gemm_f32::gemm::f32::get_gemm_fn()(
-            m,
-            n,
-            k,
-            dst as *mut f32,
-            dst_cs,
-            dst_rs,
-            read_dst,
-            lhs as *mut f32,
-            lhs_cs,
-            lhs_rs,
-            rhs as *mut f32,
-            rhs_cs,
-            rhs_rs,
-            *(&alpha as *const T as *const f32),
-            *(&beta as *const T as *const f32),
-            false,
-            false,
-            false,
-            parallelism,
-        )
+        gemm_f32::gemm::f32::reset_backend()
     } else if TypeId::of::<T>() == TypeId::of::<c64>() {
-        gemm_c64::gemm::f64::get_gemm_fn()(
-            m,
-            n,
-            k,
-            dst as *mut c64,
-            dst_cs,
-            dst_rs,
-            read_dst,
-            lhs as *mut c64,
-            lhs_cs,
-            lhs_rs,
-            rhs as *mut c64,
-            rhs_cs,
-            rhs_rs,
-            *(&alpha as *const T as *const c64),
-            *(&beta as *const T as *const c64),
-            conj_dst,
-            conj_lhs,
-            conj_rhs,
-            parallelism,
-        )
+        gemm_c64::gemm::f64::reset_backend()
     } else if TypeId::of::<T>() == TypeId::of::<c32>() {
-        gemm_c32::gemm::f32::get_gemm_fn()(
-            m,
-            n,
-            k,
-            dst as *mut c32,
-            dst_cs,
-            dst_rs,
-            read_dst,
-            lhs as *mut c32,
-            lhs_cs,
-            lhs_rs,
-            rhs as *mut c32,
-            rhs_cs,
-            rhs_rs,
-            *(&alpha as *const T as *const c32),
-            *(&beta as *const T as *const c32),
-            conj_dst,
-            conj_lhs,
-            conj_rhs,
-            parallelism,
-        )
+        gemm_c32::gemm::f32::reset_backend()
     } else {
-        panic!();
+        panic!("{}", GemmError::UnsupportedType)
     }
 }
 
-/// dst := alpha×dst + beta×lhs×rhs
+/// Calls `f` with each built-in backend for `T` supported by the current machine, including
+/// the ones that aren't selected by default, from the most portable to the fastest. Each one can
+/// be forced with [`register_backend`], e.g. to benchmark it.
 ///
 /// # Panics
 ///
 /// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
-pub unsafe fn gemm<T: 'static>(
+#[track_caller]
+pub fn for_each_builtin_backend<T: 'static>(mut f: impl FnMut(&'static GemmBackend<T>)) {
+    unsafe {
+        #[cfg(feature = "f16")]
+        if TypeId::of::<T>() == TypeId::of::<f16>() {
+            return gemm_f16::gemm::f16::for_each_builtin_backend(|backend| {
+                f(cast_backend::<T, _, _>(backend))
+            });
+        }
+
+        if TypeId::of::<T>() == TypeId::of::<f64>() {
+            gemm_f64::gemm::f64::for_each_builtin_backend(|backend| {
+                f(cast_backend::<T, _, _>(backend))
+            })
+        } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+            gemm_f32::gemm::f32::for_each_builtin_backend(|backend| {
+                f(cast_backend::<T, _, _>(backend))
+            })
+        } else if TypeId::of::<T>() == TypeId::of::<c64>() {
+            gemm_c64::gemm::f64::for_each_builtin_backend(|backend| {
+                f(cast_backend::<T, _, _>(backend))
+            })
+        } else if TypeId::of::<T>() == TypeId::of::<c32>() {
+            gemm_c32::gemm::f32::for_each_builtin_backend(|backend| {
+                f(cast_backend::<T, _, _>(backend))
+            })
+        } else {
+            panic!("{}", GemmError::UnsupportedType)
+        }
+    }
+}
+
+/// Name of the backend currently used for `T`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+#[track_caller]
+pub fn active_backend_name<T: 'static>() -> &'static str {
+    get_backend::<T>().name
+}
+
+#[inline(always)]
+pub(crate) fn is_complex<T: 'static>() -> bool {
+    TypeId::of::<T>() == TypeId::of::<c32>() || TypeId::of::<T>() == TypeId::of::<c64>()
+}
+
+/// Whether the destination with the given strides is row-oriented, in which case [`gemm`]
+/// computes the transposed product.
+#[inline(always)]
+pub(crate) fn is_transposed(dst_cs: isize, dst_rs: isize) -> bool {
+    // we want to transpose if the destination is column-oriented, since the microkernel prefers
+    // column major matrices.
+    dst_cs.abs() < dst_rs.abs()
+}
+
+/// Whether [`gemm`] computes the transposed product for the given strides, which is the case
+/// for a row-oriented destination, or if both operands are row-major and the destination isn't
+/// column-major.
+///
+/// Since a row-major destination is transposed regardless, reading the rhs of row-major operands
+/// in place only concerns destinations with no unit stride, such as a view of every other row
+/// and column. Row-major operands with a column-major destination keep a packed rhs.
+#[inline(always)]
+pub(crate) fn transposes_product(
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs_cs: isize,
+    rhs_rs: isize,
+) -> bool {
+    // the operands of the transposed product are column-major, so the microkernels read the rhs
+    // in place instead of packing it. a column-major destination would then be written with
+    // strided stores and miss the matrix-vector kernels, so it's only done for a destination
+    // that is strided either way
+    let row_major = |cs: isize, rs: isize| cs.unsigned_abs() == 1 && rs.unsigned_abs() != 1;
+    is_transposed(dst_cs, dst_rs)
+        || (dst_rs.unsigned_abs() != 1 && row_major(lhs_cs, lhs_rs) && row_major(rhs_cs, rhs_rs))
+}
+
+/// Same as [`gemm`], with an explicit backend and per-call settings. `make_config` is called
+/// with `true` if the problem is transposed before being handed to the backend.
+///
+/// The scaling vectors, the packing policies, the mask, the secondary destination and the update
+/// region of the config are given for the problem as passed to this function, and are transposed
+/// and reversed along with the operands.
+///
+/// # Panics
+///
+/// Panics if the update region isn't [`UpdateRegion::Full`] and the destination has a negative
+/// stride, since reversing its rows or columns doesn't preserve the triangles.
+///
+/// With debug assertions, also panics if distinct elements of the destination overlap, if the
+/// destination aliases an operand that isn't packed ahead of time, or if the scratch memory of
+/// the config can't hold the backend's `gemm_req`.
+pub(crate) unsafe fn gemm_with_backend<'a, T: 'static>(
+    backend: &GemmBackend<T>,
     m: usize,
     n: usize,
     k: usize,
@@ -172,11 +268,83 @@ pub unsafe fn gemm<T: 'static>(
     conj_dst: bool,
     conj_lhs: bool,
     conj_rhs: bool,
-    parallelism: Parallelism,
+    parallelism: Parallelism<'_>,
+    make_config: impl FnOnce(bool) -> GemmConfig<'a, T>,
 ) {
-    // we want to transpose if the destination is column-oriented, since the microkernel prefers
-    // column major matrices.
-    let do_transpose = dst_cs.abs() < dst_rs.abs();
+    // the events of the backend are emitted inside the span of the call
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "gemm",
+        dtype = core::any::type_name::<T>(),
+        m,
+        n,
+        k,
+        backend = backend.name,
+        ?parallelism,
+        threads = gemm_common::gemm::max_threads(parallelism),
+    )
+    .entered();
+
+    let do_transpose = transposes_product(dst_cs, dst_rs, lhs_cs, lhs_rs, rhs_cs, rhs_rs);
+    trace_event!(debug, transposed = do_transpose, "dispatch");
+    let mut config = make_config(do_transpose);
+
+    // arguments that would silently corrupt memory instead of failing
+    #[cfg(debug_assertions)]
+    {
+        let check = |result: Result<(), GemmError>| {
+            if let Err(e) = result {
+                panic!("{e}");
+            }
+        };
+        // the packed operands are given for the problem as handed to the backend
+        let (lhs_is_packed, rhs_is_packed) = if do_transpose {
+            (config.packed_rhs.is_some(), config.packed_lhs.is_some())
+        } else {
+            (config.packed_lhs.is_some(), config.packed_rhs.is_some())
+        };
+        let dst_view = (dst as *const T, m, n, dst_rs, dst_cs);
+        check(try_check_no_self_overlap(m, n, dst_rs, dst_cs));
+        if !lhs_is_packed {
+            check(try_check_no_aliasing(
+                "lhs",
+                dst_view,
+                (lhs, m, k, lhs_rs, lhs_cs),
+            ));
+        }
+        if !rhs_is_packed {
+            check(try_check_no_aliasing(
+                "rhs",
+                dst_view,
+                (rhs, k, n, rhs_rs, rhs_cs),
+            ));
+        }
+        if let Some(stack) = &config.stack {
+            let req = if do_transpose {
+                (backend.gemm_req)(n, m, k, parallelism)
+            } else {
+                (backend.gemm_req)(m, n, k, parallelism)
+            };
+            if !stack.can_hold(req) {
+                check(Err(GemmError::ScratchTooSmall {
+                    required: req.unaligned_bytes_required(),
+                    available: stack.len_bytes(),
+                }));
+            }
+        }
+    }
+
+    if do_transpose {
+        core::mem::swap(&mut config.lhs_scale, &mut config.rhs_scale);
+        core::mem::swap(&mut config.lhs_packing, &mut config.rhs_packing);
+        config.mask = config.mask.map(|(ptr, cs, rs)| (ptr, rs, cs));
+        config.accumulate = config.accumulate.map(|(ptr, cs, rs)| (ptr, rs, cs));
+        config.update_region = config.update_region.transpose();
+    }
+    assert!(
+        config.update_region == UpdateRegion::Full || (dst_cs >= 0 && dst_rs >= 0),
+        "triangular updates require a destination with nonnegative strides",
+    );
 
     let (
         m,
@@ -206,6 +374,15 @@ pub unsafe fn gemm<T: 'static>(
         dst_rs = -dst_rs;
         lhs = lhs.wrapping_offset((m - 1) as isize * lhs_rs);
         lhs_rs = -lhs_rs;
+        config.lhs_scale = config
+            .lhs_scale
+            .map(|(ptr, inc)| (ptr.wrapping_offset((m - 1) as isize * inc), -inc));
+        config.mask = config
+            .mask
+            .map(|(ptr, cs, rs)| (ptr.wrapping_offset((m - 1) as isize * rs), cs, -rs));
+        config.accumulate = config
+            .accumulate
+            .map(|(ptr, cs, rs)| (ptr.wrapping_offset((m - 1) as isize * rs), cs, -rs));
     }
 
     if dst_cs < 0 && n > 0 {
@@ -213,9 +390,242 @@ pub unsafe fn gemm<T: 'static>(
         dst_cs = -dst_cs;
         rhs = rhs.wrapping_offset((n - 1) as isize * rhs_cs);
         rhs_cs = -rhs_cs;
+        config.rhs_scale = config
+            .rhs_scale
+            .map(|(ptr, inc)| (ptr.wrapping_offset((n - 1) as isize * inc), -inc));
+        config.mask = config
+            .mask
+            .map(|(ptr, cs, rs)| (ptr.wrapping_offset((n - 1) as isize * cs), -cs, rs));
+        config.accumulate = config
+            .accumulate
+            .map(|(ptr, cs, rs)| (ptr.wrapping_offset((n - 1) as isize * cs), -cs, rs));
     }
 
-    gemm_dispatch(
+    // conjugation is a no-op for real types, and keeps them off the fast paths
+    let (conj_dst, conj_lhs, conj_rhs) = if is_complex::<T>() {
+        (conj_dst, conj_lhs, conj_rhs)
+    } else {
+        (false, false, false)
+    };
+
+    (backend.gemm)(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+        config,
+    )
+}
+
+/// Settings of a [`GemmConfig`] that carry over to the smaller products it's split into by the
+/// Strassen recursion or along `k`.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct Settings {
+    pub threading_threshold: Option<usize>,
+    pub streaming_stores: Option<bool>,
+    pub deterministic: Option<bool>,
+    pub flush_denormals: Option<bool>,
+    pub lhs_packing: Option<PackingPolicy>,
+    pub rhs_packing: Option<PackingPolicy>,
+}
+
+impl Settings {
+    fn of<T>(config: &GemmConfig<'_, T>) -> Self {
+        Self {
+            threading_threshold: config.threading_threshold,
+            streaming_stores: config.streaming_stores,
+            deterministic: config.deterministic,
+            flush_denormals: config.flush_denormals,
+            lhs_packing: config.lhs_packing,
+            rhs_packing: config.rhs_packing,
+        }
+    }
+
+    /// Config of a smaller product with these settings, using the scratch memory of `stack`.
+    pub(crate) fn config<'a, T>(self, stack: Option<DynStack<'a>>) -> GemmConfig<'a, T> {
+        GemmConfig {
+            stack,
+            threading_threshold: self.threading_threshold,
+            streaming_stores: self.streaming_stores,
+            deterministic: self.deterministic,
+            flush_denormals: self.flush_denormals,
+            lhs_packing: self.lhs_packing,
+            rhs_packing: self.rhs_packing,
+            ..Default::default()
+        }
+    }
+}
+
+/// Whether the product can be computed as a combination of smaller products, which is only the
+/// case if `config` has nothing but [`Settings`] and scratch memory.
+fn is_divisible<T>(config: &GemmConfig<'_, T>) -> bool {
+    config.kernel_params.is_none()
+        && config.packed_lhs.is_none()
+        && config.packed_rhs.is_none()
+        && config.epilogue.is_none()
+        && config.lhs_scale.is_none()
+        && config.rhs_scale.is_none()
+        && config.mask.is_none()
+        && config.update_region == UpdateRegion::Full
+        && config.accumulate.is_none()
+}
+
+/// Same as [`gemm_with_backend`], for the entry points whose scratch memory is given by
+/// [`gemm_req`]. The rhs may be packed by the weight cache of the calling thread, and products
+/// whose config is [divisible](is_divisible) may instead be split along `k` between the threads,
+/// or computed by the Strassen-Winograd recursion with the `strassen` feature.
+///
+/// The parameters set in `config.kernel_params` replace the ones chosen by the drivers, and the
+/// ones that are `0` are still chosen by the drivers. The scratch memory is taken from
+/// `config.stack`, or allocated if it's `None`.
+pub(crate) unsafe fn gemm_dispatch<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    mut config: GemmConfig<'_, T>,
+) {
+    let settings = Settings::of(&config);
+
+    // the cached panels replace the packing of the rhs, which can't be scaled once it's packed
+    #[cfg(feature = "std")]
+    if config.kernel_params.is_none()
+        && config.packed_lhs.is_none()
+        && config.packed_rhs.is_none()
+        && config.rhs_scale.is_none()
+        && config.rhs_packing != Some(PackingPolicy::Never)
+    {
+        if let Some(panels) = crate::weight_cache::cached_rhs_panels(
+            backend, m, n, k, dst_cs, dst_rs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
+        ) {
+            let packed = panels.as_ptr() as *const T;
+            return gemm_with_backend(
+                backend,
+                m,
+                n,
+                k,
+                dst,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs,
+                lhs_cs,
+                lhs_rs,
+                rhs,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                conj_dst,
+                conj_lhs,
+                conj_rhs,
+                parallelism,
+                |transposed| GemmConfig {
+                    packed_lhs: transposed.then_some(packed),
+                    packed_rhs: (!transposed).then_some(packed),
+                    ..config
+                },
+            );
+        }
+    }
+
+    #[cfg(feature = "strassen")]
+    if is_divisible(&config) && crate::strassen::applies::<T>(m, n, k, conj_dst, conj_lhs, conj_rhs)
+    {
+        trace_event!(debug, m, n, k, "strassen-winograd recursion");
+        return crate::strassen::gemm_strassen(
+            backend,
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            parallelism,
+            settings,
+            config.stack,
+        );
+    }
+
+    // the partial products are accumulated in an order that depends on the number of threads
+    let deterministic = config.deterministic.unwrap_or_else(get_deterministic);
+    let n_splits =
+        crate::split_k::n_splits(backend, m, n, k, parallelism, config.threading_threshold);
+    if n_splits > 1
+        && !deterministic
+        && is_divisible(&config)
+        && crate::split_k::fits::<T>(config.stack.as_ref(), m, n, n_splits)
+    {
+        trace_event!(debug, m, n, k, splits = n_splits, "split along k");
+        return crate::split_k::gemm_split_k(
+            backend,
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+            n_splits,
+            settings,
+            config.stack,
+        );
+    }
+
+    gemm_with_backend(
+        backend,
         m,
         n,
         k,
@@ -235,12 +645,669 @@ pub unsafe fn gemm<T: 'static>(
         conj_lhs,
         conj_rhs,
         parallelism,
+        |transposed| {
+            // the parameters block the problem as it's handed to the backend
+            if let Some(over) = config.kernel_params {
+                let (m, n) = if transposed { (n, m) } else { (m, n) };
+                config.kernel_params = Some(override_kernel_params(
+                    m,
+                    n,
+                    k,
+                    backend.mr,
+                    backend.nr,
+                    (backend.kernel_params)(m, n, k, parallelism),
+                    over,
+                ));
+            }
+            config
+        },
     )
 }
 
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Strides are given in elements. The strides of `lhs` and `rhs` may be zero, which broadcasts
+/// a single row, column or element along the other dimension without materializing it. Distinct
+/// elements of `dst` must not overlap.
+///
+/// The parallel regions run on the pool selected by `parallelism`. `Parallelism::Rayon` uses the
+/// rayon pool the caller is running in, which is the global pool unless the call is made from
+/// `ThreadPool::install`, and `Parallelism::Custom` uses a pool given by the caller, such as a
+/// dedicated `rayon::ThreadPool` or a [`ScopedSpawner`](crate::ScopedSpawner).
+/// `Parallelism::Rayon(0)` runs on a single thread when called from a rayon task that has other
+/// tasks queued behind it, such as one item of a parallel iterator over many products, instead of
+/// oversubscribing the pool.
+///
+/// Products with a small destination and a much larger depth are split along `k` between the
+/// threads allowed by `parallelism`, each of which computes a partial product that is then added
+/// to `dst`.
+///
+/// Each element of `dst` is otherwise accumulated by a single thread, but the register blocking,
+/// and with it the depth blocks and the order of the sums along `k`, may depend on the number of
+/// threads, so that the results can differ between thread counts. Only with
+/// `set_deterministic(true)` are they bitwise identical for any number of threads, on a given
+/// machine and build, since products are then never split along `k` and their blocking doesn't
+/// depend on `parallelism`. [`gemm_with_config`] selects this mode for a single product with
+/// `deterministic: Some(true)`.
+///
+/// With the `strassen` feature, products of `f32`, `f64`, `gemm::c32`, or `gemm::c64` without
+/// conjugation whose dimensions are all at least `get_strassen_threshold()` are computed with the
+/// Strassen-Winograd recursion, which needs about 12% fewer floating point operations per level
+/// at the cost of slightly larger rounding errors.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+pub unsafe fn gemm<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    gemm_dispatch(
+        get_backend::<T>(),
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+        GemmConfig::default(),
+    )
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`], with the per-call settings of `config`. Each setting that is given replaces
+/// the matching global one for this product only, and any of them can be combined in one call.
+///
+/// The scaling vectors, the mask, the secondary destination, the update region and the packing
+/// policies are given for the problem as passed to this function. The blocking parameters, the
+/// packed operands and the epilogue are given for the problem as it's handed to the backend, as
+/// in [`describe`](crate::describe), and blocking parameters that are `0` are still chosen by
+/// the drivers.
+///
+/// The threading threshold, the streaming stores, the deterministic mode, the flushing of
+/// denormals and the packing policies also apply to the smaller products computed by the
+/// Strassen recursion and by the slices of a product split along `k`. The other settings need
+/// the whole product at once, so a config with any of them is always computed in a single pass by
+/// the backend. With `config.stack`, which must satisfy [`gemm_req`], the product is only split
+/// along `k` if the stack can hold the partial products.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`, if
+/// `config.update_region` isn't [`UpdateRegion::Full`] and `dst_cs` or `dst_rs` is negative, or
+/// if `T` is `gemm::f16` and the config has packed operands, scaling vectors, a mask, a partial
+/// update region or a secondary destination.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`]. In addition, the scaling vectors, the mask and the secondary
+/// destination of the config must be valid for reads of the vectors and matrices they describe,
+/// and the secondary destination must also be valid for writes, and must not overlap `dst`, `lhs`
+/// or `rhs`.
+#[track_caller]
+pub unsafe fn gemm_with_config<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    config: GemmConfig<'_, T>,
+) {
+    gemm_dispatch(
+        get_backend::<T>(),
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+        config,
+    )
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static ALLOC_MEM: core::cell::RefCell<(StackReq, GlobalMemBuffer)> =
+        core::cell::RefCell::new((StackReq::empty(), GlobalMemBuffer::new(StackReq::empty())));
+}
+
+/// Scratch memory needed by [`gemm`] for an `m×n×k` problem with the given destination strides.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+pub fn gemm_req<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    parallelism: Parallelism<'_>,
+) -> StackReq {
+    backend_req(get_backend::<T>(), m, n, k, dst_cs, dst_rs, parallelism)
+}
+
+fn backend_req<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    parallelism: Parallelism<'_>,
+) -> StackReq {
+    #[cfg(feature = "strassen")]
+    let strassen = crate::strassen::strassen_req(backend, m, n, k, parallelism);
+    #[cfg(not(feature = "strassen"))]
+    let strassen = StackReq::empty();
+    let split_k = crate::split_k::split_k_req(backend, m, n, k, parallelism);
+
+    // a column-oriented destination is still transposed if both operands are row-major
+    if is_transposed(dst_cs, dst_rs) {
+        (backend.gemm_req)(n, m, k, parallelism)
+            .or(strassen)
+            .or(split_k)
+    } else {
+        (backend.gemm_req)(m, n, k, parallelism)
+            .or((backend.gemm_req)(n, m, k, parallelism))
+            .or(strassen)
+            .or(split_k)
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`], except that the scratch memory is computed from the backend's `gemm_req`
+/// and taken from a heap buffer that is kept around and reused by later calls on the same
+/// thread.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`].
+#[cfg(feature = "std")]
+pub unsafe fn gemm_alloc<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let backend = get_backend::<T>();
+    let req = backend_req(backend, m, n, k, dst_cs, dst_rs, parallelism);
+
+    let run = |mem: &mut GlobalMemBuffer| {
+        gemm_dispatch(
+            backend,
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+            GemmConfig {
+                stack: Some(DynStack::new(mem)),
+                ..Default::default()
+            },
+        )
+    };
+
+    ALLOC_MEM.with(|cell| match cell.try_borrow_mut() {
+        Ok(mut cached) => {
+            let (cached_req, mem) = &mut *cached;
+            let grown = cached_req.or(req);
+            if grown != *cached_req {
+                *mem = GlobalMemBuffer::new(grown);
+                *cached_req = grown;
+            }
+            run(mem)
+        }
+        // the buffer is already in use by a call further up the stack, e.g. when a rayon worker
+        // picks up another multiplication while waiting for its own
+        Err(_) => run(&mut GlobalMemBuffer::new(req)),
+    })
+}
+
+/// Returns the smallest and largest element offsets reachable by a `nrows×ncols` matrix with the
+/// given strides, or `None` if the matrix is empty.
+fn offset_range(
+    name: &'static str,
+    nrows: usize,
+    ncols: usize,
+    rs: isize,
+    cs: isize,
+) -> Result<Option<(isize, isize)>, GemmError> {
+    if nrows == 0 || ncols == 0 {
+        return Ok(None);
+    }
+    let overflow = GemmError::OffsetOverflow {
+        operand: name,
+        nrows,
+        ncols,
+        row_stride: rs,
+        col_stride: cs,
+    };
+    let last_row = isize::try_from(nrows - 1)
+        .ok()
+        .and_then(|i| i.checked_mul(rs))
+        .ok_or(overflow)?;
+    let last_col = isize::try_from(ncols - 1)
+        .ok()
+        .and_then(|j| j.checked_mul(cs))
+        .ok_or(overflow)?;
+    let min = last_row
+        .min(0)
+        .checked_add(last_col.min(0))
+        .ok_or(overflow)?;
+    let max = last_row
+        .max(0)
+        .checked_add(last_col.max(0))
+        .ok_or(overflow)?;
+    max.checked_sub(min).ok_or(overflow)?;
+    Ok(Some((min, max)))
+}
+
+/// Checks that every element of the matrix lies inside a slice of length `len`, and returns the
+/// offset of the element at `(0, 0)` from the start of the slice.
+pub(crate) fn try_check_bounds(
+    name: &'static str,
+    len: usize,
+    nrows: usize,
+    ncols: usize,
+    rs: isize,
+    cs: isize,
+) -> Result<usize, GemmError> {
+    match offset_range(name, nrows, ncols, rs, cs)? {
+        Some((min, max)) => {
+            if ((max - min) as usize) < len {
+                Ok(min.unsigned_abs())
+            } else {
+                Err(GemmError::OutOfBounds {
+                    operand: name,
+                    nrows,
+                    ncols,
+                    row_stride: rs,
+                    col_stride: cs,
+                    len,
+                })
+            }
+        }
+        None => Ok(0),
+    }
+}
+
+/// Checks that distinct `(row, col)` pairs of the destination map to distinct elements, so that
+/// writes to one element can't clobber another.
+pub(crate) fn try_check_no_self_overlap(
+    nrows: usize,
+    ncols: usize,
+    rs: isize,
+    cs: isize,
+) -> Result<(), GemmError> {
+    let overlap = GemmError::DstOverlap {
+        row_stride: rs,
+        col_stride: cs,
+    };
+    if nrows == 0 || ncols == 0 {
+        return Ok(());
+    }
+    if (nrows > 1 && rs == 0) || (ncols > 1 && cs == 0) {
+        return Err(overlap);
+    }
+    if nrows > 1 && ncols > 1 {
+        // (i, j) and (i + di, j + dj) are the same element when di×rs = -dj×cs, whose smallest
+        // nonzero solution is |di| = |cs|/g, |dj| = |rs|/g, where g = gcd(|rs|, |cs|)
+        let (rs, cs) = (rs.unsigned_abs(), cs.unsigned_abs());
+        let g = gcd(rs, cs);
+        if cs / g < nrows && rs / g < ncols {
+            return Err(overlap);
+        }
+    }
+    Ok(())
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Returns whether some element of a `nrows×ncols` matrix with the given strides lies `offset`
+/// elements past its lowest-addressed element, for a matrix whose distinct elements don't
+/// overlap.
+fn contains_offset(nrows: usize, ncols: usize, rs: isize, cs: isize, offset: isize) -> bool {
+    if offset < 0 {
+        return false;
+    }
+    let offset = offset as usize;
+    // negating a stride reverses the rows or columns without changing the set of offsets
+    let ((inner, inner_len), (outer, outer_len)) = if rs.unsigned_abs() <= cs.unsigned_abs() {
+        ((rs.unsigned_abs(), nrows), (cs.unsigned_abs(), ncols))
+    } else {
+        ((cs.unsigned_abs(), ncols), (rs.unsigned_abs(), nrows))
+    };
+    let in_inner = |rem: usize| {
+        if inner == 0 {
+            rem == 0
+        } else {
+            rem.msrv_is_multiple_of(inner) && rem / inner < inner_len
+        }
+    };
+    if outer_len == 1 {
+        return in_inner(offset);
+    }
+    // the outer indices whose inner dimension may reach the offset, of which there's only one
+    // unless the inner dimension spans more than the outer stride
+    let first = offset
+        .saturating_sub(inner * (inner_len - 1))
+        .div_ceil(outer);
+    let last = Ord::min(offset / outer, outer_len - 1);
+    (first..=last).any(|outer_idx| in_inner(offset - outer_idx * outer))
+}
+
+/// Checks that no element of the destination `(ptr, nrows, ncols, rs, cs)` shares its memory
+/// location with an element of the operand, since the destination is written while the operand
+/// is read. The destination must not overlap itself.
+pub(crate) fn try_check_no_aliasing<T>(
+    name: &'static str,
+    (dst, m, n, dst_rs, dst_cs): (*const T, usize, usize, isize, isize),
+    (src, nrows, ncols, rs, cs): (*const T, usize, usize, isize, isize),
+) -> Result<(), GemmError> {
+    let size = core::mem::size_of::<T>() as i128;
+    let (Some((dst_min, dst_max)), Some((src_min, src_max))) = (
+        offset_range("dst", m, n, dst_rs, dst_cs)?,
+        offset_range(name, nrows, ncols, rs, cs)?,
+    ) else {
+        return Ok(());
+    };
+    let aliasing = Err(GemmError::DstAliasing { operand: name });
+
+    // byte ranges spanned by the matrices, relative to the destination
+    let delta = (src as isize).wrapping_sub(dst as isize) as i128;
+    if size == 0
+        || delta + src_min as i128 * size >= (dst_max as i128 + 1) * size
+        || dst_min as i128 * size >= delta + (src_max as i128 + 1) * size
+    {
+        return Ok(());
+    }
+    // elements of the operand that straddle two elements of the destination
+    if delta % size != 0 {
+        return aliasing;
+    }
+    let delta = (delta / size) as isize;
+    for j in 0..ncols {
+        for i in 0..nrows {
+            let offset = delta + i as isize * rs + j as isize * cs - dst_min;
+            if contains_offset(m, n, dst_rs, dst_cs, offset) {
+                return aliasing;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Panicking version of [`try_check_bounds`].
+#[track_caller]
+pub(crate) fn check_bounds(
+    name: &'static str,
+    len: usize,
+    nrows: usize,
+    ncols: usize,
+    rs: isize,
+    cs: isize,
+) -> usize {
+    match try_check_bounds(name, len, nrows, ncols, rs, cs) {
+        Ok(offset) => offset,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Panicking version of [`try_check_no_self_overlap`].
+#[track_caller]
+pub(crate) fn check_no_self_overlap(nrows: usize, ncols: usize, rs: isize, cs: isize) {
+    if let Err(e) = try_check_no_self_overlap(nrows, ncols, rs, cs) {
+        panic!("{e}");
+    }
+}
+
+/// Safe version of [`gemm`] operating on slices.
+///
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Strides are given in elements and may be negative. Each matrix is laid out so that its
+/// lowest-addressed element is the first element of its slice, and all of its elements must fit
+/// inside the slice. `lhs` and `rhs` may use zero
+/// strides, but distinct elements of `dst` must not overlap. Since `dst` is borrowed mutably, it
+/// can't alias `lhs` or `rhs`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`, if any matrix
+/// doesn't fit in its slice, or if the strides of `dst` make distinct elements overlap.
+#[track_caller]
+pub fn gemm_slice<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: &mut [T],
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: &[T],
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: &[T],
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let dst_offset = check_bounds("dst", dst.len(), m, n, dst_rs, dst_cs);
+    let lhs_offset = check_bounds("lhs", lhs.len(), m, k, lhs_rs, lhs_cs);
+    let rhs_offset = check_bounds("rhs", rhs.len(), k, n, rhs_rs, rhs_cs);
+    check_no_self_overlap(m, n, dst_rs, dst_cs);
+
+    unsafe {
+        gemm(
+            m,
+            n,
+            k,
+            dst.as_mut_ptr().wrapping_add(dst_offset),
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs.as_ptr().wrapping_add(lhs_offset),
+            lhs_cs,
+            lhs_rs,
+            rhs.as_ptr().wrapping_add(rhs_offset),
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    }
+}
+
+/// Checked version of [`gemm_slice`].
+///
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Instead of panicking, returns an error if `T` is not supported, if any matrix doesn't fit in
+/// its slice, if the strides of `dst` make distinct elements overlap, or if `stack` is provided
+/// but can't hold the scratch memory given by [`gemm_req`]. Scratch memory is allocated when
+/// `stack` is `None`.
+pub fn try_gemm<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: &mut [T],
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: &[T],
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: &[T],
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    stack: Option<DynStack<'_>>,
+) -> Result<(), GemmError> {
+    let backend = try_get_backend::<T>().ok_or(GemmError::UnsupportedType)?;
+    let dst_offset = try_check_bounds("dst", dst.len(), m, n, dst_rs, dst_cs)?;
+    let lhs_offset = try_check_bounds("lhs", lhs.len(), m, k, lhs_rs, lhs_cs)?;
+    let rhs_offset = try_check_bounds("rhs", rhs.len(), k, n, rhs_rs, rhs_cs)?;
+    try_check_no_self_overlap(m, n, dst_rs, dst_cs)?;
+
+    if let Some(stack) = &stack {
+        let req = backend_req(backend, m, n, k, dst_cs, dst_rs, parallelism);
+        if !stack.can_hold(req) {
+            return Err(GemmError::ScratchTooSmall {
+                required: req.unaligned_bytes_required(),
+                available: stack.len_bytes(),
+            });
+        }
+    }
+
+    unsafe {
+        let dst = dst.as_mut_ptr().wrapping_add(dst_offset);
+        let lhs = lhs.as_ptr().wrapping_add(lhs_offset);
+        let rhs = rhs.as_ptr().wrapping_add(rhs_offset);
+
+        gemm_dispatch(
+            backend,
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+            GemmConfig {
+                stack,
+                ..Default::default()
+            },
+        )
+    }
+    Ok(())
+}
+
 #[inline(never)]
-#[cfg(test)]
-pub unsafe fn gemm_fallback<T>(
+pub(crate) unsafe fn gemm_fallback<T>(
     m: usize,
     n: usize,
     k: usize,
@@ -284,7 +1351,6 @@ pub unsafe fn gemm_fallback<T>(
 }
 
 #[inline(never)]
-#[cfg(test)]
 pub(crate) unsafe fn gemm_cplx_fallback<T>(
     m: usize,
     n: usize,
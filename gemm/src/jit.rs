@@ -0,0 +1,517 @@
+//! Microkernels generated at runtime for the shape of a [`GemmPlan`](crate::GemmPlan).
+//!
+//! The built-in microkernels have a fixed register blocking and run the `k` loop for any depth.
+//! The generated ones are emitted for the blocking given by [`JitBlocking`] and for the exact
+//! depth of the blocks of `k`, so that the loop has no remainder to check and the offsets of the
+//! packed operands are immediates, as in libxsmm. They use AVX2 and FMA, for `f32` and `f64`.
+//!
+//! The products are computed into a buffer of accumulators that holds the whole destination,
+//! which is then combined with it, so the generated kernels are meant for small and medium
+//! shapes that are multiplied repeatedly.
+
+use core::any::TypeId;
+use core::mem::size_of;
+use core::ops::{Add, Mul};
+use gemm_common::cache::DivCeil;
+use gemm_common::gemm::{max_threads, par_for_each};
+use gemm_common::{Parallelism, Ptr};
+use std::vec;
+use std::vec::Vec;
+
+/// Register blocking of the microkernels generated for a plan.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JitBlocking {
+    /// Rows of a tile, a multiple of the lanes of a vector: 8 for `f32`, 4 for `f64`.
+    pub mr: usize,
+    /// Columns of a tile.
+    pub nr: usize,
+    /// Steps of the `k` loop emitted per iteration.
+    pub unroll: usize,
+    /// Depth of the blocks of `k`, or `0` for the one chosen by the active backend.
+    pub kc: usize,
+}
+
+impl JitBlocking {
+    /// Blocking of the built-in fma microkernels for `T`, or `None` if `T` isn't `f32` or `f64`.
+    pub fn of<T: 'static>() -> Option<Self> {
+        lanes::<T>().map(|lanes| Self {
+            mr: 2 * lanes,
+            nr: 6,
+            unroll: 4,
+            kc: 0,
+        })
+    }
+
+    fn is_valid(&self, lanes: usize) -> bool {
+        let mr_div_n = self.mr / lanes;
+        // the accumulators, the lhs vectors and the broadcast rhs element live in 16 registers
+        self.mr != 0
+            && self.mr % lanes == 0
+            && self.nr != 0
+            && self.unroll != 0
+            && mr_div_n * self.nr + mr_div_n < 16
+    }
+}
+
+fn lanes<T: 'static>() -> Option<usize> {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        Some(4)
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        Some(8)
+    } else {
+        None
+    }
+}
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+
+/// Encoder for the few x86-64 instructions that the kernels are made of. The vector
+/// instructions all operate on ymm registers and the memory operands are a base register and a
+/// 32-bit displacement.
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    // three-byte VEX prefix, with `map` 1 for 0F and 2 for 0F38, and `pp` 0 for none and 1 for 66
+    fn vex(&mut self, map: u8, pp: u8, w: bool, reg: u8, vvvv: u8, rm: u8, opcode: u8) {
+        let r = (!reg >> 3) & 1;
+        let b = (!rm >> 3) & 1;
+        self.code.extend_from_slice(&[
+            0xc4,
+            (r << 7) | (1 << 6) | (b << 5) | map,
+            ((w as u8) << 7) | ((!vvvv & 15) << 3) | (1 << 2) | pp,
+            opcode,
+        ]);
+    }
+
+    fn modrm_reg(&mut self, reg: u8, rm: u8) {
+        self.code.push(0xc0 | ((reg & 7) << 3) | (rm & 7));
+    }
+
+    fn modrm_mem(&mut self, reg: u8, base: u8, disp: i32) {
+        self.code.push(0x80 | ((reg & 7) << 3) | base);
+        self.code.extend_from_slice(&disp.to_le_bytes());
+    }
+
+    fn vxorps(&mut self, dst: u8) {
+        self.vex(1, 0, false, dst, dst, dst, 0x57);
+        self.modrm_reg(dst, dst);
+    }
+
+    fn vmovups_load(&mut self, dst: u8, base: u8, disp: i32) {
+        self.vex(1, 0, false, dst, 0, base, 0x10);
+        self.modrm_mem(dst, base, disp);
+    }
+
+    fn vmovups_store(&mut self, base: u8, disp: i32, src: u8) {
+        self.vex(1, 0, false, src, 0, base, 0x11);
+        self.modrm_mem(src, base, disp);
+    }
+
+    fn vbroadcast(&mut self, f64: bool, dst: u8, base: u8, disp: i32) {
+        self.vex(2, 1, false, dst, 0, base, if f64 { 0x19 } else { 0x18 });
+        self.modrm_mem(dst, base, disp);
+    }
+
+    fn vfmadd231(&mut self, f64: bool, acc: u8, lhs: u8, rhs: u8) {
+        self.vex(2, 1, f64, acc, lhs, rhs, 0xb8);
+        self.modrm_reg(acc, rhs);
+    }
+
+    fn vadd_load(&mut self, f64: bool, dst: u8, base: u8, disp: i32) {
+        self.vex(1, f64 as u8, false, dst, dst, base, 0x58);
+        self.modrm_mem(dst, base, disp);
+    }
+
+    fn mov_imm(&mut self, dst: u8, imm: i32) {
+        self.code.extend_from_slice(&[0x48, 0xc7, 0xc0 | dst]);
+        self.code.extend_from_slice(&imm.to_le_bytes());
+    }
+
+    fn add_imm(&mut self, dst: u8, imm: i32) {
+        self.code.extend_from_slice(&[0x48, 0x81, 0xc0 | dst]);
+        self.code.extend_from_slice(&imm.to_le_bytes());
+    }
+
+    fn dec(&mut self, dst: u8) {
+        self.code.extend_from_slice(&[0x48, 0xff, 0xc8 | dst]);
+    }
+
+    fn test(&mut self, reg: u8) {
+        self.code
+            .extend_from_slice(&[0x48, 0x85, 0xc0 | (reg << 3) | reg]);
+    }
+
+    // jumps are 6 bytes long, with the offset relative to their end
+    fn jnz(&mut self, target: usize) {
+        let rel = target as i32 - (self.code.len() + 6) as i32;
+        self.code.extend_from_slice(&[0x0f, 0x85]);
+        self.code.extend_from_slice(&rel.to_le_bytes());
+    }
+
+    fn jz_forward(&mut self) -> usize {
+        self.code.extend_from_slice(&[0x0f, 0x84, 0, 0, 0, 0]);
+        self.code.len()
+    }
+
+    fn patch(&mut self, jump_end: usize) {
+        let rel = (self.code.len() - jump_end) as i32;
+        self.code[jump_end - 4..jump_end].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    fn ret(&mut self) {
+        // vzeroupper
+        self.code.extend_from_slice(&[0xc5, 0xf8, 0x77, 0xc3]);
+    }
+}
+
+/// Emits `acc := (accumulate ? acc : 0) + lhs×rhs` for an `mr×kc` lhs panel packed by columns,
+/// a `kc×nr` rhs panel packed by rows, and an `mr×nr` column-major tile of accumulators.
+fn emit_kernel(blocking: JitBlocking, kc: usize, f64: bool) -> Vec<u8> {
+    let JitBlocking { mr, nr, unroll, .. } = blocking;
+    let size = if f64 { 8 } else { 4 };
+    let lanes = 32 / size;
+    let mr_div_n = mr / lanes;
+    let accum = |i: usize, j: usize| (j * mr_div_n + i) as u8;
+    let lhs = |i: usize| (mr_div_n * nr + i) as u8;
+    let rhs = (mr_div_n * nr + mr_div_n) as u8;
+
+    let mut asm = Assembler { code: Vec::new() };
+    for j in 0..nr {
+        for i in 0..mr_div_n {
+            asm.vxorps(accum(i, j));
+        }
+    }
+
+    let step = |asm: &mut Assembler, u: usize| {
+        for i in 0..mr_div_n {
+            asm.vmovups_load(lhs(i), RDI, ((u * mr + i * lanes) * size) as i32);
+        }
+        for j in 0..nr {
+            asm.vbroadcast(f64, rhs, RSI, ((u * nr + j) * size) as i32);
+            for i in 0..mr_div_n {
+                asm.vfmadd231(f64, accum(i, j), lhs(i), rhs);
+            }
+        }
+    };
+
+    let iters = kc / unroll;
+    if iters != 0 {
+        asm.mov_imm(RAX, iters as i32);
+        let top = asm.code.len();
+        for u in 0..unroll {
+            step(&mut asm, u);
+        }
+        asm.add_imm(RDI, (unroll * mr * size) as i32);
+        asm.add_imm(RSI, (unroll * nr * size) as i32);
+        asm.dec(RAX);
+        asm.jnz(top);
+    }
+    for u in 0..kc % unroll {
+        step(&mut asm, u);
+    }
+
+    asm.test(RCX);
+    let skip_accumulate = asm.jz_forward();
+    for j in 0..nr {
+        for i in 0..mr_div_n {
+            asm.vadd_load(f64, accum(i, j), RDX, ((j * mr + i * lanes) * size) as i32);
+        }
+    }
+    asm.patch(skip_accumulate);
+    for j in 0..nr {
+        for i in 0..mr_div_n {
+            asm.vmovups_store(RDX, ((j * mr + i * lanes) * size) as i32, accum(i, j));
+        }
+    }
+    asm.ret();
+    asm.code
+}
+
+type KernelFn = unsafe extern "sysv64" fn(*const (), *const (), *mut (), usize);
+
+/// Generated code, mapped as executable.
+struct JitKernel {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+unsafe impl Send for JitKernel {}
+unsafe impl Sync for JitKernel {}
+
+impl JitKernel {
+    fn new(code: &[u8]) -> Option<Self> {
+        unsafe {
+            let len = code.len();
+            let ptr = libc::mmap(
+                core::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+            let kernel = Self { ptr, len };
+            core::ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, len);
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(kernel)
+        }
+    }
+
+    #[inline]
+    fn func(&self) -> KernelFn {
+        unsafe { core::mem::transmute::<*mut libc::c_void, KernelFn>(self.ptr) }
+    }
+}
+
+impl Drop for JitKernel {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// Generated kernels and buffers of a plan.
+pub(crate) struct JitGemm {
+    m: usize,
+    n: usize,
+    k: usize,
+    f64: bool,
+    blocking: JitBlocking,
+    kc: usize,
+    // for the blocks of depth `kc`, and for the last one if it's shallower
+    kernel: JitKernel,
+    tail_kernel: Option<JitKernel>,
+    // u64 storage keeps the elements aligned for both scalar types
+    packed_lhs: Vec<u64>,
+    packed_rhs: Vec<u64>,
+    accum: Vec<u64>,
+}
+
+impl JitGemm {
+    /// Kernels for an `m×n×k` product of `T` matrices, or `None` if `T` isn't `f32` or `f64`, the
+    /// machine doesn't support AVX2 and FMA, the problem is empty, or the blocking doesn't fit
+    /// the registers.
+    pub(crate) fn new<T: 'static>(
+        m: usize,
+        n: usize,
+        k: usize,
+        blocking: JitBlocking,
+        default_kc: usize,
+    ) -> Option<Self> {
+        let lanes = lanes::<T>()?;
+        if m == 0
+            || n == 0
+            || k == 0
+            || !blocking.is_valid(lanes)
+            || !std::is_x86_feature_detected!("avx2")
+            || !std::is_x86_feature_detected!("fma")
+        {
+            return None;
+        }
+        let f64 = lanes == 4;
+        let kc = Ord::min(
+            if blocking.kc == 0 {
+                Ord::max(default_kc, 1)
+            } else {
+                blocking.kc
+            },
+            k,
+        );
+
+        let kernel = JitKernel::new(&emit_kernel(blocking, kc, f64))?;
+        let tail_kernel = if k % kc != 0 {
+            Some(JitKernel::new(&emit_kernel(blocking, k % kc, f64))?)
+        } else {
+            None
+        };
+
+        let (mr, nr) = (blocking.mr, blocking.nr);
+        let row_tiles = m.msrv_div_ceil(mr);
+        let col_tiles = n.msrv_div_ceil(nr);
+        let words = |len: usize| (len * size_of::<T>()).msrv_div_ceil(8);
+        Some(Self {
+            m,
+            n,
+            k,
+            f64,
+            blocking,
+            kc,
+            kernel,
+            tail_kernel,
+            packed_lhs: vec![0; words(row_tiles * mr * kc)],
+            packed_rhs: vec![0; words(col_tiles * nr * kc)],
+            accum: vec![0; words(row_tiles * col_tiles * mr * nr)],
+        })
+    }
+
+    /// dst := alpha×dst + beta×lhs×rhs
+    ///
+    /// # Safety
+    ///
+    /// `T` is the type the kernels were generated for, and the matrices satisfy the requirements
+    /// of [`gemm`](crate::gemm()).
+    pub(crate) unsafe fn execute<T: 'static>(
+        &mut self,
+        dst: *mut T,
+        dst_cs: isize,
+        dst_rs: isize,
+        read_dst: bool,
+        lhs: *const T,
+        lhs_cs: isize,
+        lhs_rs: isize,
+        rhs: *const T,
+        rhs_cs: isize,
+        rhs_rs: isize,
+        alpha: T,
+        beta: T,
+        parallelism: Parallelism<'_>,
+    ) {
+        if self.f64 {
+            self.execute_impl::<f64>(
+                dst as *mut f64,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs as *const f64,
+                lhs_cs,
+                lhs_rs,
+                rhs as *const f64,
+                rhs_cs,
+                rhs_rs,
+                *(&alpha as *const T as *const f64),
+                *(&beta as *const T as *const f64),
+                parallelism,
+            )
+        } else {
+            self.execute_impl::<f32>(
+                dst as *mut f32,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs as *const f32,
+                lhs_cs,
+                lhs_rs,
+                rhs as *const f32,
+                rhs_cs,
+                rhs_rs,
+                *(&alpha as *const T as *const f32),
+                *(&beta as *const T as *const f32),
+                parallelism,
+            )
+        }
+    }
+
+    unsafe fn execute_impl<T: Copy + Default + PartialEq + Add<Output = T> + Mul<Output = T>>(
+        &mut self,
+        dst: *mut T,
+        dst_cs: isize,
+        dst_rs: isize,
+        read_dst: bool,
+        lhs: *const T,
+        lhs_cs: isize,
+        lhs_rs: isize,
+        rhs: *const T,
+        rhs_cs: isize,
+        rhs_rs: isize,
+        alpha: T,
+        beta: T,
+        parallelism: Parallelism<'_>,
+    ) {
+        let Self { m, n, k, kc, .. } = *self;
+        let JitBlocking { mr, nr, .. } = self.blocking;
+        let row_tiles = m.msrv_div_ceil(mr);
+        let col_tiles = n.msrv_div_ceil(nr);
+        let n_tiles = row_tiles * col_tiles;
+        let n_threads = Ord::min(max_threads(parallelism), n_tiles);
+
+        let packed_lhs = self.packed_lhs.as_mut_ptr() as *mut T;
+        let packed_rhs = self.packed_rhs.as_mut_ptr() as *mut T;
+        let accum = Ptr(self.accum.as_mut_ptr() as *mut T);
+
+        let mut depth = 0;
+        while depth < k {
+            let k_chunk = Ord::min(kc, k - depth);
+            let kernel = if k_chunk == kc {
+                self.kernel.func()
+            } else {
+                self.tail_kernel.as_ref().unwrap().func()
+            };
+
+            for tile in 0..row_tiles {
+                let packed = packed_lhs.add(tile * mr * kc);
+                for depth_inner in 0..k_chunk {
+                    let col = lhs.offset((depth + depth_inner) as isize * lhs_cs);
+                    for row in 0..mr {
+                        let i = tile * mr + row;
+                        *packed.add(depth_inner * mr + row) = if i < m {
+                            *col.offset(i as isize * lhs_rs)
+                        } else {
+                            T::default()
+                        };
+                    }
+                }
+            }
+            for tile in 0..col_tiles {
+                let packed = packed_rhs.add(tile * nr * kc);
+                for depth_inner in 0..k_chunk {
+                    let row = rhs.offset((depth + depth_inner) as isize * rhs_rs);
+                    for col in 0..nr {
+                        let j = tile * nr + col;
+                        *packed.add(depth_inner * nr + col) = if j < n {
+                            *row.offset(j as isize * rhs_cs)
+                        } else {
+                            T::default()
+                        };
+                    }
+                }
+            }
+
+            let packed_lhs = Ptr(packed_lhs);
+            let packed_rhs = Ptr(packed_rhs);
+            let accumulate = (depth != 0) as usize;
+            par_for_each(parallelism, n_threads, move |tid| {
+                let start = n_tiles * tid / n_threads;
+                let end = n_tiles * (tid + 1) / n_threads;
+                for tile in start..end {
+                    let (col_tile, row_tile) = (tile / row_tiles, tile % row_tiles);
+                    kernel(
+                        { packed_lhs }.0.add(row_tile * mr * kc) as *const (),
+                        { packed_rhs }.0.add(col_tile * nr * kc) as *const (),
+                        { accum }.0.add(tile * mr * nr) as *mut (),
+                        accumulate,
+                    );
+                }
+            });
+            depth += k_chunk;
+        }
+
+        let zero = T::default();
+        for j in 0..n {
+            let (col_tile, col) = (j / nr, j % nr);
+            for i in 0..m {
+                let (row_tile, row) = (i / mr, i % mr);
+                let acc = *accum
+                    .0
+                    .add((col_tile * row_tiles + row_tile) * mr * nr + col * mr + row);
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = if !read_dst || alpha == zero {
+                    beta * acc
+                } else {
+                    alpha * *dst + beta * acc
+                };
+            }
+        }
+    }
+}
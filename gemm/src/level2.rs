@@ -0,0 +1,198 @@
+use crate::gemm::{gemm_with_backend, get_backend, is_complex, is_transposed};
+use crate::Parallelism;
+use gemm_common::{
+    cache::DivCeil,
+    gemm::{max_threads, threading_threshold, GemmConfig},
+    Ptr,
+};
+
+/// Smallest number of destination rows handled by one thread in [`gemv`].
+const GEMV_ROW_BLOCK: usize = 64;
+/// Smallest number of destination columns handled by one thread in [`ger`].
+const GER_COL_BLOCK: usize = 16;
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Matrix-vector product, where `dst` has `m` elements, `lhs` is `m×k` and `rhs` has `k`
+/// elements. Strides are given in elements.
+///
+/// The rows of `dst` are split between the threads allowed by `parallelism`, and each block is
+/// computed by the vectorized kernels for column-major or row-major `lhs`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm) with `n == 1`.
+pub unsafe fn gemv<T: 'static + Copy + Send + Sync>(
+    m: usize,
+    k: usize,
+    mut dst: *mut T,
+    mut dst_rs: isize,
+    read_dst: bool,
+    mut lhs: *const T,
+    lhs_cs: isize,
+    mut lhs_rs: isize,
+    rhs: *const T,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let backend = get_backend::<T>();
+
+    if dst_rs < 0 && m > 0 {
+        dst = dst.wrapping_offset((m - 1) as isize * dst_rs);
+        dst_rs = -dst_rs;
+        lhs = lhs.wrapping_offset((m - 1) as isize * lhs_rs);
+        lhs_rs = -lhs_rs;
+    }
+
+    let (conj_dst, conj_lhs, conj_rhs) = if is_complex::<T>() {
+        (conj_dst, conj_lhs, conj_rhs)
+    } else {
+        (false, false, false)
+    };
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    let n_threads = if m.saturating_mul(k) < threading_threshold::<T>(backend.simd_bytes) {
+        1
+    } else {
+        Ord::min(max_threads(parallelism), m.msrv_div_ceil(GEMV_ROW_BLOCK))
+    };
+    let rows_per_thread = m.msrv_div_ceil(Ord::max(n_threads, 1));
+
+    let rows = |tid: usize| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, lhs, rhs) = (dst, lhs, rhs);
+        let start = Ord::min(tid * rows_per_thread, m);
+        let end = Ord::min(start + rows_per_thread, m);
+        (backend.gemm)(
+            end - start,
+            1,
+            k,
+            dst.wrapping_offset(start as isize * dst_rs).0,
+            0,
+            dst_rs,
+            read_dst,
+            lhs.wrapping_offset(start as isize * lhs_rs).0,
+            lhs_cs,
+            lhs_rs,
+            rhs.0,
+            0,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            Parallelism::None,
+            GemmConfig::default(),
+        )
+    };
+
+    if n_threads <= 1 {
+        rows(0);
+    } else {
+        gemm_common::gemm::par_for_each(parallelism, n_threads, rows);
+    }
+}
+
+/// dst := alpha×dst + beta×x×yᵀ
+///
+/// Rank-1 update, where `dst` is `m×n`, `x` has `m` elements and `y` has `n` elements. Strides
+/// are given in elements, and `incx`/`incy` may be negative or zero.
+///
+/// The destination is split into blocks along its outer dimension, which are distributed between
+/// the threads allowed by `parallelism`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm) with `k == 1`.
+pub unsafe fn ger<T: 'static + Copy + Send + Sync>(
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    x: *const T,
+    incx: isize,
+    y: *const T,
+    incy: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_x: bool,
+    conj_y: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let backend = get_backend::<T>();
+
+    // split along the columns of a column-major destination
+    let (m, n, dst_cs, dst_rs, x, incx, y, incy, conj_x, conj_y) = if is_transposed(dst_cs, dst_rs)
+    {
+        (n, m, dst_rs, dst_cs, y, incy, x, incx, conj_y, conj_x)
+    } else {
+        (m, n, dst_cs, dst_rs, x, incx, y, incy, conj_x, conj_y)
+    };
+
+    let dst = Ptr(dst);
+    let x = Ptr(x as *mut T);
+    let y = Ptr(y as *mut T);
+
+    let n_threads = if m.saturating_mul(n) < threading_threshold::<T>(backend.simd_bytes) {
+        1
+    } else {
+        Ord::min(max_threads(parallelism), n.msrv_div_ceil(GER_COL_BLOCK))
+    };
+    let cols_per_thread = n.msrv_div_ceil(Ord::max(n_threads, 1));
+
+    let cols = |tid: usize| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, x, y) = (dst, x, y);
+        let start = Ord::min(tid * cols_per_thread, n);
+        let end = Ord::min(start + cols_per_thread, n);
+        gemm_with_backend(
+            backend,
+            m,
+            end - start,
+            1,
+            dst.wrapping_offset(start as isize * dst_cs).0,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            x.0,
+            0,
+            incx,
+            y.wrapping_offset(start as isize * incy).0,
+            incy,
+            0,
+            alpha,
+            beta,
+            conj_dst,
+            conj_x,
+            conj_y,
+            Parallelism::None,
+            |_| GemmConfig::default(),
+        )
+    };
+
+    if n_threads <= 1 {
+        cols(0);
+    } else {
+        gemm_common::gemm::par_for_each(parallelism, n_threads, cols);
+    }
+}
@@ -0,0 +1,794 @@
+use crate::gemm::{gemm_with_backend, get_backend, is_complex, GemmBackend};
+use crate::Parallelism;
+use core::ops::{Div, Mul, Neg};
+use gemm_common::gemm::{Conj, GemmConfig};
+use num_traits::One;
+
+/// Triangle of a square matrix that is read or written by a structured routine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Triangle {
+    /// The diagonal and the elements below it.
+    Lower,
+    /// The diagonal and the elements above it.
+    Upper,
+}
+
+/// Size below which triangular blocks are computed one column at a time.
+const TRIANGLE_BLOCK: usize = 32;
+
+/// Lower triangle of dst := alpha×dst + beta×lhs×lhsᵀ, splitting the triangle recursively into
+/// two smaller triangles and a rectangular block.
+unsafe fn syrk_lower<T: 'static + Copy>(
+    backend: &GemmBackend<T>,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let gemm = |m: usize, n: usize, dst: *mut T, lhs: *const T, rhs: *const T| {
+        gemm_with_backend(
+            backend,
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            // the rhs is the transpose of the lhs
+            rhs,
+            lhs_rs,
+            lhs_cs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+            |_| GemmConfig::default(),
+        )
+    };
+
+    if n <= TRIANGLE_BLOCK {
+        for j in 0..n {
+            let j_ = j as isize;
+            let lhs_j = lhs.wrapping_offset(j_ * lhs_rs);
+            gemm(
+                n - j,
+                1,
+                dst.wrapping_offset(j_ * dst_rs + j_ * dst_cs),
+                lhs_j,
+                lhs_j,
+            );
+        }
+        return;
+    }
+
+    let n1 = n / 2;
+    let n1_ = n1 as isize;
+    let lhs2 = lhs.wrapping_offset(n1_ * lhs_rs);
+
+    syrk_lower(
+        backend,
+        n1,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+    );
+    gemm(n - n1, n1, dst.wrapping_offset(n1_ * dst_rs), lhs2, lhs);
+    syrk_lower(
+        backend,
+        n - n1,
+        k,
+        dst.wrapping_offset(n1_ * dst_rs + n1_ * dst_cs),
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs2,
+        lhs_cs,
+        lhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+    );
+}
+
+/// dst := alpha×dst + beta×lhs×lhsᵀ, on the `triangle` of `dst` only
+///
+/// Symmetric rank-k update, where `dst` is `n×n` and `lhs` is `n×k`. Only the elements of the
+/// given triangle of `dst` are computed, read and written, which skips about half of the work of
+/// the equivalent [`gemm`](crate::gemm) call. For complex types, `conj_rhs` gives the Hermitian
+/// update with lhsᴴ.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm), where the rhs is the transpose of `lhs`.
+pub unsafe fn syrk<T: 'static + Copy>(
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    alpha: T,
+    beta: T,
+    triangle: Triangle,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let backend = get_backend::<T>();
+    match triangle {
+        Triangle::Lower => syrk_lower(
+            backend,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        ),
+        // the upper triangle of dst is the lower triangle of dstᵀ := alpha×dstᵀ + beta×lhs×lhsᵀ,
+        // with the conjugations swapped
+        Triangle::Upper => syrk_lower(
+            backend,
+            n,
+            k,
+            dst,
+            dst_rs,
+            dst_cs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_rhs,
+            conj_lhs,
+            parallelism,
+        ),
+    }
+}
+
+/// Arguments of [`trmm`] that are shared by every block of the recursion.
+struct Trmm<'a, T: 'static> {
+    backend: &'a GemmBackend<T>,
+    n: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    beta: T,
+    one: T,
+    triangle: Triangle,
+    unit_diagonal: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'a>,
+}
+
+impl<T: 'static + Copy> Trmm<'_, T> {
+    /// dst := alpha×dst + beta×lhs×rhs, with an `m×k` block of the triangular operand.
+    #[inline]
+    unsafe fn gemm(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut T,
+        read_dst: bool,
+        lhs: *const T,
+        lhs_cs: isize,
+        rhs: *const T,
+        alpha: T,
+        beta: T,
+        conj_dst: bool,
+    ) {
+        gemm_with_backend(
+            self.backend,
+            m,
+            self.n,
+            k,
+            dst,
+            self.dst_cs,
+            self.dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            self.lhs_rs,
+            rhs,
+            self.rhs_cs,
+            self.rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            self.conj_lhs,
+            self.conj_rhs,
+            self.parallelism,
+            |_| GemmConfig::default(),
+        )
+    }
+
+    /// dst := alpha×dst + beta×tri(lhs)×rhs, where `lhs` is an `m×m` diagonal block of the
+    /// triangular operand, split recursively so that its zero blocks are skipped.
+    unsafe fn apply(
+        &self,
+        m: usize,
+        dst: *mut T,
+        read_dst: bool,
+        lhs: *const T,
+        rhs: *const T,
+        alpha: T,
+        conj_dst: bool,
+    ) {
+        let Self {
+            dst_rs,
+            lhs_cs,
+            lhs_rs,
+            rhs_rs,
+            beta,
+            one,
+            ..
+        } = *self;
+
+        if m <= TRIANGLE_BLOCK {
+            for i in 0..m {
+                let i_ = i as isize;
+                let dst_i = dst.wrapping_offset(i_ * dst_rs);
+                let lhs_i = lhs.wrapping_offset(i_ * lhs_rs);
+                // columns of the i-th row of the triangle that are read
+                let (start, end) = match (self.triangle, self.unit_diagonal) {
+                    (Triangle::Lower, false) => (0, i + 1),
+                    (Triangle::Lower, true) => (0, i),
+                    (Triangle::Upper, false) => (i, m),
+                    (Triangle::Upper, true) => (i + 1, m),
+                };
+                let start_ = start as isize;
+                self.gemm(
+                    1,
+                    end - start,
+                    dst_i,
+                    read_dst,
+                    lhs_i.wrapping_offset(start_ * lhs_cs),
+                    lhs_cs,
+                    rhs.wrapping_offset(start_ * rhs_rs),
+                    alpha,
+                    beta,
+                    conj_dst,
+                );
+                if self.unit_diagonal {
+                    // the diagonal isn't read, so multiply the row of rhs by a standalone one
+                    self.gemm(
+                        1,
+                        1,
+                        dst_i,
+                        true,
+                        &one,
+                        0,
+                        rhs.wrapping_offset(i_ * rhs_rs),
+                        one,
+                        beta,
+                        false,
+                    );
+                }
+            }
+            return;
+        }
+
+        let m1 = m / 2;
+        let m1_ = m1 as isize;
+        let dst2 = dst.wrapping_offset(m1_ * dst_rs);
+        let rhs2 = rhs.wrapping_offset(m1_ * rhs_rs);
+        let lhs22 = lhs.wrapping_offset(m1_ * lhs_rs + m1_ * lhs_cs);
+
+        match self.triangle {
+            Triangle::Lower => {
+                // dst1 = T11×rhs1, dst2 = T21×rhs1 + T22×rhs2
+                self.apply(m1, dst, read_dst, lhs, rhs, alpha, conj_dst);
+                self.gemm(
+                    m - m1,
+                    m1,
+                    dst2,
+                    read_dst,
+                    lhs.wrapping_offset(m1_ * lhs_rs),
+                    lhs_cs,
+                    rhs,
+                    alpha,
+                    beta,
+                    conj_dst,
+                );
+                self.apply(m - m1, dst2, true, lhs22, rhs2, one, false);
+            }
+            Triangle::Upper => {
+                // dst1 = T11×rhs1 + T12×rhs2, dst2 = T22×rhs2
+                self.gemm(
+                    m1,
+                    m - m1,
+                    dst,
+                    read_dst,
+                    lhs.wrapping_offset(m1_ * lhs_cs),
+                    lhs_cs,
+                    rhs2,
+                    alpha,
+                    beta,
+                    conj_dst,
+                );
+                self.apply(m1, dst, true, lhs, rhs, one, false);
+                self.apply(m - m1, dst2, read_dst, lhs22, rhs2, alpha, conj_dst);
+            }
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×tri(lhs)×rhs
+///
+/// Triangular matrix multiply, where `dst` is `m×n`, `lhs` is `m×m` and `rhs` is `m×n`. Only the
+/// `triangle` of `lhs` is read, and the blocks of zeros outside of it are skipped. If
+/// `unit_diagonal` is true, the diagonal of `lhs` isn't read either and is assumed to be all
+/// ones. The transposed triangle is used by swapping the strides of `lhs` and the triangle.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm), where `lhs` only needs to be valid on its
+/// `triangle`. `dst` must not overlap `rhs`.
+pub unsafe fn trmm<T: 'static + Copy + One>(
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    triangle: Triangle,
+    unit_diagonal: bool,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    Trmm {
+        backend: get_backend::<T>(),
+        n,
+        dst_cs,
+        dst_rs,
+        lhs_cs,
+        lhs_rs,
+        rhs_cs,
+        rhs_rs,
+        beta,
+        one: T::one(),
+        triangle,
+        unit_diagonal,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+    }
+    .apply(m, dst, read_dst, lhs, rhs, alpha, conj_dst)
+}
+
+/// Arguments of [`trsm`] that are shared by every block of the recursion.
+struct Trsm<'a, T: 'static> {
+    backend: &'a GemmBackend<T>,
+    n: usize,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    one: T,
+    triangle: Triangle,
+    unit_diagonal: bool,
+    conj_lhs: bool,
+    parallelism: Parallelism<'a>,
+}
+
+impl<T> Trsm<'_, T>
+where
+    T: 'static + Conj + One + Neg<Output = T> + Mul<Output = T> + Div<Output = T>,
+{
+    /// dst := dst - lhs×rhs, where `dst` and `rhs` are disjoint rows of the right-hand sides,
+    /// and `lhs` is an `m×k` block of the triangular operand.
+    #[inline]
+    unsafe fn update(&self, m: usize, k: usize, dst: *mut T, lhs: *const T, rhs: *const T) {
+        gemm_with_backend(
+            self.backend,
+            m,
+            self.n,
+            k,
+            dst,
+            self.rhs_cs,
+            self.rhs_rs,
+            true,
+            lhs,
+            self.lhs_cs,
+            self.lhs_rs,
+            rhs,
+            self.rhs_cs,
+            self.rhs_rs,
+            self.one,
+            -self.one,
+            false,
+            self.conj_lhs,
+            false,
+            self.parallelism,
+            |_| GemmConfig::default(),
+        )
+    }
+
+    /// Solves tri(lhs)×x = rhs in place, where `lhs` is an `m×m` diagonal block of the
+    /// triangular operand, split recursively into two smaller triangular solves and an update.
+    unsafe fn solve(&self, m: usize, lhs: *const T, rhs: *mut T) {
+        let Self {
+            lhs_cs,
+            lhs_rs,
+            rhs_cs,
+            rhs_rs,
+            one,
+            ..
+        } = *self;
+
+        if m <= TRIANGLE_BLOCK {
+            // substitution, one row at a time
+            for idx in 0..m {
+                let i = match self.triangle {
+                    Triangle::Lower => idx,
+                    Triangle::Upper => m - 1 - idx,
+                };
+                let i_ = i as isize;
+                let rhs_i = rhs.wrapping_offset(i_ * rhs_rs);
+                let lhs_i = lhs.wrapping_offset(i_ * lhs_rs);
+
+                // rows that are already solved
+                let start = match self.triangle {
+                    Triangle::Lower => 0,
+                    Triangle::Upper => i + 1,
+                };
+                let start_ = start as isize;
+                self.update(
+                    1,
+                    idx,
+                    rhs_i,
+                    lhs_i.wrapping_offset(start_ * lhs_cs),
+                    rhs.wrapping_offset(start_ * rhs_rs),
+                );
+
+                if !self.unit_diagonal {
+                    let diag = *lhs_i.wrapping_offset(i_ * lhs_cs);
+                    let diag = if self.conj_lhs { diag.conj() } else { diag };
+                    let inv = one / diag;
+                    for j in 0..self.n {
+                        let x = rhs_i.wrapping_offset(j as isize * rhs_cs);
+                        *x = inv * *x;
+                    }
+                }
+            }
+            return;
+        }
+
+        let m1 = m / 2;
+        let m1_ = m1 as isize;
+        let rhs2 = rhs.wrapping_offset(m1_ * rhs_rs);
+        let lhs22 = lhs.wrapping_offset(m1_ * lhs_rs + m1_ * lhs_cs);
+
+        match self.triangle {
+            Triangle::Lower => {
+                self.solve(m1, lhs, rhs);
+                self.update(m - m1, m1, rhs2, lhs.wrapping_offset(m1_ * lhs_rs), rhs);
+                self.solve(m - m1, lhs22, rhs2);
+            }
+            Triangle::Upper => {
+                self.solve(m - m1, lhs22, rhs2);
+                self.update(m1, m - m1, rhs, lhs.wrapping_offset(m1_ * lhs_cs), rhs2);
+                self.solve(m1, lhs, rhs);
+            }
+        }
+    }
+}
+
+/// Solves tri(lhs)×x = rhs, overwriting `rhs` with `x`
+///
+/// Triangular solve with multiple right-hand sides, where `lhs` is `m×m` and `rhs` is `m×n`.
+/// Only the `triangle` of `lhs` is read, and if `unit_diagonal` is true, its diagonal isn't read
+/// either and is assumed to be all ones. The transposed triangle is used by swapping the strides
+/// of `lhs` and the triangle.
+///
+/// The triangle is split recursively, so that most of the work is done by [`gemm`](crate::gemm)
+/// updates, and only small diagonal blocks are solved by substitution.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// `lhs` must be valid for reads on its `triangle`, and `rhs` must be valid for reads and writes.
+/// The matrices must not overlap, and distinct elements of `rhs` must not share the same memory
+/// location.
+pub unsafe fn trsm<T>(
+    m: usize,
+    n: usize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    triangle: Triangle,
+    unit_diagonal: bool,
+    rhs: *mut T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    conj_lhs: bool,
+    parallelism: Parallelism<'_>,
+) where
+    T: 'static + Conj + One + Neg<Output = T> + Mul<Output = T> + Div<Output = T>,
+{
+    Trsm {
+        backend: get_backend::<T>(),
+        n,
+        lhs_cs,
+        lhs_rs,
+        rhs_cs,
+        rhs_rs,
+        one: T::one(),
+        triangle,
+        unit_diagonal,
+        conj_lhs: conj_lhs && is_complex::<T>(),
+        parallelism,
+    }
+    .solve(m, lhs, rhs)
+}
+
+/// Arguments of [`symm`] that are shared by every block of the recursion.
+struct Symm<'a, T: 'static> {
+    backend: &'a GemmBackend<T>,
+    n: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    beta: T,
+    one: T,
+    triangle: Triangle,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'a>,
+}
+
+impl<T: 'static + Copy> Symm<'_, T> {
+    /// dst := alpha×dst + beta×lhs×rhs, with an `m×k` block of the symmetric operand.
+    #[inline]
+    unsafe fn gemm(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut T,
+        read_dst: bool,
+        (lhs, lhs_cs, lhs_rs): (*const T, isize, isize),
+        rhs: *const T,
+        alpha: T,
+        conj_dst: bool,
+    ) {
+        gemm_with_backend(
+            self.backend,
+            m,
+            self.n,
+            k,
+            dst,
+            self.dst_cs,
+            self.dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            self.rhs_cs,
+            self.rhs_rs,
+            alpha,
+            self.beta,
+            conj_dst,
+            self.conj_lhs,
+            self.conj_rhs,
+            self.parallelism,
+            |_| GemmConfig::default(),
+        )
+    }
+
+    /// dst := alpha×dst + beta×sym(lhs)×rhs, where `lhs` is an `m×m` diagonal block of the
+    /// symmetric operand. The blocks outside of the stored triangle are read from their mirror
+    /// image by swapping the strides.
+    unsafe fn apply(
+        &self,
+        m: usize,
+        dst: *mut T,
+        read_dst: bool,
+        lhs: *const T,
+        rhs: *const T,
+        alpha: T,
+        conj_dst: bool,
+    ) {
+        let Self {
+            dst_rs,
+            lhs_cs,
+            lhs_rs,
+            rhs_rs,
+            one,
+            ..
+        } = *self;
+
+        if m <= TRIANGLE_BLOCK {
+            for i in 0..m {
+                let i_ = i as isize;
+                let dst_i = dst.wrapping_offset(i_ * dst_rs);
+                // the i-th row is stored in the triangle up to (or from) the diagonal, and the
+                // rest of it is stored in the i-th column
+                let (row_start, row_end, col_start, col_end) = match self.triangle {
+                    Triangle::Lower => (0, i + 1, i + 1, m),
+                    Triangle::Upper => (i, m, 0, i),
+                };
+                let (row_start_, col_start_) = (row_start as isize, col_start as isize);
+                self.gemm(
+                    1,
+                    row_end - row_start,
+                    dst_i,
+                    read_dst,
+                    (
+                        lhs.wrapping_offset(i_ * lhs_rs + row_start_ * lhs_cs),
+                        lhs_cs,
+                        lhs_rs,
+                    ),
+                    rhs.wrapping_offset(row_start_ * rhs_rs),
+                    alpha,
+                    conj_dst,
+                );
+                self.gemm(
+                    1,
+                    col_end - col_start,
+                    dst_i,
+                    true,
+                    (
+                        lhs.wrapping_offset(col_start_ * lhs_rs + i_ * lhs_cs),
+                        lhs_rs,
+                        lhs_cs,
+                    ),
+                    rhs.wrapping_offset(col_start_ * rhs_rs),
+                    one,
+                    false,
+                );
+            }
+            return;
+        }
+
+        let m1 = m / 2;
+        let m1_ = m1 as isize;
+        let dst2 = dst.wrapping_offset(m1_ * dst_rs);
+        let rhs2 = rhs.wrapping_offset(m1_ * rhs_rs);
+        let lhs22 = lhs.wrapping_offset(m1_ * lhs_rs + m1_ * lhs_cs);
+        let (lhs12, lhs21) = match self.triangle {
+            Triangle::Lower => {
+                let lhs21 = lhs.wrapping_offset(m1_ * lhs_rs);
+                ((lhs21, lhs_rs, lhs_cs), (lhs21, lhs_cs, lhs_rs))
+            }
+            Triangle::Upper => {
+                let lhs12 = lhs.wrapping_offset(m1_ * lhs_cs);
+                ((lhs12, lhs_cs, lhs_rs), (lhs12, lhs_rs, lhs_cs))
+            }
+        };
+
+        // dst1 = A11×rhs1 + A12×rhs2, dst2 = A21×rhs1 + A22×rhs2
+        self.apply(m1, dst, read_dst, lhs, rhs, alpha, conj_dst);
+        self.gemm(m1, m - m1, dst, true, lhs12, rhs2, one, false);
+        self.gemm(m - m1, m1, dst2, read_dst, lhs21, rhs, alpha, conj_dst);
+        self.apply(m - m1, dst2, true, lhs22, rhs2, one, false);
+    }
+}
+
+/// dst := alpha×dst + beta×sym(lhs)×rhs
+///
+/// Symmetric matrix multiply, where `dst` is `m×n`, `lhs` is `m×m` and `rhs` is `m×n`. Only the
+/// `triangle` of `lhs` is read, and the blocks of the other triangle are read from their mirror
+/// image in the stored one, so that the symmetric operand is never copied.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm), where `lhs` only needs to be valid on its
+/// `triangle`.
+pub unsafe fn symm<T: 'static + Copy + One>(
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    triangle: Triangle,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    Symm {
+        backend: get_backend::<T>(),
+        n,
+        dst_cs,
+        dst_rs,
+        lhs_cs,
+        lhs_rs,
+        rhs_cs,
+        rhs_rs,
+        beta,
+        one: T::one(),
+        triangle,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+    }
+    .apply(m, dst, read_dst, lhs, rhs, alpha, conj_dst)
+}
@@ -6,19 +6,142 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![warn(rust_2018_idioms)]
 
+/// Emits a `tracing` event of the given level with the `tracing` feature, and nothing otherwise.
+macro_rules! trace_event {
+    ($level: ident, $($arg: tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)*);
+    };
+}
+
+#[cfg(feature = "std")]
+mod autotune;
+mod batch;
+mod bound;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod compensated;
+mod conv;
+mod describe;
+mod double_double;
+mod epilogue;
+mod error;
+mod fixed;
 mod gemm;
+#[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+mod jit;
+mod level2;
+mod level3;
+mod mat;
+#[cfg(feature = "std")]
+mod offload;
+mod pack;
+mod plan;
+mod reference;
+mod split_k;
+#[cfg(feature = "strassen")]
+mod strassen;
+mod strict;
+#[cfg(feature = "std")]
+mod verify;
+#[cfg(feature = "std")]
+mod weight_cache;
 
+#[cfg(feature = "std")]
+pub use crate::autotune::autotune;
+pub use crate::batch::{gemm_batched, gemm_grouped, gemm_strided_batched, GemmProblem};
+pub use crate::bound::{error_bound, ErrorBound};
+pub use crate::compensated::gemm_with_compensated_summation;
+pub use crate::conv::{conv2d, Conv2dShape};
+pub use crate::describe::{describe, DType, GemmDescription};
+pub use crate::double_double::gemm_double_double;
+pub use crate::epilogue::{gemm_epilogue, gemm_tile_epilogue, Activation, Epilogue, Tile};
+pub use crate::error::GemmError;
+pub use crate::fixed::gemm_fixed;
 #[cfg(feature = "f16")]
 pub use crate::gemm::f16;
-pub use crate::gemm::{c32, c64, gemm};
-pub use gemm_common::Parallelism;
+#[cfg(feature = "std")]
+pub use crate::gemm::gemm_alloc;
+pub use crate::gemm::{
+    active_backend_name, c32, c64, for_each_builtin_backend, gemm, gemm_req, gemm_slice,
+    gemm_with_config, register_backend, reset_backend, try_gemm, GemmBackend, GemmFn, PackFn,
+};
+#[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+pub use crate::jit::JitBlocking;
+pub use crate::level2::{gemv, ger};
+pub use crate::level3::{symm, syrk, trmm, trsm, Triangle};
+pub use crate::mat::{gemm_mat, gemm_op, MatMut, MatRef, Op};
+#[cfg(feature = "std")]
+pub use crate::offload::{gemm_async, GemmFuture};
+pub use crate::pack::{gemm_prepacked, pack_lhs, pack_rhs, Lhs, PackedLhs, PackedRhs, Rhs};
+pub use crate::plan::GemmPlan;
+pub use crate::reference::gemm_reference;
+#[cfg(feature = "strassen")]
+pub use crate::strassen::{
+    get_strassen_threshold, set_strassen_threshold, DEFAULT_STRASSEN_THRESHOLD,
+};
+pub use crate::strict::gemm_with_strict_arithmetic;
+#[cfg(feature = "std")]
+pub use crate::verify::{verify, BackendDivergence};
+#[cfg(feature = "std")]
+pub use crate::weight_cache::{
+    clear_weight_cache, get_weight_cache_capacity, set_weight_cache_capacity,
+    DEFAULT_WEIGHT_CACHE_CAPACITY,
+};
+pub use gemm_common::cache::{
+    get_kernel_params_override, set_kernel_params_override, KC_ENV, MC_ENV, NC_ENV,
+};
+pub use gemm_common::{cache::KernelParams, Parallelism};
 
+#[cfg(all(feature = "std", feature = "rayon"))]
+pub use gemm_common::affinity::pinned_thread_pool;
+#[cfg(feature = "std")]
+pub use gemm_common::affinity::{
+    available_cores, cgroup_cpu_quota, current_cores, parse_core_list, parse_cpu_max,
+    pin_current_thread, PIN_THREADS_ENV,
+};
+pub use gemm_common::denormals::{
+    get_flush_denormals, set_flush_denormals, thread_flushes_denormals, DEFAULT_FLUSH_DENORMALS,
+};
 pub use gemm_common::gemm::{
+    get_blocking_selection, get_deterministic, get_lhs_packing_policy,
     get_lhs_packing_threshold_multi_thread, get_lhs_packing_threshold_single_thread,
-    get_rhs_packing_threshold, get_threading_threshold, set_lhs_packing_threshold_multi_thread,
-    set_lhs_packing_threshold_single_thread, set_rhs_packing_threshold, set_threading_threshold,
+    get_rhs_packing_policy, get_rhs_packing_threshold, get_streaming_stores_threshold,
+    get_threading_threshold, get_type_threading_threshold, set_blocking_selection,
+    set_deterministic, set_lhs_packing_policy, set_lhs_packing_threshold_multi_thread,
+    set_lhs_packing_threshold_single_thread, set_rhs_packing_policy, set_rhs_packing_threshold,
+    set_streaming_stores_threshold, set_threading_threshold, set_type_threading_threshold,
+    threading_threshold, PackingPolicy, DEFAULT_BLOCKING_SELECTION, DEFAULT_DETERMINISTIC,
     DEFAULT_LHS_PACKING_THRESHOLD_MULTI_THREAD, DEFAULT_LHS_PACKING_THRESHOLD_SINGLE_THREAD,
-    DEFAULT_RHS_PACKING_THRESHOLD, DEFAULT_THREADING_THRESHOLD,
+    DEFAULT_RHS_PACKING_THRESHOLD, DEFAULT_STREAMING_STORES_THRESHOLD, DEFAULT_THREADING_THRESHOLD,
+};
+pub use gemm_common::gemm::{
+    Backend, GemmConfig, TileEpilogue, TileEpilogueFn, UpdateRegion, DEFAULT_BACKEND_PRIORITY,
+};
+pub use gemm_common::numa::{
+    detected_numa_nodes, get_numa_nodes, get_replicate_rhs, set_numa_nodes, set_replicate_rhs,
+    DEFAULT_NUMA_NODES, DEFAULT_REPLICATE_RHS,
+};
+#[cfg(feature = "std")]
+pub use gemm_common::pool::{
+    clear_pool, get_global_pool_enabled, pool_stats, reset_pool_stats, set_global_pool_enabled,
+    PoolStats, DEFAULT_GLOBAL_POOL_ENABLED,
+};
+pub use gemm_common::presets::{
+    detect_microarch, get_presets_enabled, set_presets_enabled, Microarch, DEFAULT_PRESETS_ENABLED,
+};
+#[cfg(feature = "rayon")]
+pub use gemm_common::spawner::RayonSpawner;
+#[cfg(feature = "std")]
+pub use gemm_common::spawner::ScopedSpawner;
+pub use gemm_common::spawner::ThreadSpawner;
+#[cfg(feature = "stats")]
+pub use gemm_common::stats::{driver_stats, reset_driver_stats, DriverStats, KernelCalls};
+#[cfg(feature = "std")]
+pub use gemm_common::tuning::{
+    clear_tuned_kernel_params, cpu_model, default_tuning_cache_path, get_tuning_cache_path,
+    load_tuning_cache, save_tuning_cache, set_tuning_cache_path, TUNING_CACHE_ENV,
+    TUNING_CACHE_VERSION,
 };
 pub use gemm_common::{get_wasm_simd128, set_wasm_simd128, DEFAULT_WASM_SIMD128};
 
@@ -29,6 +152,10 @@ mod tests {
     use alloc::{vec, vec::Vec};
     use num_traits::Float;
 
+    // held by the tests that change process-wide settings, so that they don't run concurrently
+    // and restore them before the next one starts
+    static SETTINGS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
     #[test]
     fn test_gemm_f16() {
         let mut mnks = vec![];
@@ -614,4 +741,6635 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_gemm_slice() {
+        let (m, n, k) = (17, 9, 5);
+        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+        let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+        let mut d = c.clone();
+
+        // row-major dst with both strides negative, so (0, 0) is the last element of the slice
+        gemm_slice(
+            m,
+            n,
+            k,
+            &mut c[..],
+            -1,
+            -(n as isize),
+            true,
+            &a,
+            m as isize,
+            1,
+            &b,
+            k as isize,
+            1,
+            2.0,
+            0.5,
+            false,
+            false,
+            false,
+            Parallelism::None,
+        );
+        unsafe {
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                d.as_mut_ptr().add(m * n - 1),
+                -1,
+                -(n as isize),
+                true,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                2.0,
+                0.5,
+            );
+        }
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c, d);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_gemm_slice_out_of_bounds() {
+        let a = vec![0.0f32; 16];
+        let b = vec![0.0f32; 16];
+        let mut c = vec![0.0f32; 15];
+        gemm_slice(
+            4,
+            4,
+            4,
+            &mut c,
+            4,
+            1,
+            false,
+            &a,
+            4,
+            1,
+            &b,
+            4,
+            1,
+            0.0,
+            1.0,
+            false,
+            false,
+            false,
+            Parallelism::None,
+        );
+    }
+
+    #[test]
+    fn test_check_no_aliasing() {
+        use gemm::try_check_no_aliasing;
+
+        let buf = vec![0.0f64; 64];
+        let ptr = buf.as_ptr();
+        // the even and the odd columns of a column-major 8×8 matrix
+        let even = (ptr, 8, 4, 1, 16);
+        let odd = (ptr.wrapping_add(8), 8, 4, 1, 16);
+        assert_eq!(try_check_no_aliasing("lhs", even, odd), Ok(()));
+        assert_eq!(
+            try_check_no_aliasing("lhs", even, even),
+            Err(GemmError::DstAliasing { operand: "lhs" })
+        );
+        assert_eq!(
+            try_check_no_aliasing("rhs", even, (ptr.wrapping_add(19), 1, 1, 0, 0)),
+            Err(GemmError::DstAliasing { operand: "rhs" })
+        );
+
+        // the first row and the following rows of the same matrix, whose ranges interleave
+        let first_row = (ptr, 1, 8, 1, 8);
+        let next_rows = (ptr.wrapping_add(1), 3, 8, 1, 8);
+        assert_eq!(try_check_no_aliasing("rhs", first_row, next_rows), Ok(()));
+        assert_eq!(
+            try_check_no_aliasing("rhs", next_rows, (ptr.wrapping_add(3), 5, 8, 1, 8)),
+            Err(GemmError::DstAliasing { operand: "rhs" })
+        );
+        // negative strides span the same elements
+        let reversed = (ptr.wrapping_add(63), 8, 4, -1, -16);
+        assert_eq!(
+            try_check_no_aliasing("lhs", reversed, (ptr.wrapping_add(56), 8, 1, 1, 0)),
+            Err(GemmError::DstAliasing { operand: "lhs" })
+        );
+        assert_eq!(
+            try_check_no_aliasing("lhs", reversed, (ptr.wrapping_add(48), 8, 1, 1, 0)),
+            Ok(())
+        );
+
+        // rows two elements apart and columns three elements apart interleave without
+        // overlapping, at offsets 0, 2, 3, 4, 5, 6, 7, 8 and 10, until the fourth row
+        assert_eq!(gemm::try_check_no_self_overlap(3, 3, 2, 3), Ok(()));
+        assert_eq!(gemm::try_check_no_self_overlap(3, 3, -2, 3), Ok(()));
+        assert_eq!(
+            gemm::try_check_no_self_overlap(4, 3, 2, 3),
+            Err(GemmError::DstOverlap {
+                row_stride: 2,
+                col_stride: 3
+            })
+        );
+        let interleaved = (ptr, 3, 3, 2, 3);
+        for (offset, aliases) in [(1, false), (8, true), (9, false), (10, true)] {
+            assert_eq!(
+                try_check_no_aliasing("lhs", interleaved, (ptr.wrapping_add(offset), 1, 1, 0, 0))
+                    .is_err(),
+                aliases,
+            );
+        }
+
+        let mut dst = [0.0f32; 8];
+        gemm_slice(
+            3,
+            2,
+            4,
+            &mut dst,
+            3,
+            2,
+            false,
+            &[1.0; 12],
+            3,
+            1,
+            &[1.0; 8],
+            4,
+            1,
+            0.0,
+            1.0,
+            false,
+            false,
+            false,
+            Parallelism::None,
+        );
+        assert_eq!(dst, [4.0, 0.0, 4.0, 4.0, 4.0, 4.0, 0.0, 4.0]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dst shares memory with lhs")]
+    fn test_gemm_dst_aliasing_lhs() {
+        let mut a = vec![1.0f32; 16];
+        let b = vec![1.0f32; 16];
+        unsafe {
+            gemm(
+                4,
+                4,
+                4,
+                a.as_mut_ptr(),
+                4,
+                1,
+                true,
+                a.as_ptr(),
+                4,
+                1,
+                b.as_ptr(),
+                4,
+                1,
+                1.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+        }
+    }
+
+    #[test]
+    fn test_gemm_mat() {
+        let (m, n, k) = (13, 7, 21);
+        let a: Vec<c64> = (0..m * k)
+            .map(|_| c64::new(rand::random(), rand::random()))
+            .collect();
+        let b: Vec<c64> = (0..k * n)
+            .map(|_| c64::new(rand::random(), rand::random()))
+            .collect();
+        let mut c: Vec<c64> = (0..m * n)
+            .map(|_| c64::new(rand::random(), rand::random()))
+            .collect();
+        let mut d = c.clone();
+        let alpha = c64::new(0.5, 1.0);
+        let beta = c64::new(2.0, -1.0);
+
+        // lhs is stored as its transpose
+        gemm_mat(
+            MatMut::from_col_major_slice(&mut c, m, n),
+            true,
+            MatRef::from_row_major_slice(&a, m, k),
+            MatRef::from_col_major_slice(&b, k, n),
+            alpha,
+            beta,
+            false,
+            true,
+            false,
+            Parallelism::None,
+        );
+        unsafe {
+            gemm::gemm_cplx_fallback(
+                m,
+                n,
+                k,
+                d.as_mut_ptr(),
+                m as isize,
+                1,
+                true,
+                a.as_ptr(),
+                1,
+                k as isize,
+                b.as_ptr(),
+                k as isize,
+                1,
+                alpha,
+                beta,
+                false,
+                true,
+                false,
+            );
+        }
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c.re, d.re);
+            assert_approx_eq::assert_approx_eq!(c.im, d.im);
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        for (dtype, mr, nr) in [
+            (
+                DType::F32,
+                gemm::get_backend::<f32>().mr,
+                gemm::get_backend::<f32>().nr,
+            ),
+            (
+                DType::C64,
+                gemm::get_backend::<c64>().mr,
+                gemm::get_backend::<c64>().nr,
+            ),
+        ] {
+            let desc = describe(1024, 1024, 1024, dtype);
+            assert_eq!((desc.mr, desc.nr), (mr, nr));
+            assert!(!desc.backend.is_empty());
+            let KernelParams { kc, mc, nc } = desc.kernel_params;
+            assert!(kc > 0 && mc > 0 && nc > 0);
+            assert!(desc.to_string().starts_with(desc.backend));
+        }
+
+        // products with few rows are computed with the wide blocking of the shaped backends
+        let desc = describe(8, 1024, 256, DType::F32);
+        if gemm::get_backend::<f32>().name == "fma" {
+            assert_eq!((desc.mr, desc.nr), (8, 12));
+        }
+        let params = gemm_common::gemm::gemm_kernel_params::<f32>(
+            8,
+            1024,
+            256,
+            desc.mr,
+            desc.nr,
+            Parallelism::None,
+        );
+        assert_eq!(
+            (
+                desc.kernel_params.kc,
+                desc.kernel_params.mc,
+                desc.kernel_params.nc
+            ),
+            (params.kc, params.mc, params.nc)
+        );
+    }
+
+    #[test]
+    fn test_gemm_fixed() {
+        fn check<const M: usize, const N: usize, const K: usize>() {
+            let (alpha, beta) = (0.5, 2.0);
+            let lhs: [[f64; M]; K] =
+                core::array::from_fn(|_| core::array::from_fn(|_| rand::random()));
+            let rhs: [[f64; K]; N] =
+                core::array::from_fn(|_| core::array::from_fn(|_| rand::random()));
+            let init: [[f64; M]; N] =
+                core::array::from_fn(|_| core::array::from_fn(|_| rand::random()));
+
+            for read_dst in [false, true] {
+                let mut dst = init;
+                let mut target = init;
+                gemm_fixed(&mut dst, read_dst, &lhs, &rhs, alpha, beta);
+                unsafe {
+                    gemm::gemm_fallback(
+                        M,
+                        N,
+                        K,
+                        target.as_mut_ptr() as *mut f64,
+                        M as isize,
+                        1,
+                        read_dst,
+                        lhs.as_ptr() as *const f64,
+                        M as isize,
+                        1,
+                        rhs.as_ptr() as *const f64,
+                        K as isize,
+                        1,
+                        alpha,
+                        beta,
+                    );
+                }
+                for (dst, target) in dst.concat().iter().zip(target.concat().iter()) {
+                    assert_approx_eq::assert_approx_eq!(dst, target);
+                }
+            }
+        }
+
+        check::<4, 4, 4>();
+        check::<3, 5, 2>();
+        check::<16, 16, 16>();
+        check::<1, 7, 0>();
+    }
+
+    #[test]
+    fn test_gemm_batched() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (m, n, k) = (7, 5, 9);
+        let batch_size = 6;
+        let a: Vec<Vec<f64>> = (0..batch_size)
+            .map(|_| (0..m * k).map(|_| rand::random()).collect())
+            .collect();
+        let b: Vec<Vec<f64>> = (0..batch_size)
+            .map(|_| (0..k * n).map(|_| rand::random()).collect())
+            .collect();
+
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(0),
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(4),
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(16),
+        ] {
+            // make sure the batch is split between the threads
+            let threshold = get_threading_threshold();
+            set_threading_threshold(0);
+
+            let mut c: Vec<Vec<f64>> = (0..batch_size)
+                .map(|_| (0..m * n).map(|_| rand::random()).collect())
+                .collect();
+            let mut d = c.clone();
+            unsafe {
+                gemm_batched(
+                    m,
+                    n,
+                    k,
+                    &c.iter_mut().map(|c| c.as_mut_ptr()).collect::<Vec<_>>(),
+                    m as isize,
+                    1,
+                    true,
+                    &a.iter().map(|a| a.as_ptr()).collect::<Vec<_>>(),
+                    1,
+                    k as isize,
+                    &b.iter().map(|b| b.as_ptr()).collect::<Vec<_>>(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                );
+                set_threading_threshold(threshold);
+
+                for ((d, a), b) in d.iter_mut().zip(&a).zip(&b) {
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        k,
+                        d.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        a.as_ptr(),
+                        1,
+                        k as isize,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                    );
+                }
+            }
+            for (c, d) in c.iter().flatten().zip(d.iter().flatten()) {
+                assert_approx_eq::assert_approx_eq!(c, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_strided_batched() {
+        let (m, n, k) = (6, 9, 4);
+        let batch_size = 5;
+        // padded batch strides, with column-major lhs and row-major rhs
+        let (dst_bs, lhs_bs, rhs_bs) = (m * n + 3, m * k + 1, k * n + 7);
+        let a: Vec<f32> = (0..batch_size * lhs_bs).map(|_| rand::random()).collect();
+        let b: Vec<f32> = (0..batch_size * rhs_bs).map(|_| rand::random()).collect();
+        let mut c: Vec<f32> = (0..batch_size * dst_bs).map(|_| rand::random()).collect();
+        let mut d = c.clone();
+
+        unsafe {
+            gemm_strided_batched(
+                m,
+                n,
+                k,
+                batch_size,
+                c.as_mut_ptr(),
+                m as isize,
+                1,
+                dst_bs as isize,
+                true,
+                a.as_ptr(),
+                m as isize,
+                1,
+                lhs_bs as isize,
+                b.as_ptr(),
+                1,
+                n as isize,
+                rhs_bs as isize,
+                1.5,
+                -1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+            for i in 0..batch_size {
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    d.as_mut_ptr().add(i * dst_bs),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr().add(i * lhs_bs),
+                    m as isize,
+                    1,
+                    b.as_ptr().add(i * rhs_bs),
+                    1,
+                    n as isize,
+                    1.5,
+                    -1.0,
+                );
+            }
+        }
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c, d, 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_gemm_grouped() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let shapes = [
+            (3, 4, 5),
+            (64, 48, 80),
+            (0, 7, 3),
+            (9, 1, 13),
+            (17, 17, 0),
+            (5, 31, 2),
+            (40, 3, 25),
+        ];
+        let a: Vec<Vec<c32>> = shapes
+            .iter()
+            .map(|&(m, _, k)| {
+                (0..m * k)
+                    .map(|_| c32::new(rand::random(), rand::random()))
+                    .collect()
+            })
+            .collect();
+        let b: Vec<Vec<c32>> = shapes
+            .iter()
+            .map(|&(_, n, k)| {
+                (0..k * n)
+                    .map(|_| c32::new(rand::random(), rand::random()))
+                    .collect()
+            })
+            .collect();
+        let alpha = c32::new(0.5, -1.0);
+        let beta = c32::new(1.5, 2.0);
+
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(3),
+        ] {
+            let mut c: Vec<Vec<c32>> = shapes
+                .iter()
+                .map(|&(m, n, _)| {
+                    (0..m * n)
+                        .map(|_| c32::new(rand::random(), rand::random()))
+                        .collect()
+                })
+                .collect();
+            let mut d = c.clone();
+
+            // row-major destinations, column-major lhs and row-major rhs
+            let problems: Vec<GemmProblem<c32>> = shapes
+                .iter()
+                .zip(c.iter_mut())
+                .zip(a.iter().zip(b.iter()))
+                .map(|((&(m, n, k), c), (a, b))| GemmProblem {
+                    m,
+                    n,
+                    k,
+                    dst: c.as_mut_ptr(),
+                    dst_cs: 1,
+                    dst_rs: n as isize,
+                    lhs: a.as_ptr(),
+                    lhs_cs: m as isize,
+                    lhs_rs: 1,
+                    rhs: b.as_ptr(),
+                    rhs_cs: 1,
+                    rhs_rs: n as isize,
+                })
+                .collect();
+
+            // make sure the small problems are split between the threads
+            let threshold = get_threading_threshold();
+            set_threading_threshold(0);
+            unsafe {
+                gemm_grouped(
+                    &problems,
+                    true,
+                    alpha,
+                    beta,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                )
+            };
+            set_threading_threshold(threshold);
+
+            for ((&(m, n, k), d), (a, b)) in
+                shapes.iter().zip(d.iter_mut()).zip(a.iter().zip(b.iter()))
+            {
+                unsafe {
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        k,
+                        d.as_mut_ptr(),
+                        1,
+                        n as isize,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        1,
+                        n as isize,
+                        alpha,
+                        beta,
+                    )
+                };
+            }
+            for (c, d) in c.iter().flatten().zip(d.iter().flatten()) {
+                assert!((c - d).l1_norm() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemv() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (m, k) = (1000, 300);
+        let a: Vec<f32> = (0..m * k).map(|_| rand::random()).collect();
+        let x: Vec<f32> = (0..2 * k).map(|_| rand::random()).collect();
+
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(4),
+        ] {
+            for (lhs_cs, lhs_rs, dst_rs) in [
+                (m as isize, 1, 1isize),
+                (1, k as isize, 1),
+                (1, k as isize, -2),
+                (m as isize, 1, 3),
+            ] {
+                let len = m * dst_rs.unsigned_abs();
+                let mut y: Vec<f32> = (0..len).map(|_| rand::random()).collect();
+                let mut target = y.clone();
+                let dst_offset = if dst_rs < 0 { len - 1 } else { 0 };
+
+                let threshold = get_threading_threshold();
+                set_threading_threshold(0);
+                unsafe {
+                    gemv(
+                        m,
+                        k,
+                        y.as_mut_ptr().add(dst_offset),
+                        dst_rs,
+                        true,
+                        a.as_ptr(),
+                        lhs_cs,
+                        lhs_rs,
+                        x.as_ptr(),
+                        2,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                    );
+                }
+                set_threading_threshold(threshold);
+
+                unsafe {
+                    gemm::gemm_fallback(
+                        m,
+                        1,
+                        k,
+                        target.as_mut_ptr().add(dst_offset),
+                        0,
+                        dst_rs,
+                        true,
+                        a.as_ptr(),
+                        lhs_cs,
+                        lhs_rs,
+                        x.as_ptr(),
+                        0,
+                        2,
+                        0.5,
+                        2.0,
+                    );
+                }
+                for (y, target) in y.iter().zip(target.iter()) {
+                    assert_approx_eq::assert_approx_eq!(y, target, 1e-2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ger() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (m, n) = (150, 70);
+        let x: Vec<f64> = (0..2 * m).map(|_| rand::random()).collect();
+        let y: Vec<f64> = (0..n).map(|_| rand::random()).collect();
+
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(3),
+        ] {
+            for (dst_cs, dst_rs) in [(m as isize, 1), (1, n as isize)] {
+                for incx in [2isize, -2] {
+                    let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                    let mut d = c.clone();
+                    let x_offset = if incx < 0 { 2 * m - 1 } else { 0 };
+
+                    let threshold = get_threading_threshold();
+                    set_threading_threshold(0);
+                    unsafe {
+                        ger(
+                            m,
+                            n,
+                            c.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            x.as_ptr().add(x_offset),
+                            incx,
+                            y.as_ptr(),
+                            1,
+                            0.5,
+                            3.0,
+                            false,
+                            false,
+                            false,
+                            parallelism,
+                        );
+                    }
+                    set_threading_threshold(threshold);
+
+                    unsafe {
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            1,
+                            d.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            x.as_ptr().add(x_offset),
+                            0,
+                            incx,
+                            y.as_ptr(),
+                            1,
+                            0,
+                            0.5,
+                            3.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_syrk() {
+        let (n, k) = (150, 40);
+        let a: Vec<c64> = (0..n * k)
+            .map(|_| c64::new(rand::random(), rand::random()))
+            .collect();
+        let alpha = c64::new(0.5, 0.25);
+        let beta = c64::new(2.0, -1.0);
+
+        for triangle in [Triangle::Lower, Triangle::Upper] {
+            for (dst_cs, dst_rs) in [(n as isize, 1), (1, n as isize)] {
+                for conj_rhs in [false, true] {
+                    let mut c: Vec<c64> = (0..n * n)
+                        .map(|_| c64::new(rand::random(), rand::random()))
+                        .collect();
+                    let mut d = c.clone();
+                    let init = c.clone();
+
+                    unsafe {
+                        syrk(
+                            n,
+                            k,
+                            c.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            a.as_ptr(),
+                            n as isize,
+                            1,
+                            alpha,
+                            beta,
+                            triangle,
+                            false,
+                            false,
+                            conj_rhs,
+                            Parallelism::None,
+                        );
+                        gemm(
+                            n,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            a.as_ptr(),
+                            n as isize,
+                            1,
+                            a.as_ptr(),
+                            1,
+                            n as isize,
+                            alpha,
+                            beta,
+                            false,
+                            false,
+                            conj_rhs,
+                            Parallelism::None,
+                        );
+                    }
+
+                    for j in 0..n {
+                        for i in 0..n {
+                            let idx = (i as isize * dst_rs + j as isize * dst_cs) as usize;
+                            let in_triangle = match triangle {
+                                Triangle::Lower => i >= j,
+                                Triangle::Upper => i <= j,
+                            };
+                            if in_triangle {
+                                assert!((c[idx] - d[idx]).l1_norm() < 1e-10);
+                            } else {
+                                assert_eq!(c[idx], init[idx]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trmm() {
+        let (m, n) = (100, 37);
+        let b: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+
+        for triangle in [Triangle::Lower, Triangle::Upper] {
+            for unit_diagonal in [false, true] {
+                for (lhs_cs, lhs_rs) in [(m as isize, 1), (1, m as isize)] {
+                    // the other triangle is filled with NaNs, to check that it isn't read
+                    let mut a = vec![f64::NAN; m * m];
+                    let mut full = vec![0.0; m * m];
+                    for j in 0..m {
+                        for i in 0..m {
+                            let idx = (i as isize * lhs_rs + j as isize * lhs_cs) as usize;
+                            let (in_triangle, diag) = match triangle {
+                                Triangle::Lower => (i >= j, i == j),
+                                Triangle::Upper => (i <= j, i == j),
+                            };
+                            if in_triangle && !(diag && unit_diagonal) {
+                                a[idx] = rand::random();
+                                full[idx] = a[idx];
+                            } else if diag {
+                                full[idx] = 1.0;
+                            }
+                        }
+                    }
+
+                    let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                    let mut d = c.clone();
+                    unsafe {
+                        trmm(
+                            m,
+                            n,
+                            c.as_mut_ptr(),
+                            1,
+                            n as isize,
+                            true,
+                            a.as_ptr(),
+                            lhs_cs,
+                            lhs_rs,
+                            triangle,
+                            unit_diagonal,
+                            b.as_ptr(),
+                            m as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            m,
+                            d.as_mut_ptr(),
+                            1,
+                            n as isize,
+                            true,
+                            full.as_ptr(),
+                            lhs_cs,
+                            lhs_rs,
+                            b.as_ptr(),
+                            m as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trsm() {
+        let (m, n) = (90, 23);
+
+        for triangle in [Triangle::Lower, Triangle::Upper] {
+            for unit_diagonal in [false, true] {
+                for conj_lhs in [false, true] {
+                    // well conditioned triangle, with NaNs outside of it
+                    let mut a = vec![c64::new(f64::NAN, f64::NAN); m * m];
+                    for j in 0..m {
+                        for i in 0..m {
+                            let in_triangle = match triangle {
+                                Triangle::Lower => i > j,
+                                Triangle::Upper => i < j,
+                            };
+                            if in_triangle {
+                                a[i + j * m] = c64::new(rand::random(), rand::random()) * 0.1;
+                            } else if i == j && !unit_diagonal {
+                                a[i + j * m] = c64::new(1.0 + rand::random::<f64>(), 0.5);
+                            }
+                        }
+                    }
+                    let b: Vec<c64> = (0..m * n)
+                        .map(|_| c64::new(rand::random(), rand::random()))
+                        .collect();
+                    let mut x = b.clone();
+
+                    unsafe {
+                        trsm(
+                            m,
+                            n,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            triangle,
+                            unit_diagonal,
+                            x.as_mut_ptr(),
+                            1,
+                            n as isize,
+                            conj_lhs,
+                            Parallelism::None,
+                        );
+                    }
+
+                    // multiply the solution back
+                    let mut bb = vec![c64::new(0.0, 0.0); m * n];
+                    unsafe {
+                        trmm(
+                            m,
+                            n,
+                            bb.as_mut_ptr(),
+                            1,
+                            n as isize,
+                            false,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            triangle,
+                            unit_diagonal,
+                            x.as_ptr(),
+                            1,
+                            n as isize,
+                            c64::new(0.0, 0.0),
+                            c64::new(1.0, 0.0),
+                            false,
+                            conj_lhs,
+                            false,
+                            Parallelism::None,
+                        );
+                    }
+                    for (b, bb) in b.iter().zip(bb.iter()) {
+                        assert!((b - bb).l1_norm() < 1e-8);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_symm() {
+        let (m, n) = (110, 29);
+        let b: Vec<f32> = (0..m * n).map(|_| rand::random()).collect();
+
+        for triangle in [Triangle::Lower, Triangle::Upper] {
+            for (lhs_cs, lhs_rs) in [(m as isize, 1), (1, m as isize)] {
+                // the other triangle is filled with NaNs, to check that it isn't read
+                let mut a = vec![f32::NAN; m * m];
+                let mut full = vec![0.0; m * m];
+                for j in 0..m {
+                    for i in j..m {
+                        let value = rand::random();
+                        let (i, j) = match triangle {
+                            Triangle::Lower => (i, j),
+                            Triangle::Upper => (j, i),
+                        };
+                        a[(i as isize * lhs_rs + j as isize * lhs_cs) as usize] = value;
+                        full[i + j * m] = value;
+                        full[j + i * m] = value;
+                    }
+                }
+
+                let mut c: Vec<f32> = (0..m * n).map(|_| rand::random()).collect();
+                let mut d = c.clone();
+                unsafe {
+                    symm(
+                        m,
+                        n,
+                        c.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        a.as_ptr(),
+                        lhs_cs,
+                        lhs_rs,
+                        triangle,
+                        b.as_ptr(),
+                        1,
+                        n as isize,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        Parallelism::None,
+                    );
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        m,
+                        d.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        full.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        1,
+                        n as isize,
+                        0.5,
+                        2.0,
+                    );
+                }
+                for (c, d) in c.iter().zip(d.iter()) {
+                    assert_approx_eq::assert_approx_eq!(c, d, 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_op() {
+        let (m, n, k) = (17, 13, 29);
+        let ops = [Op::NoTrans, Op::Trans, Op::ConjTrans, Op::Conj];
+        let random = |len: usize| -> Vec<c32> {
+            (0..len)
+                .map(|_| c32::new(rand::random(), rand::random()))
+                .collect()
+        };
+        let alpha = c32::new(0.5, 0.0);
+        let beta = c32::new(1.0, -2.0);
+
+        for op_lhs in ops {
+            for op_rhs in ops {
+                let a = random(m * k);
+                let b = random(k * n);
+                let mut c = random(m * n);
+
+                // explicitly computed op(a) and op(b), in column-major order
+                let apply = |op: Op, x: &[c32], nrows: usize, ncols: usize| -> Vec<c32> {
+                    let (stored_nrows, transposed, conj) = match op {
+                        Op::NoTrans => (nrows, false, false),
+                        Op::Trans => (ncols, true, false),
+                        Op::ConjTrans => (ncols, true, true),
+                        Op::Conj => (nrows, false, true),
+                    };
+                    let mut out = vec![c32::new(0.0, 0.0); nrows * ncols];
+                    for j in 0..ncols {
+                        for i in 0..nrows {
+                            let v = if transposed {
+                                x[j + i * stored_nrows]
+                            } else {
+                                x[i + j * stored_nrows]
+                            };
+                            out[i + j * nrows] = if conj { v.conj() } else { v };
+                        }
+                    }
+                    out
+                };
+                let op_a = apply(op_lhs, &a, m, k);
+                let op_b = apply(op_rhs, &b, k, n);
+                let mut d = c.clone();
+
+                let (a_nrows, a_ncols) = match op_lhs {
+                    Op::NoTrans | Op::Conj => (m, k),
+                    Op::Trans | Op::ConjTrans => (k, m),
+                };
+                let (b_nrows, b_ncols) = match op_rhs {
+                    Op::NoTrans | Op::Conj => (k, n),
+                    Op::Trans | Op::ConjTrans => (n, k),
+                };
+                gemm_op(
+                    MatMut::from_col_major_slice(&mut c, m, n),
+                    true,
+                    op_lhs,
+                    MatRef::from_col_major_slice(&a, a_nrows, a_ncols),
+                    op_rhs,
+                    MatRef::from_col_major_slice(&b, b_nrows, b_ncols),
+                    alpha,
+                    beta,
+                    Parallelism::None,
+                );
+                gemm_mat(
+                    MatMut::from_col_major_slice(&mut d, m, n),
+                    true,
+                    MatRef::from_col_major_slice(&op_a, m, k),
+                    MatRef::from_col_major_slice(&op_b, k, n),
+                    alpha,
+                    beta,
+                    false,
+                    false,
+                    false,
+                    Parallelism::None,
+                );
+                for (c, d) in c.iter().zip(d.iter()) {
+                    assert!((c - d).l1_norm() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_epilogue() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let activations = [
+            Activation::Relu,
+            #[cfg(feature = "std")]
+            Activation::Gelu,
+            #[cfg(feature = "std")]
+            Activation::Sigmoid,
+        ];
+        let apply = |activation: Activation, x: f64| match activation {
+            Activation::Relu => x.max(0.0),
+            #[cfg(feature = "std")]
+            Activation::Gelu => {
+                0.5 * x
+                    * (1.0
+                        + ((2.0 / core::f64::consts::PI).sqrt() * (x + 0.044715 * x.powi(3)))
+                            .tanh())
+            }
+            #[cfg(feature = "std")]
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+        };
+
+        // the k == 0 and matrix-vector shapes skip the microkernels
+        for (m, n, k) in [
+            (37, 29, 41),
+            (64, 64, 64),
+            (16, 1, 17),
+            (1, 16, 17),
+            (8, 8, 0),
+        ] {
+            for &activation in &activations {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(0),
+                ] {
+                    let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+                    let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+                    let init: Vec<f64> = (0..m * n).map(|i| ((i % 3) as f64 - 1.0) / 2.0).collect();
+                    let mut dst = init.clone();
+                    let mut target = init.clone();
+
+                    let threshold = get_threading_threshold();
+                    set_threading_threshold(0);
+                    unsafe {
+                        gemm_epilogue(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            Epilogue {
+                                activation: Some(activation),
+                                clamp: None,
+                            },
+                            parallelism,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    set_threading_threshold(threshold);
+
+                    for (&dst, &target) in dst.iter().zip(target.iter()) {
+                        assert!((dst - apply(activation, target)).abs() < 1e-10);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_tile_epilogue() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // adds a value depending on the position of each element, so that elements that are
+        // skipped, visited twice or misplaced are caught
+        let bias = |i: usize, j: usize| (i * 3 + j * 7) as f64;
+        let epilogue = |tile: Tile<f64>| {
+            for j in 0..tile.ncols {
+                for i in 0..tile.nrows {
+                    unsafe {
+                        let x = tile.get(i, j);
+                        *x += bias(tile.row + i, tile.col + j);
+                    }
+                }
+            }
+        };
+
+        // the k == 0 and matrix-vector shapes skip the microkernels
+        for (m, n, k) in [
+            (37usize, 29usize, 41),
+            (64, 64, 300),
+            (16, 1, 17),
+            (1, 16, 17),
+            (8, 8, 0),
+        ] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| ((i % 3) as f64 - 1.0) / 2.0).collect();
+
+            // column-major, row-major, and reversed rows and columns
+            for (dst_offset, dst_cs, dst_rs) in [
+                (0, m as isize, 1isize),
+                (0, 1, n as isize),
+                (m.saturating_sub(1), m as isize, -1),
+                ((n.saturating_sub(1)) * m, -(m as isize), 1),
+            ] {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(0),
+                ] {
+                    let mut dst = init.clone();
+                    let mut target = init.clone();
+
+                    let threshold = get_threading_threshold();
+                    set_threading_threshold(0);
+                    unsafe {
+                        gemm_tile_epilogue(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr().wrapping_add(dst_offset),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            &epilogue,
+                            parallelism,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr().wrapping_add(dst_offset),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    set_threading_threshold(threshold);
+
+                    for i in 0..m {
+                        for j in 0..n {
+                            let idx =
+                                (dst_offset as isize + i as isize * dst_rs + j as isize * dst_cs)
+                                    as usize;
+                            assert!((dst[idx] - (target[idx] + bias(i, j))).abs() < 1e-10);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_epilogue_clamp() {
+        for (m, n, k) in [(37, 29, 41), (16, 1, 17), (8, 8, 0)] {
+            for activation in [None, Some(Activation::Relu)] {
+                let lhs: Vec<f32> = (0..m * k).map(|i| (i % 7) as f32 - 3.0).collect();
+                let rhs: Vec<f32> = (0..k * n).map(|i| (i % 5) as f32 - 2.0).collect();
+                let init: Vec<f32> = (0..m * n).map(|i| (i % 9) as f32 - 4.0).collect();
+                let mut dst = init.clone();
+                let mut target = init.clone();
+
+                unsafe {
+                    gemm_epilogue(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        1,
+                        n as isize,
+                        true,
+                        lhs.as_ptr(),
+                        m as isize,
+                        1,
+                        rhs.as_ptr(),
+                        k as isize,
+                        1,
+                        1.0,
+                        1.0,
+                        Epilogue {
+                            activation,
+                            clamp: Some((-2.5, 6.0)),
+                        },
+                        Parallelism::None,
+                    );
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        k,
+                        target.as_mut_ptr(),
+                        1,
+                        n as isize,
+                        true,
+                        lhs.as_ptr(),
+                        m as isize,
+                        1,
+                        rhs.as_ptr(),
+                        k as isize,
+                        1,
+                        1.0,
+                        1.0,
+                    );
+                }
+
+                for (&dst, &target) in dst.iter().zip(target.iter()) {
+                    let target = match activation {
+                        Some(Activation::Relu) => target.max(0.0),
+                        _ => target,
+                    };
+                    assert_eq!(dst, target.clamp(-2.5, 6.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_scaled() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let value = |i: usize, j: usize| {
+            c64::new(
+                ((i * 7 + j) % 11) as f64 - 5.0,
+                ((i + 3 * j) % 5) as f64 - 2.0,
+            )
+        };
+
+        for (m, n, k) in [
+            (37, 29, 41),
+            (64, 64, 300),
+            (16, 1, 17),
+            (1, 16, 17),
+            (8, 8, 0),
+        ] {
+            let lhs: Vec<c64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<c64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let row_scale: Vec<c64> = (0..m).map(|i| value(i, 3)).collect();
+            let col_scale: Vec<c64> = (0..n).map(|i| value(i, 4)).collect();
+            let init: Vec<c64> = (0..m * n).map(|i| value(i, 5)).collect();
+
+            for (conj_lhs, conj_rhs) in [(false, false), (true, false), (false, true)] {
+                let scaled_lhs: Vec<c64> = (0..m * k)
+                    .map(|idx| {
+                        let x = if conj_lhs { lhs[idx].conj() } else { lhs[idx] };
+                        row_scale[idx % m] * x
+                    })
+                    .collect();
+                let scaled_rhs: Vec<c64> = (0..k * n)
+                    .map(|idx| {
+                        let x = if conj_rhs { rhs[idx].conj() } else { rhs[idx] };
+                        x * col_scale[idx / k]
+                    })
+                    .collect();
+
+                // column-major, row-major, and column-major with reversed rows
+                for (dst_cs, dst_rs, offset) in [
+                    (m as isize, 1isize, 0usize),
+                    (1, n as isize, 0),
+                    (m as isize, -1, m.saturating_sub(1)),
+                ] {
+                    for parallelism in [
+                        Parallelism::None,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(0),
+                    ] {
+                        let mut dst = init.clone();
+                        let mut target = init.clone();
+
+                        let threshold = get_threading_threshold();
+                        set_threading_threshold(0);
+                        unsafe {
+                            gemm_with_config(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr().wrapping_add(offset),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                c64::new(0.5, 1.0),
+                                c64::new(2.0, -1.0),
+                                false,
+                                conj_lhs,
+                                conj_rhs,
+                                parallelism,
+                                GemmConfig {
+                                    lhs_scale: Some((row_scale.as_ptr(), 1)),
+                                    rhs_scale: Some((col_scale.as_ptr(), 1)),
+                                    ..Default::default()
+                                },
+                            );
+                            gemm::gemm_cplx_fallback(
+                                m,
+                                n,
+                                k,
+                                target.as_mut_ptr().wrapping_add(offset),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                scaled_lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                scaled_rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                c64::new(0.5, 1.0),
+                                c64::new(2.0, -1.0),
+                                false,
+                                false,
+                                false,
+                            );
+                        }
+                        set_threading_threshold(threshold);
+
+                        for (&dst, &target) in dst.iter().zip(target.iter()) {
+                            assert!((dst - target).norm_sqr() < 1e-16);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_masked() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let value = |i: usize, j: usize| {
+            c64::new(
+                ((i * 7 + j) % 11) as f64 - 5.0,
+                ((i + 3 * j) % 5) as f64 - 2.0,
+            )
+        };
+
+        for (m, n, k) in [
+            (37, 29, 41),
+            (64, 64, 300),
+            (16, 1, 17),
+            (1, 16, 17),
+            (8, 8, 0),
+        ] {
+            let lhs: Vec<c64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<c64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<c64> = (0..m * n).map(|i| value(i, 3)).collect();
+
+            // scattered elements, and a band that leaves whole blocks unselected
+            let masks: [Vec<bool>; 2] = [
+                (0..m * n).map(|i| (i * 7) % 5 < 2).collect(),
+                (0..m * n)
+                    .map(|i| i % m + 3 > i / m && i / m + 3 > i % m)
+                    .collect(),
+            ];
+
+            for mask in &masks {
+                // column-major, row-major, and column-major with reversed rows
+                for (dst_cs, dst_rs, offset) in [
+                    (m as isize, 1isize, 0usize),
+                    (1, n as isize, 0),
+                    (m as isize, -1, m.saturating_sub(1)),
+                ] {
+                    for parallelism in [
+                        Parallelism::None,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(0),
+                    ] {
+                        let mut dst = init.clone();
+                        let mut target = init.clone();
+
+                        let threshold = get_threading_threshold();
+                        set_threading_threshold(0);
+                        unsafe {
+                            gemm_with_config(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr().wrapping_add(offset),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                c64::new(0.5, 1.0),
+                                c64::new(2.0, -1.0),
+                                true,
+                                false,
+                                true,
+                                parallelism,
+                                GemmConfig {
+                                    mask: Some((
+                                        mask.as_ptr().wrapping_add(offset),
+                                        dst_cs,
+                                        dst_rs,
+                                    )),
+                                    ..Default::default()
+                                },
+                            );
+                            gemm::gemm_cplx_fallback(
+                                m,
+                                n,
+                                k,
+                                target.as_mut_ptr().wrapping_add(offset),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                c64::new(0.5, 1.0),
+                                c64::new(2.0, -1.0),
+                                true,
+                                false,
+                                true,
+                            );
+                        }
+                        set_threading_threshold(threshold);
+
+                        for idx in 0..m * n {
+                            let expected = if mask[idx] { target[idx] } else { init[idx] };
+                            assert!((dst[idx] - expected).norm_sqr() < 1e-16);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_region() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let value = |i: usize, j: usize| ((i * 7 + j) % 11) as f64 - 5.0;
+
+        for (m, n, k) in [
+            (37, 29, 41),
+            (64, 64, 300),
+            (16, 1, 17),
+            (1, 16, 17),
+            (8, 8, 0),
+        ] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| value(i, 3)).collect();
+
+            for region in [UpdateRegion::Full, UpdateRegion::Lower, UpdateRegion::Upper] {
+                for (dst_cs, dst_rs) in [(m as isize, 1isize), (1, n as isize)] {
+                    for parallelism in [
+                        Parallelism::None,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(0),
+                    ] {
+                        let mut dst = init.clone();
+                        let mut target = init.clone();
+
+                        let threshold = get_threading_threshold();
+                        set_threading_threshold(0);
+                        unsafe {
+                            gemm_with_config(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                0.5,
+                                2.0,
+                                false,
+                                false,
+                                false,
+                                parallelism,
+                                GemmConfig {
+                                    update_region: region,
+                                    ..Default::default()
+                                },
+                            );
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                target.as_mut_ptr(),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                0.5,
+                                2.0,
+                            );
+                        }
+                        set_threading_threshold(threshold);
+
+                        for j in 0..n {
+                            for i in 0..m {
+                                let idx = (i as isize * dst_rs + j as isize * dst_cs) as usize;
+                                let expected = if region.contains(i, j) {
+                                    target[idx]
+                                } else {
+                                    init[idx]
+                                };
+                                assert_eq!(dst[idx], expected);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_conv2d() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let shapes = [
+            Conv2dShape {
+                in_channels: 3,
+                out_channels: 8,
+                input: (17, 19),
+                kernel: (3, 3),
+                stride: (1, 1),
+                padding: (1, 1),
+                dilation: (1, 1),
+            },
+            Conv2dShape {
+                in_channels: 5,
+                out_channels: 13,
+                input: (40, 33),
+                kernel: (3, 2),
+                stride: (2, 1),
+                padding: (2, 0),
+                dilation: (1, 2),
+            },
+            Conv2dShape {
+                in_channels: 4,
+                out_channels: 2,
+                input: (9, 9),
+                kernel: (1, 1),
+                stride: (3, 3),
+                padding: (0, 0),
+                dilation: (1, 1),
+            },
+        ];
+
+        for shape in shapes {
+            let (height, width) = shape.input;
+            let (out_height, out_width) = shape.output();
+            let (c_in, c_out) = (shape.in_channels, shape.out_channels);
+            let depth = shape.depth();
+
+            let input: Vec<f64> = (0..height * width * c_in)
+                .map(|i| ((i * 7) % 11) as f64 - 5.0)
+                .collect();
+            // column-major weights
+            let weights: Vec<f64> = (0..depth * c_out)
+                .map(|i| ((i * 3) % 7) as f64 - 3.0)
+                .collect();
+            let init: Vec<f64> = (0..out_height * out_width * c_out)
+                .map(|i| (i % 5) as f64)
+                .collect();
+
+            let mut target = init.clone();
+            for oc in 0..c_out {
+                for oi in 0..out_height {
+                    for oj in 0..out_width {
+                        let mut acc = 0.0;
+                        for ki in 0..shape.kernel.0 {
+                            for kj in 0..shape.kernel.1 {
+                                let i = (oi * shape.stride.0 + ki * shape.dilation.0) as isize
+                                    - shape.padding.0 as isize;
+                                let j = (oj * shape.stride.1 + kj * shape.dilation.1) as isize
+                                    - shape.padding.1 as isize;
+                                if i < 0 || j < 0 || i >= height as isize || j >= width as isize {
+                                    continue;
+                                }
+                                for c in 0..c_in {
+                                    let x = input[(i as usize * width + j as usize) * c_in + c];
+                                    let w =
+                                        weights[oc * depth + (ki * shape.kernel.1 + kj) * c_in + c];
+                                    acc += x * w;
+                                }
+                            }
+                        }
+                        let dst = &mut target[(oi * out_width + oj) * c_out + oc];
+                        *dst = 0.5 * *dst + 2.0 * acc;
+                    }
+                }
+            }
+
+            for parallelism in [
+                Parallelism::None,
+                #[cfg(feature = "rayon")]
+                Parallelism::Rayon(0),
+            ] {
+                let mut dst = init.clone();
+                let threshold = get_threading_threshold();
+                set_threading_threshold(0);
+                unsafe {
+                    conv2d(
+                        shape,
+                        dst.as_mut_ptr(),
+                        1,
+                        c_out as isize,
+                        true,
+                        input.as_ptr(),
+                        weights.as_ptr(),
+                        depth as isize,
+                        1,
+                        0.5,
+                        2.0,
+                        parallelism,
+                    );
+                }
+                set_threading_threshold(threshold);
+                assert_eq!(dst, target);
+            }
+        }
+    }
+
+    #[cfg(feature = "strassen")]
+    #[test]
+    fn test_gemm_strassen() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let value = |i: usize, j: usize| ((i * 7 + j) % 11) as f64 - 5.0;
+
+        let threshold = get_strassen_threshold();
+        set_strassen_threshold(8);
+        for (m, n, k) in [(64, 64, 64), (37, 29, 41), (33, 50, 17), (8, 9, 300)] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| value(i, 3)).collect();
+
+            for read_dst in [true, false] {
+                for (dst_cs, dst_rs) in [(m as isize, 1isize), (1, n as isize)] {
+                    let mut dst = init.clone();
+                    let mut target = init.clone();
+
+                    let mut mem = dyn_stack::GlobalMemBuffer::new(gemm_req::<f64>(
+                        m,
+                        n,
+                        k,
+                        dst_cs,
+                        dst_rs,
+                        Parallelism::None,
+                    ));
+                    try_gemm(
+                        m,
+                        n,
+                        k,
+                        &mut dst,
+                        dst_cs,
+                        dst_rs,
+                        read_dst,
+                        &lhs,
+                        m as isize,
+                        1,
+                        &rhs,
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        Parallelism::None,
+                        Some(dyn_stack::DynStack::new(&mut mem)),
+                    )
+                    .unwrap();
+                    unsafe {
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            read_dst,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+
+                    for (&actual, &expected) in dst.iter().zip(&target) {
+                        assert!((actual - expected).abs() < 1e-9 * (1.0 + expected.abs()));
+                    }
+                }
+            }
+        }
+        set_strassen_threshold(threshold);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_gemm_split_k() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let value = |i: usize, j: usize| {
+            c64::new(
+                ((i * 7 + j) % 11) as f64 - 5.0,
+                ((i + 3 * j) % 5) as f64 - 2.0,
+            )
+        };
+        let parallelism = Parallelism::Rayon(4);
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        let backend = gemm::get_backend::<c64>();
+        for (m, n, k) in [(4, 3, 5000), (17, 9, 3001), (1, 33, 4096)] {
+            assert!(split_k::n_splits(backend, m, n, k, parallelism, None) > 1);
+            assert_eq!(
+                split_k::n_splits(backend, m, n, k, parallelism, Some(usize::MAX)),
+                1
+            );
+
+            let lhs: Vec<c64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<c64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<c64> = (0..m * n).map(|i| value(i, 3)).collect();
+
+            for (conj_dst, conj_lhs, conj_rhs) in [(false, false, false), (true, true, false)] {
+                for (dst_cs, dst_rs) in [(m as isize, 1isize), (1, n as isize)] {
+                    let mut dst = init.clone();
+                    let mut target = init.clone();
+                    let alpha = c64::new(0.5, 1.0);
+                    let beta = c64::new(2.0, -1.0);
+
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            alpha,
+                            beta,
+                            conj_dst,
+                            conj_lhs,
+                            conj_rhs,
+                            parallelism,
+                        );
+                        gemm::gemm_cplx_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            alpha,
+                            beta,
+                            conj_dst,
+                            conj_lhs,
+                            conj_rhs,
+                        );
+                    }
+
+                    for (&actual, &expected) in dst.iter().zip(&target) {
+                        assert!((actual - expected).norm_sqr() < 1e-16 * expected.norm_sqr());
+                    }
+                }
+            }
+        }
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_gemm_skinny() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        use gemm_common::gemm::Shape;
+
+        let value = |i: usize, j: usize| {
+            c64::new(
+                ((i * 7 + j) % 11) as f64 - 5.0,
+                ((i + 3 * j) % 5) as f64 - 2.0,
+            )
+        };
+        let parallelism = Parallelism::Rayon(4);
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        for (m, n, k, shape) in [
+            (1000, 5, 67, Shape::TallSkinny),
+            (997, 33, 130, Shape::TallSkinny),
+            (3, 2000, 67, Shape::ShortFat),
+            (20, 1001, 9, Shape::ShortFat),
+        ] {
+            assert_eq!(Shape::of(m, n), shape);
+
+            let lhs: Vec<c64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<c64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<c64> = (0..m * n).map(|i| value(i, 3)).collect();
+
+            for (read_dst, conj_dst, conj_lhs, conj_rhs) in [
+                (true, false, false, false),
+                (true, true, false, true),
+                (false, false, true, false),
+            ] {
+                for (dst_cs, dst_rs) in [(m as isize, 1isize), (1, n as isize)] {
+                    let mut dst = init.clone();
+                    let mut target = init.clone();
+                    let alpha = c64::new(0.5, 1.0);
+                    let beta = c64::new(2.0, -1.0);
+
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            read_dst,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            alpha,
+                            beta,
+                            conj_dst,
+                            conj_lhs,
+                            conj_rhs,
+                            parallelism,
+                        );
+                        gemm::gemm_cplx_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            read_dst,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            alpha,
+                            beta,
+                            conj_dst,
+                            conj_lhs,
+                            conj_rhs,
+                        );
+                    }
+
+                    for (&actual, &expected) in dst.iter().zip(&target) {
+                        assert!(
+                            (actual - expected).norm_sqr() < 1e-16 * (1.0 + expected.norm_sqr())
+                        );
+                    }
+                }
+            }
+        }
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_tall_skinny_driver() {
+        // several chunks of rows and blocks of depth per thread, with edge tiles on both sides
+        for (m, n, k) in [(20_003, 3, 700), (4099, 32, 1500), (1024, 17, 5)] {
+            let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+            let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+            let init: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+
+            for (rhs_cs, rhs_rs) in [(k as isize, 1), (1, n as isize)] {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                ] {
+                    let mut c = init.clone();
+                    let mut d = init.clone();
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            c.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            rhs_cs,
+                            rhs_rs,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            parallelism,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            rhs_cs,
+                            rhs_rs,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d, 1e-9 * k as f64);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_short_fat_driver() {
+        // several chunks of columns and blocks of depth per thread, with edge tiles on both sides
+        for (m, n, k) in [(3, 20_003, 700), (32, 4099, 1500), (17, 1024, 5)] {
+            let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+            let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+            let init: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+
+            for (lhs_cs, lhs_rs) in [(m as isize, 1), (1, k as isize)] {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                ] {
+                    let mut c = init.clone();
+                    let mut d = init.clone();
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            c.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a.as_ptr(),
+                            lhs_cs,
+                            lhs_rs,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            parallelism,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a.as_ptr(),
+                            lhs_cs,
+                            lhs_rs,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d, 1e-9 * k as f64);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_broadcast() {
+        let value = |i: usize, j: usize| ((i * 7 + j) % 11) as f64 - 5.0;
+
+        for (m, n, k) in [
+            (1, 1, 1),
+            (37, 1, 41),
+            (1, 29, 41),
+            (37, 29, 2),
+            (37, 29, 41),
+            (130, 70, 300),
+        ] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| value(i, 3)).collect();
+
+            // a row or column repeated along the other dimension, or a single element
+            let lhs_strides = [
+                (m as isize, 1isize),
+                (0, 1),
+                (m as isize, 0),
+                (0, 0),
+                (1, k as isize),
+                (1, 0),
+                (0, k as isize),
+            ];
+            let rhs_strides = [
+                (k as isize, 1isize),
+                (0, 1),
+                (k as isize, 0),
+                (0, 0),
+                (1, n as isize),
+                (1, 0),
+                (0, n as isize),
+            ];
+
+            for (lhs_cs, lhs_rs) in lhs_strides {
+                for (rhs_cs, rhs_rs) in rhs_strides {
+                    for (dst_cs, dst_rs) in [(m as isize, 1isize), (1, n as isize)] {
+                        for parallelism in [
+                            Parallelism::None,
+                            #[cfg(feature = "rayon")]
+                            Parallelism::Rayon(0),
+                        ] {
+                            let mut dst = init.clone();
+                            let mut target = init.clone();
+
+                            unsafe {
+                                gemm(
+                                    m,
+                                    n,
+                                    k,
+                                    dst.as_mut_ptr(),
+                                    dst_cs,
+                                    dst_rs,
+                                    true,
+                                    lhs.as_ptr(),
+                                    lhs_cs,
+                                    lhs_rs,
+                                    rhs.as_ptr(),
+                                    rhs_cs,
+                                    rhs_rs,
+                                    0.5,
+                                    2.0,
+                                    false,
+                                    false,
+                                    false,
+                                    parallelism,
+                                );
+                                gemm::gemm_fallback(
+                                    m,
+                                    n,
+                                    k,
+                                    target.as_mut_ptr(),
+                                    dst_cs,
+                                    dst_rs,
+                                    true,
+                                    lhs.as_ptr(),
+                                    lhs_cs,
+                                    lhs_rs,
+                                    rhs.as_ptr(),
+                                    rhs_cs,
+                                    rhs_rs,
+                                    0.5,
+                                    2.0,
+                                );
+                            }
+                            assert_eq!(
+                                dst,
+                                target,
+                                "{:?}",
+                                (m, n, k, lhs_cs, lhs_rs, rhs_cs, rhs_rs, dst_cs, dst_rs)
+                            );
+                        }
+
+                        // the broadcast operands are expanded while packing
+                        let lhs_mat = MatRef::from_slice(&lhs, m, k, lhs_rs, lhs_cs);
+                        let rhs_mat = MatRef::from_slice(&rhs, k, n, rhs_rs, rhs_cs);
+                        let mut dst = init.clone();
+                        let mut target = init.clone();
+                        gemm_prepacked(
+                            MatMut::from_slice(&mut dst, m, n, dst_rs, dst_cs),
+                            true,
+                            &pack_lhs(lhs_mat),
+                            &pack_rhs(rhs_mat),
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                        );
+                        gemm_mat(
+                            MatMut::from_slice(&mut target, m, n, dst_rs, dst_cs),
+                            true,
+                            lhs_mat,
+                            rhs_mat,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                        );
+                        assert_eq!(dst, target);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_accumulate() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let value = |i: usize, j: usize| {
+            c64::new(
+                ((i * 7 + j) % 11) as f64 - 5.0,
+                ((i + 3 * j) % 5) as f64 - 2.0,
+            )
+        };
+
+        for (m, n, k) in [
+            (37, 29, 41),
+            (64, 64, 300),
+            (16, 1, 17),
+            (1, 16, 17),
+            (8, 8, 0),
+        ] {
+            let lhs: Vec<c64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<c64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<c64> = (0..m * n).map(|i| value(i, 3)).collect();
+            let acc_init: Vec<c64> = (0..m * n).map(|i| value(i, 4)).collect();
+
+            for (conj_dst, conj_lhs, conj_rhs) in [(false, false, false), (true, true, false)] {
+                for (dst_cs, dst_rs) in [(m as isize, 1isize), (1, n as isize)] {
+                    for parallelism in [
+                        Parallelism::None,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(0),
+                    ] {
+                        let (acc_cs, acc_rs) = (1, n as isize);
+                        let alpha = c64::new(0.5, 1.0);
+                        let beta = c64::new(2.0, -1.0);
+
+                        let mut dst = init.clone();
+                        let mut acc = acc_init.clone();
+                        let mut target = init.clone();
+                        let mut acc_target = acc_init.clone();
+
+                        let threshold = get_threading_threshold();
+                        set_threading_threshold(0);
+                        unsafe {
+                            gemm_with_config(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                                conj_dst,
+                                conj_lhs,
+                                conj_rhs,
+                                parallelism,
+                                GemmConfig {
+                                    accumulate: Some((acc.as_mut_ptr(), acc_cs, acc_rs)),
+                                    ..Default::default()
+                                },
+                            );
+                            gemm::gemm_cplx_fallback(
+                                m,
+                                n,
+                                k,
+                                target.as_mut_ptr(),
+                                dst_cs,
+                                dst_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                                conj_dst,
+                                conj_lhs,
+                                conj_rhs,
+                            );
+                            gemm::gemm_cplx_fallback(
+                                m,
+                                n,
+                                k,
+                                acc_target.as_mut_ptr(),
+                                acc_cs,
+                                acc_rs,
+                                true,
+                                lhs.as_ptr(),
+                                m as isize,
+                                1,
+                                rhs.as_ptr(),
+                                k as isize,
+                                1,
+                                c64::new(1.0, 0.0),
+                                beta,
+                                false,
+                                conj_lhs,
+                                conj_rhs,
+                            );
+                        }
+                        set_threading_threshold(threshold);
+
+                        for (&actual, &expected) in
+                            dst.iter().chain(&acc).zip(target.iter().chain(&acc_target))
+                        {
+                            assert!((actual - expected).norm_sqr() < 1e-16);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_custom_spawner() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        // runs each task on its own scoped thread, and counts the tasks
+        struct Spawner {
+            n_tasks: AtomicUsize,
+        }
+        impl ThreadSpawner for Spawner {
+            fn num_threads(&self) -> usize {
+                3
+            }
+            fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+                assert!(n_tasks <= self.num_threads());
+                self.n_tasks.fetch_add(n_tasks, Ordering::Relaxed);
+                std::thread::scope(|s| {
+                    for tid in 0..n_tasks {
+                        s.spawn(move || func(tid));
+                    }
+                });
+            }
+        }
+        static SPAWNER: Spawner = Spawner {
+            n_tasks: AtomicUsize::new(0),
+        };
+
+        let value = |i: usize, j: usize| {
+            c64::new(
+                ((i * 7 + j) % 11) as f64 - 5.0,
+                ((i + 3 * j) % 5) as f64 - 2.0,
+            )
+        };
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        // general, tall-skinny and split along k
+        for (m, n, k) in [(64, 64, 64), (37, 29, 300), (1000, 5, 67), (16, 16, 2048)] {
+            let lhs: Vec<c64> = (0..m * k).map(|i| value(i, 1)).collect();
+            let rhs: Vec<c64> = (0..k * n).map(|i| value(i, 2)).collect();
+            let init: Vec<c64> = (0..m * n).map(|i| value(i, 3)).collect();
+            let mut dst = init.clone();
+            let mut target = init.clone();
+            let alpha = c64::new(0.5, 1.0);
+            let beta = c64::new(2.0, -1.0);
+
+            let before = SPAWNER.n_tasks.load(Ordering::Relaxed);
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    alpha,
+                    beta,
+                    false,
+                    true,
+                    false,
+                    Parallelism::Custom(&SPAWNER),
+                );
+                gemm::gemm_cplx_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    alpha,
+                    beta,
+                    false,
+                    true,
+                    false,
+                );
+            }
+            assert!(SPAWNER.n_tasks.load(Ordering::Relaxed) > before);
+
+            for (&dst, &target) in dst.iter().zip(target.iter()) {
+                assert!((dst - target).norm_sqr() < 1e-16);
+            }
+        }
+
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_gemm_scoped_spawner() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        static SPAWNER: ScopedSpawner = ScopedSpawner::new(3);
+        assert_eq!(SPAWNER.num_threads(), 3);
+        assert!(ScopedSpawner::new(0).num_threads() >= 1);
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        for (m, n, k) in [(64, 64, 64), (37, 29, 300), (1000, 5, 67)] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| ((i % 3) as f64 - 1.0) / 2.0).collect();
+            let mut dst = init.clone();
+            let mut target = init.clone();
+
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    1,
+                    n as isize,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    Parallelism::Custom(&SPAWNER),
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    1,
+                    n as isize,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+
+            for (&dst, &target) in dst.iter().zip(target.iter()) {
+                assert!((dst - target).abs() < 1e-10);
+            }
+        }
+
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_gemm_thread_pool() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let pool = &rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+        assert_eq!(gemm_common::gemm::max_threads(Parallelism::Custom(pool)), 3);
+        assert_eq!(
+            pool.install(|| gemm_common::gemm::max_threads(Parallelism::Rayon(0))),
+            3
+        );
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        for (m, n, k) in [(64, 64, 64), (37, 29, 300), (1000, 5, 67)] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| ((i % 3) as f64 - 1.0) / 2.0).collect();
+            let mut target = init.clone();
+            unsafe {
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+
+            // handed to the pool, or called from inside it
+            for installed in [false, true] {
+                let mut dst = init.clone();
+                let run = |dst: &mut [f64], parallelism: Parallelism<'_>| unsafe {
+                    gemm(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        lhs.as_ptr(),
+                        m as isize,
+                        1,
+                        rhs.as_ptr(),
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                    )
+                };
+                if installed {
+                    pool.install(|| run(&mut dst, Parallelism::Rayon(0)));
+                } else {
+                    run(&mut dst, Parallelism::Custom(pool));
+                }
+
+                for (&dst, &target) in dst.iter().zip(target.iter()) {
+                    assert!((dst - target).abs() < 1e-10);
+                }
+            }
+        }
+
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_gemm_numa() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        assert!(detected_numa_nodes() >= 1);
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+        set_numa_nodes(2);
+        assert_eq!(get_numa_nodes(), 2);
+
+        // the lhs panels are shared by the threads when m is small and not a multiple of the
+        // register size, or when lhs is row-major
+        for (m, n, k, lhs_cs, lhs_rs) in [
+            (37, 300, 41, 37isize, 1isize),
+            (64, 257, 300, 1, 300),
+            (3, 128, 17, 3, 1),
+        ] {
+            let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| ((i % 3) as f64 - 1.0) / 2.0).collect();
+            let mut dst = init.clone();
+            let mut target = init.clone();
+
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    lhs_cs,
+                    lhs_rs,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                    #[cfg(not(feature = "rayon"))]
+                    Parallelism::None,
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    lhs_cs,
+                    lhs_rs,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+
+            for (&dst, &target) in dst.iter().zip(target.iter()) {
+                assert!((dst - target).abs() < 1e-10);
+            }
+        }
+
+        set_numa_nodes(DEFAULT_NUMA_NODES);
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_gemm_pinned_pool() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        assert_eq!(
+            parse_core_list("0-3,8, 10-11"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_core_list("5"), Some(vec![5]));
+        assert_eq!(parse_core_list("3-1"), None);
+        assert_eq!(parse_core_list("0,a"), None);
+
+        let cores = match current_cores() {
+            Some(cores) if !cores.is_empty() => cores,
+            _ => return,
+        };
+        let cores = &cores[..Ord::min(cores.len(), 2)];
+        let pool = &pinned_thread_pool(cores).unwrap();
+        assert_eq!(pool.current_num_threads(), cores.len());
+        // each worker only runs on its own core
+        pool.broadcast(|ctx| {
+            assert_eq!(current_cores(), Some(vec![cores[ctx.index()]]));
+        });
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        let (m, n, k) = (64, 64, 64);
+        let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+        let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+        let mut dst = vec![0.0; m * n];
+        let mut target = vec![0.0; m * n];
+        unsafe {
+            gemm(
+                m,
+                n,
+                k,
+                dst.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::Custom(pool),
+            );
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                target.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+        set_threading_threshold(threshold);
+
+        for (&dst, &target) in dst.iter().zip(target.iter()) {
+            assert!((dst - target).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_gemm_deterministic() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        static SPAWNER: ScopedSpawner = ScopedSpawner::new(5);
+        let parallelisms = [
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(2),
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(3),
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(8),
+            Parallelism::Custom(&SPAWNER),
+        ];
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+        set_deterministic(true);
+
+        // general, split along k by default, tall-skinny, short-fat, and matrix-vector, and
+        // tall-skinny with panels small enough to be blocked differently from the whole product
+        for (m, n, k) in [
+            (97, 83, 611),
+            (16, 16, 4096),
+            (1000, 5, 300),
+            (3, 2000, 67),
+            (301, 1, 257),
+            (512, 12, 3000),
+            (12, 512, 3000),
+        ] {
+            let value = |i: usize| (((i * 2654435761) % 1000) as f32 - 500.0) / 37.0;
+            let lhs: Vec<f32> = (0..m * k).map(value).collect();
+            let rhs: Vec<f32> = (0..k * n).map(|i| value(i + 17)).collect();
+            let init: Vec<f32> = (0..m * n).map(|i| value(i + 5)).collect();
+
+            for (lhs_cs, lhs_rs) in [(m as isize, 1isize), (1, k as isize)] {
+                let run = |parallelism: Parallelism<'_>| {
+                    let mut dst = init.clone();
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            lhs.as_ptr(),
+                            lhs_cs,
+                            lhs_rs,
+                            rhs.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            1.5,
+                            false,
+                            false,
+                            false,
+                            parallelism,
+                        );
+                    }
+                    dst
+                };
+
+                let expected = run(Parallelism::None);
+                for parallelism in parallelisms {
+                    let dst = run(parallelism);
+                    assert!(dst
+                        .iter()
+                        .zip(expected.iter())
+                        .all(|(dst, expected)| dst.to_bits() == expected.to_bits()));
+                }
+            }
+        }
+
+        set_deterministic(DEFAULT_DETERMINISTIC);
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_gemm_deterministic_order() {
+        // single products opt in, and products with fixed blocking parameters are always
+        // accumulated in the same order, on every run and for any number of threads
+        for (m, n, k) in [(512, 12, 3000), (12, 512, 3000), (97, 83, 611)] {
+            let value = |i: usize| (((i * 2654435761) % 1000) as f64 - 500.0) / 37.0;
+            let lhs: Vec<f64> = (0..m * k).map(value).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i + 17)).collect();
+
+            let run = |parallelism: Parallelism<'_>, params: Option<KernelParams>| {
+                let mut dst = vec![0.0; m * n];
+                unsafe {
+                    gemm_with_config(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        false,
+                        lhs.as_ptr(),
+                        m as isize,
+                        1,
+                        rhs.as_ptr(),
+                        k as isize,
+                        1,
+                        0.0,
+                        1.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                        GemmConfig {
+                            kernel_params: params,
+                            deterministic: Some(true),
+                            ..Default::default()
+                        },
+                    );
+                }
+                dst
+            };
+
+            for params in [
+                None,
+                Some(KernelParams {
+                    kc: 200,
+                    mc: 0,
+                    nc: 0,
+                }),
+            ] {
+                let expected = run(Parallelism::None, params);
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(2),
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(8),
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(8),
+                ] {
+                    let dst = run(parallelism, params);
+                    assert!(dst
+                        .iter()
+                        .zip(expected.iter())
+                        .all(|(dst, expected)| dst.to_bits() == expected.to_bits()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_deterministic_blocking() {
+        use gemm_common::gemm::select_blocking;
+
+        // products whose register blocking depends on the number of threads, which is ignored in
+        // deterministic mode
+        let spawner = ScopedSpawner::new(32);
+        let blockings = [(16, 6), (24, 4), (8, 12), (8, 6)];
+        for (m, n, k) in [(8, 177, 700), (15, 167, 300)] {
+            let config = |deterministic| GemmConfig::<f32> {
+                threading_threshold: Some(0),
+                deterministic: Some(deterministic),
+                ..Default::default()
+            };
+            let select = |parallelism, deterministic| {
+                select_blocking(m, n, k, 8, &blockings, parallelism, &config(deterministic))
+            };
+            assert_ne!(
+                select(Parallelism::None, false),
+                select(Parallelism::Custom(&spawner), false)
+            );
+            assert_eq!(
+                select(Parallelism::None, true),
+                select(Parallelism::Custom(&spawner), true)
+            );
+
+            let value = |i: usize| (((i * 2654435761) % 1000) as f32 - 500.0) / 37.0;
+            let lhs: Vec<f32> = (0..m * k).map(value).collect();
+            let rhs: Vec<f32> = (0..k * n).map(|i| value(i + 17)).collect();
+            let run = |parallelism: Parallelism<'_>| {
+                let mut dst = vec![0.0; m * n];
+                unsafe {
+                    gemm_with_config(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        false,
+                        lhs.as_ptr(),
+                        m as isize,
+                        1,
+                        rhs.as_ptr(),
+                        k as isize,
+                        1,
+                        0.0,
+                        1.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                        config(true),
+                    );
+                }
+                dst
+            };
+
+            let expected = run(Parallelism::None);
+            for parallelism in [
+                Parallelism::Custom(&spawner),
+                #[cfg(feature = "rayon")]
+                Parallelism::Rayon(32),
+            ] {
+                let dst = run(parallelism);
+                assert!(dst
+                    .iter()
+                    .zip(expected.iter())
+                    .all(|(dst, expected)| dst.to_bits() == expected.to_bits()));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_gemm_nested() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        use rayon::prelude::*;
+
+        let max_threads = || gemm_common::gemm::max_threads(Parallelism::Rayon(0));
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let pair = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        // the other half of the join is still queued on the only worker
+        assert_eq!(single.install(|| rayon::join(max_threads, || ()).0), 1);
+        assert_eq!(pair.install(max_threads), 2);
+        assert_eq!(
+            pair.install(|| gemm_common::gemm::max_threads(Parallelism::Rayon(2))),
+            2
+        );
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        let (m, n, k) = (67, 45, 129);
+        let lhs: Vec<f64> = (0..m * k).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+        let rhs: Vec<f64> = (0..k * n).map(|i| ((i % 5) as f64 - 2.0) / 3.0).collect();
+        let mut target = vec![0.0; m * n];
+        unsafe {
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                target.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+
+        let mut dsts = vec![vec![0.0; m * n]; 16];
+        dsts.par_iter_mut().for_each(|dst| unsafe {
+            gemm(
+                m,
+                n,
+                k,
+                dst.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::Rayon(0),
+            )
+        });
+        for dst in &dsts {
+            for (dst, target) in dst.iter().zip(target.iter()) {
+                assert!((dst - target).abs() < 1e-10);
+            }
+        }
+
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_gemm_late_thread() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // the first task only starts once every other one has returned, so that its share of
+        // the work must be taken over by the others
+        struct Spawner;
+        impl ThreadSpawner for Spawner {
+            fn num_threads(&self) -> usize {
+                4
+            }
+            fn for_each(&self, n_tasks: usize, func: &(dyn Fn(usize) + Send + Sync)) {
+                std::thread::scope(|s| {
+                    for tid in 1..n_tasks {
+                        s.spawn(move || func(tid));
+                    }
+                });
+                if n_tasks > 0 {
+                    func(0);
+                }
+            }
+        }
+        static SPAWNER: Spawner = Spawner;
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        for (m, n, k) in [(127, 93, 77), (301, 47, 200), (33, 250, 64)] {
+            let value = |i: usize| ((i * 37 % 101) as f32 - 50.0) / 16.0;
+            let lhs: Vec<f64> = (0..m * k).map(|i| value(i) as f64).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i + 1) as f64).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| value(i + 2) as f64).collect();
+            let lhs16: Vec<f16> = (0..m * k).map(|i| f16::from_f32(value(i))).collect();
+            let rhs16: Vec<f16> = (0..k * n).map(|i| f16::from_f32(value(i + 1))).collect();
+            let init16: Vec<f16> = (0..m * n).map(|i| f16::from_f32(value(i + 2))).collect();
+
+            let run = |parallelism: Parallelism<'_>| unsafe {
+                let mut dst = init.clone();
+                let mut dst16 = init16.clone();
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                );
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst16.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs16.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs16.as_ptr(),
+                    k as isize,
+                    1,
+                    f16::from_f32(0.5),
+                    f16::from_f32(2.0),
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                );
+                (dst, dst16)
+            };
+
+            let (expected, expected16) = run(Parallelism::None);
+            let (dst, dst16) = run(Parallelism::Custom(&SPAWNER));
+            assert!(dst
+                .iter()
+                .zip(expected.iter())
+                .all(|(dst, expected)| dst.to_bits() == expected.to_bits()));
+            assert!(dst16
+                .iter()
+                .zip(expected16.iter())
+                .all(|(dst, expected)| dst.to_bits() == expected.to_bits()));
+        }
+
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_tile_blocks() {
+        use gemm_common::{cache::DivCeil, gemm::tile_blocks};
+
+        assert_eq!(tile_blocks(1, 40, 30, 8, 4), (40, 30));
+        for n_threads in [2, 3, 8, 64] {
+            for (row_tiles, col_tiles, mr, nr) in [
+                (48, 48, 8, 8),
+                (200, 3, 16, 4),
+                (2, 300, 8, 6),
+                (1, 1, 8, 4),
+            ] {
+                let (block_rows, block_cols) = tile_blocks(n_threads, row_tiles, col_tiles, mr, nr);
+                assert!(block_rows >= 1 && block_rows <= row_tiles);
+                assert!(block_cols >= 1 && block_cols <= col_tiles);
+
+                // enough rectangles to keep the threads busy, if there are enough tiles
+                let n_blocks =
+                    row_tiles.msrv_div_ceil(block_rows) * col_tiles.msrv_div_ceil(block_cols);
+                assert!(n_blocks >= Ord::min(n_threads, row_tiles * col_tiles));
+            }
+        }
+
+        // square grids are split into square rectangles
+        let (block_rows, block_cols) = tile_blocks(4, 64, 64, 8, 8);
+        assert_eq!(block_rows, block_cols);
+    }
+
+    #[test]
+    fn test_gemm_async() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Wake, Waker};
+
+        // polls on the calling thread, which is unparked by the waker
+        struct Unpark(std::thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        fn block_on(mut future: GemmFuture) {
+            let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+            let mut cx = Context::from_waker(&waker);
+            while std::pin::Pin::new(&mut future).poll(&mut cx).is_pending() {
+                std::thread::park();
+            }
+            assert!(future.is_done());
+        }
+
+        let (m, n, k) = (193, 157, 211);
+        let lhs: Vec<f32> = (0..m * k).map(|i| ((i % 7) as f32 - 3.0) / 4.0).collect();
+        let rhs: Vec<f32> = (0..k * n).map(|i| ((i % 5) as f32 - 2.0) / 3.0).collect();
+        let init: Vec<f32> = (0..m * n).map(|i| ((i % 3) as f32 - 1.0) / 2.0).collect();
+
+        let run = |dst: &mut [f32], parallelism: Parallelism<'static>| unsafe {
+            gemm_async(
+                m,
+                n,
+                k,
+                dst.as_mut_ptr(),
+                m as isize,
+                1,
+                true,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                0.5,
+                2.0,
+                false,
+                false,
+                false,
+                parallelism,
+            )
+        };
+
+        let mut target = init.clone();
+        unsafe {
+            gemm(
+                m,
+                n,
+                k,
+                target.as_mut_ptr(),
+                m as isize,
+                1,
+                true,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                0.5,
+                2.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+        }
+
+        // a panicking multiplication doesn't stop the background thread of the later ones
+        let src = [1u8; 4];
+        let panicked = std::panic::catch_unwind(|| unsafe {
+            let mut dst = [0u8; 4];
+            gemm_async(
+                2,
+                2,
+                2,
+                dst.as_mut_ptr(),
+                2,
+                1,
+                false,
+                src.as_ptr(),
+                2,
+                1,
+                src.as_ptr(),
+                2,
+                1,
+                0,
+                1,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            )
+            .wait();
+        });
+        assert!(panicked.is_err());
+
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(0),
+        ] {
+            // awaited, waited on, and dropped before completion
+            let mut awaited = init.clone();
+            block_on(run(&mut awaited, parallelism));
+            let mut waited = init.clone();
+            run(&mut waited, parallelism).wait();
+            let mut dropped = init.clone();
+            drop(run(&mut dropped, parallelism));
+
+            for dst in [&awaited, &waited, &dropped] {
+                for (dst, target) in dst.iter().zip(target.iter()) {
+                    assert!((dst - target).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "background thread")]
+    fn test_gemm_async_panic() {
+        let mut dst = [0u8; 4];
+        let src = [1u8; 4];
+        unsafe {
+            gemm_async(
+                2,
+                2,
+                2,
+                dst.as_mut_ptr(),
+                2,
+                1,
+                false,
+                src.as_ptr(),
+                2,
+                1,
+                src.as_ptr(),
+                2,
+                1,
+                0,
+                1,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            )
+            .wait();
+        }
+    }
+
+    #[test]
+    fn test_available_cores() {
+        assert_eq!(parse_cpu_max("max 100000"), None);
+        assert_eq!(parse_cpu_max("-1 100000"), None);
+        assert_eq!(parse_cpu_max("200000 100000"), Some(2));
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(2));
+        assert_eq!(parse_cpu_max("50000"), Some(1));
+        assert_eq!(parse_cpu_max("abc 100000"), None);
+
+        let cores = available_cores();
+        assert!(cores >= 1);
+        if let Some(quota) = cgroup_cpu_quota() {
+            assert!(cores <= quota);
+        }
+        assert_eq!(ScopedSpawner::new(0).num_threads(), cores);
+        #[cfg(feature = "rayon")]
+        assert!(gemm_common::gemm::max_threads(Parallelism::Rayon(0)) <= cores);
+    }
+
+    #[test]
+    fn test_gemm_replicate_rhs() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        static SPAWNER: ScopedSpawner = ScopedSpawner::new(3);
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+        set_replicate_rhs(true);
+
+        for (m, n, k) in [(300, 200, 150), (97, 61, 300), (513, 17, 64)] {
+            let value = |i: usize| ((i * 29 % 97) as f64 - 48.0) / 8.0;
+            let lhs: Vec<f64> = (0..m * k).map(value).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i + 3)).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| value(i + 7)).collect();
+
+            // a row-major rhs is always packed
+            for (rhs_cs, rhs_rs) in [(k as isize, 1isize), (1, n as isize)] {
+                let run = |parallelism: Parallelism<'_>| {
+                    let mut dst = init.clone();
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            lhs.as_ptr(),
+                            m as isize,
+                            1,
+                            rhs.as_ptr(),
+                            rhs_cs,
+                            rhs_rs,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            parallelism,
+                        );
+                    }
+                    dst
+                };
+
+                let expected = run(Parallelism::None);
+                for parallelism in [
+                    Parallelism::Custom(&SPAWNER),
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                ] {
+                    let dst = run(parallelism);
+                    assert!(dst
+                        .iter()
+                        .zip(expected.iter())
+                        .all(|(dst, expected)| dst.to_bits() == expected.to_bits()));
+                }
+            }
+        }
+
+        set_replicate_rhs(DEFAULT_REPLICATE_RHS);
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_gemm_rhs_packing_threads() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // no more rhs panels than threads, so that the shared packing has threads with no panel
+        // to pack
+        let spawner = ScopedSpawner::new(16);
+        let parallelism = Parallelism::Custom(&spawner);
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+        set_replicate_rhs(false);
+
+        // not split along k, and too large to be read in place
+        let (m, k) = (150, 200);
+        for n in [13, 30, 61] {
+            let value = |i: usize| ((i * 29 % 97) as f64 - 48.0) / 64.0;
+            let lhs: Vec<f64> = (0..m * k).map(value).collect();
+            let rhs: Vec<f64> = (0..k * n).map(|i| value(i + 3)).collect();
+            let init: Vec<f64> = (0..m * n).map(|i| value(i + 7)).collect();
+
+            let mut dst = init.clone();
+            let mut target = init.clone();
+            unsafe {
+                gemm_with_config(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    GemmConfig {
+                        rhs_packing: Some(PackingPolicy::Always),
+                        ..Default::default()
+                    },
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+            for (dst, target) in dst.iter().zip(&target) {
+                assert_approx_eq::assert_approx_eq!(dst, target, 1e-10);
+            }
+
+            let lhs: Vec<f16> = lhs.iter().map(|&x| f16::from_f64(x)).collect();
+            let rhs: Vec<f16> = rhs.iter().map(|&x| f16::from_f64(x)).collect();
+            let init: Vec<f16> = init.iter().map(|&x| f16::from_f64(x)).collect();
+            let mut dst = init.clone();
+            let mut target = init.clone();
+            unsafe {
+                gemm_with_config(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    f16::from_f32(0.5),
+                    f16::from_f32(2.0),
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    GemmConfig {
+                        rhs_packing: Some(PackingPolicy::Always),
+                        ..Default::default()
+                    },
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    f16::from_f32(0.5),
+                    f16::from_f32(2.0),
+                );
+            }
+            for (dst, target) in dst.iter().zip(&target) {
+                let eps = f32::max(target.to_f32().abs() * 1e-1, 1e-1);
+                assert_approx_eq::assert_approx_eq!(dst.to_f32(), target.to_f32(), eps);
+            }
+        }
+
+        set_replicate_rhs(DEFAULT_REPLICATE_RHS);
+        set_threading_threshold(threshold);
+    }
+
+    #[test]
+    fn test_cache_info() {
+        use gemm_common::cache::CACHE_INFO;
+
+        for info in &CACHE_INFO[..2] {
+            assert!(info.cache_bytes > 0);
+            assert!(info.cache_line_bytes > 0);
+            assert!(info.associativity > 0);
+            assert!(info.shared_threads >= 1);
+            assert!(info.bytes_per_thread() <= info.cache_bytes);
+        }
+        assert!(CACHE_INFO[0].cache_bytes <= CACHE_INFO[1].cache_bytes);
+    }
+
+    #[test]
+    fn test_kernel_params_l3_domains() {
+        use gemm_common::cache::{kernel_params_for_caches, CacheInfo};
+
+        // one ccd of a chiplet processor, whose l3 is shared by 16 threads
+        let cache = |kib: usize, associativity: usize, shared_threads: usize| CacheInfo {
+            associativity,
+            cache_bytes: kib * 1024,
+            cache_line_bytes: 64,
+            shared_threads,
+        };
+        let (m, n, k) = (4096, 15360, 4096);
+        let (mr, nr, sizeof) = (8, 6, 8);
+        let private = kernel_params_for_caches(
+            &[cache(32, 8, 2), cache(512, 8, 2), cache(32 * 1024, 16, 1)],
+            m,
+            n,
+            k,
+            mr,
+            nr,
+            sizeof,
+        );
+        let shared = kernel_params_for_caches(
+            &[cache(32, 8, 2), cache(512, 8, 2), cache(32 * 1024, 16, 16)],
+            m,
+            n,
+            k,
+            mr,
+            nr,
+            sizeof,
+        );
+        assert_eq!((shared.kc, shared.mc), (private.kc, private.mc));
+        assert!(shared.nc < private.nc);
+        assert_eq!(shared.nc % nr, 0);
+        assert!((shared.kc * shared.nc + 16 * shared.mc * shared.kc) * sizeof <= 32 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_autotune() {
+        use gemm_common::cache::DivCeil;
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mr = gemm::get_backend::<f64>().mr;
+        let nr = gemm::get_backend::<f64>().nr;
+
+        let path = std::env::temp_dir().join(std::format!("gemm-tuning-{}", std::process::id()));
+        set_tuning_cache_path(Some(path.clone()));
+
+        let tuned = autotune(192, 192, 192, DType::F64);
+        let KernelParams { kc, mc, nc } = describe(192, 192, 192, DType::F64).kernel_params;
+        assert_eq!((kc, mc, nc), (tuned.kc, tuned.mc, tuned.nc));
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with(&std::format!(
+                "gemm tuning cache {}\n",
+                TUNING_CACHE_VERSION
+            )));
+
+        // adjusted to other problems
+        let KernelParams { kc, mc, nc } = describe(100, 300, 50, DType::F64).kernel_params;
+        assert!(kc <= 50);
+        assert!(mc % mr == 0 && mc <= 100usize.msrv_next_multiple_of(mr));
+        assert!(nc % nr == 0 && nc <= 300usize.msrv_next_multiple_of(nr));
+
+        let (m, n, k) = (100, 300, 50);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        let mut dst = vec![0.0; m * n];
+        let mut target = vec![0.0; m * n];
+        unsafe {
+            gemm::gemm(
+                m,
+                n,
+                k,
+                dst.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                target.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+        for (dst, target) in dst.iter().zip(&target) {
+            assert_approx_eq::assert_approx_eq!(dst, target);
+        }
+
+        clear_tuned_kernel_params();
+        let KernelParams { kc, mc, nc } = describe(192, 192, 192, DType::F64).kernel_params;
+        let model =
+            gemm_common::gemm::gemm_kernel_params::<f64>(192, 192, 192, mr, nr, Parallelism::None);
+        assert_eq!((kc, mc, nc), (model.kc, model.mc, model.nc));
+
+        // installed again from the cache
+        assert_eq!(load_tuning_cache().unwrap(), 1);
+        let KernelParams { kc, mc, nc } = describe(192, 192, 192, DType::F64).kernel_params;
+        assert_eq!((kc, mc, nc), (tuned.kc, tuned.mc, tuned.nc));
+        clear_tuned_kernel_params();
+
+        // entries of other CPUs and files of other versions are ignored
+        let entry = std::format!(
+            "\t{}\t{}\t{}\tgeneral\t{}\t8\t{}\t{}\n",
+            core::any::type_name::<f64>(),
+            mr,
+            nr,
+            gemm::get_backend::<f64>().name,
+            mr,
+            nr,
+        );
+        let other_cpu = std::format!("other cpu{}", entry);
+        let this_cpu = std::format!("{}{}", cpu_model(), entry);
+        for (version, lines, count) in [
+            (
+                TUNING_CACHE_VERSION,
+                std::format!("{}{}", other_cpu, this_cpu),
+                1,
+            ),
+            (TUNING_CACHE_VERSION, other_cpu, 0),
+            (TUNING_CACHE_VERSION + 1, this_cpu, 0),
+        ] {
+            std::fs::write(
+                &path,
+                std::format!("gemm tuning cache {}\n{}", version, lines),
+            )
+            .unwrap();
+            assert_eq!(load_tuning_cache().unwrap(), count);
+            clear_tuned_kernel_params();
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        set_tuning_cache_path(default_tuning_cache_path());
+    }
+
+    #[test]
+    fn test_kernel_params_override() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mr = gemm::get_backend::<f64>().mr;
+        let nr = gemm::get_backend::<f64>().nr;
+        let model = describe(300, 300, 300, DType::F64).kernel_params;
+
+        set_kernel_params_override(KernelParams {
+            kc: 100,
+            mc: 0,
+            nc: 2 * nr - 1,
+        });
+        let params = describe(300, 300, 300, DType::F64).kernel_params;
+        set_kernel_params_override(KernelParams::default());
+        assert_eq!((params.kc, params.mc, params.nc), (100, model.mc, 2 * nr));
+
+        let mut init = 0.0;
+        let (m, n, k) = (100, 90, 50);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        for colmajor in [true, false] {
+            for params in [
+                KernelParams {
+                    kc: 7,
+                    mc: 2 * mr + 1,
+                    nc: 3 * nr,
+                },
+                KernelParams {
+                    kc: 0,
+                    mc: mr,
+                    nc: 0,
+                },
+            ] {
+                init += 1.0;
+                let mut dst = vec![init; m * n];
+                let mut target = dst.clone();
+                let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+                unsafe {
+                    gemm_with_config(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        Parallelism::None,
+                        GemmConfig {
+                            kernel_params: Some(params),
+                            ..Default::default()
+                        },
+                    );
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        k,
+                        target.as_mut_ptr(),
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                    );
+                }
+                for (dst, target) in dst.iter().zip(&target) {
+                    assert_approx_eq::assert_approx_eq!(dst, target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        use gemm_common::{cache::DivCeil, presets::get_preset_kernel_params};
+
+        assert_eq!(
+            Microarch::from_cpuid("AuthenticAMD", 0x17, 0x31),
+            Some(Microarch::Zen2)
+        );
+        assert_eq!(Microarch::from_cpuid("AuthenticAMD", 0x17, 0x08), None);
+        assert_eq!(
+            Microarch::from_cpuid("AuthenticAMD", 0x19, 0x21),
+            Some(Microarch::Zen3)
+        );
+        assert_eq!(
+            Microarch::from_cpuid("AuthenticAMD", 0x19, 0x61),
+            Some(Microarch::Zen4)
+        );
+        assert_eq!(
+            Microarch::from_cpuid("GenuineIntel", 6, 0x55),
+            Some(Microarch::SkylakeX)
+        );
+        assert_eq!(
+            Microarch::from_cpuid("GenuineIntel", 6, 0x6a),
+            Some(Microarch::IceLakeServer)
+        );
+        assert_eq!(Microarch::from_cpuid("GenuineIntel", 6, 0x8f), None);
+
+        let params = get_preset_kernel_params::<f64>(Microarch::Zen3, 8, 6).unwrap();
+        assert_eq!(params.mc % 8, 0);
+        assert_eq!(params.nc % 6, 0);
+        assert!(get_preset_kernel_params::<f64>(Microarch::Zen3, 16, 6).is_none());
+        assert!(get_preset_kernel_params::<c64>(Microarch::Zen3, 8, 6).is_none());
+
+        let backend = gemm::get_backend::<f64>();
+        let (m, n, k) = (300, 300, 300);
+        let preset = gemm_common::presets::preset_kernel_params::<f64>;
+        match detect_microarch()
+            .and_then(|arch| get_preset_kernel_params::<f64>(arch, backend.mr, backend.nr))
+        {
+            Some(params) => {
+                let fitted = preset(m, n, k, backend.mr, backend.nr).unwrap();
+                assert!(fitted.kc <= params.kc);
+                assert!(fitted.mc <= params.mc.msrv_next_multiple_of(backend.mr));
+            }
+            None => assert!(preset(m, n, k, backend.mr, backend.nr).is_none()),
+        }
+
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_presets_enabled(false);
+        assert!(!get_presets_enabled());
+        assert!(preset(m, n, k, backend.mr, backend.nr).is_none());
+        set_presets_enabled(DEFAULT_PRESETS_ENABLED);
+    }
+
+    #[test]
+    fn test_threading_threshold() {
+        assert_eq!(get_type_threading_threshold::<c32>(), None);
+        set_type_threading_threshold::<c32>(Some(1000));
+        assert_eq!(get_type_threading_threshold::<c32>(), Some(1000));
+        assert_eq!(threading_threshold::<c32>(64), 1000);
+        assert_eq!(threading_threshold::<c32>(16), 1000);
+        set_type_threading_threshold::<c32>(None);
+        assert_eq!(get_type_threading_threshold::<c32>(), None);
+        assert_eq!(get_type_threading_threshold::<u8>(), None);
+
+        let (m, n, k) = (130, 90, 70);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        for threshold in [0, 1000, usize::MAX] {
+            for parallelism in [
+                Parallelism::None,
+                #[cfg(feature = "rayon")]
+                Parallelism::Rayon(4),
+            ] {
+                let mut dst = vec![1.0; m * n];
+                let mut target = dst.clone();
+                unsafe {
+                    gemm_with_config(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                        GemmConfig {
+                            threading_threshold: Some(threshold),
+                            ..Default::default()
+                        },
+                    );
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        k,
+                        target.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.5,
+                        2.0,
+                    );
+                }
+                for (dst, target) in dst.iter().zip(&target) {
+                    assert_approx_eq::assert_approx_eq!(dst, target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_gemm_with_config() {
+        // a product split along k, whose slices get the settings of the config, and products with
+        // several settings at once
+        let (m, n, k) = (4, 3, 5000);
+        let parallelism = Parallelism::Rayon(4);
+        let backend = gemm::get_backend::<f64>();
+        assert!(split_k::n_splits(backend, m, n, k, parallelism, Some(0)) > 1);
+
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        for (threshold, deterministic, streaming_stores, rhs_packing) in [
+            (Some(0), None, None, None),
+            (Some(0), Some(true), Some(true), Some(PackingPolicy::Always)),
+            (
+                Some(usize::MAX),
+                Some(false),
+                Some(false),
+                Some(PackingPolicy::Never),
+            ),
+            (None, Some(true), None, Some(PackingPolicy::Auto)),
+        ] {
+            let mut dst = vec![1.0; m * n];
+            let mut target = dst.clone();
+            unsafe {
+                gemm_with_config(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    GemmConfig {
+                        threading_threshold: threshold,
+                        deterministic,
+                        streaming_stores,
+                        rhs_packing,
+                        ..Default::default()
+                    },
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    target.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+            for (dst, target) in dst.iter().zip(&target) {
+                assert_approx_eq::assert_approx_eq!(dst, target, 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_stores() {
+        assert!(get_streaming_stores_threshold() > 0);
+
+        let (m, n, k) = (96, 60, 40);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        for read_dst in [false, true] {
+            for colmajor in [true, false] {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                ] {
+                    let mut dst = vec![1.0; m * n];
+                    let mut target = dst.clone();
+                    let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+                    unsafe {
+                        gemm_with_config(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            read_dst,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            parallelism,
+                            GemmConfig {
+                                streaming_stores: Some(true),
+                                ..Default::default()
+                            },
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            read_dst,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (dst, target) in dst.iter().zip(&target) {
+                        assert_approx_eq::assert_approx_eq!(dst, target);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_packing_policy() {
+        use PackingPolicy::{Always, Auto, Never};
+
+        assert_eq!(get_lhs_packing_policy(), Auto);
+        assert_eq!(get_rhs_packing_policy(), Auto);
+
+        // column-major rhs with a large depth, and a row-major lhs that is always packed
+        let (m, n, k) = (300, 70, 900);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        for lhs_packing in [Auto, Always, Never] {
+            for rhs_packing in [Auto, Always, Never] {
+                for (lhs_cs, lhs_rs) in [(m as isize, 1), (1, k as isize)] {
+                    for colmajor in [true, false] {
+                        let mut dst = vec![1.0; m * n];
+                        let mut target = dst.clone();
+                        let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+                        unsafe {
+                            gemm_with_config(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                dst_cs as isize,
+                                dst_rs as isize,
+                                true,
+                                a.as_ptr(),
+                                lhs_cs,
+                                lhs_rs,
+                                b.as_ptr(),
+                                k as isize,
+                                1,
+                                0.5,
+                                2.0,
+                                false,
+                                false,
+                                false,
+                                #[cfg(feature = "rayon")]
+                                Parallelism::Rayon(4),
+                                #[cfg(not(feature = "rayon"))]
+                                Parallelism::None,
+                                GemmConfig {
+                                    lhs_packing: Some(lhs_packing),
+                                    rhs_packing: Some(rhs_packing),
+                                    ..Default::default()
+                                },
+                            );
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                target.as_mut_ptr(),
+                                dst_cs as isize,
+                                dst_rs as isize,
+                                true,
+                                a.as_ptr(),
+                                lhs_cs,
+                                lhs_rs,
+                                b.as_ptr(),
+                                k as isize,
+                                1,
+                                0.5,
+                                2.0,
+                            );
+                        }
+                        for (dst, target) in dst.iter().zip(&target) {
+                            assert_approx_eq::assert_approx_eq!(dst, target, 1e-9 * k as f64);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_weight_cache() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        assert_eq!(get_weight_cache_capacity(), DEFAULT_WEIGHT_CACHE_CAPACITY);
+
+        let (m, n, k) = (100, 70, 300);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let mut b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        let run = |b: &[f64], colmajor: bool, reference: bool| {
+            let mut dst = vec![0.0; m * n];
+            let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+            unsafe {
+                if reference {
+                    gemm::gemm_fallback(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        false,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.0,
+                        1.0,
+                    );
+                } else {
+                    gemm(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        false,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.0,
+                        1.0,
+                        false,
+                        false,
+                        false,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(4),
+                        #[cfg(not(feature = "rayon"))]
+                        Parallelism::None,
+                    );
+                }
+            }
+            dst
+        };
+        let check = |dst: &[f64], target: &[f64]| {
+            for (dst, target) in dst.iter().zip(target) {
+                assert_approx_eq::assert_approx_eq!(dst, target, 1e-9 * k as f64);
+            }
+        };
+
+        set_weight_cache_capacity(1 << 20);
+        for colmajor in [true, false] {
+            let old = b.clone();
+            check(&run(&b, colmajor, false), &run(&b, colmajor, true));
+
+            // the panels of the modified rhs are found at the same address
+            for x in &mut b {
+                *x = rand::random();
+            }
+            check(&run(&b, colmajor, false), &run(&old, colmajor, true));
+
+            clear_weight_cache();
+            check(&run(&b, colmajor, false), &run(&b, colmajor, true));
+        }
+
+        // the panels are dropped once the cache is too small to hold them
+        set_weight_cache_capacity(1024);
+        for x in &mut b {
+            *x = rand::random();
+        }
+        check(&run(&b, false, false), &run(&b, false, true));
+
+        set_weight_cache_capacity(DEFAULT_WEIGHT_CACHE_CAPACITY);
+        clear_weight_cache();
+    }
+
+    #[test]
+    fn test_row_major_operands() {
+        // the products with a strided destination are transposed to read both operands in place,
+        // while the ones with a column-major destination keep its unit-stride stores
+        assert!(!gemm::transposes_product(64, 1, 1, 64, 1, 64));
+        assert!(gemm::transposes_product(128, 2, 1, 64, 1, 64));
+        for (m, n, k) in [(1, 50, 30), (5, 700, 40), (64, 64, 192), (300, 70, 900)] {
+            let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+            let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+            for (dst_cs, dst_rs) in [(m, 1), (2 * m, 2)] {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                ] {
+                    let mut dst = vec![1.0; dst_cs * n];
+                    let mut target = dst.clone();
+                    let mut mem = dyn_stack::GlobalMemBuffer::new(gemm_req::<f64>(
+                        m,
+                        n,
+                        k,
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        parallelism,
+                    ));
+                    try_gemm(
+                        m,
+                        n,
+                        k,
+                        &mut dst,
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        true,
+                        &a,
+                        1,
+                        k as isize,
+                        &b,
+                        1,
+                        n as isize,
+                        0.5,
+                        2.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                        Some(dyn_stack::DynStack::new(&mut mem)),
+                    )
+                    .unwrap();
+                    unsafe {
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            target.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            true,
+                            a.as_ptr(),
+                            1,
+                            k as isize,
+                            b.as_ptr(),
+                            1,
+                            n as isize,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (dst, target) in dst.iter().zip(&target) {
+                        assert_approx_eq::assert_approx_eq!(dst, target, 1e-9 * k as f64);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_row_major_operands_packing() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (m, n, k) = (48, 96, 200);
+        let a = (0..m * k).map(|_| rand::random()).collect::<Vec<f64>>();
+        let b = (0..k * n).map(|_| rand::random()).collect::<Vec<f64>>();
+        let packed_rhs_bytes = |dst_cs: usize, dst_rs: usize| {
+            let mut dst = vec![0.0; dst_cs * n];
+            reset_driver_stats();
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    dst_cs as isize,
+                    dst_rs as isize,
+                    false,
+                    a.as_ptr(),
+                    1,
+                    k as isize,
+                    b.as_ptr(),
+                    1,
+                    n as isize,
+                    0.0,
+                    1.0,
+                    false,
+                    false,
+                    false,
+                    Parallelism::None,
+                )
+            };
+            driver_stats().packed_rhs_bytes
+        };
+        let rhs_bytes = (k * n * core::mem::size_of::<f64>()) as u64;
+
+        // a column-major destination keeps the rhs packed
+        assert!(packed_rhs_bytes(m, 1) >= rhs_bytes);
+        // the transposed product of a strided destination reads `b` in place, and at most packs
+        // the transposed lhs, which is smaller. other tests may pack concurrently, so one of a
+        // few calls must pack less than `b`
+        assert!((0..8).any(|_| packed_rhs_bytes(2 * m, 2) < rhs_bytes));
+    }
+
+    #[test]
+    fn test_select_blocking() {
+        use gemm_common::gemm::select_blocking;
+
+        let config = GemmConfig::<f32>::default;
+        // square, tall, wide and small blockings of 256-bit f32 vectors
+        let blockings = [(16, 6), (24, 4), (8, 12), (8, 6)];
+        assert_eq!(
+            select_blocking(1024, 1024, 256, 8, &blockings, Parallelism::None, &config()),
+            0
+        );
+        // 1032 rows are a whole number of tall blocks, but leave a partial square one
+        assert_eq!(
+            select_blocking(1032, 2, 256, 8, &blockings, Parallelism::None, &config()),
+            1
+        );
+        assert_eq!(
+            select_blocking(8, 1024, 256, 8, &blockings, Parallelism::None, &config()),
+            2
+        );
+        assert_eq!(
+            select_blocking(
+                1024,
+                2,
+                256,
+                8,
+                &blockings[..1],
+                Parallelism::None,
+                &config()
+            ),
+            0
+        );
+        // products with fewer square blocks than threads are split into small blocks
+        let threaded = GemmConfig::<f32> {
+            threading_threshold: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            select_blocking(32, 12, 256, 8, &blockings, Parallelism::None, &threaded),
+            0
+        );
+        #[cfg(feature = "rayon")]
+        assert_eq!(
+            select_blocking(32, 12, 256, 8, &blockings, Parallelism::Rayon(8), &threaded),
+            3
+        );
+        // the blocking parameters are given for the reported blocking
+        let config = GemmConfig::<f32> {
+            kernel_params: Some(KernelParams {
+                kc: 256,
+                mc: 96,
+                nc: 96,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            select_blocking(1024, 2, 256, 8, &blockings, Parallelism::None, &config),
+            0
+        );
+
+        // products with few columns or few rows, computed with the tall and wide blockings, and
+        // small products split between threads, computed with the small blocking
+        for (m, n, k, parallelism) in [
+            (1024, 12, 256, Parallelism::None),
+            (12, 1024, 256, Parallelism::None),
+            (17, 1000, 50, Parallelism::None),
+            (4, 301, 33, Parallelism::None),
+            #[cfg(feature = "rayon")]
+            (32, 12, 256, Parallelism::Rayon(8)),
+            #[cfg(feature = "rayon")]
+            (41, 13, 64, Parallelism::Rayon(8)),
+        ] {
+            let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+            let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+            let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+            let mut d = c.clone();
+
+            unsafe {
+                gemm_with_config(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    GemmConfig {
+                        threading_threshold: Some(0),
+                        ..Default::default()
+                    },
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    d.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+            for (c, d) in c.iter().zip(d.iter()) {
+                assert_approx_eq::assert_approx_eq!(c, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dual_accumulators() {
+        // partial blocks are computed by kernels with few accumulators, which alternate between
+        // two sets of them, with an odd depth so that the leftover step only updates one set
+        for m in [9, 17, 33] {
+            for n in [9, 13] {
+                for k in [1, 2, 3, 7, 64] {
+                    let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+                    let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+                    let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                    let mut d = c.clone();
+
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            c.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dual_accumulators_x1x1() {
+        use gemm_common::microkernel::MicroKernelFn;
+
+        // the x1x1 kernels have a single accumulator, so they alternate between two sets of them,
+        // which are only summed at the end of the depth
+        let mut kernels: Vec<(usize, MicroKernelFn<f64>)> =
+            vec![(1, gemm_f64::microkernel::scalar::f64::UKR[0][0])];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("fma") {
+            kernels.push((4, gemm_f64::microkernel::fma::f64::UKR[0][0]));
+        }
+
+        for (mr, ukr) in kernels {
+            for m in [mr, 1] {
+                for k in [1, 2, 3, 17] {
+                    for alpha_status in [0u8, 1, 2] {
+                        let lhs: Vec<f64> = (0..mr * k).map(|_| rand::random()).collect();
+                        let rhs: Vec<f64> = (0..k).map(|_| rand::random()).collect();
+                        let mut dst: Vec<f64> = (0..m).map(|_| rand::random()).collect();
+                        let (alpha, beta) = if alpha_status == 1 {
+                            (1.0, 2.0)
+                        } else {
+                            (0.5, 2.0)
+                        };
+
+                        let expected: Vec<f64> = (0..m)
+                            .map(|i| {
+                                let acc: f64 =
+                                    (0..k).map(|depth| lhs[depth * mr + i] * rhs[depth]).sum();
+                                let dst = if alpha_status == 0 {
+                                    0.0
+                                } else {
+                                    alpha * dst[i]
+                                };
+                                dst + beta * acc
+                            })
+                            .collect();
+
+                        unsafe {
+                            ukr(
+                                m,
+                                1,
+                                k,
+                                dst.as_mut_ptr(),
+                                lhs.as_ptr(),
+                                rhs.as_ptr(),
+                                m as isize,
+                                1,
+                                mr as isize,
+                                1,
+                                1,
+                                alpha,
+                                beta,
+                                alpha_status,
+                                false,
+                                false,
+                                false,
+                                core::ptr::null(),
+                            );
+                        }
+                        for (c, d) in dst.iter().zip(expected.iter()) {
+                            assert_approx_eq::assert_approx_eq!(c, d);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemv_layouts() {
+        // products with at most 8 columns or rows, with each layout of the operands that has a
+        // unit stride along the depth or the long dimension
+        for small in 1..=8 {
+            for (long, k) in [(3, 37), (1000, 301), (2049, 64)] {
+                for (m, n) in [(long, small), (small, long)] {
+                    // (dst_cs, dst_rs, lhs_cs, lhs_rs, rhs_cs, rhs_rs)
+                    let layouts = [
+                        (m, 1, m, 1, k, 1),
+                        (m, 1, 1, k, k, 1),
+                        (1, n, 1, k, 1, n),
+                        (1, n, m, 1, 1, n),
+                    ];
+                    for (dst_cs, dst_rs, lhs_cs, lhs_rs, rhs_cs, rhs_rs) in layouts {
+                        for parallelism in [
+                            Parallelism::None,
+                            #[cfg(feature = "rayon")]
+                            Parallelism::Rayon(4),
+                        ] {
+                            let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+                            let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+                            let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                            let mut d = c.clone();
+
+                            unsafe {
+                                gemm(
+                                    m,
+                                    n,
+                                    k,
+                                    c.as_mut_ptr(),
+                                    dst_cs as isize,
+                                    dst_rs as isize,
+                                    true,
+                                    a.as_ptr(),
+                                    lhs_cs as isize,
+                                    lhs_rs as isize,
+                                    b.as_ptr(),
+                                    rhs_cs as isize,
+                                    rhs_rs as isize,
+                                    0.5,
+                                    2.0,
+                                    false,
+                                    false,
+                                    false,
+                                    parallelism,
+                                );
+                                gemm::gemm_fallback(
+                                    m,
+                                    n,
+                                    k,
+                                    d.as_mut_ptr(),
+                                    dst_cs as isize,
+                                    dst_rs as isize,
+                                    true,
+                                    a.as_ptr(),
+                                    lhs_cs as isize,
+                                    lhs_rs as isize,
+                                    b.as_ptr(),
+                                    rhs_cs as isize,
+                                    rhs_rs as isize,
+                                    0.5,
+                                    2.0,
+                                );
+                            }
+                            for (c, d) in c.iter().zip(d.iter()) {
+                                assert_approx_eq::assert_approx_eq!(c, d);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gevm() {
+        // products with a row-major lhs of at most 8 rows and a column-major rhs
+        for m in 1..=8 {
+            for (n, k) in [(3, 37), (1000, 301), (2049, 64)] {
+                for parallelism in [
+                    Parallelism::None,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                ] {
+                    for read_dst in [false, true] {
+                        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+                        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+                        let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                        let mut d = c.clone();
+
+                        unsafe {
+                            gemm(
+                                m,
+                                n,
+                                k,
+                                c.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                read_dst,
+                                a.as_ptr(),
+                                1,
+                                k as isize,
+                                b.as_ptr(),
+                                k as isize,
+                                1,
+                                0.5,
+                                2.0,
+                                false,
+                                false,
+                                false,
+                                parallelism,
+                            );
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                d.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                read_dst,
+                                a.as_ptr(),
+                                1,
+                                k as isize,
+                                b.as_ptr(),
+                                k as isize,
+                                1,
+                                0.5,
+                                2.0,
+                            );
+                        }
+                        for (c, d) in c.iter().zip(d.iter()) {
+                            assert_approx_eq::assert_approx_eq!(c, d);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_direct_kernels() {
+        // products small enough to be computed without packing, with edge tiles on both sides
+        for (m, n, k) in [
+            (1, 9, 3),
+            (7, 5, 11),
+            (16, 16, 16),
+            (33, 65, 17),
+            (128, 128, 128),
+        ] {
+            for (rhs_cs, rhs_rs) in [(k as isize, 1), (1, n as isize)] {
+                for read_dst in [false, true] {
+                    let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+                    let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+                    let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                    let mut d = c.clone();
+
+                    unsafe {
+                        gemm(
+                            m,
+                            n,
+                            k,
+                            c.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            read_dst,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            rhs_cs,
+                            rhs_rs,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                            Parallelism::None,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            read_dst,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            rhs_cs,
+                            rhs_rs,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_flush_denormals() {
+        let supported = cfg!(any(target_arch = "x86_64", target_arch = "aarch64"));
+        assert!(!thread_flushes_denormals());
+
+        // the products of the denormal lhs are denormal, and flushed to zero along with it
+        let (m, n, k) = (192, 160, 128);
+        let a = vec![1e-310f64; m * k];
+        let b = vec![1.0f64; k * n];
+        for flush in [false, true] {
+            for parallelism in [
+                Parallelism::None,
+                #[cfg(feature = "rayon")]
+                Parallelism::Rayon(4),
+            ] {
+                let mut c = vec![1.0f64; m * n];
+                unsafe {
+                    gemm_with_config(
+                        m,
+                        n,
+                        k,
+                        c.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        false,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        0.0,
+                        1.0,
+                        false,
+                        false,
+                        false,
+                        parallelism,
+                        GemmConfig {
+                            flush_denormals: Some(flush),
+                            ..Default::default()
+                        },
+                    );
+                }
+                assert!(!thread_flushes_denormals());
+                if flush && supported {
+                    assert!(c.iter().all(|&c| c == 0.0));
+                } else {
+                    assert!(c.iter().all(|&c| c == 1e-310 * k as f64));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compensated_summation() {
+        // a deep sum of positive terms, whose plain f32 accumulation loses several digits
+        let (m, n, k) = (2, 3, 1 << 20);
+        let a: Vec<f32> = (0..m * k).map(|_| 1.0 + rand::random::<f32>()).collect();
+        let b: Vec<f32> = (0..k * n).map(|_| 0.1 + rand::random::<f32>()).collect();
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(4),
+        ] {
+            let mut c = vec![0.0f32; m * n];
+            unsafe {
+                gemm_with_compensated_summation(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    false,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.0,
+                    1.0,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    true,
+                );
+            }
+            for i in 0..m {
+                for j in 0..n {
+                    let target: f64 = (0..k)
+                        .map(|depth| a[i + depth * m] as f64 * b[depth + j * k] as f64)
+                        .sum();
+                    let error = ((c[i + j * m] as f64 - target) / target).abs();
+                    assert!(error < 4.0 * f32::EPSILON as f64);
+                }
+            }
+        }
+
+        // both layouts of the destination, with conjugated complex operands
+        let (m, n, k) = (17, 13, 65);
+        let a: Vec<c64> = (0..m * k)
+            .map(|_| c64::new(rand::random(), rand::random()))
+            .collect();
+        let b: Vec<c64> = (0..k * n)
+            .map(|_| c64::new(rand::random(), rand::random()))
+            .collect();
+        let alpha = c64::new(0.5, -1.0);
+        let beta = c64::new(2.0, 0.25);
+        for colmajor in [true, false] {
+            for conj in [false, true] {
+                let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+                let mut c: Vec<c64> = (0..m * n).map(|_| c64::new(rand::random(), 1.0)).collect();
+                let mut d = c.clone();
+                unsafe {
+                    gemm_with_compensated_summation(
+                        m,
+                        n,
+                        k,
+                        c.as_mut_ptr(),
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        alpha,
+                        beta,
+                        conj,
+                        conj,
+                        !conj,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(3),
+                        #[cfg(not(feature = "rayon"))]
+                        Parallelism::None,
+                        true,
+                    );
+                    gemm::gemm_cplx_fallback(
+                        m,
+                        n,
+                        k,
+                        d.as_mut_ptr(),
+                        dst_cs as isize,
+                        dst_rs as isize,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        k as isize,
+                        1,
+                        alpha,
+                        beta,
+                        conj,
+                        conj,
+                        !conj,
+                    );
+                }
+                for (c, d) in c.iter().zip(d.iter()) {
+                    assert_approx_eq::assert_approx_eq!(c.re, d.re);
+                    assert_approx_eq::assert_approx_eq!(c.im, d.im);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_double_double() {
+        // the terms cancel each other out, leaving only the ones lost by f64 sums
+        let (m, n, k) = (3, 2, 4);
+        let a = [
+            1e16, 1e20, -3e17, 1.0, 1.0, 1.0, -1e16, -1e20, 3e17, 1.0, 1.0, 2.0,
+        ];
+        let b = [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.5];
+        let mut dst = [0.0; 6];
+        unsafe {
+            gemm_double_double(
+                m,
+                n,
+                k,
+                dst.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+                Parallelism::None,
+            );
+        }
+        assert_eq!(dst, [2.0, 2.0, 3.0, 1.5, 1.5, 2.0]);
+
+        // both layouts of the destination, on several threads
+        let (m, n, k) = (37, 29, 300);
+        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+        for colmajor in [true, false] {
+            let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+            let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+            let mut d = c.clone();
+            unsafe {
+                gemm_double_double(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    dst_cs as isize,
+                    dst_rs as isize,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    #[cfg(feature = "rayon")]
+                    Parallelism::Rayon(4),
+                    #[cfg(not(feature = "rayon"))]
+                    Parallelism::None,
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    d.as_mut_ptr(),
+                    dst_cs as isize,
+                    dst_rs as isize,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+            for (c, d) in c.iter().zip(d.iter()) {
+                assert_approx_eq::assert_approx_eq!(c, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_reference() {
+        // 2×2 product with a row-major rhs
+        let a = [1.0f64, 3.0, 2.0, 4.0];
+        let b = [5.0f64, 6.0, 7.0, 8.0];
+        let mut c = [1.0f64, 1.0, 1.0, 1.0];
+        unsafe {
+            gemm_reference(
+                2,
+                2,
+                2,
+                c.as_mut_ptr(),
+                2,
+                1,
+                true,
+                a.as_ptr(),
+                2,
+                1,
+                b.as_ptr(),
+                1,
+                2,
+                10.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+        }
+        assert_eq!(c, [29.0, 53.0, 32.0, 60.0]);
+
+        // the conjugation flags apply to the complex types
+        let a = [c32::new(1.0, 2.0)];
+        let b = [c32::new(3.0, -1.0)];
+        let mut c = [c32::new(1.0, 1.0)];
+        unsafe {
+            gemm_reference(
+                1,
+                1,
+                1,
+                c.as_mut_ptr(),
+                1,
+                1,
+                true,
+                a.as_ptr(),
+                1,
+                1,
+                b.as_ptr(),
+                1,
+                1,
+                c32::new(2.0, 0.0),
+                c32::new(1.0, 0.0),
+                true,
+                true,
+                false,
+                Parallelism::None,
+            );
+        }
+        // 2×conj(1 + i) + conj(1 + 2i)×(3 - i)
+        assert_eq!(c, [c32::new(3.0, -9.0)]);
+    }
+
+    #[test]
+    fn test_strict_arithmetic() {
+        fn check<T>()
+        where
+            T: 'static + Copy + PartialEq + core::fmt::Debug + num_traits::Float + Send + Sync,
+            for<'a> &'a T: core::ops::Add<&'a T, Output = T>,
+            for<'a> &'a T: core::ops::Mul<&'a T, Output = T>,
+            rand::distributions::Standard: rand::distributions::Distribution<T>,
+        {
+            for (m, n, k) in [(1, 1, 1), (37, 29, 300), (70, 9, 1100), (3, 64, 17)] {
+                let a: Vec<T> = (0..m * k).map(|_| rand::random()).collect();
+                let b: Vec<T> = (0..k * n).map(|_| rand::random()).collect();
+                let alpha = T::from(0.3).unwrap();
+                let beta = T::from(1.7).unwrap();
+                for (colmajor, read_dst) in [(true, true), (false, true), (true, false)] {
+                    let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+                    for parallelism in [
+                        Parallelism::None,
+                        #[cfg(feature = "rayon")]
+                        Parallelism::Rayon(4),
+                    ] {
+                        let mut c: Vec<T> = (0..m * n).map(|_| rand::random()).collect();
+                        let mut d = c.clone();
+                        unsafe {
+                            gemm_with_strict_arithmetic(
+                                m,
+                                n,
+                                k,
+                                c.as_mut_ptr(),
+                                dst_cs as isize,
+                                dst_rs as isize,
+                                read_dst,
+                                a.as_ptr(),
+                                m as isize,
+                                1,
+                                b.as_ptr(),
+                                1,
+                                n as isize,
+                                alpha,
+                                beta,
+                                false,
+                                false,
+                                false,
+                                parallelism,
+                                true,
+                            );
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                d.as_mut_ptr(),
+                                dst_cs as isize,
+                                dst_rs as isize,
+                                read_dst,
+                                a.as_ptr(),
+                                m as isize,
+                                1,
+                                b.as_ptr(),
+                                1,
+                                n as isize,
+                                alpha,
+                                beta,
+                            );
+                        }
+                        // bitwise identical to the naive loop
+                        assert_eq!(c, d);
+                    }
+                }
+            }
+        }
+
+        check::<f32>();
+        check::<f64>();
+    }
+
+    #[test]
+    fn test_error_bound() {
+        let (m, n, k) = (20, 30, 5000);
+        for parallelism in [
+            Parallelism::None,
+            #[cfg(feature = "rayon")]
+            Parallelism::Rayon(4),
+        ] {
+            let bound = error_bound(m, n, k, DType::F32, parallelism);
+            assert!(bound.kc >= 1 && bound.kc <= k);
+            assert!(bound.relative <= bound.relative_any_blocking);
+            // the blocked sums round each term far fewer times than a single sum of length `k`
+            assert!(bound.roundings < k);
+            assert!(bound.relative < k as f64 * bound.unit_roundoff);
+            assert!(
+                error_bound(m, n, k, DType::C32, parallelism).relative > bound.relative
+                    && error_bound(m, n, k, DType::F64, parallelism).relative < bound.relative
+            );
+
+            let a: Vec<f32> = (0..m * k).map(|_| rand::random::<f32>() - 0.25).collect();
+            let b: Vec<f32> = (0..k * n).map(|_| rand::random::<f32>() - 0.25).collect();
+            let c: Vec<f32> = (0..m * n).map(|_| rand::random::<f32>()).collect();
+            let (alpha, beta) = (0.5f32, 1.5f32);
+            let mut dst = c.clone();
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    alpha,
+                    beta,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                );
+            }
+            for i in 0..m {
+                for j in 0..n {
+                    let (mut exact, mut abs) = (0.0f64, 0.0f64);
+                    for depth in 0..k {
+                        let product = a[i + depth * m] as f64 * b[depth + j * k] as f64;
+                        exact += product;
+                        abs += product.abs();
+                    }
+                    let old = c[i + j * m] as f64;
+                    let exact = alpha as f64 * old + beta as f64 * exact;
+                    let abs = (alpha as f64 * old).abs() + (beta as f64 * abs).abs();
+                    assert!((dst[i + j * m] as f64 - exact).abs() <= bound.relative * abs);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let (m, n, k) = (37, 29, 300);
+        #[cfg(feature = "rayon")]
+        let parallelism = Parallelism::Rayon(4);
+        #[cfg(not(feature = "rayon"))]
+        let parallelism = Parallelism::None;
+        for dtype in [
+            #[cfg(feature = "f16")]
+            DType::F16,
+            DType::F32,
+            DType::F64,
+            DType::C32,
+            DType::C64,
+        ] {
+            let divergences = verify(m, n, k, dtype, 42, parallelism);
+            assert_eq!(divergences[0].backend, "scalar");
+            assert_eq!(divergences, verify(m, n, k, dtype, 42, parallelism));
+            // also covers the error of the f64 reference
+            let bound = error_bound(m, n, k, dtype, Parallelism::None).relative_any_blocking;
+            for divergence in divergences {
+                assert!(divergence.max_relative_divergence <= 2.0 * bound);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_plan() {
+        for (m, n, k) in [(4, 4, 4), (63, 65, 10), (256, 128, 300)] {
+            for parallelism in [
+                Parallelism::None,
+                #[cfg(feature = "rayon")]
+                Parallelism::Rayon(0),
+            ] {
+                let mut plan = GemmPlan::<f32>::new(m, n, k, parallelism);
+                for colmajor in [true, false, true] {
+                    let a: Vec<f32> = (0..m * k).map(|_| rand::random()).collect();
+                    let b: Vec<f32> = (0..k * n).map(|_| rand::random()).collect();
+                    let mut c: Vec<f32> = (0..m * n).map(|_| rand::random()).collect();
+                    let mut d = c.clone();
+                    let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+
+                    unsafe {
+                        plan.execute(
+                            c.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            k as isize,
+                            1,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d, 1e-3 * d.abs().max(1.0));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+    fn test_gemm_plan_jit() {
+        let blockings = [
+            JitBlocking::of::<f64>().unwrap(),
+            // unrolled loop with a remainder, and a shallower last block of k
+            JitBlocking {
+                mr: 4,
+                nr: 3,
+                unroll: 3,
+                kc: 7,
+            },
+            JitBlocking {
+                mr: 12,
+                nr: 2,
+                unroll: 1,
+                kc: 16,
+            },
+        ];
+        for blocking in blockings {
+            for (m, n, k) in [(4, 4, 4), (63, 65, 10), (256, 128, 300), (1, 17, 33)] {
+                #[cfg(feature = "rayon")]
+                let parallelism = Parallelism::Rayon(0);
+                #[cfg(not(feature = "rayon"))]
+                let parallelism = Parallelism::None;
+                let mut plan =
+                    GemmPlan::<f64>::new_jit_with_blocking(m, n, k, parallelism, blocking);
+                if !std::is_x86_feature_detected!("avx2") || !std::is_x86_feature_detected!("fma") {
+                    assert!(!plan.is_jit());
+                    return;
+                }
+                assert!(plan.is_jit());
+                for (colmajor, read_dst) in [(true, true), (false, true), (true, false)] {
+                    let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+                    let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+                    let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+                    let mut d = c.clone();
+                    let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+
+                    unsafe {
+                        plan.execute(
+                            c.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            read_dst,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            1,
+                            n as isize,
+                            0.5,
+                            2.0,
+                            false,
+                            false,
+                            false,
+                        );
+                        gemm::gemm_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            read_dst,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            1,
+                            n as isize,
+                            0.5,
+                            2.0,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(c, d);
+                    }
+                }
+            }
+        }
+
+        // too many accumulators for the registers
+        let blocking = JitBlocking {
+            mr: 16,
+            nr: 6,
+            unroll: 1,
+            kc: 0,
+        };
+        assert!(
+            !GemmPlan::<f64>::new_jit_with_blocking(4, 4, 4, Parallelism::None, blocking).is_jit()
+        );
+
+        let mut plan = GemmPlan::<f32>::new_jit(63, 65, 10, Parallelism::None);
+        let a: Vec<f32> = (0..63 * 10).map(|_| rand::random()).collect();
+        let b: Vec<f32> = (0..10 * 65).map(|_| rand::random()).collect();
+        let mut c = vec![0.0f32; 63 * 65];
+        let mut d = c.clone();
+        unsafe {
+            plan.execute(
+                c.as_mut_ptr(),
+                63,
+                1,
+                false,
+                a.as_ptr(),
+                63,
+                1,
+                b.as_ptr(),
+                10,
+                1,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+            );
+            gemm::gemm_fallback(
+                63,
+                65,
+                10,
+                d.as_mut_ptr(),
+                63,
+                1,
+                false,
+                a.as_ptr(),
+                63,
+                1,
+                b.as_ptr(),
+                10,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c, d, 1e-3 * d.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_gemm_alloc() {
+        // the shapes grow and shrink so the cached buffer is both reallocated and reused
+        for (m, n, k) in [(4, 4, 4), (256, 128, 300), (63, 65, 10), (300, 17, 600)] {
+            let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+            let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+            let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+            let mut d = c.clone();
+
+            unsafe {
+                gemm_alloc(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    Parallelism::None,
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    d.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    k as isize,
+                    1,
+                    0.5,
+                    2.0,
+                );
+            }
+            for (c, d) in c.iter().zip(d.iter()) {
+                assert_approx_eq::assert_approx_eq!(c, d, 1e-10 * d.abs().max(1.0));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_gemm_global_pool() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_global_pool_enabled(true);
+        // the rhs is row-major so it gets packed, which needs scratch memory.
+        // other tests may use the pool concurrently, so only check that the counters grow
+        let before = pool_stats();
+        for (m, n, k) in [
+            (256, 128, 300),
+            (63, 65, 10),
+            (256, 128, 300),
+            (300, 17, 600),
+        ] {
+            let a: Vec<f32> = (0..m * k).map(|_| rand::random()).collect();
+            let b: Vec<f32> = (0..k * n).map(|_| rand::random()).collect();
+            let mut c: Vec<f32> = (0..m * n).map(|_| rand::random()).collect();
+            let mut d = c.clone();
+
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    1,
+                    n as isize,
+                    0.5,
+                    2.0,
+                    false,
+                    false,
+                    false,
+                    Parallelism::None,
+                );
+                gemm::gemm_fallback(
+                    m,
+                    n,
+                    k,
+                    d.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    1,
+                    n as isize,
+                    0.5,
+                    2.0,
+                );
+            }
+            for (c, d) in c.iter().zip(d.iter()) {
+                assert_approx_eq::assert_approx_eq!(c, d, 1e-3 * d.abs().max(1.0));
+            }
+        }
+        let after = pool_stats();
+        assert!(after.hits > before.hits);
+        assert!(after.hits + after.misses >= before.hits + before.misses + 4);
+        assert!(after.hit_rate() > 0.0);
+    }
+
+    #[test]
+    fn test_pack_operands() {
+        let (m, n, k) = (37, 13, 9);
+        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+        let lhs = MatRef::from_col_major_slice(&a, m, k);
+        let rhs = MatRef::from_row_major_slice(&b, k, n);
+
+        let packed = pack_lhs(lhs);
+        let (mr, stride) = (packed.mr(), packed.panel_stride());
+        assert_eq!(stride, k * mr);
+        assert_eq!(packed.as_ptr() as usize % packed.align(), 0);
+        let data = packed.as_slice();
+        for i in 0..m.next_multiple_of(mr) {
+            for depth in 0..k {
+                let expected = if i < m { a[i + depth * m] } else { 0.0 };
+                assert_eq!(data[i / mr * stride + depth * mr + i % mr], expected);
+            }
+        }
+
+        let packed = pack_rhs(rhs);
+        let (nr, stride) = (packed.nr(), packed.panel_stride());
+        assert_eq!(stride, k * nr);
+        assert_eq!(packed.as_ptr() as usize % packed.align(), 0);
+        let data = packed.as_slice();
+        for j in 0..n.next_multiple_of(nr) {
+            for depth in 0..k {
+                let expected = if j < n { b[depth * n + j] } else { 0.0 };
+                assert_eq!(data[j / nr * stride + depth * nr + j % nr], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_prepacked() {
+        let alpha = c64::new(0.5, 1.0);
+        let beta = c64::new(2.0, -1.0);
+        for (m, n, k) in [
+            (1, 1, 1),
+            (4, 4, 4),
+            (63, 65, 10),
+            (5, 700, 40),
+            (300, 257, 700),
+        ] {
+            let a: Vec<c64> = (0..m * k)
+                .map(|_| c64::new(rand::random(), rand::random()))
+                .collect();
+            let b: Vec<c64> = (0..k * n)
+                .map(|_| c64::new(rand::random(), rand::random()))
+                .collect();
+            let lhs = MatRef::from_col_major_slice(&a, m, k);
+            let rhs = MatRef::from_row_major_slice(&b, k, n);
+            let packed_lhs = pack_lhs(lhs);
+            let packed_rhs = pack_rhs(rhs);
+
+            for (pack_l, pack_r) in [(true, false), (false, true), (true, true)] {
+                for colmajor in [true, false] {
+                    let mut c: Vec<c64> = (0..m * n)
+                        .map(|_| c64::new(rand::random(), rand::random()))
+                        .collect();
+                    let mut d = c.clone();
+                    let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+                    let dst = if colmajor {
+                        MatMut::from_col_major_slice(&mut c, m, n)
+                    } else {
+                        MatMut::from_row_major_slice(&mut c, m, n)
+                    };
+                    let lhs_op = if pack_l {
+                        Lhs::Packed(&packed_lhs)
+                    } else {
+                        Lhs::Mat(lhs)
+                    };
+                    let rhs_op = if pack_r {
+                        Rhs::Packed(&packed_rhs)
+                    } else {
+                        Rhs::Mat(rhs)
+                    };
+
+                    gemm_prepacked(
+                        dst,
+                        true,
+                        lhs_op,
+                        rhs_op,
+                        alpha,
+                        beta,
+                        false,
+                        true,
+                        false,
+                        Parallelism::None,
+                    );
+                    unsafe {
+                        gemm::gemm_cplx_fallback(
+                            m,
+                            n,
+                            k,
+                            d.as_mut_ptr(),
+                            dst_cs as isize,
+                            dst_rs as isize,
+                            true,
+                            a.as_ptr(),
+                            m as isize,
+                            1,
+                            b.as_ptr(),
+                            1,
+                            n as isize,
+                            alpha,
+                            beta,
+                            false,
+                            true,
+                            false,
+                        );
+                    }
+                    for (c, d) in c.iter().zip(d.iter()) {
+                        assert_approx_eq::assert_approx_eq!(
+                            c.re,
+                            d.re,
+                            1e-9 * d.l1_norm().max(1.0)
+                        );
+                        assert_approx_eq::assert_approx_eq!(
+                            c.im,
+                            d.im,
+                            1e-9 * d.l1_norm().max(1.0)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_gemm() {
+        let (m, n, k) = (13, 7, 21);
+        let a: Vec<f32> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f32> = (0..k * n).map(|_| rand::random()).collect();
+        let mut c = vec![0.0f32; m * n];
+        let mut d = vec![0.0f32; m * n];
+
+        let mut mem = dyn_stack::GlobalMemBuffer::new(gemm_req::<f32>(
+            m,
+            n,
+            k,
+            m as isize,
+            1,
+            Parallelism::None,
+        ));
+        try_gemm(
+            m,
+            n,
+            k,
+            &mut c,
+            m as isize,
+            1,
+            false,
+            &a,
+            m as isize,
+            1,
+            &b,
+            k as isize,
+            1,
+            0.0,
+            1.0,
+            false,
+            false,
+            false,
+            Parallelism::None,
+            Some(dyn_stack::DynStack::new(&mut mem)),
+        )
+        .unwrap();
+        unsafe {
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                d.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c, d);
+        }
+
+        let try_with = |dst_len: usize, dst_cs: isize, lhs_len: usize| {
+            let mut c = vec![0.0f32; dst_len];
+            try_gemm(
+                m,
+                n,
+                k,
+                &mut c,
+                dst_cs,
+                1,
+                false,
+                &a[..lhs_len],
+                m as isize,
+                1,
+                &b,
+                k as isize,
+                1,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+                None,
+            )
+        };
+        assert_eq!(try_with(m * n, m as isize, m * k), Ok(()));
+        assert!(matches!(
+            try_with(m * n, m as isize, m * k - 1),
+            Err(GemmError::OutOfBounds { operand: "lhs", .. })
+        ));
+        assert!(matches!(
+            try_with(m * n - 1, m as isize, m * k),
+            Err(GemmError::OutOfBounds { operand: "dst", .. })
+        ));
+        assert_eq!(
+            try_with(m * n, 2, m * k),
+            Err(GemmError::DstOverlap {
+                row_stride: 1,
+                col_stride: 2
+            })
+        );
+
+        let mut tiny = [core::mem::MaybeUninit::new(0u8); 1];
+        let mut c = vec![0.0f32; m * n];
+        assert!(matches!(
+            try_gemm(
+                m,
+                n,
+                k,
+                &mut c,
+                1,
+                n as isize,
+                false,
+                &a,
+                m as isize,
+                1,
+                &b,
+                1,
+                n as isize,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+                Some(dyn_stack::DynStack::new(&mut tiny)),
+            ),
+            Err(GemmError::ScratchTooSmall { available: 1, .. })
+        ));
+
+        let mut c = vec![0u8; 4];
+        assert_eq!(
+            try_gemm(
+                2,
+                2,
+                0,
+                &mut c,
+                2,
+                1,
+                false,
+                &[],
+                2,
+                1,
+                &[],
+                0,
+                1,
+                0,
+                1,
+                false,
+                false,
+                false,
+                Parallelism::None,
+                None,
+            ),
+            Err(GemmError::UnsupportedType)
+        );
+    }
+
+    #[test]
+    fn test_register_backend() {
+        use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+        // the built-in backend, so that the test backends compute the same result, since other
+        // tests may run while they are registered
+        static BUILTIN: AtomicPtr<GemmBackend<f32>> = AtomicPtr::new(core::ptr::null_mut());
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn builtin() -> &'static GemmBackend<f32> {
+            unsafe { &*BUILTIN.load(Ordering::Relaxed) }
+        }
+        unsafe fn counting_gemm(
+            m: usize,
+            n: usize,
+            k: usize,
+            dst: *mut f32,
+            dst_cs: isize,
+            dst_rs: isize,
+            read_dst: bool,
+            lhs: *const f32,
+            lhs_cs: isize,
+            lhs_rs: isize,
+            rhs: *const f32,
+            rhs_cs: isize,
+            rhs_rs: isize,
+            alpha: f32,
+            beta: f32,
+            conj_dst: bool,
+            conj_lhs: bool,
+            conj_rhs: bool,
+            parallelism: Parallelism<'_>,
+            config: GemmConfig<'_, f32>,
+        ) {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            (builtin().gemm)(
+                m,
+                n,
+                k,
+                dst,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs,
+                lhs_cs,
+                lhs_rs,
+                rhs,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                conj_dst,
+                conj_lhs,
+                conj_rhs,
+                parallelism,
+                config,
+            )
+        }
+        fn gemm_req(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> dyn_stack::StackReq {
+            (builtin().gemm_req)(m, n, k, parallelism)
+        }
+        fn kernel_params(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> gemm_common::cache::KernelParams {
+            (builtin().kernel_params)(m, n, k, parallelism)
+        }
+        fn blocking(
+            m: usize,
+            n: usize,
+            k: usize,
+            parallelism: Parallelism<'_>,
+        ) -> (usize, usize, gemm_common::cache::KernelParams) {
+            (builtin().blocking)(m, n, k, parallelism)
+        }
+
+        static HIGH: GemmBackend<f32> = Backend {
+            name: "high",
+            gemm: counting_gemm,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: 0,
+            nr: 0,
+            simd_bytes: 32,
+            pack_lhs: None,
+            pack_rhs: None,
+        };
+        static LOW: GemmBackend<f32> = Backend {
+            name: "low",
+            gemm: counting_gemm,
+            gemm_req,
+            kernel_params,
+            blocking,
+            mr: 0,
+            nr: 0,
+            simd_bytes: 32,
+            pack_lhs: None,
+            pack_rhs: None,
+        };
+
+        let name = active_backend_name::<f32>();
+        BUILTIN.store(
+            gemm::get_backend::<f32>() as *const _ as *mut _,
+            Ordering::Relaxed,
+        );
+
+        register_backend::<f32>(&HIGH, DEFAULT_BACKEND_PRIORITY + 2);
+        assert_eq!(active_backend_name::<f32>(), "high");
+        register_backend::<f32>(&LOW, DEFAULT_BACKEND_PRIORITY + 1);
+        assert_eq!(active_backend_name::<f32>(), "high");
+
+        let (m, n, k) = (9, 11, 5);
+        let a: Vec<f32> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f32> = (0..k * n).map(|_| rand::random()).collect();
+        let mut c = vec![0.0f32; m * n];
+        let mut d = vec![0.0f32; m * n];
+        unsafe {
+            gemm(
+                m,
+                n,
+                k,
+                c.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                d.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+        assert!(CALLS.load(Ordering::Relaxed) > 0);
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c, d);
+        }
+
+        reset_backend::<f32>();
+        assert_eq!(active_backend_name::<f32>(), name);
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_tracing() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // records the names of the spans and the messages of the events
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        struct Message<'a>(&'a mut String);
+
+        impl Visit for Message<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+                if field.name() == "message" {
+                    *self.0 = format!("{value:?}");
+                }
+            }
+        }
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                self.0.lock().unwrap().push(span.metadata().name().into());
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut message = String::new();
+                event.record(&mut Message(&mut message));
+                self.0.lock().unwrap().push(message);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let trace = |m: usize, n: usize, k: usize| {
+            let recorded = Arc::new(Mutex::new(Vec::new()));
+            let mut dst = vec![0.0f32; m * n];
+            let lhs = vec![1.0f32; m * k];
+            let rhs = vec![1.0f32; k * n];
+            tracing::subscriber::with_default(Recorder(recorded.clone()), || unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    false,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    0.0,
+                    1.0,
+                    false,
+                    false,
+                    false,
+                    Parallelism::None,
+                )
+            });
+            assert!(dst.iter().all(|&x| x == k as f32));
+            let recorded = recorded.lock().unwrap().clone();
+            recorded
+        };
+
+        let recorded = trace(64, 64, 192);
+        for expected in [
+            "gemm",
+            "dispatch",
+            "kernel params",
+            "packing",
+            "block",
+            "row block",
+        ] {
+            assert!(
+                recorded.iter().any(|name| name == expected),
+                "{expected} is missing from {recorded:?}",
+            );
+        }
+        let recorded = trace(64, 1, 64);
+        assert!(recorded.iter().any(|name| name == "matrix-vector kernels"));
+        assert!(!recorded.iter().any(|name| name == "packing"));
+        let recorded = trace(64, 64, 64);
+        assert!(recorded.iter().any(|name| name == "direct kernels"));
+        assert!(!recorded.iter().any(|name| name == "packing"));
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_driver_stats() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let run = |m: usize, n: usize, k: usize| {
+            let mut dst = vec![0.0f32; m * n];
+            let lhs = vec![1.0f32; m * k];
+            let rhs = vec![1.0f32; k * n];
+            // the rhs is row-major so it gets packed
+            unsafe {
+                gemm(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    false,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    1,
+                    n as isize,
+                    0.0,
+                    1.0,
+                    false,
+                    false,
+                    false,
+                    Parallelism::None,
+                )
+            };
+            assert!(dst.iter().all(|&x| x == k as f32));
+        };
+
+        // other tests may multiply concurrently, so only check that the counters grow by at
+        // least the work of these calls
+        reset_driver_stats();
+        run(63, 63, 192);
+        run(64, 64, 0);
+        run(64, 1, 64);
+        let stats = driver_stats();
+
+        assert!(stats.packed_rhs_bytes >= (63 * 64 * core::mem::size_of::<f32>()) as u64);
+        assert!(stats.kernel_calls.partial_both >= 1);
+        assert!(stats.kernel_calls.total() >= stats.kernel_calls.partial_both);
+        assert!(stats.jobs >= 1);
+        assert!(stats.max_jobs_per_thread >= 1);
+        assert!(stats.jobs_per_thread() >= 1.0);
+        assert!(stats.fallbacks >= 2);
+    }
+
+    #[test]
+    #[cfg(feature = "capi")]
+    fn test_cblas_dgemm() {
+        use crate::capi::*;
+
+        let (m, n, k) = (13, 7, 21);
+        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+        let c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+
+        for order in [CBLAS_ROW_MAJOR, CBLAS_COL_MAJOR] {
+            for (trans_a, trans_b) in [
+                (CBLAS_NO_TRANS, CBLAS_NO_TRANS),
+                (CBLAS_TRANS, CBLAS_NO_TRANS),
+                (CBLAS_NO_TRANS, CBLAS_CONJ_TRANS),
+            ] {
+                // element (i, j) of a matrix stored with the given order and leading dimension
+                let at = |x: &[f64], ld: usize, i: usize, j: usize| {
+                    if order == CBLAS_ROW_MAJOR {
+                        x[i * ld + j]
+                    } else {
+                        x[i + j * ld]
+                    }
+                };
+                // leading dimension of a stored `nrows×ncols` matrix
+                let ld = |nrows: usize, ncols: usize| {
+                    if order == CBLAS_ROW_MAJOR {
+                        ncols
+                    } else {
+                        nrows
+                    }
+                };
+                let lda = if trans_a == CBLAS_NO_TRANS {
+                    ld(m, k)
+                } else {
+                    ld(k, m)
+                };
+                let ldb = if trans_b == CBLAS_NO_TRANS {
+                    ld(k, n)
+                } else {
+                    ld(n, k)
+                };
+                let ldc = ld(m, n);
+
+                let mut expected = c.clone();
+                for i in 0..m {
+                    for j in 0..n {
+                        let mut acc = 0.0;
+                        for p in 0..k {
+                            let a_ip = if trans_a == CBLAS_NO_TRANS {
+                                at(&a, lda, i, p)
+                            } else {
+                                at(&a, lda, p, i)
+                            };
+                            let b_pj = if trans_b == CBLAS_NO_TRANS {
+                                at(&b, ldb, p, j)
+                            } else {
+                                at(&b, ldb, j, p)
+                            };
+                            acc += a_ip * b_pj;
+                        }
+                        let idx = if order == CBLAS_ROW_MAJOR {
+                            i * ldc + j
+                        } else {
+                            i + j * ldc
+                        };
+                        expected[idx] = 2.0 * acc + 0.5 * c[idx];
+                    }
+                }
+
+                let mut actual = c.clone();
+                unsafe {
+                    cblas_dgemm(
+                        order,
+                        trans_a,
+                        trans_b,
+                        m as _,
+                        n as _,
+                        k as _,
+                        2.0,
+                        a.as_ptr(),
+                        lda as _,
+                        b.as_ptr(),
+                        ldb as _,
+                        0.5,
+                        actual.as_mut_ptr(),
+                        ldc as _,
+                    )
+                };
+                for (actual, expected) in actual.iter().zip(expected.iter()) {
+                    assert_approx_eq::assert_approx_eq!(actual, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "capi")]
+    fn test_bench_gemm_f64() {
+        use crate::capi::*;
+
+        let (m, n, k) = (63, 65, 300);
+        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+        let mut c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+        let mut d = c.clone();
+
+        // rhs is row-major, so that it gets packed
+        let req = bench_gemm_req(BENCH_GEMM_F64, m, n, k, m as isize, 1, 1);
+        assert!(req > 0);
+        assert_eq!(bench_gemm_req(-1, m, n, k, m as isize, 1, 1), 0);
+        let mut scratch = vec![0u8; req];
+
+        unsafe {
+            assert_eq!(
+                bench_gemm_f64(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    1,
+                    n as isize,
+                    0.5,
+                    2.0,
+                    1,
+                    scratch.as_mut_ptr(),
+                    scratch.len(),
+                ),
+                BENCH_GEMM_OK,
+            );
+            assert_eq!(
+                bench_gemm_f64(
+                    m,
+                    n,
+                    k,
+                    c.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a.as_ptr(),
+                    m as isize,
+                    1,
+                    b.as_ptr(),
+                    1,
+                    n as isize,
+                    0.5,
+                    2.0,
+                    1,
+                    scratch.as_mut_ptr(),
+                    1,
+                ),
+                BENCH_GEMM_SCRATCH_TOO_SMALL,
+            );
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                d.as_mut_ptr(),
+                m as isize,
+                1,
+                true,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                1,
+                n as isize,
+                0.5,
+                2.0,
+            );
+        }
+        for (c, d) in c.iter().zip(d.iter()) {
+            assert_approx_eq::assert_approx_eq!(c, d, 1e-10 * d.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "capi")]
+    fn test_bench_gemm_pool() {
+        let _guard = SETTINGS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        use crate::capi::*;
+        use core::ffi::c_void;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        // a pool that lives on the stack of the test, as one owned by a C caller would
+        struct Pool {
+            spawner: ScopedSpawner,
+            calls: AtomicUsize,
+        }
+        unsafe extern "C" fn for_each(
+            ctx: *mut c_void,
+            n_tasks: usize,
+            task: unsafe extern "C" fn(*const c_void, usize),
+            task_ctx: *const c_void,
+        ) {
+            let pool = &*(ctx as *const Pool);
+            pool.calls.fetch_add(1, Ordering::Relaxed);
+            let task_ctx = task_ctx as usize;
+            pool.spawner
+                .for_each(n_tasks, &|i| unsafe { task(task_ctx as *const c_void, i) });
+        }
+
+        let state = Pool {
+            spawner: ScopedSpawner::new(3),
+            calls: AtomicUsize::new(0),
+        };
+        let pool = BenchGemmPool {
+            ctx: &state as *const Pool as *mut c_void,
+            num_threads: 3,
+            for_each,
+        };
+
+        let threshold = get_threading_threshold();
+        set_threading_threshold(0);
+
+        let (m, n, k) = (63, 65, 300);
+        let a: Vec<f64> = (0..m * k).map(|_| rand::random()).collect();
+        let b: Vec<f64> = (0..k * n).map(|_| rand::random()).collect();
+        let c: Vec<f64> = (0..m * n).map(|_| rand::random()).collect();
+        let mut d = c.clone();
+        unsafe {
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                d.as_mut_ptr(),
+                m as isize,
+                1,
+                true,
+                a.as_ptr(),
+                m as isize,
+                1,
+                b.as_ptr(),
+                1,
+                n as isize,
+                0.5,
+                2.0,
+            );
+        }
+
+        for pool in [&pool as *const BenchGemmPool, core::ptr::null()] {
+            let mut c = c.clone();
+            let req = unsafe { bench_gemm_req_on(BENCH_GEMM_F64, m, n, k, m as isize, 1, pool) };
+            let mut scratch = vec![0u8; req];
+            unsafe {
+                assert_eq!(
+                    bench_gemm_f64_on(
+                        m,
+                        n,
+                        k,
+                        c.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        true,
+                        a.as_ptr(),
+                        m as isize,
+                        1,
+                        b.as_ptr(),
+                        1,
+                        n as isize,
+                        0.5,
+                        2.0,
+                        pool,
+                        scratch.as_mut_ptr(),
+                        scratch.len(),
+                    ),
+                    BENCH_GEMM_OK,
+                );
+            }
+            for (c, d) in c.iter().zip(d.iter()) {
+                assert_approx_eq::assert_approx_eq!(c, d, 1e-10 * d.abs().max(1.0));
+            }
+        }
+        set_threading_threshold(threshold);
+
+        assert!(state.calls.load(Ordering::Relaxed) > 0);
+    }
 }
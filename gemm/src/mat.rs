@@ -0,0 +1,390 @@
+use crate::gemm::{check_bounds, check_no_self_overlap, gemm};
+use crate::Parallelism;
+use core::marker::PhantomData;
+
+/// Immutable view over a strided matrix.
+#[derive(Debug)]
+pub struct MatRef<'a, T> {
+    ptr: *const T,
+    nrows: usize,
+    ncols: usize,
+    row_stride: isize,
+    col_stride: isize,
+    __marker: PhantomData<&'a T>,
+}
+
+/// Mutable view over a strided matrix.
+#[derive(Debug)]
+pub struct MatMut<'a, T> {
+    ptr: *mut T,
+    nrows: usize,
+    ncols: usize,
+    row_stride: isize,
+    col_stride: isize,
+    __marker: PhantomData<&'a mut T>,
+}
+
+unsafe impl<T: Sync> Send for MatRef<'_, T> {}
+unsafe impl<T: Sync> Sync for MatRef<'_, T> {}
+unsafe impl<T: Send> Send for MatMut<'_, T> {}
+unsafe impl<T: Sync> Sync for MatMut<'_, T> {}
+
+impl<T> Copy for MatRef<'_, T> {}
+impl<T> Clone for MatRef<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> MatRef<'a, T> {
+    /// Creates a view from a pointer to the element at `(0, 0)` and strides in elements.
+    ///
+    /// # Safety
+    ///
+    /// Every element of the matrix must be valid for reads for the lifetime `'a`, and must not be
+    /// mutated during that lifetime.
+    #[inline]
+    pub unsafe fn from_raw_parts(
+        ptr: *const T,
+        nrows: usize,
+        ncols: usize,
+        row_stride: isize,
+        col_stride: isize,
+    ) -> Self {
+        Self {
+            ptr,
+            nrows,
+            ncols,
+            row_stride,
+            col_stride,
+            __marker: PhantomData,
+        }
+    }
+
+    /// Creates a view over a slice. The matrix is laid out so that its lowest-addressed element is
+    /// the first element of the slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix doesn't fit in the slice.
+    #[track_caller]
+    pub fn from_slice(
+        slice: &'a [T],
+        nrows: usize,
+        ncols: usize,
+        row_stride: isize,
+        col_stride: isize,
+    ) -> Self {
+        let offset = check_bounds("matrix", slice.len(), nrows, ncols, row_stride, col_stride);
+        unsafe {
+            Self::from_raw_parts(
+                slice.as_ptr().wrapping_add(offset),
+                nrows,
+                ncols,
+                row_stride,
+                col_stride,
+            )
+        }
+    }
+
+    /// Creates a view over a contiguous column-major slice.
+    #[track_caller]
+    pub fn from_col_major_slice(slice: &'a [T], nrows: usize, ncols: usize) -> Self {
+        Self::from_slice(slice, nrows, ncols, 1, nrows as isize)
+    }
+
+    /// Creates a view over a contiguous row-major slice.
+    #[track_caller]
+    pub fn from_row_major_slice(slice: &'a [T], nrows: usize, ncols: usize) -> Self {
+        Self::from_slice(slice, nrows, ncols, ncols as isize, 1)
+    }
+
+    #[inline]
+    pub fn as_ptr(self) -> *const T {
+        self.ptr
+    }
+    #[inline]
+    pub fn nrows(self) -> usize {
+        self.nrows
+    }
+    #[inline]
+    pub fn ncols(self) -> usize {
+        self.ncols
+    }
+    #[inline]
+    pub fn row_stride(self) -> isize {
+        self.row_stride
+    }
+    #[inline]
+    pub fn col_stride(self) -> isize {
+        self.col_stride
+    }
+
+    /// Returns a view over the transpose of the matrix.
+    #[inline]
+    pub fn transpose(self) -> Self {
+        Self {
+            ptr: self.ptr,
+            nrows: self.ncols,
+            ncols: self.nrows,
+            row_stride: self.col_stride,
+            col_stride: self.row_stride,
+            __marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> MatMut<'a, T> {
+    /// Creates a view from a pointer to the element at `(0, 0)` and strides in elements.
+    ///
+    /// # Safety
+    ///
+    /// Every element of the matrix must be valid for reads and writes for the lifetime `'a`, and
+    /// must not be accessed through any other pointer during that lifetime. Distinct elements must
+    /// not overlap.
+    #[inline]
+    pub unsafe fn from_raw_parts(
+        ptr: *mut T,
+        nrows: usize,
+        ncols: usize,
+        row_stride: isize,
+        col_stride: isize,
+    ) -> Self {
+        Self {
+            ptr,
+            nrows,
+            ncols,
+            row_stride,
+            col_stride,
+            __marker: PhantomData,
+        }
+    }
+
+    /// Creates a view over a slice. The matrix is laid out so that its lowest-addressed element is
+    /// the first element of the slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix doesn't fit in the slice, or if the strides make distinct elements
+    /// overlap.
+    #[track_caller]
+    pub fn from_slice(
+        slice: &'a mut [T],
+        nrows: usize,
+        ncols: usize,
+        row_stride: isize,
+        col_stride: isize,
+    ) -> Self {
+        let offset = check_bounds("matrix", slice.len(), nrows, ncols, row_stride, col_stride);
+        check_no_self_overlap(nrows, ncols, row_stride, col_stride);
+        unsafe {
+            Self::from_raw_parts(
+                slice.as_mut_ptr().wrapping_add(offset),
+                nrows,
+                ncols,
+                row_stride,
+                col_stride,
+            )
+        }
+    }
+
+    /// Creates a view over a contiguous column-major slice.
+    #[track_caller]
+    pub fn from_col_major_slice(slice: &'a mut [T], nrows: usize, ncols: usize) -> Self {
+        Self::from_slice(slice, nrows, ncols, 1, nrows as isize)
+    }
+
+    /// Creates a view over a contiguous row-major slice.
+    #[track_caller]
+    pub fn from_row_major_slice(slice: &'a mut [T], nrows: usize, ncols: usize) -> Self {
+        Self::from_slice(slice, nrows, ncols, ncols as isize, 1)
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr
+    }
+    #[inline]
+    pub fn nrows(&self) -> usize {
+        self.nrows
+    }
+    #[inline]
+    pub fn ncols(&self) -> usize {
+        self.ncols
+    }
+    #[inline]
+    pub fn row_stride(&self) -> isize {
+        self.row_stride
+    }
+    #[inline]
+    pub fn col_stride(&self) -> isize {
+        self.col_stride
+    }
+
+    /// Returns an immutable view over the same matrix.
+    #[inline]
+    pub fn rb(&self) -> MatRef<'_, T> {
+        unsafe {
+            MatRef::from_raw_parts(
+                self.ptr,
+                self.nrows,
+                self.ncols,
+                self.row_stride,
+                self.col_stride,
+            )
+        }
+    }
+
+    /// Reborrows the view, so that it can be passed by value without being consumed.
+    #[inline]
+    pub fn rb_mut(&mut self) -> MatMut<'_, T> {
+        unsafe {
+            MatMut::from_raw_parts(
+                self.ptr,
+                self.nrows,
+                self.ncols,
+                self.row_stride,
+                self.col_stride,
+            )
+        }
+    }
+
+    /// Returns a view over the transpose of the matrix.
+    #[inline]
+    pub fn transpose(self) -> Self {
+        Self {
+            ptr: self.ptr,
+            nrows: self.ncols,
+            ncols: self.nrows,
+            row_stride: self.col_stride,
+            col_stride: self.row_stride,
+            __marker: PhantomData,
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`], with the dimensions and strides taken from the matrix views.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`, or if the matrix
+/// dimensions are incompatible.
+#[track_caller]
+pub fn gemm_mat<T: 'static>(
+    mut dst: MatMut<'_, T>,
+    read_dst: bool,
+    lhs: MatRef<'_, T>,
+    rhs: MatRef<'_, T>,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    assert!(
+        dst.nrows() == lhs.nrows() && dst.ncols() == rhs.ncols() && lhs.ncols() == rhs.nrows(),
+        "dimension mismatch: dst is {}×{}, lhs is {}×{}, rhs is {}×{}",
+        dst.nrows(),
+        dst.ncols(),
+        lhs.nrows(),
+        lhs.ncols(),
+        rhs.nrows(),
+        rhs.ncols(),
+    );
+
+    unsafe {
+        gemm(
+            dst.nrows(),
+            dst.ncols(),
+            lhs.ncols(),
+            dst.as_mut_ptr(),
+            dst.col_stride(),
+            dst.row_stride(),
+            read_dst,
+            lhs.as_ptr(),
+            lhs.col_stride(),
+            lhs.row_stride(),
+            rhs.as_ptr(),
+            rhs.col_stride(),
+            rhs.row_stride(),
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    }
+}
+
+/// Operation applied to an operand of [`gemm_op`], as in the `trans` arguments of BLAS.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// `X`
+    NoTrans,
+    /// `Xᵀ`
+    Trans,
+    /// `Xᴴ`, the conjugate transpose.
+    ConjTrans,
+    /// The conjugate of `X`, without transposing it.
+    Conj,
+}
+
+impl Op {
+    /// View over `op(mat)`, and whether its elements are conjugated.
+    #[inline]
+    pub fn apply<T>(self, mat: MatRef<'_, T>) -> (MatRef<'_, T>, bool) {
+        match self {
+            Op::NoTrans => (mat, false),
+            Op::Trans => (mat.transpose(), false),
+            Op::ConjTrans => (mat.transpose(), true),
+            Op::Conj => (mat, true),
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×op_lhs(lhs)×op_rhs(rhs)
+///
+/// Same as [`gemm_mat`], with the operands given as stored and the operations applied to them,
+/// like `zgemm`. Transposition only swaps the strides and conjugation is done by the
+/// microkernels, so no operand is copied. Conjugation is a no-op for real types.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`, or if the matrix
+/// dimensions are incompatible.
+#[track_caller]
+pub fn gemm_op<T: 'static>(
+    dst: MatMut<'_, T>,
+    read_dst: bool,
+    op_lhs: Op,
+    lhs: MatRef<'_, T>,
+    op_rhs: Op,
+    rhs: MatRef<'_, T>,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism<'_>,
+) {
+    let (lhs, conj_lhs) = op_lhs.apply(lhs);
+    let (rhs, conj_rhs) = op_rhs.apply(rhs);
+    gemm_mat(
+        dst,
+        read_dst,
+        lhs,
+        rhs,
+        alpha,
+        beta,
+        false,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+    )
+}
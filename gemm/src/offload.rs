@@ -0,0 +1,221 @@
+use crate::gemm::gemm;
+use crate::Parallelism;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use gemm_common::Ptr;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// the background thread, which is started by the first call and runs the multiplications one
+// after the other
+static WORKER: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+fn run_in_background(job: Job) {
+    let mut worker = WORKER.lock().unwrap_or_else(|poison| poison.into_inner());
+    let sender = worker.get_or_insert_with(|| {
+        let (sender, receiver) = channel::<Job>();
+        std::thread::Builder::new()
+            .name("gemm-async".into())
+            .spawn(move || {
+                for job in receiver {
+                    // the panic is reported by the future of the multiplication, and mustn't
+                    // stop the thread
+                    let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+                }
+            })
+            .expect("failed to spawn the background thread");
+        sender
+    });
+    sender
+        .send(job)
+        .expect("the background thread stopped unexpectedly");
+}
+
+#[derive(Default)]
+struct State {
+    done: bool,
+    panicked: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    done: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+}
+
+/// Marks the multiplication as done when dropped, including when it panics.
+struct Completion(Arc<Shared>);
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = self.0.lock();
+            state.done = true;
+            state.panicked = std::thread::panicking();
+            state.waker.take()
+        };
+        self.0.done.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Multiplication running on a background thread, started by [`gemm_async`].
+///
+/// The future resolves once the multiplication is done. Dropping it blocks until then, since the
+/// background thread keeps writing to the destination.
+#[must_use = "dropping the future blocks until the multiplication is done"]
+pub struct GemmFuture {
+    shared: Arc<Shared>,
+}
+
+impl GemmFuture {
+    /// Whether the multiplication is done.
+    pub fn is_done(&self) -> bool {
+        self.shared.lock().done
+    }
+
+    /// Blocks the calling thread until the multiplication is done.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the multiplication panicked.
+    pub fn wait(self) {
+        let panicked = {
+            let mut state = self.shared.lock();
+            while !state.done {
+                state = self
+                    .shared
+                    .done
+                    .wait(state)
+                    .unwrap_or_else(|poison| poison.into_inner());
+            }
+            state.panicked
+        };
+        if panicked {
+            panic!("the multiplication panicked on its background thread");
+        }
+    }
+}
+
+impl Future for GemmFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.shared.lock();
+        if !state.done {
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        if state.panicked {
+            drop(state);
+            panic!("the multiplication panicked on its background thread");
+        }
+        Poll::Ready(())
+    }
+}
+
+impl Drop for GemmFuture {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        while !state.done {
+            state = self
+                .shared
+                .done
+                .wait(state)
+                .unwrap_or_else(|poison| poison.into_inner());
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`], except that the multiplication runs on a background thread, which spreads
+/// it on the pool selected by `parallelism`, and the returned future resolves once it's done.
+/// Async code can then await large multiplications without blocking the threads of its executor.
+///
+/// The background thread is started by the first call and kept for the later ones, whose
+/// multiplications it runs one after the other, in the order they're started. Since it outlives
+/// the call, a custom pool must be `'static`. Handing the multiplication to the thread and waking
+/// the future costs a few microseconds, so small products are better computed with [`gemm`]
+/// directly.
+///
+/// # Panics
+///
+/// The future panics when polled if the multiplication panicked, such as when `T` is not `f32`,
+/// `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`], which must hold until the future is done or dropped. The future
+/// must not be leaked with [`core::mem::forget`], since it would stop guarding the buffers.
+pub unsafe fn gemm_async<T: 'static + Send>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'static>,
+) -> GemmFuture {
+    let shared = Arc::new(Shared::default());
+    let completion = Completion(shared.clone());
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    run_in_background(Box::new(move || {
+        // capture the whole pointers, which are `Send` unlike their fields
+        let (completion, dst, lhs, rhs) = (completion, dst, lhs, rhs);
+        gemm(
+            m,
+            n,
+            k,
+            dst.0,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs.0,
+            lhs_cs,
+            lhs_rs,
+            rhs.0,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        );
+        drop(completion);
+    }));
+
+    GemmFuture { shared }
+}
@@ -0,0 +1,347 @@
+use crate::gemm::{get_backend, is_complex, GemmBackend};
+use crate::mat::{MatMut, MatRef};
+use crate::Parallelism;
+use dyn_stack::{GlobalMemBuffer, StackReq};
+use gemm_common::{
+    cache::DivCeil,
+    gemm::{GemmConfig, CACHELINE_ALIGN},
+};
+
+/// Allocates zeroed, cacheline-aligned storage for `n_panels` panels of `panel_stride` elements.
+pub(crate) fn alloc_panels<T>(n_panels: usize, panel_stride: usize) -> GlobalMemBuffer {
+    let len = n_panels
+        .checked_mul(panel_stride)
+        .expect("packed operand size overflows usize");
+    let mut mem = GlobalMemBuffer::new(StackReq::new_aligned::<T>(len, CACHELINE_ALIGN));
+    // the panels are padded to a full `mr`/`nr`, and the padding is never written by the
+    // packing routines
+    unsafe { core::ptr::write_bytes(mem.as_mut_ptr() as *mut T, 0, len) };
+    mem
+}
+
+pub(crate) fn packing_backend<T: 'static>() -> &'static GemmBackend<T> {
+    let backend = get_backend::<T>();
+    assert!(
+        backend.pack_lhs.is_some() && backend.pack_rhs.is_some(),
+        "the {} backend doesn't support packing operands ahead of time",
+        backend.name,
+    );
+    backend
+}
+
+/// Left-hand side operand packed in the layout used by the microkernels.
+///
+/// The `m×k` matrix is split into `ceil(m / mr)` panels of `mr` rows, stored `panel_stride`
+/// elements apart. Within a panel, element `(i, depth)` is stored at `depth * mr + i`, and the
+/// rows past `m` are zero.
+pub struct PackedLhs<T: 'static> {
+    m: usize,
+    k: usize,
+    backend: &'static GemmBackend<T>,
+    mem: GlobalMemBuffer,
+}
+
+/// Right-hand side operand packed in the layout used by the microkernels.
+///
+/// The `k×n` matrix is split into `ceil(n / nr)` panels of `nr` columns, stored `panel_stride`
+/// elements apart. Within a panel, element `(depth, j)` is stored at `depth * nr + j`, and the
+/// columns past `n` are zero.
+pub struct PackedRhs<T: 'static> {
+    n: usize,
+    k: usize,
+    backend: &'static GemmBackend<T>,
+    mem: GlobalMemBuffer,
+}
+
+unsafe impl<T: Send> Send for PackedLhs<T> {}
+unsafe impl<T: Sync> Sync for PackedLhs<T> {}
+unsafe impl<T: Send> Send for PackedRhs<T> {}
+unsafe impl<T: Sync> Sync for PackedRhs<T> {}
+
+/// Packs `lhs` for the backend selected for `T` on the current machine.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::c32`, or `gemm::c64`.
+pub fn pack_lhs<T: 'static>(lhs: MatRef<'_, T>) -> PackedLhs<T> {
+    let backend = packing_backend::<T>();
+    let (m, k) = (lhs.nrows(), lhs.ncols());
+    let mr = backend.mr;
+    let mut mem = alloc_panels::<T>(m.msrv_div_ceil(mr), k * mr);
+
+    if m > 0 && k > 0 {
+        unsafe {
+            (backend.pack_lhs.unwrap())(
+                m,
+                k,
+                mem.as_mut_ptr() as *mut T,
+                lhs.as_ptr(),
+                lhs.col_stride(),
+                lhs.row_stride(),
+                k * mr,
+            )
+        };
+    }
+
+    PackedLhs { m, k, backend, mem }
+}
+
+/// Packs `rhs` for the backend selected for `T` on the current machine.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::c32`, or `gemm::c64`.
+pub fn pack_rhs<T: 'static>(rhs: MatRef<'_, T>) -> PackedRhs<T> {
+    let backend = packing_backend::<T>();
+    let (k, n) = (rhs.nrows(), rhs.ncols());
+    let nr = backend.nr;
+    let mut mem = alloc_panels::<T>(n.msrv_div_ceil(nr), k * nr);
+
+    if n > 0 && k > 0 {
+        unsafe {
+            (backend.pack_rhs.unwrap())(
+                n,
+                k,
+                mem.as_mut_ptr() as *mut T,
+                rhs.as_ptr(),
+                rhs.col_stride(),
+                rhs.row_stride(),
+                k * nr,
+            )
+        };
+    }
+
+    PackedRhs { n, k, backend, mem }
+}
+
+impl<T: 'static> PackedLhs<T> {
+    #[inline]
+    pub fn m(&self) -> usize {
+        self.m
+    }
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+    /// Number of rows of each panel.
+    #[inline]
+    pub fn mr(&self) -> usize {
+        self.backend.mr
+    }
+    /// Distance between the starts of two consecutive panels, in elements.
+    #[inline]
+    pub fn panel_stride(&self) -> usize {
+        self.k * self.backend.mr
+    }
+    /// Alignment of the packed storage, in bytes.
+    #[inline]
+    pub fn align(&self) -> usize {
+        CACHELINE_ALIGN
+    }
+    /// Name of the backend the operand was packed for.
+    #[inline]
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name
+    }
+    #[inline]
+    pub fn as_ptr(&self) -> *const T {
+        self.mem.as_ptr() as *const T
+    }
+    /// Packed panels, including the padding.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        let len = self.m.msrv_div_ceil(self.backend.mr) * self.panel_stride();
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), len) }
+    }
+}
+
+impl<T: 'static> PackedRhs<T> {
+    #[inline]
+    pub fn n(&self) -> usize {
+        self.n
+    }
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+    /// Number of columns of each panel.
+    #[inline]
+    pub fn nr(&self) -> usize {
+        self.backend.nr
+    }
+    /// Distance between the starts of two consecutive panels, in elements.
+    #[inline]
+    pub fn panel_stride(&self) -> usize {
+        self.k * self.backend.nr
+    }
+    /// Alignment of the packed storage, in bytes.
+    #[inline]
+    pub fn align(&self) -> usize {
+        CACHELINE_ALIGN
+    }
+    /// Name of the backend the operand was packed for.
+    #[inline]
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name
+    }
+    #[inline]
+    pub fn as_ptr(&self) -> *const T {
+        self.mem.as_ptr() as *const T
+    }
+    /// Packed panels, including the padding.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        let len = self.n.msrv_div_ceil(self.backend.nr) * self.panel_stride();
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), len) }
+    }
+}
+
+/// Left-hand side operand of [`gemm_prepacked`].
+pub enum Lhs<'a, T: 'static> {
+    Mat(MatRef<'a, T>),
+    Packed(&'a PackedLhs<T>),
+}
+
+/// Right-hand side operand of [`gemm_prepacked`].
+pub enum Rhs<'a, T: 'static> {
+    Mat(MatRef<'a, T>),
+    Packed(&'a PackedRhs<T>),
+}
+
+impl<'a, T: 'static> From<MatRef<'a, T>> for Lhs<'a, T> {
+    #[inline]
+    fn from(mat: MatRef<'a, T>) -> Self {
+        Lhs::Mat(mat)
+    }
+}
+impl<'a, T: 'static> From<&'a PackedLhs<T>> for Lhs<'a, T> {
+    #[inline]
+    fn from(packed: &'a PackedLhs<T>) -> Self {
+        Lhs::Packed(packed)
+    }
+}
+impl<'a, T: 'static> From<MatRef<'a, T>> for Rhs<'a, T> {
+    #[inline]
+    fn from(mat: MatRef<'a, T>) -> Self {
+        Rhs::Mat(mat)
+    }
+}
+impl<'a, T: 'static> From<&'a PackedRhs<T>> for Rhs<'a, T> {
+    #[inline]
+    fn from(packed: &'a PackedRhs<T>) -> Self {
+        Rhs::Packed(packed)
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm_mat`](crate::gemm_mat), where either operand may have been packed ahead of
+/// time with [`pack_lhs`] or [`pack_rhs`], in which case the packing step is skipped.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::c32`, or `gemm::c64`, if the matrix dimensions are
+/// incompatible, or if both operands are packed for different backends.
+#[track_caller]
+pub fn gemm_prepacked<'a, T: 'static>(
+    mut dst: MatMut<'_, T>,
+    read_dst: bool,
+    lhs: impl Into<Lhs<'a, T>>,
+    rhs: impl Into<Rhs<'a, T>>,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let lhs = lhs.into();
+    let rhs = rhs.into();
+
+    let (lhs_ptr, lhs_cs, lhs_rs, lhs_nrows, lhs_ncols, packed_lhs) = match lhs {
+        Lhs::Mat(lhs) => (
+            lhs.as_ptr(),
+            lhs.col_stride(),
+            lhs.row_stride(),
+            lhs.nrows(),
+            lhs.ncols(),
+            None,
+        ),
+        Lhs::Packed(lhs) => (lhs.as_ptr(), 0, 0, lhs.m, lhs.k, Some(lhs)),
+    };
+    let (rhs_ptr, rhs_cs, rhs_rs, rhs_nrows, rhs_ncols, packed_rhs) = match rhs {
+        Rhs::Mat(rhs) => (
+            rhs.as_ptr(),
+            rhs.col_stride(),
+            rhs.row_stride(),
+            rhs.nrows(),
+            rhs.ncols(),
+            None,
+        ),
+        Rhs::Packed(rhs) => (rhs.as_ptr(), 0, 0, rhs.k, rhs.n, Some(rhs)),
+    };
+
+    assert!(
+        dst.nrows() == lhs_nrows && dst.ncols() == rhs_ncols && lhs_ncols == rhs_nrows,
+        "dimension mismatch: dst is {}×{}, lhs is {}×{}, rhs is {}×{}",
+        dst.nrows(),
+        dst.ncols(),
+        lhs_nrows,
+        lhs_ncols,
+        rhs_nrows,
+        rhs_ncols,
+    );
+
+    // the packed operands are only valid for the backend that packed them
+    let backend = match (packed_lhs, packed_rhs) {
+        (Some(lhs), Some(rhs)) => {
+            assert!(
+                core::ptr::eq(lhs.backend, rhs.backend),
+                "lhs is packed for {}, but rhs is packed for {}",
+                lhs.backend.name,
+                rhs.backend.name,
+            );
+            lhs.backend
+        }
+        (Some(lhs), None) => lhs.backend,
+        (None, Some(rhs)) => rhs.backend,
+        (None, None) => get_backend::<T>(),
+    };
+
+    let (conj_dst, conj_lhs, conj_rhs) = if is_complex::<T>() {
+        (conj_dst, conj_lhs, conj_rhs)
+    } else {
+        (false, false, false)
+    };
+
+    // unlike `gemm`, the problem is never transposed, since the packed panels only fit on one
+    // side of the product
+    unsafe {
+        (backend.gemm)(
+            dst.nrows(),
+            dst.ncols(),
+            lhs_ncols,
+            dst.as_mut_ptr(),
+            dst.col_stride(),
+            dst.row_stride(),
+            read_dst,
+            lhs_ptr,
+            lhs_cs,
+            lhs_rs,
+            rhs_ptr,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+            GemmConfig {
+                packed_lhs: packed_lhs.map(PackedLhs::as_ptr),
+                packed_rhs: packed_rhs.map(PackedRhs::as_ptr),
+                ..Default::default()
+            },
+        )
+    }
+}
@@ -0,0 +1,193 @@
+use crate::gemm::{gemm_with_backend, get_backend, transposes_product, GemmBackend};
+use crate::Parallelism;
+use dyn_stack::{DynStack, GlobalMemBuffer};
+use gemm_common::{cache::KernelParams, gemm::GemmConfig};
+
+/// Precomputed state for repeatedly multiplying matrices of the same shape.
+///
+/// The backend, the blocking parameters and the scratch memory are resolved once in
+/// [`GemmPlan::new`], so that [`GemmPlan::execute`] only does the multiplication.
+pub struct GemmPlan<'a, T: 'static> {
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism<'a>,
+    backend: &'static GemmBackend<T>,
+    // indexed by whether the problem is transposed before reaching the backend
+    kernel_params: [KernelParams; 2],
+    mem: GlobalMemBuffer,
+    #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+    jit: Option<crate::jit::JitGemm>,
+}
+
+impl<'a, T: 'static> GemmPlan<'a, T> {
+    /// Creates a plan for computing `m×n` destinations from `m×k` and `k×n` operands.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+    pub fn new(m: usize, n: usize, k: usize, parallelism: Parallelism<'a>) -> Self {
+        let backend = get_backend::<T>();
+        let kernel_params = [
+            (backend.kernel_params)(m, n, k, parallelism),
+            (backend.kernel_params)(n, m, k, parallelism),
+        ];
+        let req =
+            (backend.gemm_req)(m, n, k, parallelism).or((backend.gemm_req)(n, m, k, parallelism));
+
+        Self {
+            m,
+            n,
+            k,
+            parallelism,
+            backend,
+            kernel_params,
+            mem: GlobalMemBuffer::new(req),
+            #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+            jit: None,
+        }
+    }
+
+    /// Creates a plan that runs microkernels generated for the shape, with the blocking of
+    /// [`JitBlocking::of`], if they're available. See [`GemmPlan::new_jit_with_blocking`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+    ///
+    /// [`JitBlocking::of`]: crate::JitBlocking::of
+    #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+    pub fn new_jit(m: usize, n: usize, k: usize, parallelism: Parallelism<'a>) -> Self {
+        match crate::JitBlocking::of::<T>() {
+            Some(blocking) => Self::new_jit_with_blocking(m, n, k, parallelism, blocking),
+            None => Self::new(m, n, k, parallelism),
+        }
+    }
+
+    /// Creates a plan that runs microkernels generated for the shape and for `blocking`, which
+    /// is worth it when the plan is executed many times.
+    ///
+    /// The kernels are generated for `f32` and `f64` on machines with AVX2 and FMA. In other
+    /// cases, or if the blocking doesn't fit the 16 vector registers, the plan uses the active
+    /// backend, as if created with [`GemmPlan::new`], which [`GemmPlan::is_jit`] tells apart.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+    #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+    pub fn new_jit_with_blocking(
+        m: usize,
+        n: usize,
+        k: usize,
+        parallelism: Parallelism<'a>,
+        blocking: crate::JitBlocking,
+    ) -> Self {
+        let mut plan = Self::new(m, n, k, parallelism);
+        let default_kc = Ord::min(plan.kernel_params[0].kc, plan.kernel_params[1].kc);
+        plan.jit = crate::jit::JitGemm::new::<T>(m, n, k, blocking, default_kc);
+        plan
+    }
+
+    /// Whether the plan runs microkernels generated for its shape.
+    #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+    #[inline]
+    pub fn is_jit(&self) -> bool {
+        self.jit.is_some()
+    }
+
+    #[inline]
+    pub fn m(&self) -> usize {
+        self.m
+    }
+    #[inline]
+    pub fn n(&self) -> usize {
+        self.n
+    }
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Name of the backend used by the plan.
+    #[inline]
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name
+    }
+
+    /// dst := alpha×dst + beta×lhs×rhs
+    ///
+    /// Same as [`gemm`](crate::gemm()), with the dimensions and parallelism taken from the plan.
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as [`gemm`](crate::gemm()).
+    pub unsafe fn execute(
+        &mut self,
+        dst: *mut T,
+        dst_cs: isize,
+        dst_rs: isize,
+        read_dst: bool,
+        lhs: *const T,
+        lhs_cs: isize,
+        lhs_rs: isize,
+        rhs: *const T,
+        rhs_cs: isize,
+        rhs_rs: isize,
+        alpha: T,
+        beta: T,
+        conj_dst: bool,
+        conj_lhs: bool,
+        conj_rhs: bool,
+    ) {
+        #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+        if let Some(jit) = &mut self.jit {
+            // the kernels are generated for real types only, which ignore the conjugations
+            return jit.execute(
+                dst,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs,
+                lhs_cs,
+                lhs_rs,
+                rhs,
+                rhs_cs,
+                rhs_rs,
+                alpha,
+                beta,
+                self.parallelism,
+            );
+        }
+
+        let transposed = transposes_product(dst_cs, dst_rs, lhs_cs, lhs_rs, rhs_cs, rhs_rs);
+        let kernel_params = self.kernel_params[transposed as usize];
+        let mem = &mut self.mem;
+        gemm_with_backend(
+            self.backend,
+            self.m,
+            self.n,
+            self.k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            self.parallelism,
+            move |_| GemmConfig {
+                kernel_params: Some(kernel_params),
+                stack: Some(DynStack::new(mem)),
+                ..Default::default()
+            },
+        )
+    }
+}
@@ -0,0 +1,109 @@
+use crate::gemm::{c32, c64, gemm_cplx_fallback, gemm_fallback};
+use crate::Parallelism;
+use core::any::TypeId;
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Naive triple loop computing the same product as [`gemm`](crate::gemm), with the same
+/// arguments and stride semantics, meant as an authoritative comparison point for tests and
+/// benchmarks.
+///
+/// Each element is computed on its own, by adding the products along `k` one after the other
+/// with separate multiplies and adds, then scaling the sum by `beta` and adding `alpha×dst`
+/// (whose value isn't read if `read_dst` is false). The result only depends on the operands, and
+/// is bitwise identical to the one of
+/// [`gemm_with_strict_arithmetic`](crate::gemm_with_strict_arithmetic) for `f32` and `f64`.
+/// `gemm::f16` is computed in `f16` arithmetic, rather than accumulated in `f32` like the backend.
+///
+/// The product is always computed sequentially on the current thread: `parallelism` is only
+/// accepted for symmetry with [`gemm`](crate::gemm). The conjugation flags are ignored for the
+/// real types, like in [`gemm`](crate::gemm).
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `gemm::f16`, `gemm::c32`, or `gemm::c64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm).
+#[track_caller]
+pub unsafe fn gemm_reference<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+) {
+    let _ = parallelism;
+
+    macro_rules! dispatch_real {
+        ($($ty: ty),*) => {$(
+            if TypeId::of::<T>() == TypeId::of::<$ty>() {
+                return gemm_fallback::<$ty>(
+                    m,
+                    n,
+                    k,
+                    dst as *mut $ty,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs as *const $ty,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs as *const $ty,
+                    rhs_cs,
+                    rhs_rs,
+                    core::mem::transmute_copy(&alpha),
+                    core::mem::transmute_copy(&beta),
+                );
+            }
+        )*};
+    }
+
+    macro_rules! dispatch_cplx {
+        ($($ty: ty),*) => {$(
+            if TypeId::of::<T>() == TypeId::of::<$ty>() {
+                return gemm_cplx_fallback(
+                    m,
+                    n,
+                    k,
+                    dst as *mut $ty,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs as *const $ty,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs as *const $ty,
+                    rhs_cs,
+                    rhs_rs,
+                    core::mem::transmute_copy::<T, $ty>(&alpha),
+                    core::mem::transmute_copy::<T, $ty>(&beta),
+                    conj_dst,
+                    conj_lhs,
+                    conj_rhs,
+                );
+            }
+        )*};
+    }
+
+    #[cfg(feature = "f16")]
+    dispatch_real!(crate::f16);
+    dispatch_real!(f64, f32);
+    dispatch_cplx!(c64, c32);
+    panic!("{}", crate::GemmError::UnsupportedType)
+}
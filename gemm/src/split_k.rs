@@ -0,0 +1,262 @@
+use crate::gemm::{c32, c64, gemm_with_backend, is_transposed, GemmBackend, Settings};
+use crate::Parallelism;
+use core::any::TypeId;
+use core::ops::Add;
+use dyn_stack::{DynStack, GlobalMemBuffer, StackReq};
+use gemm_common::{
+    gemm::{
+        get_deterministic, inner_parallelism, max_threads, par_for_each, threading_threshold,
+        CACHELINE_ALIGN,
+    },
+    Ptr,
+};
+
+/// Smallest depth handled by each thread in a product split along `k`.
+const SPLIT_K_MIN_DEPTH: usize = 256;
+/// Largest destination, in elements, for which a product is split along `k`. Larger ones have
+/// enough blocks to keep the threads busy without it.
+const SPLIT_K_MAX_DST: usize = 256 * 256;
+/// Smallest ratio between `k` and the largest destination dimension for which a product is
+/// split along `k`.
+const SPLIT_K_MIN_RATIO: usize = 4;
+
+/// Number of slices of `k` computed in parallel for this problem, or `1` if the product
+/// shouldn't be split. Products whose work is below `threshold`, or the
+/// [threading threshold](threading_threshold) of `T` on `backend` if it's `None`, aren't split.
+pub(crate) fn n_splits<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism<'_>,
+    threshold: Option<usize>,
+) -> usize {
+    let max_threads = max_threads(parallelism);
+    let threshold = threshold.unwrap_or_else(|| threading_threshold::<T>(backend.simd_bytes));
+    if max_threads <= 1
+        || m.saturating_mul(n) > SPLIT_K_MAX_DST
+        || k < SPLIT_K_MIN_RATIO.saturating_mul(Ord::max(m, n))
+        || m.saturating_mul(n).saturating_mul(k) < threshold
+    {
+        return 1;
+    }
+    Ord::max(Ord::min(max_threads, k / SPLIT_K_MIN_DEPTH), 1)
+}
+
+fn partial_req<T: 'static>(m: usize, n: usize, n_splits: usize) -> StackReq {
+    StackReq::new_aligned::<T>((n_splits - 1) * m * n, CACHELINE_ALIGN)
+}
+
+/// Whether `stack` can hold the partial products of `n_splits` slices, or they're allocated if
+/// it's `None`.
+pub(crate) fn fits<T: 'static>(
+    stack: Option<&DynStack<'_>>,
+    m: usize,
+    n: usize,
+    n_splits: usize,
+) -> bool {
+    match stack {
+        Some(stack) => stack.can_hold(partial_req::<T>(m, n, n_splits)),
+        None => true,
+    }
+}
+
+/// Scratch memory needed by [`gemm_split_k`], or an empty requirement if the product isn't
+/// split, including in deterministic mode.
+pub(crate) fn split_k_req<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism<'_>,
+) -> StackReq {
+    let n_splits = n_splits(backend, m, n, k, parallelism, None);
+    if n_splits <= 1 || get_deterministic() {
+        StackReq::empty()
+    } else {
+        partial_req::<T>(m, n, n_splits)
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Splits `k` into `n_splits` slices whose products are computed in parallel. The first one is
+/// accumulated into `dst` directly and the others are stored in private buffers, which are then
+/// added to `dst` by a parallel reduction. Each slice is computed with `settings`. The buffers are
+/// taken from `stack`, or allocated if it's `None`.
+pub(crate) unsafe fn gemm_split_k<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    n_splits: usize,
+    settings: Settings,
+    stack: Option<DynStack<'_>>,
+) {
+    macro_rules! dispatch {
+        ($($ty: ty),*) => {$(
+            if TypeId::of::<T>() == TypeId::of::<$ty>() {
+                return split_k::<$ty>(
+                    &*(backend as *const GemmBackend<T> as *const GemmBackend<$ty>),
+                    m,
+                    n,
+                    k,
+                    Ptr(dst as *mut $ty),
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    Ptr(lhs as *mut $ty),
+                    lhs_cs,
+                    lhs_rs,
+                    Ptr(rhs as *mut $ty),
+                    rhs_cs,
+                    rhs_rs,
+                    core::mem::transmute_copy(&alpha),
+                    core::mem::transmute_copy(&beta),
+                    conj_dst,
+                    conj_lhs,
+                    conj_rhs,
+                    parallelism,
+                    n_splits,
+                    settings,
+                    stack,
+                );
+            }
+        )*};
+    }
+
+    #[cfg(feature = "f16")]
+    dispatch!(crate::f16);
+    dispatch!(f64, f32, c64, c32);
+    unreachable!()
+}
+
+unsafe fn split_k<T: 'static + Copy + Send + Sync + Add<Output = T>>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: Ptr<T>,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: Ptr<T>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    n_splits: usize,
+    settings: Settings,
+    stack: Option<DynStack<'_>>,
+) {
+    let mut mem = None;
+    let stack = match stack {
+        Some(stack) => stack,
+        None => {
+            let req = partial_req::<T>(m, n, n_splits);
+            DynStack::new(mem.insert(GlobalMemBuffer::new(req)))
+        }
+    };
+    let (mut partial, _) = stack.make_aligned_uninit::<T>((n_splits - 1) * m * n, CACHELINE_ALIGN);
+    let partial = Ptr(partial.as_mut_ptr() as *mut T);
+
+    // the buffers have the same orientation as dst, so that the products aren't transposed
+    // differently, and are contiguous along the outer dimension of the reduction
+    let transposed = is_transposed(dst_cs, dst_rs);
+    let (partial_cs, partial_rs) = if transposed {
+        (1, n as isize)
+    } else {
+        (m as isize, 1)
+    };
+
+    let inner = inner_parallelism(parallelism, n_splits);
+
+    par_for_each(parallelism, n_splits, |t| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, lhs, rhs, partial) = (dst, lhs, rhs, partial);
+        let start = k * t / n_splits;
+        let end = k * (t + 1) / n_splits;
+        let lhs = lhs.wrapping_offset(start as isize * lhs_cs);
+        let rhs = rhs.wrapping_offset(start as isize * rhs_rs);
+
+        let (dst, dst_cs, dst_rs, read_dst, conj_dst) = if t == 0 {
+            (dst, dst_cs, dst_rs, read_dst, conj_dst)
+        } else {
+            (
+                partial.wrapping_add((t - 1) * m * n),
+                partial_cs,
+                partial_rs,
+                false,
+                false,
+            )
+        };
+        gemm_with_backend(
+            backend,
+            m,
+            n,
+            end - start,
+            dst.0,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs.0,
+            lhs_cs,
+            lhs_rs,
+            rhs.0,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            inner,
+            |_| settings.config(None),
+        )
+    });
+
+    let (n_outer, n_inner, dst_outer, dst_inner) = if transposed {
+        (m, n, dst_rs, dst_cs)
+    } else {
+        (n, m, dst_cs, dst_rs)
+    };
+    let n_threads = Ord::min(max_threads(parallelism), n_outer);
+
+    par_for_each(parallelism, n_threads, |tid| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, partial) = (dst, partial);
+        for j in n_outer * tid / n_threads..n_outer * (tid + 1) / n_threads {
+            let dst = dst.wrapping_offset(j as isize * dst_outer).0;
+            for s in 0..n_splits - 1 {
+                let src = partial.wrapping_add(s * m * n + j * n_inner).0;
+                for i in 0..n_inner {
+                    let dst = dst.offset(i as isize * dst_inner);
+                    *dst = *dst + *src.add(i);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,500 @@
+use crate::gemm::{c32, c64, gemm_with_backend, is_complex, GemmBackend, Settings};
+use crate::Parallelism;
+use core::any::TypeId;
+use core::ops::{Add, Mul, Sub};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use dyn_stack::{DynStack, GlobalMemBuffer, ReborrowMut, StackReq};
+use gemm_common::gemm::CACHELINE_ALIGN;
+use num_traits::{One, Zero};
+
+/// Smallest dimension for which a product is split by one level of the Strassen-Winograd
+/// recursion, so that `8192×8192×8192` products take a single level.
+pub const DEFAULT_STRASSEN_THRESHOLD: usize = 8192;
+
+static STRASSEN_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_STRASSEN_THRESHOLD);
+
+#[inline]
+pub fn get_strassen_threshold() -> usize {
+    STRASSEN_THRESHOLD.load(Ordering::Relaxed)
+}
+#[inline]
+pub fn set_strassen_threshold(value: usize) {
+    STRASSEN_THRESHOLD.store(value, Ordering::Relaxed);
+}
+
+/// Scalar types supported by the recursion.
+trait Scalar:
+    'static + Copy + Zero + One + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+}
+
+impl<T: 'static + Copy + Zero + One + Add<Output = T> + Sub<Output = T> + Mul<Output = T>> Scalar
+    for T
+{
+}
+
+fn is_supported<T: 'static>() -> bool {
+    TypeId::of::<T>() == TypeId::of::<f64>()
+        || TypeId::of::<T>() == TypeId::of::<f32>()
+        || TypeId::of::<T>() == TypeId::of::<c64>()
+        || TypeId::of::<T>() == TypeId::of::<c32>()
+}
+
+#[inline]
+fn splits(m: usize, n: usize, k: usize, threshold: usize) -> bool {
+    // halving a dimension below 2 would leave empty blocks
+    Ord::min(Ord::min(m, n), k) >= Ord::max(threshold, 2)
+}
+
+/// Whether [`gemm`](crate::gemm) goes through the Strassen-Winograd recursion for this problem.
+pub(crate) fn applies<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+) -> bool {
+    let conj = is_complex::<T>() && (conj_dst || conj_lhs || conj_rhs);
+    !conj && is_supported::<T>() && splits(m, n, k, get_strassen_threshold())
+}
+
+fn leaf_req<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism<'_>,
+) -> StackReq {
+    // the leaves may be transposed depending on the strides of their destination
+    (backend.gemm_req)(m, n, k, parallelism).or((backend.gemm_req)(n, m, k, parallelism))
+}
+
+fn recursion_req<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism<'_>,
+    threshold: usize,
+) -> StackReq {
+    if !splits(m, n, k, threshold) {
+        return leaf_req(backend, m, n, k, parallelism);
+    }
+    let (m2, n2, k2) = (m / 2, n / 2, k / 2);
+    let temps = StackReq::new_aligned::<T>(m2 * k2, CACHELINE_ALIGN)
+        .and(StackReq::new_aligned::<T>(k2 * n2, CACHELINE_ALIGN))
+        .and(StackReq::new_aligned::<T>(m2 * n2, CACHELINE_ALIGN))
+        .and(StackReq::new_aligned::<T>(m2 * n2, CACHELINE_ALIGN));
+    let inner = recursion_req(backend, m2, n2, k2, parallelism, threshold)
+        .or(leaf_req(backend, 2 * m2, 2 * n2, 1, parallelism))
+        .or(leaf_req(backend, m, 1, k, parallelism))
+        .or(leaf_req(backend, 1, 2 * n2, k, parallelism));
+    temps.and(inner)
+}
+
+/// Scratch memory needed by [`gemm_strassen`], or an empty requirement if the recursion doesn't
+/// apply to `T` or to the shape of the problem.
+pub(crate) fn strassen_req<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism<'_>,
+) -> StackReq {
+    let threshold = get_strassen_threshold();
+    if !is_supported::<T>() || !splits(m, n, k, threshold) {
+        return StackReq::empty();
+    }
+    recursion_req(backend, m, n, k, parallelism, threshold)
+}
+
+/// Matrix block, with strides given in elements.
+#[derive(Copy, Clone)]
+struct Block<T> {
+    ptr: *mut T,
+    cs: isize,
+    rs: isize,
+}
+
+impl<T> Block<T> {
+    #[inline]
+    fn new(ptr: *const T, cs: isize, rs: isize) -> Self {
+        Self {
+            ptr: ptr as *mut T,
+            cs,
+            rs,
+        }
+    }
+
+    /// Column-major temporary with `nrows` rows.
+    #[inline]
+    fn temp(ptr: *mut T, nrows: usize) -> Self {
+        Self {
+            ptr,
+            cs: nrows as isize,
+            rs: 1,
+        }
+    }
+
+    #[inline]
+    fn at(self, row: usize, col: usize) -> Self {
+        Self {
+            ptr: self
+                .ptr
+                .wrapping_offset(row as isize * self.rs + col as isize * self.cs),
+            ..self
+        }
+    }
+
+    /// Quadrant `(i, j)` of the `2×2` split with `nrows×ncols` blocks.
+    #[inline]
+    fn quad(self, i: usize, j: usize, nrows: usize, ncols: usize) -> Self {
+        self.at(i * nrows, j * ncols)
+    }
+}
+
+struct Strassen<'a, T> {
+    backend: &'a GemmBackend<T>,
+    threshold: usize,
+    parallelism: Parallelism<'a>,
+    settings: Settings,
+}
+
+impl<T: Scalar> Strassen<'_, T> {
+    /// dst := f(a, b), element-wise. `dst` may be the same block as `a` or `b`.
+    unsafe fn combine(
+        &self,
+        m: usize,
+        n: usize,
+        dst: Block<T>,
+        a: Block<T>,
+        b: Block<T>,
+        f: impl Fn(T, T) -> T,
+    ) {
+        for j in 0..n {
+            for i in 0..m {
+                *dst.at(i, j).ptr = f(*a.at(i, j).ptr, *b.at(i, j).ptr);
+            }
+        }
+    }
+
+    /// dst += beta×src
+    unsafe fn add_scaled(&self, m: usize, n: usize, dst: Block<T>, src: Block<T>, beta: T) {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.at(i, j).ptr;
+                *dst = *dst + beta * *src.at(i, j).ptr;
+            }
+        }
+    }
+
+    unsafe fn leaf(
+        &self,
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: Block<T>,
+        read_dst: bool,
+        lhs: Block<T>,
+        rhs: Block<T>,
+        alpha: T,
+        beta: T,
+        stack: DynStack<'_>,
+    ) {
+        gemm_with_backend(
+            self.backend,
+            m,
+            n,
+            k,
+            dst.ptr,
+            dst.cs,
+            dst.rs,
+            read_dst,
+            lhs.ptr,
+            lhs.cs,
+            lhs.rs,
+            rhs.ptr,
+            rhs.cs,
+            rhs.rs,
+            alpha,
+            beta,
+            false,
+            false,
+            false,
+            self.parallelism,
+            |_| self.settings.config(Some(stack)),
+        )
+    }
+
+    /// dst := alpha×dst + beta×lhs×rhs, where `dst` isn't read if `read_dst` is false.
+    unsafe fn product(
+        &self,
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: Block<T>,
+        read_dst: bool,
+        lhs: Block<T>,
+        rhs: Block<T>,
+        alpha: T,
+        beta: T,
+        stack: DynStack<'_>,
+    ) {
+        if !splits(m, n, k, self.threshold) {
+            return self.leaf(m, n, k, dst, read_dst, lhs, rhs, alpha, beta, stack);
+        }
+
+        let one = T::one();
+        let add = |a: T, b: T| a + b;
+        let sub = |a: T, b: T| a - b;
+        let (m2, n2, k2) = (m / 2, n / 2, k / 2);
+
+        let (mut x, stack) = stack.make_aligned_uninit::<T>(m2 * k2, CACHELINE_ALIGN);
+        let (mut y, stack) = stack.make_aligned_uninit::<T>(k2 * n2, CACHELINE_ALIGN);
+        let (mut z0, stack) = stack.make_aligned_uninit::<T>(m2 * n2, CACHELINE_ALIGN);
+        let (mut z1, mut stack) = stack.make_aligned_uninit::<T>(m2 * n2, CACHELINE_ALIGN);
+        let x = Block::temp(x.as_mut_ptr() as *mut T, m2);
+        let y = Block::temp(y.as_mut_ptr() as *mut T, k2);
+        let z0 = Block::temp(z0.as_mut_ptr() as *mut T, m2);
+        let z1 = Block::temp(z1.as_mut_ptr() as *mut T, m2);
+
+        let a = |i, j| lhs.quad(i, j, m2, k2);
+        let b = |i, j| rhs.quad(i, j, k2, n2);
+        let c = |i, j| dst.quad(i, j, m2, n2);
+
+        // the even part of dst is scaled once, then only accumulated into
+        for j in 0..2 * n2 {
+            for i in 0..2 * m2 {
+                let dst = dst.at(i, j).ptr;
+                *dst = if read_dst { alpha * *dst } else { T::zero() };
+            }
+        }
+
+        // Winograd's variant: 7 products and 15 additions of blocks, where
+        // s1 = a21 + a22, s2 = s1 - a11, s3 = a11 - a21, s4 = a12 - s2,
+        // t1 = b12 - b11, t2 = b22 - t1, t3 = b22 - b12, t4 = t2 - b21.
+
+        // p1 = a11×b11, p2 = a12×b21
+        // c11 += p1 + p2
+        self.product(
+            m2,
+            n2,
+            k2,
+            z0,
+            false,
+            a(0, 0),
+            b(0, 0),
+            one,
+            one,
+            stack.rb_mut(),
+        );
+        self.add_scaled(m2, n2, c(0, 0), z0, beta);
+        self.product(
+            m2,
+            n2,
+            k2,
+            c(0, 0),
+            true,
+            a(0, 1),
+            b(1, 0),
+            one,
+            beta,
+            stack.rb_mut(),
+        );
+
+        // p5 = s1×t1
+        // c12 += p5, c22 += p5
+        self.combine(m2, k2, x, a(1, 0), a(1, 1), add);
+        self.combine(k2, n2, y, b(0, 1), b(0, 0), sub);
+        self.product(m2, n2, k2, z1, false, x, y, one, one, stack.rb_mut());
+        self.add_scaled(m2, n2, c(0, 1), z1, beta);
+        self.add_scaled(m2, n2, c(1, 1), z1, beta);
+
+        // p6 = s2×t2, u2 = p1 + p6
+        // c12 += u2, c21 += u2, c22 += u2
+        self.combine(m2, k2, x, x, a(0, 0), sub);
+        self.combine(k2, n2, y, b(1, 1), y, sub);
+        self.product(m2, n2, k2, z0, true, x, y, one, one, stack.rb_mut());
+        self.add_scaled(m2, n2, c(0, 1), z0, beta);
+        self.add_scaled(m2, n2, c(1, 0), z0, beta);
+        self.add_scaled(m2, n2, c(1, 1), z0, beta);
+
+        // p3 = s4×b22, p4 = a22×t4
+        // c12 += p3, c21 -= p4
+        self.combine(m2, k2, x, a(0, 1), x, sub);
+        self.product(
+            m2,
+            n2,
+            k2,
+            c(0, 1),
+            true,
+            x,
+            b(1, 1),
+            one,
+            beta,
+            stack.rb_mut(),
+        );
+        self.combine(k2, n2, y, y, b(1, 0), sub);
+        self.product(
+            m2,
+            n2,
+            k2,
+            c(1, 0),
+            true,
+            a(1, 1),
+            y,
+            one,
+            T::zero() - beta,
+            stack.rb_mut(),
+        );
+
+        // p7 = s3×t3
+        // c21 += p7, c22 += p7
+        self.combine(m2, k2, x, a(0, 0), a(1, 0), sub);
+        self.combine(k2, n2, y, b(1, 1), b(0, 1), sub);
+        self.product(m2, n2, k2, z0, false, x, y, one, one, stack.rb_mut());
+        self.add_scaled(m2, n2, c(1, 0), z0, beta);
+        self.add_scaled(m2, n2, c(1, 1), z0, beta);
+
+        // odd dimensions are peeled off and handled by regular products
+        if k % 2 == 1 {
+            self.leaf(
+                2 * m2,
+                2 * n2,
+                1,
+                dst,
+                true,
+                lhs.at(0, k - 1),
+                rhs.at(k - 1, 0),
+                one,
+                beta,
+                stack.rb_mut(),
+            );
+        }
+        if n % 2 == 1 {
+            self.leaf(
+                m,
+                1,
+                k,
+                dst.at(0, n - 1),
+                read_dst,
+                lhs,
+                rhs.at(0, n - 1),
+                alpha,
+                beta,
+                stack.rb_mut(),
+            );
+        }
+        if m % 2 == 1 {
+            self.leaf(
+                1,
+                2 * n2,
+                k,
+                dst.at(m - 1, 0),
+                read_dst,
+                lhs.at(m - 1, 0),
+                rhs,
+                alpha,
+                beta,
+                stack.rb_mut(),
+            );
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Strassen-Winograd recursion: while the smallest dimension is at least the threshold given by
+/// [`get_strassen_threshold`], the product is split in `2×2` blocks and computed with 7 block
+/// products instead of 8, and the blocks below the threshold are handed to the packed kernels
+/// with `settings`. The scratch memory is taken from `stack`, or allocated if it's `None`.
+///
+/// The caller must check [`applies`] first.
+pub(crate) unsafe fn gemm_strassen<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism<'_>,
+    settings: Settings,
+    stack: Option<DynStack<'_>>,
+) {
+    macro_rules! dispatch {
+        ($($ty: ty),*) => {$(
+            if TypeId::of::<T>() == TypeId::of::<$ty>() {
+                return strassen::<$ty>(
+                    &*(backend as *const GemmBackend<T> as *const GemmBackend<$ty>),
+                    m,
+                    n,
+                    k,
+                    Block::new(dst as *const $ty, dst_cs, dst_rs),
+                    read_dst,
+                    Block::new(lhs as *const $ty, lhs_cs, lhs_rs),
+                    Block::new(rhs as *const $ty, rhs_cs, rhs_rs),
+                    core::mem::transmute_copy(&alpha),
+                    core::mem::transmute_copy(&beta),
+                    parallelism,
+                    settings,
+                    stack,
+                );
+            }
+        )*};
+    }
+
+    dispatch!(f64, f32, c64, c32);
+    unreachable!()
+}
+
+unsafe fn strassen<T: Scalar>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: Block<T>,
+    read_dst: bool,
+    lhs: Block<T>,
+    rhs: Block<T>,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism<'_>,
+    settings: Settings,
+    stack: Option<DynStack<'_>>,
+) {
+    let strassen = Strassen {
+        backend,
+        threshold: get_strassen_threshold(),
+        parallelism,
+        settings,
+    };
+    let run = |stack: DynStack<'_>| {
+        strassen.product(m, n, k, dst, read_dst, lhs, rhs, alpha, beta, stack)
+    };
+
+    match stack {
+        Some(stack) => run(stack),
+        None => {
+            let mut mem = GlobalMemBuffer::new(recursion_req(
+                backend,
+                m,
+                n,
+                k,
+                parallelism,
+                strassen.threshold,
+            ));
+            run(DynStack::new(&mut mem))
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::gemm::{gemm, get_backend, is_transposed};
+use crate::Parallelism;
+use core::any::TypeId;
+use core::ops::{Add, Mul};
+use gemm_common::{
+    cache::DivCeil,
+    gemm::{max_threads, par_for_each, threading_threshold},
+    Ptr,
+};
+use num_traits::Zero;
+
+/// Rows of the destination accumulated together, along which the sums are vectorized.
+const STRICT_MR: usize = 32;
+/// Columns of the destination accumulated together, which share the loads of the lhs.
+const STRICT_NR: usize = 4;
+
+/// dst := alpha×dst + beta×lhs×rhs
+///
+/// Same as [`gemm`](crate::gemm) for `f32` and `f64`, where `strict` selects whether each
+/// element is computed with the same operations as the naive triple loop: the products along `k`
+/// are rounded and added one after the other, with separate multiplies and adds, and the sum is
+/// then scaled by `beta` and added to `alpha×dst`. The results are then bitwise identical to the
+/// ones of [`gemm_reference`](crate::gemm_reference), on every machine and for any `parallelism`, instead of depending on
+/// whether the backend uses fused multiply-adds and on its blocking parameters.
+///
+/// The strict product is vectorized along the rows of the destination (or its columns, for a
+/// row-major destination), and its columns are split between the threads allowed by
+/// `parallelism`. It doesn't block `k` or pack the operands, so that it's slower than the
+/// backend for large products.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`.
+///
+/// # Safety
+///
+/// Same requirements as [`gemm`](crate::gemm).
+#[track_caller]
+pub unsafe fn gemm_with_strict_arithmetic<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism<'_>,
+    strict: bool,
+) {
+    if !strict {
+        return gemm(
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        );
+    }
+
+    macro_rules! dispatch {
+        ($($ty: ty),*) => {$(
+            if TypeId::of::<T>() == TypeId::of::<$ty>() {
+                return gemm_strict::<$ty>(
+                    m,
+                    n,
+                    k,
+                    Ptr(dst as *mut $ty),
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    Ptr(lhs as *mut $ty),
+                    lhs_cs,
+                    lhs_rs,
+                    Ptr(rhs as *mut $ty),
+                    rhs_cs,
+                    rhs_rs,
+                    core::mem::transmute_copy(&alpha),
+                    core::mem::transmute_copy(&beta),
+                    parallelism,
+                );
+            }
+        )*};
+    }
+
+    dispatch!(f64, f32);
+    panic!("{}", crate::GemmError::UnsupportedType)
+}
+
+unsafe fn gemm_strict<
+    T: 'static + Copy + Send + Sync + Zero + Add<Output = T> + Mul<Output = T>,
+>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: Ptr<T>,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: Ptr<T>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism<'_>,
+) {
+    // the transposed product multiplies the same pairs of elements, in the other order, which
+    // gives the same results since the products are commutative
+    let (m, n, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs) =
+        if is_transposed(dst_cs, dst_rs) {
+            (
+                n, m, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs,
+            )
+        } else {
+            (
+                m, n, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
+            )
+        };
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let threshold = threading_threshold::<T>(get_backend::<T>().simd_bytes);
+    let n_threads = if m.saturating_mul(n).saturating_mul(k) < threshold {
+        1
+    } else {
+        Ord::min(max_threads(parallelism), n.msrv_div_ceil(STRICT_NR))
+    };
+
+    let columns = |tid: usize| {
+        // capture the whole pointers, which are `Sync` unlike their fields
+        let (dst, lhs, rhs) = (dst, lhs, rhs);
+        let end = n * (tid + 1) / n_threads;
+        let mut col = n * tid / n_threads;
+        while col < end {
+            let n_cols = Ord::min(STRICT_NR, end - col);
+            let mut row = 0;
+            while row < m {
+                let n_rows = Ord::min(STRICT_MR, m - row);
+                let mut acc = [[T::zero(); STRICT_MR]; STRICT_NR];
+                for depth in 0..k as isize {
+                    let lhs = lhs
+                        .wrapping_offset(row as isize * lhs_rs + depth * lhs_cs)
+                        .0;
+                    for (j, acc) in acc[..n_cols].iter_mut().enumerate() {
+                        let b = *rhs
+                            .wrapping_offset(depth * rhs_rs + (col + j) as isize * rhs_cs)
+                            .0;
+                        for (i, acc) in acc[..n_rows].iter_mut().enumerate() {
+                            *acc = *acc + *lhs.offset(i as isize * lhs_rs) * b;
+                        }
+                    }
+                }
+
+                for (j, acc) in acc[..n_cols].iter().enumerate() {
+                    let dst = dst
+                        .wrapping_offset(row as isize * dst_rs + (col + j) as isize * dst_cs)
+                        .0;
+                    for (i, &acc) in acc[..n_rows].iter().enumerate() {
+                        let dst = dst.offset(i as isize * dst_rs);
+                        let mut value = acc * beta;
+                        if read_dst {
+                            value = value + alpha * *dst;
+                        }
+                        *dst = value;
+                    }
+                }
+                row += n_rows;
+            }
+            col += n_cols;
+        }
+    };
+
+    if n_threads <= 1 {
+        columns(0);
+    } else {
+        par_for_each(parallelism, n_threads, columns);
+    }
+}
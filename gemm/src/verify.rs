@@ -0,0 +1,216 @@
+use crate::describe::DType;
+use crate::gemm::{for_each_builtin_backend, gemm_with_backend};
+use crate::{c32, c64, Parallelism};
+use gemm_common::gemm::GemmConfig;
+use std::vec;
+use std::vec::Vec;
+
+/// Divergence of one built-in backend from the exact product, as reported by [`verify`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BackendDivergence {
+    /// Name of the backend, which is the instruction set it targets.
+    pub backend: &'static str,
+    /// Largest divergence `|d̂ - d| / (|alpha|×|dst| + |beta|×(|lhs|×|rhs|))` of an element `d̂`
+    /// computed by the backend from the exact result `d`, which is the quantity bounded by
+    /// [`ErrorBound::relative`](crate::ErrorBound::relative). `NaN` if the backend produced a
+    /// `NaN` or an infinity.
+    pub max_relative_divergence: f64,
+}
+
+/// Scalar that can be sampled and compared in `f64` arithmetic, as `(re, im)`.
+trait Sample: 'static + Copy {
+    fn from_parts(re: f64, im: f64) -> Self;
+    fn parts(self) -> (f64, f64);
+}
+
+impl Sample for f32 {
+    fn from_parts(re: f64, _: f64) -> Self {
+        re as f32
+    }
+    fn parts(self) -> (f64, f64) {
+        (self as f64, 0.0)
+    }
+}
+impl Sample for f64 {
+    fn from_parts(re: f64, _: f64) -> Self {
+        re
+    }
+    fn parts(self) -> (f64, f64) {
+        (self, 0.0)
+    }
+}
+impl Sample for c32 {
+    fn from_parts(re: f64, im: f64) -> Self {
+        c32::new(re as f32, im as f32)
+    }
+    fn parts(self) -> (f64, f64) {
+        (self.re as f64, self.im as f64)
+    }
+}
+impl Sample for c64 {
+    fn from_parts(re: f64, im: f64) -> Self {
+        c64::new(re, im)
+    }
+    fn parts(self) -> (f64, f64) {
+        (self.re, self.im)
+    }
+}
+#[cfg(feature = "f16")]
+impl Sample for crate::f16 {
+    fn from_parts(re: f64, _: f64) -> Self {
+        crate::f16::from_f64(re)
+    }
+    fn parts(self) -> (f64, f64) {
+        (self.to_f64(), 0.0)
+    }
+}
+
+/// Uniform sample in `[-1, 1)`, with the splitmix64 generator.
+fn uniform(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+}
+
+fn mul((a_re, a_im): (f64, f64), (b_re, b_im): (f64, f64)) -> (f64, f64) {
+    (a_re * b_re - a_im * b_im, a_re * b_im + a_im * b_re)
+}
+
+fn abs((re, im): (f64, f64)) -> f64 {
+    f64::hypot(re, im)
+}
+
+fn verify_impl<T: Sample>(
+    m: usize,
+    n: usize,
+    k: usize,
+    seed: u64,
+    parallelism: Parallelism<'_>,
+) -> Vec<BackendDivergence> {
+    let mut state = seed;
+    let mut sample = || {
+        let re = uniform(&mut state);
+        let im = uniform(&mut state);
+        T::from_parts(re, im)
+    };
+
+    // column-major operands
+    let lhs: Vec<T> = (0..m * k).map(|_| sample()).collect();
+    let rhs: Vec<T> = (0..k * n).map(|_| sample()).collect();
+    let init: Vec<T> = (0..m * n).map(|_| sample()).collect();
+    let alpha = sample();
+    let beta = sample();
+
+    // exact results, up to the roundings of the f64 arithmetic, and their scales
+    let mut exact = vec![(0.0, 0.0); m * n];
+    let mut scale = vec![0.0; m * n];
+    for j in 0..n {
+        for i in 0..m {
+            let (mut sum, mut sum_abs) = ((0.0, 0.0), 0.0);
+            for depth in 0..k {
+                let a = lhs[i + depth * m].parts();
+                let b = rhs[depth + j * k].parts();
+                let product = mul(a, b);
+                sum = (sum.0 + product.0, sum.1 + product.1);
+                sum_abs += abs(a) * abs(b);
+            }
+            let old = init[i + j * m].parts();
+            let (old, sum) = (mul(alpha.parts(), old), mul(beta.parts(), sum));
+            exact[i + j * m] = (old.0 + sum.0, old.1 + sum.1);
+            scale[i + j * m] = abs(old) + abs(beta.parts()) * sum_abs;
+        }
+    }
+
+    let mut divergences = Vec::new();
+    for_each_builtin_backend::<T>(|backend| {
+        let mut max = 0.0f64;
+        // the backend sees the transposed problem for row-major destinations
+        for colmajor in [true, false] {
+            let (dst_cs, dst_rs) = if colmajor { (m, 1) } else { (1, n) };
+            let mut dst = init.clone();
+            if !colmajor {
+                for j in 0..n {
+                    for i in 0..m {
+                        dst[i * n + j] = init[i + j * m];
+                    }
+                }
+            }
+
+            unsafe {
+                gemm_with_backend(
+                    backend,
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    dst_cs as isize,
+                    dst_rs as isize,
+                    true,
+                    lhs.as_ptr(),
+                    m as isize,
+                    1,
+                    rhs.as_ptr(),
+                    k as isize,
+                    1,
+                    alpha,
+                    beta,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    |_| GemmConfig::default(),
+                );
+            }
+
+            for j in 0..n {
+                for i in 0..m {
+                    let (re, im) = dst[i * dst_rs + j * dst_cs].parts();
+                    let (exact_re, exact_im) = exact[i + j * m];
+                    let divergence = abs((re - exact_re, im - exact_im))
+                        / f64::max(scale[i + j * m], f64::MIN_POSITIVE);
+                    // propagates the NaNs
+                    if divergence.is_nan() || divergence > max {
+                        max = divergence;
+                    }
+                }
+            }
+        }
+        divergences.push(BackendDivergence {
+            backend: backend.name,
+            max_relative_divergence: max,
+        });
+    });
+    divergences
+}
+
+/// Runs the same random `m×n×k` product of `dtype` matrices through each built-in backend
+/// supported by the current machine, including the ones that aren't selected by default, and
+/// returns how far each one diverges from the exact product, from the most portable backend to
+/// the fastest.
+///
+/// Each backend computes `alpha×dst + beta×lhs×rhs` for a column-major and a row-major
+/// destination, with the operands sampled uniformly in `[-1, 1)` from `seed`, so that a
+/// divergence can be reproduced. A backend whose divergence is far above the ones of the others,
+/// or above the bound of [`error_bound`](crate::error_bound), has a bug in one of its
+/// microkernels. The exact product is computed in `f64` arithmetic, whose own error is included
+/// in the divergences of the `f64` and `c64` products.
+pub fn verify(
+    m: usize,
+    n: usize,
+    k: usize,
+    dtype: DType,
+    seed: u64,
+    parallelism: Parallelism<'_>,
+) -> Vec<BackendDivergence> {
+    match dtype {
+        #[cfg(feature = "f16")]
+        DType::F16 => verify_impl::<crate::f16>(m, n, k, seed, parallelism),
+        DType::F32 => verify_impl::<f32>(m, n, k, seed, parallelism),
+        DType::F64 => verify_impl::<f64>(m, n, k, seed, parallelism),
+        DType::C32 => verify_impl::<c32>(m, n, k, seed, parallelism),
+        DType::C64 => verify_impl::<c64>(m, n, k, seed, parallelism),
+    }
+}
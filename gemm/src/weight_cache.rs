@@ -0,0 +1,189 @@
+use crate::gemm::{transposes_product, GemmBackend};
+use crate::pack::alloc_panels;
+use core::any::TypeId;
+use dyn_stack::GlobalMemBuffer;
+use gemm_common::cache::DivCeil;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Capacity of the weight cache of each thread by default, which disables it.
+pub const DEFAULT_WEIGHT_CACHE_CAPACITY: usize = 0;
+
+// rhs as given to `gemm`, along with the type, the backend and the side of the backend's product
+// it's packed for
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Key {
+    ptr: usize,
+    k: usize,
+    n: usize,
+    cs: isize,
+    rs: isize,
+    ty: TypeId,
+    backend: usize,
+    transposed: bool,
+}
+
+struct Entry {
+    key: Key,
+    panels: Rc<GlobalMemBuffer>,
+    bytes: usize,
+    last_use: u64,
+}
+
+struct WeightCache {
+    capacity: usize,
+    bytes: usize,
+    clock: u64,
+    entries: Vec<Entry>,
+}
+
+impl WeightCache {
+    // evicts the least recently used panels until `bytes` more fit in the cache
+    fn evict(&mut self, bytes: usize) {
+        while self.bytes + bytes > self.capacity {
+            let Some((idx, _)) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_use)
+            else {
+                return;
+            };
+            self.bytes -= self.entries.swap_remove(idx).bytes;
+        }
+    }
+}
+
+thread_local! {
+    static WEIGHT_CACHE: RefCell<WeightCache> = const {
+        RefCell::new(WeightCache {
+            capacity: DEFAULT_WEIGHT_CACHE_CAPACITY,
+            bytes: 0,
+            clock: 0,
+            entries: Vec::new(),
+        })
+    };
+}
+
+/// Size in bytes of the packed rhs panels that the calling thread keeps between calls to
+/// [`gemm`](crate::gemm), or `0` if the cache is disabled.
+#[inline]
+pub fn get_weight_cache_capacity() -> usize {
+    WEIGHT_CACHE.with(|cache| cache.borrow().capacity)
+}
+
+/// Enables the weight cache of the calling thread, which keeps the packed panels of the rhs of
+/// its products between calls to [`gemm`](crate::gemm), up to `bytes` bytes, or disables it if
+/// `bytes` is `0`. The least recently used panels are evicted to make room for new ones, or
+/// when the capacity shrinks.
+///
+/// The panels are looked up by the address, the dimensions and the strides of the rhs, so that
+/// a loop multiplying with the same weight matrix only packs it once. The cache must be cleared
+/// with [`clear_weight_cache`] whenever a cached rhs is modified, or freed while another matrix
+/// may be allocated at the same address, or the products keep using the old panels.
+///
+/// Only the products whose destination has nonnegative strides are cached, and only on the
+/// backends that support packing operands ahead of time. A product whose rhs comes from the cache
+/// reuses its panels in a single pass, so it's never split along `k` or into Strassen blocks,
+/// which would each pack a different part of the rhs. Products given blocking parameters, packed
+/// operands, a column scaling or `PackingPolicy::Never` for the rhs through
+/// [`gemm_with_config`](crate::gemm_with_config) don't use the cache.
+pub fn set_weight_cache_capacity(bytes: usize) {
+    WEIGHT_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.capacity = bytes;
+        cache.evict(0);
+    })
+}
+
+/// Frees the panels in the weight cache of the calling thread.
+pub fn clear_weight_cache() {
+    WEIGHT_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.entries.clear();
+        cache.bytes = 0;
+    })
+}
+
+/// Returns the rhs panels of the product from the weight cache of the calling thread, packing
+/// them first if they're missing, or `None` if the cache is disabled or can't hold the panels,
+/// or if the product can't use them. The panels are packed for the side of the product that the
+/// rhs ends up on once it's handed to the backend.
+pub(crate) unsafe fn cached_rhs_panels<T: 'static>(
+    backend: &GemmBackend<T>,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+) -> Option<Rc<GlobalMemBuffer>> {
+    // the panels don't follow the rows or columns that are reversed for a negative stride
+    if m == 0 || n == 0 || k == 0 || dst_cs < 0 || dst_rs < 0 {
+        return None;
+    }
+    let (Some(pack_lhs), Some(pack_rhs)) = (backend.pack_lhs, backend.pack_rhs) else {
+        return None;
+    };
+
+    // the rhs is the lhs of the transposed product, with `n` rows
+    let transposed = transposes_product(dst_cs, dst_rs, lhs_cs, lhs_rs, rhs_cs, rhs_rs);
+    let (pack, width, src_cs, src_rs) = if transposed {
+        (pack_lhs, backend.mr, rhs_rs, rhs_cs)
+    } else {
+        (pack_rhs, backend.nr, rhs_cs, rhs_rs)
+    };
+    let n_panels = n.msrv_div_ceil(width);
+    let bytes = n_panels
+        .saturating_mul(k * width)
+        .saturating_mul(core::mem::size_of::<T>());
+    let key = Key {
+        ptr: rhs as usize,
+        k,
+        n,
+        cs: rhs_cs,
+        rs: rhs_rs,
+        ty: TypeId::of::<T>(),
+        backend: backend as *const GemmBackend<T> as usize,
+        transposed,
+    };
+
+    WEIGHT_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if bytes > cache.capacity {
+            return None;
+        }
+        cache.clock += 1;
+        let clock = cache.clock;
+        if let Some(entry) = cache.entries.iter_mut().find(|entry| entry.key == key) {
+            entry.last_use = clock;
+            return Some(entry.panels.clone());
+        }
+
+        trace_event!(debug, n, k, transposed, "weight cache miss");
+        let mut mem = alloc_panels::<T>(n_panels, k * width);
+        pack(
+            n,
+            k,
+            mem.as_mut_ptr() as *mut T,
+            rhs,
+            src_cs,
+            src_rs,
+            k * width,
+        );
+        let panels = Rc::new(mem);
+        cache.evict(bytes);
+        cache.bytes += bytes;
+        cache.entries.push(Entry {
+            key,
+            panels: panels.clone(),
+            bytes,
+            last_use: clock,
+        });
+        Some(panels)
+    })
+}